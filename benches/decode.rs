@@ -0,0 +1,121 @@
+//! Decoding throughput, in MB/s of decompressed data, per kind of input and block type.
+//!
+//! Run with `cargo bench --bench decode`. Set `ENWIK8` to the path of the enwik8 file to use its
+//! first 4 MiB as the text corpus instead of generated text.
+
+use std::hint::black_box;
+use std::io::{Read, Write};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rzlib::{Compression, GzEncoder, RZLibReader};
+
+const CORPUS_SIZE: usize = 4 << 20;
+
+// Deterministic pseudo-random numbers, so that runs are comparable.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+}
+
+fn text() -> Vec<u8> {
+    if let Some(path) = std::env::var_os("ENWIK8") {
+        let mut data = std::fs::read(path).expect("cannot read ENWIK8");
+        data.truncate(CORPUS_SIZE);
+        return data;
+    }
+    const WORDS: [&str; 24] = [
+        "the", "of", "and", "to", "in", "a", "is", "that", "for", "it", "as", "was", "with", "be",
+        "by", "on", "not", "he", "this", "are", "compression", "stream", "window", "huffman",
+    ];
+    let mut rng = Lcg(1);
+    let mut data = Vec::with_capacity(CORPUS_SIZE);
+    while data.len() < CORPUS_SIZE {
+        // favor the first words, roughly like natural language
+        let index = (rng.next() % 24).min(rng.next() % 24) as usize;
+        data.extend_from_slice(WORDS[index].as_bytes());
+        data.push(if rng.next().is_multiple_of(12) { b'\n' } else { b' ' });
+    }
+    data.truncate(CORPUS_SIZE);
+    data
+}
+
+// Records of small, mostly increasing integers with noise, like typical binary formats.
+fn binary() -> Vec<u8> {
+    let mut rng = Lcg(2);
+    let mut data = Vec::with_capacity(CORPUS_SIZE);
+    let mut counter = 0u32;
+    while data.len() < CORPUS_SIZE {
+        counter += rng.next() % 16;
+        data.extend_from_slice(&counter.to_le_bytes());
+        data.extend_from_slice(&(rng.next() % 1000).to_le_bytes());
+        data.extend_from_slice(&rng.next().to_le_bytes()[..2]);
+    }
+    data.truncate(CORPUS_SIZE);
+    data
+}
+
+fn runs() -> Vec<u8> {
+    let mut rng = Lcg(3);
+    let mut data = Vec::with_capacity(CORPUS_SIZE);
+    while data.len() < CORPUS_SIZE {
+        let byte = rng.next() as u8 % 4;
+        let len = 100 + rng.next() as usize % 10_000;
+        data.extend(std::iter::repeat_n(byte, len));
+    }
+    data.truncate(CORPUS_SIZE);
+    data
+}
+
+fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], level);
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// Small blocks do not pay for the header of a dynamic code, so flushing every 256 bytes makes
+// the encoder choose fixed Huffman blocks, each followed by the empty stored block of the flush.
+fn compress_fixed(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    for chunk in data.chunks(256) {
+        encoder.write_all(chunk).unwrap();
+        encoder.flush().unwrap();
+    }
+    encoder.finish().unwrap()
+}
+
+fn decode(compressed: &[u8], out: &mut [u8]) -> usize {
+    let mut reader = RZLibReader::new(compressed);
+    let mut total = 0;
+    loop {
+        match reader.read(out).unwrap() {
+            0 => return total,
+            n => total += n,
+        }
+    }
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut out = vec![0; 64 * 1024];
+    for (name, data) in [("text", text()), ("binary", binary()), ("runs", runs())] {
+        let mut group = c.benchmark_group(format!("decode/{}", name));
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.sample_size(20);
+        // level 0 only emits stored blocks, higher levels mostly dynamic Huffman blocks
+        let inputs = [
+            ("stored", compress(&data, Compression::none())),
+            ("fixed", compress_fixed(&data)),
+            ("dynamic", compress(&data, Compression::default())),
+        ];
+        for (block_type, compressed) in inputs {
+            group.bench_function(block_type, |b| b.iter(|| decode(black_box(&compressed), &mut out)));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
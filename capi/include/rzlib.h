@@ -0,0 +1,52 @@
+/* C API of rzlib, a memory-safe gzip/zlib/deflate decoder, modelled on zlib's inflate. */
+
+#ifndef RZLIB_H
+#define RZLIB_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RZLIB_OK 0
+#define RZLIB_STREAM_END 1
+#define RZLIB_STREAM_ERROR (-2)
+#define RZLIB_DATA_ERROR (-3)
+#define RZLIB_BUF_ERROR (-5)
+
+#define RZLIB_FORMAT_GZIP 0
+#define RZLIB_FORMAT_ZLIB 1
+#define RZLIB_FORMAT_DEFLATE 2
+
+typedef struct rzlib_stream {
+    const uint8_t *next_in; /* next input byte */
+    size_t avail_in;        /* number of bytes available at next_in */
+    uint64_t total_in;      /* total number of input bytes consumed so far */
+
+    uint8_t *next_out;      /* next output byte will go here */
+    size_t avail_out;       /* remaining free space at next_out */
+    uint64_t total_out;     /* total number of bytes output so far */
+
+    const char *msg;        /* last error message, NULL if no error */
+    void *state;            /* not visible by applications */
+} rzlib_stream;
+
+/* Sets up strm to decode a stream of the given RZLIB_FORMAT_*. A gzip stream ends after its first
+ * member, leaving what follows in next_in. */
+int rzlib_inflate_init(rzlib_stream *strm, int format);
+
+/* Decodes as much as possible, updating next_in/avail_in and next_out/avail_out. Returns
+ * RZLIB_STREAM_END once the whole stream has been written out, RZLIB_OK on progress,
+ * RZLIB_BUF_ERROR if no progress was possible, RZLIB_DATA_ERROR on malformed input (see msg). */
+int rzlib_inflate(rzlib_stream *strm);
+
+/* Frees the state of strm. */
+int rzlib_inflate_end(rzlib_stream *strm);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RZLIB_H */
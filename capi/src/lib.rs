@@ -0,0 +1,270 @@
+//! A C API for the rzlib decoder, following the calling convention of zlib's `inflate`: point
+//! `next_in`/`avail_in` at compressed data and `next_out`/`avail_out` at room for the output, and
+//! call `rzlib_inflate` until it returns `RZLIB_STREAM_END`. Declared in `include/rzlib.h`.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::io::{self, BufRead, ErrorKind, Read};
+use std::{mem, ptr, slice};
+
+use rzlib::{Format, RZLibReader};
+
+pub const RZLIB_OK: c_int = 0;
+pub const RZLIB_STREAM_END: c_int = 1;
+pub const RZLIB_STREAM_ERROR: c_int = -2;
+pub const RZLIB_DATA_ERROR: c_int = -3;
+pub const RZLIB_BUF_ERROR: c_int = -5;
+
+pub const RZLIB_FORMAT_GZIP: c_int = 0;
+pub const RZLIB_FORMAT_ZLIB: c_int = 1;
+pub const RZLIB_FORMAT_DEFLATE: c_int = 2;
+
+#[repr(C)]
+pub struct rzlib_stream {
+    pub next_in: *const u8,
+    pub avail_in: usize,
+    pub total_in: u64,
+    pub next_out: *mut u8,
+    pub avail_out: usize,
+    pub total_out: u64,
+    /// Description of the last error, or null. Owned by the stream.
+    pub msg: *const c_char,
+    /// Internal state, set up by `rzlib_inflate_init`.
+    pub state: *mut c_void,
+}
+
+struct Inflate {
+    reader: RZLibReader<CallerInput>,
+    msg: Option<CString>,
+}
+
+// The input buffer of the current `rzlib_inflate` call. Running out of it is reported as
+// `WouldBlock`, which the decoder recovers from on the next call.
+struct CallerInput {
+    next: *const u8,
+    avail: usize,
+}
+
+impl CallerInput {
+    fn empty() -> CallerInput {
+        CallerInput { next: ptr::null(), avail: 0 }
+    }
+}
+
+impl Read for CallerInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for CallerInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.avail == 0 {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        // the caller of rzlib_inflate guarantees that the buffer is valid for the call
+        Ok(unsafe { slice::from_raw_parts(self.next, self.avail) })
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.next = self.next.wrapping_add(amt);
+        self.avail -= amt;
+    }
+}
+
+/// Sets up `strm` for decoding a stream of the given `RZLIB_FORMAT_*`. Like zlib, a gzip stream
+/// ends after its first member, leaving whatever follows in `next_in`.
+///
+/// # Safety
+///
+/// `strm` must be null or point to a `rzlib_stream`, which is later released with
+/// `rzlib_inflate_end`.
+#[no_mangle]
+pub unsafe extern "C" fn rzlib_inflate_init(strm: *mut rzlib_stream, format: c_int) -> c_int {
+    let Some(strm) = strm.as_mut() else {
+        return RZLIB_STREAM_ERROR;
+    };
+    let format = match format {
+        RZLIB_FORMAT_GZIP => Format::Gzip,
+        RZLIB_FORMAT_ZLIB => Format::Zlib,
+        RZLIB_FORMAT_DEFLATE => Format::Deflate,
+        _ => return RZLIB_STREAM_ERROR,
+    };
+    let mut reader = RZLibReader::with_format(CallerInput::empty(), format);
+    reader.set_multi_member(false);
+    strm.state = Box::into_raw(Box::new(Inflate { reader, msg: None })) as *mut c_void;
+    strm.total_in = 0;
+    strm.total_out = 0;
+    strm.msg = ptr::null();
+    RZLIB_OK
+}
+
+/// Decodes as much of the input as possible into the output, advancing `next_in`/`avail_in` and
+/// `next_out`/`avail_out` past what was consumed and produced. Returns `RZLIB_STREAM_END` once
+/// the end of the stream has been decoded and written out, `RZLIB_OK` if progress was made,
+/// `RZLIB_BUF_ERROR` if none was possible (more input or output space is needed), and
+/// `RZLIB_DATA_ERROR` if the stream is malformed, with the reason in `msg`.
+///
+/// # Safety
+///
+/// `strm` must have been set up with `rzlib_inflate_init`, and `next_in` and `next_out` must
+/// point to buffers of at least `avail_in` and `avail_out` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rzlib_inflate(strm: *mut rzlib_stream) -> c_int {
+    let Some(strm) = strm.as_mut() else {
+        return RZLIB_STREAM_ERROR;
+    };
+    let Some(inflate) = (strm.state as *mut Inflate).as_mut() else {
+        return RZLIB_STREAM_ERROR;
+    };
+    if (strm.next_in.is_null() && strm.avail_in > 0) || (strm.next_out.is_null() && strm.avail_out > 0) {
+        return RZLIB_STREAM_ERROR;
+    }
+
+    *inflate.reader.get_mut() = CallerInput { next: strm.next_in, avail: strm.avail_in };
+    let out: &mut [u8] = match strm.avail_out {
+        0 => &mut [],
+        len => slice::from_raw_parts_mut(strm.next_out, len),
+    };
+    let mut written = 0;
+    let mut status = loop {
+        if written == out.len() {
+            break RZLIB_OK;
+        }
+        match inflate.reader.read(&mut out[written..]) {
+            Ok(0) => break RZLIB_STREAM_END,
+            Ok(n) => written += n,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break RZLIB_OK,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => {
+                inflate.msg = CString::new(err.to_string()).ok();
+                break RZLIB_DATA_ERROR;
+            }
+        }
+    };
+    let input = mem::replace(inflate.reader.get_mut(), CallerInput::empty());
+    let consumed = strm.avail_in - input.avail;
+    if status == RZLIB_OK && consumed == 0 && written == 0 {
+        status = RZLIB_BUF_ERROR;
+    }
+
+    strm.next_in = input.next;
+    strm.avail_in = input.avail;
+    strm.total_in += consumed as u64;
+    strm.next_out = strm.next_out.wrapping_add(written);
+    strm.avail_out -= written;
+    strm.total_out += written as u64;
+    strm.msg = inflate.msg.as_ref().map_or(ptr::null(), |msg| msg.as_ptr());
+    status
+}
+
+/// Frees the state of `strm`.
+///
+/// # Safety
+///
+/// `strm` must have been set up with `rzlib_inflate_init`, and is not to be used afterwards
+/// unless set up again.
+#[no_mangle]
+pub unsafe extern "C" fn rzlib_inflate_end(strm: *mut rzlib_stream) -> c_int {
+    let Some(strm) = strm.as_mut() else {
+        return RZLIB_STREAM_ERROR;
+    };
+    if strm.state.is_null() {
+        return RZLIB_STREAM_ERROR;
+    }
+    drop(Box::from_raw(strm.state as *mut Inflate));
+    strm.state = ptr::null_mut();
+    strm.msg = ptr::null();
+    RZLIB_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::io::Write;
+    use std::ptr;
+
+    use rzlib::{Compression, GzEncoder};
+
+    use super::*;
+
+    fn new_stream() -> rzlib_stream {
+        rzlib_stream {
+            next_in: ptr::null(),
+            avail_in: 0,
+            total_in: 0,
+            next_out: ptr::null_mut(),
+            avail_out: 0,
+            total_out: 0,
+            msg: ptr::null(),
+            state: ptr::null_mut(),
+        }
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_inflate_in_small_steps() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * i / 7 % 251) as u8).collect();
+        let compressed = compress(&data);
+        let input = [&compressed[..], b"trailing"].concat();
+
+        let mut strm = new_stream();
+        let mut out = vec![0; data.len() + 100];
+        unsafe {
+            assert_eq!(rzlib_inflate_init(&mut strm, RZLIB_FORMAT_GZIP), RZLIB_OK);
+            strm.next_out = out.as_mut_ptr();
+            let mut fed = 0;
+            let status = loop {
+                // feed 3 bytes and allow 5 bytes of output at a time
+                let avail_in = (input.len() - fed).min(3);
+                strm.next_in = input[fed..].as_ptr();
+                strm.avail_in = avail_in;
+                strm.avail_out = 5;
+                let status = rzlib_inflate(&mut strm);
+                fed += avail_in - strm.avail_in;
+                if status != RZLIB_OK {
+                    break status;
+                }
+            };
+            assert_eq!(status, RZLIB_STREAM_END);
+            assert_eq!(strm.total_out, data.len() as u64);
+            assert_eq!(strm.total_in, compressed.len() as u64);
+            assert_eq!(&input[fed..], b"trailing");
+            assert_eq!(rzlib_inflate_end(&mut strm), RZLIB_OK);
+        }
+        assert_eq!(&out[..data.len()], &data[..]);
+    }
+
+    #[test]
+    fn test_inflate_errors() {
+        let mut compressed = compress(b"hello, hello, hello");
+        let len = compressed.len();
+        compressed[len - 8] ^= 1;
+
+        let mut strm = new_stream();
+        let mut out = [0; 100];
+        unsafe {
+            assert_eq!(rzlib_inflate(&mut strm), RZLIB_STREAM_ERROR);
+            assert_eq!(rzlib_inflate_init(&mut strm, 7), RZLIB_STREAM_ERROR);
+            assert_eq!(rzlib_inflate_init(&mut strm, RZLIB_FORMAT_GZIP), RZLIB_OK);
+            strm.next_out = out.as_mut_ptr();
+            strm.avail_out = out.len();
+            assert_eq!(rzlib_inflate(&mut strm), RZLIB_BUF_ERROR);
+
+            strm.next_in = compressed.as_ptr();
+            strm.avail_in = compressed.len();
+            assert_eq!(rzlib_inflate(&mut strm), RZLIB_DATA_ERROR);
+            let msg = CStr::from_ptr(strm.msg).to_str().unwrap();
+            assert!(msg.starts_with("checksum mismatch"), "{}", msg);
+            assert_eq!(rzlib_inflate_end(&mut strm), RZLIB_OK);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Feeds arbitrary bytes to the decoder, which must fail cleanly rather than panic or hang.
+
+#![no_main]
+
+use std::io::Read;
+
+use libfuzzer_sys::fuzz_target;
+use rzlib::{Format, RZLibReader};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, input)) = data.split_first() else {
+        return;
+    };
+    let format = match selector % 3 {
+        0 => Format::Gzip,
+        1 => Format::Zlib,
+        _ => Format::Deflate,
+    };
+    let mut reader = RZLibReader::with_format(input, format);
+    // small inputs can expand a thousandfold, keep that bounded
+    reader.set_max_output_bytes(Some(64 << 20));
+    let mut buf = [0; 4096];
+    while let Ok(n) = reader.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+    }
+});
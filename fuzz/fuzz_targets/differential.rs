@@ -0,0 +1,56 @@
+//! Checks rzlib against flate2: data compressed by flate2 must decode to the original, and
+//! arbitrary raw deflate input must be accepted by both or neither, and decode to the same bytes.
+
+#![no_main]
+
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use libfuzzer_sys::fuzz_target;
+use rzlib::{Format, RZLibReader};
+
+const MAX_OUTPUT: u64 = 64 << 20;
+
+fn rzlib_inflate(input: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = RZLibReader::with_format(input, Format::Deflate);
+    reader.set_max_output_bytes(Some(MAX_OUTPUT));
+    let mut out = vec![];
+    reader.read_to_end(&mut out).ok().map(|_| out)
+}
+
+// Stops after MAX_OUTPUT + 1 bytes, so that going over the cap shows.
+fn flate2_inflate(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    DeflateDecoder::new(input).take(MAX_OUTPUT + 1).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&level, payload)) = data.split_first() else {
+        return;
+    };
+
+    let mut encoder = GzEncoder::new(vec![], Compression::new(u32::from(level % 10)));
+    encoder.write_all(payload).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let mut out = vec![];
+    RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+    assert!(out == payload, "round trip through flate2 at level {} differs", level % 10);
+
+    let theirs = flate2_inflate(data);
+    if theirs.as_ref().is_some_and(|out| out.len() as u64 > MAX_OUTPUT) {
+        // over the cap, where rzlib fails on purpose
+        return;
+    }
+    let ours = rzlib_inflate(data);
+    assert!(
+        ours.is_some() == theirs.is_some(),
+        "raw deflate accepted by {} only",
+        if ours.is_some() { "rzlib" } else { "flate2" }
+    );
+    if let (Some(ours), Some(theirs)) = (ours, theirs) {
+        assert!(ours == theirs, "raw deflate decoded differently");
+    }
+});
@@ -0,0 +1,89 @@
+// Adler-32 as used by the zlib format (RFC 1950, section 8.2)
+
+const MOD_ADLER: u32 = 65521;
+// The most bytes that can be summed before `b` may overflow a u32 (as computed by zlib), so that
+// the modulo is only needed once per chunk of this size rather than for every byte.
+const NMAX: usize = 5552;
+
+/// Incrementally computed Adler-32 checksum.
+#[derive(Debug, Clone)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adler32 {
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    // Continues a checksum from its value over the data so far.
+    pub(crate) fn from_sum(sum: u32) -> Adler32 {
+        Adler32 { a: sum & 0xffff, b: sum >> 16 }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        for chunk in buf.chunks(NMAX) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= MOD_ADLER;
+            self.b %= MOD_ADLER;
+        }
+    }
+
+    pub fn sum(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+
+    pub fn reset(&mut self) {
+        self.a = 1;
+        self.b = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Adler32;
+
+    #[test]
+    fn test_known_values() {
+        let mut adler = Adler32::new();
+        assert_eq!(adler.sum(), 1);
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.sum(), 0x11e60398);
+    }
+
+    // The straightforward definition, taking the modulo after every byte.
+    fn reference(data: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        (b << 16) | a
+    }
+
+    #[test]
+    fn test_deferred_modulo() {
+        // all 0xff is the worst case for overflow
+        for data in [vec![0xff; 100_000], (0..100_000).map(|i| (i * 31 % 256) as u8).collect()] {
+            let mut adler = Adler32::new();
+            adler.update(&data);
+            assert_eq!(adler.sum(), reference(&data));
+
+            let mut adler = Adler32::new();
+            for chunk in data.chunks(5551) {
+                adler.update(chunk);
+            }
+            assert_eq!(adler.sum(), reference(&data));
+        }
+    }
+}
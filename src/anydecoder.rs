@@ -0,0 +1,248 @@
+use crate::io;
+use crate::io::ErrorKind::Interrupted;
+use crate::io::{BufRead, Read};
+
+use crate::gzheader::GzHeader;
+use crate::rzlibreader::{Format, RZLibReader, Strictness};
+
+/// Tells the format of a compressed stream from its first bytes: gzip if they are the gzip magic
+/// `1f 8b`, zlib if they are a valid zlib header (deflate method, window of at most 32 KiB,
+/// header check passing), and raw deflate otherwise.
+///
+/// A raw deflate stream can start with bytes that pass for a zlib header, though unlikely: it
+/// would have to start with a non-final stored block.
+pub fn detect_format(start: &[u8]) -> Format {
+    match *start {
+        [0x1f, 0x8b, ..] => Format::Gzip,
+        [cmf, flg, ..]
+            if cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && (((cmf as u16) << 8) | flg as u16).is_multiple_of(31) =>
+        {
+            Format::Zlib
+        }
+        _ => Format::Deflate,
+    }
+}
+
+// A reader serving up to 2 bytes already taken from `inner` before the rest of it, so that the
+// start of a stream can be looked at even if `inner` buffers less than that at a time.
+struct Prefixed<R> {
+    prefix: [u8; 2],
+    start: usize,
+    end: usize,
+    inner: R,
+}
+
+impl<R: Read + BufRead> Read for Prefixed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.start == self.end {
+            return self.inner.read(buf);
+        }
+        let len = (self.end - self.start).min(buf.len());
+        buf[..len].copy_from_slice(&self.prefix[self.start..self.start + len]);
+        self.start += len;
+        Ok(len)
+    }
+}
+
+impl<R: Read + BufRead> BufRead for Prefixed<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.start == self.end {
+            return self.inner.fill_buf();
+        }
+        Ok(&self.prefix[self.start..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.start == self.end {
+            self.inner.consume(amt);
+        } else {
+            debug_assert!(amt <= self.end - self.start);
+            self.start += amt;
+        }
+    }
+}
+
+/// Decompresses a gzip, zlib or raw deflate stream read from `R`, whichever it turns out to be,
+/// see [`detect_format`]. This is what HTTP clients need for `Content-Encoding: deflate`, which
+/// servers send as either zlib or raw deflate.
+///
+/// Concatenated gzip members are decoded one after another, as by [`RZLibReader`].
+pub struct AnyDecoder<R: Read + BufRead> {
+    inner: RZLibReader<Prefixed<R>>,
+}
+
+impl<R: Read + BufRead> AnyDecoder<R> {
+    /// Creates a decoder of the format the first 2 bytes of `reader` tell, or fewer if it ends
+    /// before. Fails if reading them does.
+    ///
+    /// If `reader` buffers fewer than 2 bytes, the first ones are taken from it and kept by the
+    /// decoder until decoded: see [`AnyDecoder::into_inner`].
+    pub fn new(mut reader: R) -> io::Result<AnyDecoder<R>> {
+        let mut prefix = [0; 2];
+        let mut end = 0;
+        let format = loop {
+            match reader.fill_buf() {
+                Ok(start) if end == 0 && start.len() >= 2 => break detect_format(start),
+                Ok([]) => break detect_format(&prefix[..end]),
+                Ok(start) => {
+                    let len = start.len().min(2 - end);
+                    prefix[end..end + len].copy_from_slice(&start[..len]);
+                    reader.consume(len);
+                    end += len;
+                    if end == 2 {
+                        break detect_format(&prefix);
+                    }
+                }
+                Err(err) if err.kind() == Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        let reader = Prefixed { prefix, start: 0, end, inner: reader };
+        Ok(AnyDecoder {
+            inner: RZLibReader::with_format(reader, format),
+        })
+    }
+
+    /// The format detected.
+    pub fn format(&self) -> Format {
+        self.inner.format()
+    }
+
+    /// See [`RZLibReader::set_verify_checksum`].
+    pub fn set_verify_checksum(&mut self, verify: bool) {
+        self.inner.set_verify_checksum(verify);
+    }
+
+    /// See [`RZLibReader::set_strictness`].
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.inner.set_strictness(strictness);
+    }
+
+    /// See [`RZLibReader::set_max_output_bytes`].
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.inner.set_max_output_bytes(limit);
+    }
+
+    /// See [`RZLibReader::set_stop_at_sync_points`].
+    pub fn set_stop_at_sync_points(&mut self, stop: bool) {
+        self.inner.set_stop_at_sync_points(stop);
+    }
+
+    /// See [`RZLibReader::at_sync_point`].
+    pub fn at_sync_point(&self) -> bool {
+        self.inner.at_sync_point()
+    }
+
+    /// See [`RZLibReader::total_in`].
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// See [`RZLibReader::total_out`].
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    /// See [`RZLibReader::header`]. `None` for other formats than gzip.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.header()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner.get_ref().inner
+    }
+
+    /// See [`RZLibReader::get_mut`].
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner.get_mut().inner
+    }
+
+    /// Returns the underlying reader. Bytes that [`AnyDecoder::new`] had to take from it are
+    /// lost if they were not decoded yet.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().inner
+    }
+}
+
+impl<R: Read + BufRead> Read for AnyDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    #[cfg(feature = "std")]
+    fn read_vectored(&mut self, bufs: &mut [std::io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read, Write};
+
+    use super::{detect_format, AnyDecoder};
+    use crate::deflateencoder::{Compression, DeflateEncoder};
+    use crate::gzencoder::GzEncoder;
+    use crate::rzlibreader::Format;
+    use crate::zlibencoder::ZlibEncoder;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(&[0x1f, 0x8b, 0x08]), Format::Gzip);
+        for header in [
+            [0x78, 0x9c],
+            [0x78, 0x01],
+            [0x78, 0xda],
+            [0x58, 0x85],
+            [0x08, 0x1d],
+        ] {
+            assert_eq!(detect_format(&header), Format::Zlib, "{:x?}", header);
+        }
+        // bad header check, method other than deflate, window too large
+        for header in [[0x78, 0x9d], [0x79, 0x9c], [0x88, 0x98]] {
+            assert_eq!(detect_format(&header), Format::Deflate, "{:x?}", header);
+        }
+        assert_eq!(detect_format(&[0x1f]), Format::Deflate);
+        assert_eq!(detect_format(&[]), Format::Deflate);
+    }
+
+    #[test]
+    fn test_any_decoder() {
+        let data = b"hello, any decoder! hello, any decoder!";
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(data).unwrap();
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(data).unwrap();
+        let mut deflate = DeflateEncoder::new(vec![], Compression::default());
+        deflate.write_all(data).unwrap();
+
+        for (input, format) in [
+            (gzip.finish().unwrap(), Format::Gzip),
+            (zlib.finish().unwrap(), Format::Zlib),
+            (deflate.finish().unwrap(), Format::Deflate),
+        ] {
+            let mut decoder = AnyDecoder::new(&input[..]).unwrap();
+            assert_eq!(decoder.format(), format);
+            let mut out = vec![];
+            decoder.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+            assert_eq!(decoder.total_in(), input.len() as u64);
+
+            // even if the reader buffers a single byte at a time
+            let reader = BufReader::with_capacity(1, &input[..]);
+            let mut decoder = AnyDecoder::new(reader).unwrap();
+            assert_eq!(decoder.format(), format);
+            let mut out = vec![];
+            decoder.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+            assert_eq!(decoder.total_in(), input.len() as u64);
+        }
+
+        // streams too short to tell, which are taken as raw deflate
+        for input in [&[][..], &[0x1f]] {
+            let reader = BufReader::with_capacity(1, input);
+            assert_eq!(AnyDecoder::new(reader).unwrap().format(), Format::Deflate);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::io;
+use std::io::{BufRead, ErrorKind, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use crate::gzheader::GzHeader;
+use crate::rzlibreader::{Format, RZLibReader};
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+// Makes an `AsyncBufRead` usable as a `BufRead` by polling it with the waker of the task
+// currently polling the decoder. `Poll::Pending` becomes a `WouldBlock` error, which the decoder
+// recovers from on the next read.
+struct PollReader<R> {
+    inner: R,
+    waker: Waker,
+}
+
+impl<R: AsyncBufRead + Unpin> Read for PollReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> BufRead for PollReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let mut cx = Context::from_waker(&self.waker);
+        match Pin::new(&mut self.inner).poll_fill_buf(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+/// Decompresses data read from a [`tokio::io::AsyncBufRead`], for use in async code.
+///
+/// This is [`RZLibReader`] driven from `poll_read`: whenever the underlying reader has no input
+/// ready, the decoder stops where it is and picks up from there once the task is woken again, so
+/// no thread is ever blocked.
+pub struct AsyncRZLibReader<R: AsyncBufRead + Unpin> {
+    inner: RZLibReader<PollReader<R>>,
+    // decompressed data for `poll_fill_buf`
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRZLibReader<R> {
+    pub fn new(reader: R) -> AsyncRZLibReader<R> {
+        Self::with_format(reader, Format::Gzip)
+    }
+
+    pub fn with_format(reader: R, format: Format) -> AsyncRZLibReader<R> {
+        let reader = PollReader { inner: reader, waker: Waker::noop().clone() };
+        AsyncRZLibReader {
+            inner: RZLibReader::with_format(reader, format),
+            buf: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.header()
+    }
+
+    /// See [`RZLibReader::total_in`].
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// See [`RZLibReader::total_out`].
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner.get_ref().inner
+    }
+
+    /// See [`RZLibReader::get_mut`].
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner.get_mut().inner
+    }
+
+    /// See [`RZLibReader::into_inner`].
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().inner
+    }
+
+    // Decodes into `buf`, returning `Poll::Pending` if the underlying reader has no input ready.
+    fn poll_decode(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let reader = self.inner.get_mut();
+        if !reader.waker.will_wake(cx.waker()) {
+            reader.waker = cx.waker().clone();
+        }
+        loop {
+            return match self.inner.read(buf) {
+                Ok(n) => Poll::Ready(Ok(n)),
+                // the underlying reader returned `Poll::Pending`, so the task will be woken
+                Err(err) if err.kind() == ErrorKind::WouldBlock => Poll::Pending,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => Poll::Ready(Err(err)),
+            };
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for AsyncRZLibReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos < this.filled {
+            let n = buf.remaining().min(this.filled - this.pos);
+            buf.put_slice(&this.buf[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        let n = match this.poll_decode(cx, buf.initialize_unfilled()) {
+            Poll::Ready(result) => result?,
+            Poll::Pending => return Poll::Pending,
+        };
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for AsyncRZLibReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos == this.filled {
+            let mut buf = std::mem::take(&mut this.buf);
+            let result = this.poll_decode(cx, &mut buf);
+            this.buf = buf;
+            match result {
+                Poll::Ready(Ok(n)) => (this.pos, this.filled) = (0, n),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = (this.pos + amt).min(this.filled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Write;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+    use super::AsyncRZLibReader;
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+
+    // Serves `data` a few bytes at a time, returning `Poll::Pending` before each chunk.
+    struct Chunked {
+        data: Vec<u8>,
+        pos: usize,
+        ready: bool,
+    }
+
+    impl AsyncRead for Chunked {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            unimplemented!("only read through AsyncBufRead")
+        }
+    }
+
+    impl AsyncBufRead for Chunked {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            let this = self.get_mut();
+            if !this.ready && this.pos < this.data.len() {
+                this.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let end = this.data.len().min(this.pos + 7);
+            Poll::Ready(Ok(&this.data[this.pos..end]))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            let this = self.get_mut();
+            this.pos += amt;
+            this.ready = false;
+        }
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_poll_read() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * i / 5 % 241) as u8).collect();
+        let input = [compress(&data), compress(b"second member")].concat();
+        let mut reader = AsyncRZLibReader::new(Chunked { data: input, pos: 0, ready: false });
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut out = vec![];
+        let mut pending = 0;
+        loop {
+            let mut buf = [0; 1000];
+            let mut buf = ReadBuf::new(&mut buf);
+            match Pin::new(&mut reader).poll_read(&mut cx, &mut buf) {
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => break,
+                Poll::Ready(Ok(())) => out.extend_from_slice(buf.filled()),
+                Poll::Ready(Err(err)) => panic!("unexpected error {}", err),
+                Poll::Pending => pending += 1,
+            }
+        }
+        assert!(pending > 0);
+        assert!(out == [&data[..], b"second member"].concat());
+        assert_eq!(reader.total_out(), out.len() as u64);
+    }
+
+    #[test]
+    fn test_poll_fill_buf() {
+        let input = compress(b"hello, hello, hello");
+        let mut reader = AsyncRZLibReader::new(Chunked { data: input, pos: 0, ready: false });
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut out = vec![];
+        loop {
+            match Pin::new(&mut reader).poll_fill_buf(&mut cx) {
+                Poll::Ready(Ok([])) => break,
+                Poll::Ready(Ok(buf)) => {
+                    let n = buf.len().min(4);
+                    out.extend_from_slice(&buf[..n]);
+                    Pin::new(&mut reader).consume(n);
+                }
+                Poll::Ready(Err(err)) => panic!("unexpected error {}", err),
+                Poll::Pending => {}
+            }
+        }
+        assert_eq!(out, b"hello, hello, hello");
+    }
+}
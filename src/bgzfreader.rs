@@ -0,0 +1,333 @@
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use crate::error::RzlibError;
+use crate::gzdecoder::GzDecoder;
+use crate::gzheader::split_subfields;
+
+// The empty block that ends every BGZF file (SAM/BAM specification, section 4.1.2).
+const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// ID1 to XLEN
+const FIXED_HEADER_SIZE: usize = 12;
+
+/// Decompresses a BGZF file, the blocked gzip format of BAM, tabix-indexed VCF and other
+/// bioinformatics files, read from `R`.
+///
+/// A BGZF file is a series of gzip members of at most 64 KiB, each recording its compressed size
+/// in a `BC` extra subfield, and ending with an empty member, the EOF marker. A position in the
+/// decompressed data is given by a virtual offset, `coffset << 16 | uoffset`: the offset of a
+/// block in the file, and an offset in the data of that block. Reaching the end of the file
+/// without seeing the EOF marker fails with [`RzlibError::TruncatedStream`].
+///
+/// When `R` implements [`Seek`], so does the reader, but over virtual offsets:
+/// `SeekFrom::Start(voffset)` moves to a virtual offset, and `SeekFrom::Current(0)` returns the
+/// current one. Other positions are rejected.
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    // offset in `inner` of the current block, and of the next one
+    block_offset: u64,
+    next_block_offset: u64,
+    // decompressed data of the current block
+    data: Vec<u8>,
+    pos: usize,
+    // whether the last block read was the EOF marker
+    at_eof_marker: bool,
+    done: bool,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Creates a reader of the BGZF file starting at the current position of `inner`.
+    pub fn new(inner: R) -> BgzfReader<R> {
+        BgzfReader {
+            inner,
+            block_offset: 0,
+            next_block_offset: 0,
+            data: vec![],
+            pos: 0,
+            at_eof_marker: false,
+            done: false,
+        }
+    }
+
+    /// The virtual offset of the next byte to be read.
+    pub fn virtual_position(&self) -> u64 {
+        (self.block_offset << 16) | self.pos as u64
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Reads and decodes the next block. Returns false at the end of the input.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut block = vec![0; FIXED_HEADER_SIZE];
+        if !read_exact_or_eof(&mut self.inner, &mut block)? {
+            return Ok(false);
+        }
+        if block[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            return Err(RzlibError::BadHeaderField("not a BGZF block").into());
+        }
+        let xlen = u16::from_le_bytes([block[10], block[11]]) as usize;
+        block.resize(FIXED_HEADER_SIZE + xlen, 0);
+        read_rest(&mut self.inner, &mut block[FIXED_HEADER_SIZE..])?;
+        let block_size = block_size(&block[FIXED_HEADER_SIZE..])?;
+        if block_size < block.len() + 8 {
+            return Err(RzlibError::BadHeaderField("BGZF block size too small").into());
+        }
+        let header_len = block.len();
+        block.resize(block_size, 0);
+        read_rest(&mut self.inner, &mut block[header_len..])?;
+
+        let mut decoder = GzDecoder::new(&block[..]);
+        self.data.clear();
+        decoder.read_to_end(&mut self.data)?;
+        if decoder.total_in() != block_size as u64 {
+            return Err(
+                RzlibError::BadHeaderField("BGZF block size does not match its data").into(),
+            );
+        }
+        self.pos = 0;
+        self.block_offset = self.next_block_offset;
+        self.next_block_offset += block_size as u64;
+        self.at_eof_marker = block == EOF_MARKER;
+        Ok(true)
+    }
+}
+
+// Returns the size of the whole block, from the BSIZE field of the BC subfield in `extra`.
+fn block_size(extra: &[u8]) -> io::Result<usize> {
+    let subfields = split_subfields(extra).unwrap_or_default();
+    match subfields.iter().find(|&&(id, _)| id == u16::from_be_bytes(*b"BC")) {
+        Some((_, &[lo, hi])) => Ok(u16::from_le_bytes([lo, hi]) as usize + 1),
+        _ => Err(RzlibError::BadHeaderField("missing BGZF block size").into()),
+    }
+}
+
+// Fills `buf`, returning false if the input ended right away. Ending partway through is an
+// error.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(RzlibError::TruncatedStream.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+// Reads the rest of a block, which the input must not end before.
+fn read_rest<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    match read_exact_or_eof(reader, buf)? {
+        true => Ok(()),
+        false => Err(RzlibError::TruncatedStream.into()),
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // skip empty blocks, such as the EOF marker
+        while self.pos == self.data.len() && !self.done {
+            if !self.read_block()? {
+                if !self.at_eof_marker {
+                    return Err(RzlibError::TruncatedStream.into());
+                }
+                self.done = true;
+            }
+        }
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Moves to the virtual offset `voffset`, as found in a BAI or tabix index.
+    pub fn seek_virtual(&mut self, voffset: u64) -> io::Result<()> {
+        let (coffset, uoffset) = (voffset >> 16, (voffset & 0xffff) as usize);
+        self.inner.seek(SeekFrom::Start(coffset))?;
+        self.next_block_offset = coffset;
+        self.done = false;
+        if !self.read_block()? {
+            // the end of the file, which is only a valid position right after the EOF marker
+            self.block_offset = coffset;
+            self.data.clear();
+            self.pos = 0;
+            self.done = true;
+            return match uoffset {
+                0 => Ok(()),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "virtual offset past the end",
+                )),
+            };
+        }
+        if uoffset > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "virtual offset past the end of its block",
+            ));
+        }
+        self.pos = uoffset;
+        Ok(())
+    }
+
+    /// Whether the file ends with the EOF marker, which it lacks when it was truncated. The
+    /// position is left unchanged.
+    pub fn has_eof_marker(&mut self) -> io::Result<bool> {
+        let position = self.inner.stream_position()?;
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        let mut tail = [0; EOF_MARKER.len()];
+        let result = match len.checked_sub(tail.len() as u64) {
+            Some(start) => {
+                self.inner.seek(SeekFrom::Start(start))?;
+                self.inner
+                    .read_exact(&mut tail)
+                    .map(|()| tail == EOF_MARKER)
+            }
+            None => Ok(false),
+        };
+        self.inner.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+impl<R: Read + Seek> Seek for BgzfReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Start(voffset) => self.seek_virtual(voffset)?,
+            SeekFrom::Current(0) => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "BGZF only seeks to virtual offsets",
+                ))
+            }
+        }
+        Ok(self.virtual_position())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
+
+    use super::{BgzfReader, EOF_MARKER};
+    use crate::crc32::Crc32;
+    use crate::deflateencoder::{Compression, DeflateEncoder};
+    use crate::error::RzlibError;
+
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        let cdata = encoder.finish().unwrap();
+        let mut crc = Crc32::new();
+        crc.update(data);
+
+        let bsize = (18 + cdata.len() + 8 - 1) as u16;
+        let mut block = vec![
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ];
+        block.extend_from_slice(&bsize.to_le_bytes());
+        block.extend_from_slice(&cdata);
+        block.extend_from_slice(&crc.sum().to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block
+    }
+
+    fn bgzf_file(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut file: Vec<u8> = blocks.iter().flat_map(|data| bgzf_block(data)).collect();
+        file.extend_from_slice(&EOF_MARKER);
+        file
+    }
+
+    #[test]
+    fn test_read() {
+        let file = bgzf_file(&[b"first block\n", b"", b"second block\n"]);
+        let mut reader = BgzfReader::new(&file[..]);
+        let mut lines = vec![];
+        for line in (&mut reader).lines() {
+            lines.push(line.unwrap());
+        }
+        assert_eq!(lines, ["first block", "second block"]);
+
+        let err = BgzfReader::new(&file[..file.len() - 28])
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::TruncatedStream)
+        );
+        let err = BgzfReader::new(&file[..file.len() - 3])
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::TruncatedStream)
+        );
+    }
+
+    #[test]
+    fn test_not_bgzf() {
+        let mut file = bgzf_file(&[b"data"]);
+        file[12] = b'X';
+        let err = BgzfReader::new(&file[..])
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        assert!(matches!(
+            RzlibError::from_io_error(&err),
+            Some(RzlibError::BadHeaderField(_))
+        ));
+    }
+
+    #[test]
+    fn test_seek_virtual() {
+        let first_len = bgzf_block(b"hello, bgzf").len() as u64;
+        let file = bgzf_file(&[b"hello, bgzf", b"0123456789"]);
+        let mut reader = BgzfReader::new(Cursor::new(&file));
+        assert!(reader.has_eof_marker().unwrap());
+
+        let mut buf = [0; 7];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.virtual_position(), 7);
+        assert_eq!(reader.stream_position().unwrap(), 7);
+
+        reader.seek(SeekFrom::Start(first_len << 16 | 4)).unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "456789");
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"llo, bg");
+
+        assert!(reader.seek(SeekFrom::Start(first_len << 16 | 12)).is_err());
+        assert!(reader.seek(SeekFrom::Current(3)).is_err());
+
+        let truncated = &file[..file.len() - 1];
+        assert!(!BgzfReader::new(Cursor::new(truncated))
+            .has_eof_marker()
+            .unwrap());
+    }
+}
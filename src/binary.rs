@@ -0,0 +1,126 @@
+//! A compact little-endian binary layout, for saving decoder state.
+
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::ErrorKind::InvalidData;
+
+pub(crate) struct ByteWriter {
+    buf: Vec<u8>,
+}
+
+impl ByteWriter {
+    pub(crate) fn new(magic: &[u8]) -> ByteWriter {
+        ByteWriter {
+            buf: magic.to_vec(),
+        }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn optional_bytes(&mut self, value: Option<&[u8]>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.bytes(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+// Reads what `ByteWriter` wrote, failing with `InvalidData` on anything else.
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+pub(crate) fn invalid(what: &'static str) -> io::Error {
+    io::Error::new(InvalidData, what)
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8], magic: &[u8]) -> io::Result<ByteReader<'a>> {
+        match data.strip_prefix(magic) {
+            Some(data) => Ok(ByteReader { data }),
+            None => Err(invalid("wrong magic number")),
+        }
+    }
+
+    fn take(&mut self, len: u64) -> io::Result<&'a [u8]> {
+        if len > self.data.len() as u64 {
+            return Err(invalid("truncated data"));
+        }
+        let (taken, rest) = self.data.split_at(len as usize);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("bad boolean")),
+        }
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("value out of range"))
+    }
+
+    pub(crate) fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        Ok(self.slice()?.to_vec())
+    }
+
+    // Like `bytes`, without copying them.
+    pub(crate) fn slice(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u64()?;
+        self.take(len)
+    }
+
+    pub(crate) fn optional_bytes(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(if self.bool()? {
+            Some(self.bytes()?)
+        } else {
+            None
+        })
+    }
+
+    // Fails unless everything has been read.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        if !self.data.is_empty() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,63 @@
-use std::{cmp, io};
-use std::io::{BufRead, ErrorKind, Read};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
+use core::marker::PhantomData;
 
+use crate::io;
+use crate::io::{BufRead, ErrorKind, Read};
+
+/// Order in which the bits of each byte are read by [`BitReader`] or written by
+/// [`BitWriter`](crate::BitWriter).
+pub trait BitOrder {
+    const MSB_FIRST: bool;
+}
+
+/// Least significant bit first, as in deflate. Values read or written are made of the bits of
+/// the stream from their least significant bit up.
+pub struct LsbFirst;
+
+/// Most significant bit first, as in bzip2 and many other formats. Values read or written are
+/// made of the bits of the stream from their most significant bit down.
+pub struct MsbFirst;
+
+impl BitOrder for LsbFirst {
+    const MSB_FIRST: bool = false;
+}
+
+impl BitOrder for MsbFirst {
+    const MSB_FIRST: bool = true;
+}
+
+/// The most bits [`BitRead::peek_bits`] can be asked for.
+pub const MAX_PEEK_BITS: usize = 56;
+
+// Fails with `InvalidInput` if `n` bits cannot be peeked at once.
+pub(crate) fn check_peek_bits(n: usize) -> io::Result<()> {
+    if n > MAX_PEEK_BITS {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "cannot peek more than 56 bits at once",
+        ));
+    }
+    Ok(())
+}
+
+// Reverses the order of the low `n` bits of `value`.
+pub(crate) fn reverse_low_bits(value: u64, n: usize) -> u64 {
+    if n == 0 {
+        0
+    } else {
+        value.reverse_bits() >> (64 - n)
+    }
+}
+
+/// A source of bits, read least significant bit first as in deflate unless stated otherwise.
 pub trait BitRead {
+    /// Reads up to `n` bits into the low bits of `buf`, returning how many were read.
+    ///
+    /// Returns `Ok(0)` only on end of input.
     fn read_bits(&mut self, buf: &mut u64, n: usize) -> io::Result<usize>;
+    /// Reads exactly `n` bits, failing with `UnexpectedEof` if the input ends first.
     fn read_bits_exact(&mut self, n: usize) -> io::Result<u64> {
         let mut out = 0;
         let mut buf = 0;
@@ -18,46 +73,273 @@ pub trait BitRead {
 
         Ok(out)
     }
+    /// Returns upcoming bits without consuming them, and how many there are: at least `n`, or
+    /// fewer only at the end of input. More bits may be returned if they are available without
+    /// reading further.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if `n` is over [`MAX_PEEK_BITS`]. There is no
+    /// default: looking ahead needs a source able to give the bits back.
+    fn peek_bits(&mut self, n: usize) -> io::Result<(u64, usize)>;
+    /// Skips `n` bits, typically ones returned by `peek_bits` before.
+    fn consume_bits(&mut self, n: usize) -> io::Result<()> {
+        let mut left = n;
+        while left > 0 {
+            let chunk = cmp::min(left, 64);
+            self.read_bits_exact(chunk)?;
+            left -= chunk;
+        }
+        Ok(())
+    }
 }
 
-pub struct BitReader<R: BufRead> {
+/// Reads bits out of a byte stream.
+///
+/// Besides bit-level reads, the byte-level `Read` and `BufRead` impls and the `read_u*` methods
+/// are available whenever the reader sits at a byte boundary (see `drop_remaining_bits`).
+///
+/// A position can be marked with `mark` and returned to with `rewind`, which gives everything
+/// read since back to be read again, even though it was already taken from the underlying
+/// reader. This lets callers retry a read that the underlying reader interrupted halfway, e.g.
+/// with `WouldBlock`.
+///
+/// Bits are read least significant bit first as in deflate, unless created with
+/// [`BitReader::with_bit_order`] for [`MsbFirst`]. Peeked bits are then returned with the
+/// first one as the most significant too. [`HuffmanTable`](crate::HuffmanTable) only decodes
+/// from least significant bit first readers.
+pub struct BitReader<R: BufRead, O: BitOrder = LsbFirst> {
     reader: R,
+    // number of bytes taken from `reader` or `replay`, including the ones buffered in `bits`
+    bytes_consumed: u64,
+    // see `set_limit`
+    limit: Option<u64>,
+    bits_count: usize,
+    bits: u64,
+    // scratch space for serving whole bytes left in `bits` through `fill_buf`
+    byte_buf: [u8; 8],
+    // the marked position, if `marked`
+    marked: bool,
+    mark: Mark,
+    // bytes given back by `rewind`, served before reading further from `reader`
+    replay: Vec<u8>,
+    replay_pos: usize,
+    // bytes are loaded into `bits` with their bits reversed when reading most significant bit
+    // first, so that the bits are buffered in stream order either way
+    order: PhantomData<O>,
+}
+
+// State of a `BitReader` at a marked position, and the bytes it has taken since.
+struct Mark {
+    bytes_consumed: u64,
     bits_count: usize,
     bits: u64,
+    taken: Vec<u8>,
 }
 
 impl<R: BufRead> BitReader<R> {
     pub fn new(reader: R) -> BitReader<R> {
+        Self::with_bit_order(reader)
+    }
+}
+
+impl<R: BufRead, O: BitOrder> BitReader<R, O> {
+    /// Creates a reader of the bits of each byte in order `O`, e.g.
+    /// `BitReader::<_, MsbFirst>::with_bit_order(reader)`.
+    pub fn with_bit_order(reader: R) -> BitReader<R, O> {
         BitReader {
-            reader: reader,
+            reader,
+            bytes_consumed: 0,
+            limit: None,
             bits_count: 0,
             bits: 0,
+            byte_buf: [0; 8],
+            marked: false,
+            mark: Mark { bytes_consumed: 0, bits_count: 0, bits: 0, taken: vec![] },
+            replay: vec![],
+            replay_pos: 0,
+            order: PhantomData,
         }
     }
 
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the underlying reader. Reading from it directly skips the bits buffered by this
+    /// reader, if any.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns the underlying reader. Bits buffered by this reader, if any, are lost, as are bytes
+    /// given back by `rewind` and not read again yet.
+    ///
+    /// Bits are loaded one byte at a time, only when they are needed, so after reading up to a
+    /// byte boundary (see `drop_remaining_bits`) nothing is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Forgets the bits buffered and the bytes to replay, after the underlying reader was moved to
+    // `byte_offset`, which positions are counted from now on.
+    pub(crate) fn restart_at(&mut self, byte_offset: u64) {
+        self.bytes_consumed = byte_offset;
+        self.bits_count = 0;
+        self.bits = 0;
+        self.marked = false;
+        self.replay.clear();
+        self.replay_pos = 0;
+    }
+
+    /// Sets the position, in bytes from the start of the underlying reader, past which nothing
+    /// is taken from it: the input looks like it ends there.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
     // drops remaining unread bits in the currently processed byte
     pub fn drop_remaining_bits(&mut self) {
-        self.bits = 0;
-        self.bits_count = 0;
+        let partial = self.bits_count % 8;
+        self.bits >>= partial;
+        self.bits_count -= partial;
     }
 
     pub fn read_u8(&mut self) -> io::Result<u8> {
         let mut buf: [u8; 1] = [0; 1];
-        self.reader.read_exact(&mut buf)?;
+        self.read_exact(&mut buf)?;
         Ok(u8::from_le_bytes(buf))
     }
 
     pub fn read_u16(&mut self) -> io::Result<u16> {
         let mut buf: [u8; 2] = [0; 2];
-        self.reader.read_exact(&mut buf)?;
+        self.read_exact(&mut buf)?;
         Ok(u16::from_le_bytes(buf))
     }
 
     pub fn read_u32(&mut self) -> io::Result<u32> {
         let mut buf: [u8; 4] = [0; 4];
-        self.reader.read_exact(&mut buf)?;
+        self.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
+
+    /// Number of bits consumed so far, counted from the start of the underlying reader.
+    pub fn bit_position(&self) -> u64 {
+        self.bytes_consumed * 8 - self.bits_count as u64
+    }
+
+    /// Marks the current position, replacing the previous mark if any.
+    pub fn mark(&mut self) {
+        self.marked = true;
+        self.mark.bytes_consumed = self.bytes_consumed;
+        self.mark.bits_count = self.bits_count;
+        self.mark.bits = self.bits;
+        self.mark.taken.clear();
+    }
+
+    /// Returns to the marked position, so that everything read since is read again. The mark is
+    /// removed. Does nothing if there is no mark.
+    pub fn rewind(&mut self) {
+        if self.marked {
+            self.marked = false;
+            self.bytes_consumed = self.mark.bytes_consumed;
+            self.bits_count = self.mark.bits_count;
+            self.bits = self.mark.bits;
+            self.replay.drain(..self.replay_pos);
+            self.replay.splice(..0, self.mark.taken.drain(..));
+            self.replay_pos = 0;
+        }
+    }
+
+    /// Removes the mark, if any, without moving.
+    pub fn unmark(&mut self) {
+        self.marked = false;
+    }
+
+    // Like `BufRead::fill_buf` on the underlying reader, serving replayed bytes first.
+    fn source_fill_buf(&mut self) -> io::Result<&[u8]> {
+        let left = match self.limit {
+            Some(limit) => limit.saturating_sub(self.bytes_consumed),
+            None => u64::MAX,
+        };
+        let available = if self.replay_pos < self.replay.len() {
+            &self.replay[self.replay_pos..]
+        } else {
+            self.reader.fill_buf()?
+        };
+        Ok(&available[..cmp::min(available.len() as u64, left) as usize])
+    }
+
+    // Takes `amt` bytes returned by `source_fill_buf`, keeping them if there is a mark.
+    fn source_consume(&mut self, amt: usize) {
+        self.bytes_consumed += amt as u64;
+        if self.replay_pos < self.replay.len() {
+            if self.marked {
+                let taken = &self.replay[self.replay_pos..self.replay_pos + amt];
+                self.mark.taken.extend_from_slice(taken);
+            }
+            self.replay_pos += amt;
+            if self.replay_pos == self.replay.len() {
+                self.replay.clear();
+                self.replay_pos = 0;
+            }
+            return;
+        }
+        if self.marked {
+            // the bytes are still buffered by the underlying reader, so this does no I/O
+            if let Ok(buf) = self.reader.fill_buf() {
+                self.mark.taken.extend_from_slice(&buf[..amt]);
+            }
+        }
+        self.reader.consume(amt);
+    }
+
+    // Loads one more byte from the input into the buffered bits. Returns false on end of input.
+    fn load_byte(&mut self) -> io::Result<bool> {
+        debug_assert!(self.bits_count <= 56);
+        let byte = match self.source_fill_buf()?.first() {
+            Some(&b) => b,
+            None => return Ok(false),
+        };
+        self.source_consume(1);
+        let byte = if O::MSB_FIRST { byte.reverse_bits() } else { byte };
+        self.bits |= (byte as u64) << self.bits_count;
+        self.bits_count += 8;
+        Ok(true)
+    }
+
+    // Drops `n` of the buffered bits.
+    fn drop_bits(&mut self, n: usize) {
+        debug_assert!(n <= self.bits_count);
+        self.bits = if n >= 64 { 0 } else { self.bits >> n };
+        self.bits_count -= n;
+    }
+
+    // Reads up to `n` bits in stream order, the first one in the least significant position.
+    fn read_stream_bits(&mut self, buf: &mut u64, n: usize) -> io::Result<usize> {
+        if n == 0 {
+            return Ok(0);
+        }
+        if self.bits_count == 0 && !self.load_byte()? {
+            // bail early if EOF
+            return Ok(0);
+        }
+
+        // at this point, n > 0 and self.bits_count > 0
+        let bits_from_partial = cmp::min(n, self.bits_count);
+        *buf = self.bits & bitmask(bits_from_partial as u64);
+        self.drop_bits(bits_from_partial);
+        Ok(bits_from_partial)
+    }
+
+    // The whole bytes buffered in `bits`, as they are in the input.
+    fn buffered_bytes(&self) -> [u8; 8] {
+        let mut bytes = self.bits.to_le_bytes();
+        if O::MSB_FIRST {
+            for byte in bytes.iter_mut() {
+                *byte = byte.reverse_bits();
+            }
+        }
+        bytes
+    }
 }
 
 fn bitmask(n: u64) -> u64 {
@@ -68,52 +350,221 @@ fn bitmask(n: u64) -> u64 {
     }
 }
 
-impl<R: BufRead> BitRead for BitReader<R> {
+impl<R: BufRead, O: BitOrder> BitRead for BitReader<R, O> {
     fn read_bits(&mut self, buf: &mut u64, n: usize) -> io::Result<usize> {
-        if n == 0 {
-            return Ok(0);
+        let read = self.read_stream_bits(buf, n)?;
+        if O::MSB_FIRST {
+            *buf = reverse_low_bits(*buf, read);
         }
-        if self.bits_count == 0 {
-            // try to fill partial, and bail early if EOF
-            let byte_buf = self.fill_buf()?;
-            if byte_buf.is_empty() {
-                return Ok(0);
+        Ok(read)
+    }
+
+    fn read_bits_exact(&mut self, n: usize) -> io::Result<u64> {
+        let mut out = 0;
+        let mut total_read = 0;
+        while total_read < n {
+            let mut buf = 0;
+            let read = self.read_stream_bits(&mut buf, n - total_read)?;
+            if read == 0 {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "got eof when reading bits"));
             }
-            self.bits = byte_buf[0] as u64;
-            self.bits_count = 8;
-            self.consume(1);
+            out |= buf << total_read;
+            total_read += read;
         }
+        Ok(if O::MSB_FIRST { reverse_low_bits(out, n) } else { out })
+    }
 
-        // at this point, n > 0 and self.bits_count > 0
-        let bits_from_partial = cmp::min(n, self.bits_count);
-        *buf = (self.bits as u64) & bitmask(bits_from_partial as u64);
-        self.bits >>= bits_from_partial;
-        self.bits_count -= bits_from_partial;
-        return Ok(bits_from_partial)
+    // Loads whole bytes only as far as needed to have `n` bits buffered, and returns all the
+    // buffered bits. Bytes loaded but not consumed are still served by the byte-level reads below.
+    fn peek_bits(&mut self, n: usize) -> io::Result<(u64, usize)> {
+        check_peek_bits(n)?;
+        while self.bits_count < n && self.load_byte()? {}
+        if O::MSB_FIRST {
+            return Ok((reverse_low_bits(self.bits, self.bits_count), self.bits_count));
+        }
+        Ok((self.bits, self.bits_count))
+    }
+
+    fn consume_bits(&mut self, n: usize) -> io::Result<()> {
+        if n <= self.bits_count {
+            self.drop_bits(n);
+            return Ok(());
+        }
+        let buffered = self.bits_count;
+        self.drop_bits(buffered);
+        self.read_bits_exact(n - buffered).map(|_| ())
+    }
+}
+
+/// Reads bits, least significant bit first, straight out of a byte slice: unlike [`BitReader`],
+/// it needs no `BufRead` underneath, and refills its bit buffer eight bytes at a time.
+#[derive(Debug, Clone)]
+pub struct BitSliceReader<'a> {
+    data: &'a [u8],
+    // next byte of `data` to load into `bits`
+    next: usize,
+    // upcoming bits, the first one in the least significant position. Bits past `bits_count` are
+    // either zero or the bits that follow in `data`.
+    bits: u64,
+    bits_count: usize,
+}
+
+impl<'a> BitSliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitSliceReader<'a> {
+        BitSliceReader { data, next: 0, bits: 0, bits_count: 0 }
+    }
+
+    /// Number of bits consumed so far.
+    pub fn bit_position(&self) -> u64 {
+        self.next as u64 * 8 - self.bits_count as u64
+    }
+
+    /// Skips the rest of the current byte, if partially consumed.
+    pub fn drop_remaining_bits(&mut self) {
+        self.drop_bits(self.bits_count % 8);
+    }
+
+    /// Returns the bytes following the current one, and the current one too if it is not
+    /// partially consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.bit_position().div_ceil(8) as usize..]
+    }
+
+    // Returns the buffered bits and how many there are, first refilling the buffer if fewer than
+    // `n` bits are buffered. At most 56 bits can be asked for.
+    #[inline]
+    pub(crate) fn fill(&mut self, n: usize) -> (u64, usize) {
+        debug_assert!(n <= MAX_PEEK_BITS);
+        if self.bits_count < n {
+            self.refill();
+        }
+        (self.bits, self.bits_count)
+    }
+
+    // Drops `n` of the buffered bits.
+    #[inline]
+    pub(crate) fn drop_bits(&mut self, n: usize) {
+        debug_assert!(n <= self.bits_count);
+        self.bits = if n >= 64 { 0 } else { self.bits >> n };
+        self.bits_count -= n;
+    }
+
+    // Loads whole bytes until at least 56 bits are buffered, or the slice ends.
+    #[inline]
+    fn refill(&mut self) {
+        if let Some(chunk) = self.data.get(self.next..self.next + 8) {
+            // load all 8 bytes, but only count those that fit whole
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            self.bits |= word << self.bits_count;
+            let loaded = (63 - self.bits_count) / 8;
+            self.next += loaded;
+            self.bits_count += loaded * 8;
+        } else {
+            while self.bits_count <= 56 && self.next < self.data.len() {
+                self.bits |= (self.data[self.next] as u64) << self.bits_count;
+                self.next += 1;
+                self.bits_count += 8;
+            }
+        }
+    }
+
+    fn bits_left(&self) -> u64 {
+        (self.data.len() - self.next) as u64 * 8 + self.bits_count as u64
     }
 }
 
-impl<R: BufRead> Read for BitReader<R> {
+impl BitRead for BitSliceReader<'_> {
+    fn read_bits(&mut self, buf: &mut u64, n: usize) -> io::Result<usize> {
+        let (bits, available) = self.fill(cmp::min(n, MAX_PEEK_BITS));
+        let read = cmp::min(n, available);
+        *buf = bits & bitmask(read as u64);
+        self.drop_bits(read);
+        Ok(read)
+    }
+
+    fn read_bits_exact(&mut self, n: usize) -> io::Result<u64> {
+        if n > MAX_PEEK_BITS {
+            let low = self.read_bits_exact(32)?;
+            return Ok(low | self.read_bits_exact(n - 32)? << 32);
+        }
+        let (bits, available) = self.fill(n);
+        if available < n {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "got eof when reading bits"));
+        }
+        self.drop_bits(n);
+        Ok(bits & bitmask(n as u64))
+    }
+
+    // Returns all the buffered bits, refilled up to 56 or more if fewer than `n` were buffered.
+    fn peek_bits(&mut self, n: usize) -> io::Result<(u64, usize)> {
+        check_peek_bits(n)?;
+        Ok(self.fill(n))
+    }
+
+    fn consume_bits(&mut self, n: usize) -> io::Result<()> {
+        if n <= self.bits_count {
+            self.drop_bits(n);
+            return Ok(());
+        }
+        if n as u64 > self.bits_left() {
+            self.next = self.data.len();
+            self.bits = 0;
+            self.bits_count = 0;
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "got eof when reading bits"));
+        }
+        // skip whole bytes past the buffer, then the bits left of a partial one
+        let skip = n - self.bits_count;
+        self.next += skip / 8;
+        self.bits = 0;
+        self.bits_count = 0;
+        self.refill();
+        self.drop_bits(skip % 8);
+        Ok(())
+    }
+}
+
+// Byte-level access. Whole bytes that were loaded for bit-level reads but not consumed yet are
+// served before reading further from the underlying reader.
+impl<R: BufRead, O: BitOrder> Read for BitReader<R, O> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        assert_eq!(self.bits_count, 0);
-        self.reader.read(buf)
+        assert_eq!(self.bits_count % 8, 0);
+        if self.bits_count == 0 {
+            let available = self.source_fill_buf()?;
+            let n = cmp::min(buf.len(), available.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            self.source_consume(n);
+            return Ok(n);
+        }
+        let n = cmp::min(buf.len(), self.bits_count / 8);
+        buf[..n].copy_from_slice(&self.buffered_bytes()[..n]);
+        self.drop_bits(n * 8);
+        Ok(n)
     }
 }
 
-impl<R: BufRead> BufRead for BitReader<R> {
+impl<R: BufRead, O: BitOrder> BufRead for BitReader<R, O> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        assert_eq!(self.bits_count, 0);
-        self.reader.fill_buf()
+        assert_eq!(self.bits_count % 8, 0);
+        if self.bits_count == 0 {
+            return self.source_fill_buf();
+        }
+        self.byte_buf = self.buffered_bytes();
+        Ok(&self.byte_buf[..self.bits_count / 8])
     }
     fn consume(&mut self, amt: usize) {
-        self.reader.consume(amt)
+        if self.bits_count == 0 {
+            self.source_consume(amt);
+        } else {
+            self.drop_bits(amt * 8)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::bitreader::{BitRead, BitReader};
-    use std::io::Cursor;
+    use crate::bitreader::{BitRead, BitReader, BitSliceReader};
+    use std::io;
+    use std::io::{BufRead, Cursor, Read};
 
     #[test]
     fn test_read_bits() {
@@ -134,4 +585,165 @@ mod tests {
 
         assert_eq!(reader.read_bits_exact(4+8+8).unwrap(), 0b11111111000000000011);
     }
+
+    #[test]
+    fn test_slice_reader() {
+        let bytes_in = [0b00001111, 0b00110011, 0b00000000, 0b11111111];
+        let mut reader = BitSliceReader::new(&bytes_in);
+        assert_eq!(reader.read_bits_exact(3).unwrap(), 0b111);
+        assert_eq!(reader.read_bits_exact(2).unwrap(), 0b01);
+        assert_eq!(reader.peek_bits(8).unwrap(), (0xff00330f >> 5, 27));
+        reader.consume_bits(2).unwrap();
+        assert_eq!(reader.read_bits_exact(5).unwrap(), 0b00110);
+        assert_eq!(reader.bit_position(), 12);
+        assert_eq!(reader.remaining(), [0, 0xff]);
+        reader.drop_remaining_bits();
+        assert_eq!(reader.remaining(), [0, 0xff]);
+        let mut buf = 0;
+        assert_eq!(reader.read_bits(&mut buf, 20).unwrap(), 16);
+        assert_eq!(buf, 0xff00);
+        assert_eq!(reader.read_bits(&mut buf, 1).unwrap(), 0);
+        assert_eq!(reader.read_bits_exact(1).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // reads spanning more than 8 bytes, and at every alignment
+        let data: Vec<u8> = (0..40u8).map(|i| i.wrapping_mul(37)).collect();
+        for skip in 0..8 {
+            for n in [1, 13, 56, 57, 64] {
+                let mut expected = BitReader::new(&data[..]);
+                let mut reader = BitSliceReader::new(&data);
+                expected.consume_bits(skip).unwrap();
+                reader.consume_bits(skip).unwrap();
+                while let Ok(bits) = expected.read_bits_exact(n) {
+                    assert_eq!(reader.read_bits_exact(n).unwrap(), bits, "{} {}", skip, n);
+                }
+                assert!(reader.read_bits_exact(n).is_err());
+            }
+        }
+        assert!(BitSliceReader::new(&data).consume_bits(321).is_err());
+
+        // skips past the buffered bits, mixed with peeks refilling them
+        for skip in [3, 60, 75, 200, 310] {
+            let mut expected = BitReader::new(&data[..]);
+            let mut reader = BitSliceReader::new(&data);
+            assert_eq!(reader.peek_bits(1).unwrap().1, 56);
+            reader.read_bits_exact(5).unwrap();
+            expected.read_bits_exact(5).unwrap();
+            reader.consume_bits(skip).unwrap();
+            for _ in 0..skip {
+                expected.read_bits_exact(1).unwrap();
+            }
+            assert_eq!(reader.bit_position(), expected.bit_position());
+            while let Ok(bits) = expected.read_bits_exact(7) {
+                assert_eq!(reader.read_bits_exact(7).unwrap(), bits, "{}", skip);
+            }
+            assert_eq!(reader.remaining(), &data[expected.bit_position().div_ceil(8) as usize..]);
+        }
+    }
+
+    #[test]
+    fn test_bytes_after_bits() {
+        let bytes_in: Vec<u8> = vec![0b10101010, 0x12, 0x34, 0x56];
+        let mut reader = BitReader::new(Cursor::new(bytes_in));
+        assert_eq!(reader.read_bits_exact(3).unwrap(), 0b010);
+        // load two whole bytes more than needed, as a table lookup would
+        assert_eq!(reader.peek_bits(21).unwrap(), (0x3412aa >> 3, 21));
+        assert_eq!(reader.peek_bits(0).unwrap(), (0x3412aa >> 3, 21));
+        reader.drop_remaining_bits();
+        assert_eq!(reader.read_u16().unwrap(), 0x3412);
+        assert_eq!(reader.read_u8().unwrap(), 0x56);
+        assert_eq!(reader.peek_bits(8).unwrap(), (0, 0));
+        assert_eq!(reader.bit_position(), 32);
+    }
+
+    #[test]
+    fn test_peek_and_consume_bits() {
+        let bytes_in: Vec<u8> = vec![0b11001010, 0b01010101];
+        let mut reader = BitReader::new(Cursor::new(bytes_in));
+        assert_eq!(reader.peek_bits(4).unwrap(), (0b11001010, 8));
+        assert_eq!(reader.peek_bits(10).unwrap(), (0b0101010111001010, 16));
+        assert_eq!(reader.bit_position(), 0);
+        reader.consume_bits(3).unwrap();
+        assert_eq!(reader.bit_position(), 3);
+        assert_eq!(reader.read_bits_exact(3).unwrap(), 0b001);
+        reader.consume_bits(4).unwrap();
+        assert_eq!(reader.peek_bits(16).unwrap(), (0b010101, 6));
+        assert_eq!(reader.peek_bits(0).unwrap(), (0b010101, 6));
+        assert!(reader.consume_bits(7).is_err());
+
+        // more than 56 bits cannot be peeked at once
+        let err = reader.peek_bits(57).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = BitSliceReader::new(&[0; 16]).peek_bits(57).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    // Serves `data`, but fails with `WouldBlock` past `limit` until it is raised.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        pos: usize,
+        limit: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.fill_buf()?.read(buf)?;
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for Trickle<'_> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if self.pos == self.limit && self.pos < self.data.len() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(&self.data[self.pos..self.limit])
+        }
+        fn consume(&mut self, amt: usize) {
+            self.pos += amt;
+        }
+    }
+
+    #[test]
+    fn test_mark_and_rewind() {
+        let data = [0x21, 0x43, 0x65, 0x87, 0xa9, 0xcb];
+        let mut reader = BitReader::new(Trickle { data: &data, pos: 0, limit: 2 });
+        assert_eq!(reader.read_bits_exact(4).unwrap(), 0x1);
+        reader.mark();
+        assert_eq!(reader.read_bits_exact(8).unwrap(), 0x32);
+        let err = reader.read_bits_exact(12).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        reader.rewind();
+        assert_eq!(reader.bit_position(), 4);
+
+        // byte-level reads are given back too
+        reader.get_mut().limit = 3;
+        reader.mark();
+        assert_eq!(reader.read_bits_exact(12).unwrap(), 0x432);
+        assert_eq!(reader.read_u8().unwrap(), 0x65);
+        assert!(reader.read_u8().is_err());
+        reader.rewind();
+        reader.get_mut().limit = 6;
+        assert_eq!(reader.read_bits_exact(12).unwrap(), 0x432);
+        assert_eq!(reader.read_u16().unwrap(), 0x8765);
+        assert_eq!(reader.bit_position(), 32);
+
+        // nothing is kept for rewinding without a mark
+        reader.mark();
+        reader.unmark();
+        assert_eq!(reader.read_u8().unwrap(), 0xa9);
+        reader.rewind();
+        assert_eq!(reader.bit_position(), 40);
+        assert_eq!(reader.read_u8().unwrap(), 0xcb);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let bytes_in: &[u8] = &[0xff, 0x12, 0x34];
+        let mut reader = BitReader::new(bytes_in);
+        assert_eq!(reader.read_bits_exact(4).unwrap(), 0xf);
+        assert_eq!(reader.get_ref(), &[0x12, 0x34]);
+        reader.drop_remaining_bits();
+        assert_eq!(reader.into_inner(), &[0x12, 0x34]);
+    }
 }
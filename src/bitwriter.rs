@@ -0,0 +1,224 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::io;
+use crate::io::Write;
+
+use crate::bitreader::{reverse_low_bits, BitOrder, LsbFirst};
+
+// complete bytes are collected up to this size before being written to the underlying writer
+const BUFFER_SIZE: usize = 8192;
+
+/// Writes bits into a byte stream, least significant bit first as in deflate. This is the
+/// write-side counterpart of [`BitReader`](crate::BitReader).
+///
+/// Output is buffered; it reaches the underlying writer on `flush` and `finish`, or once enough
+/// of it has accumulated. Besides bit-level writes, the byte-level `Write` impl and the
+/// `write_u*` methods are available whenever the writer sits at a byte boundary (see `align`).
+///
+/// Created with [`BitWriter::with_bit_order`] for [`MsbFirst`](crate::MsbFirst), it writes the
+/// bits of each byte most significant bit first instead.
+pub struct BitWriter<W: Write, O: BitOrder = LsbFirst> {
+    writer: W,
+    // complete bytes not written to `writer` yet
+    bytes: Vec<u8>,
+    // bits of the last, partial byte
+    bits: u64,
+    bits_count: usize,
+    // bits are buffered in stream order, and reversed into bytes when writing most significant
+    // bit first
+    order: PhantomData<O>,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(writer: W) -> BitWriter<W> {
+        Self::with_bit_order(writer)
+    }
+}
+
+impl<W: Write, O: BitOrder> BitWriter<W, O> {
+    /// Creates a writer of the bits of each byte in order `O`, e.g.
+    /// `BitWriter::<_, MsbFirst>::with_bit_order(writer)`.
+    pub fn with_bit_order(writer: W) -> BitWriter<W, O> {
+        BitWriter {
+            writer,
+            bytes: Vec::with_capacity(BUFFER_SIZE),
+            bits: 0,
+            bits_count: 0,
+            order: PhantomData,
+        }
+    }
+
+    /// Writes the low `n` (at most 56) bits of `value`.
+    pub fn write_bits(&mut self, value: u64, n: usize) -> io::Result<()> {
+        debug_assert!(n <= 56 && (n == 56 || value >> n == 0));
+        let value = if O::MSB_FIRST { reverse_low_bits(value, n) } else { value };
+        self.bits |= value << self.bits_count;
+        self.bits_count += n;
+        while self.bits_count >= 8 {
+            let byte = self.bits as u8;
+            self.bytes.push(if O::MSB_FIRST { byte.reverse_bits() } else { byte });
+            self.bits >>= 8;
+            self.bits_count -= 8;
+        }
+        if self.bytes.len() >= BUFFER_SIZE {
+            self.flush_bytes()?;
+        }
+        Ok(())
+    }
+
+    /// Number of bits written since the last byte boundary.
+    pub fn pending_bits(&self) -> usize {
+        self.bits_count
+    }
+
+    /// Pads the partial byte, if any, with zero bits.
+    pub fn align(&mut self) -> io::Result<()> {
+        if self.bits_count > 0 {
+            self.write_bits(0, 8 - self.bits_count)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns the underlying writer. Writing to it directly is only safe while nothing is
+    /// buffered, i.e. after `flush` at a byte boundary.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Pads the output to a byte boundary, flushes it and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.align()?;
+        self.flush()?;
+        Ok(self.writer)
+    }
+
+    fn flush_bytes(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.bytes)?;
+        self.bytes.clear();
+        Ok(())
+    }
+}
+
+// Byte-level access, for stored blocks and container headers and trailers.
+impl<W: Write, O: BitOrder> Write for BitWriter<W, O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert_eq!(self.bits_count, 0);
+        if self.bytes.len() + buf.len() > BUFFER_SIZE {
+            self.flush_bytes()?;
+            if buf.len() >= BUFFER_SIZE {
+                return self.writer.write(buf);
+            }
+        }
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Writes out all complete bytes. A partial byte stays buffered until more bits complete it
+    /// or `align` pads it.
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_bytes()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use crate::bitreader::{BitRead, BitReader, MsbFirst};
+    use crate::bitwriter::BitWriter;
+
+    #[test]
+    fn test_write_bits() {
+        let mut writer = BitWriter::new(vec![]);
+        writer.write_bits(0b111, 3).unwrap();
+        writer.write_bits(0b01, 2).unwrap();
+        writer.write_bits(0, 1).unwrap();
+        writer.write_bits(0, 1).unwrap();
+        assert_eq!(writer.pending_bits(), 7);
+        writer.write_bits(0b00110, 5).unwrap();
+        writer.write_bits(0b1111111000000000011, 19).unwrap();
+        assert_eq!(writer.pending_bits(), 7);
+        assert_eq!(
+            writer.finish().unwrap(),
+            vec![0b00001111, 0b00110011, 0b00000000, 0b01111111]
+        );
+    }
+
+    #[test]
+    fn test_bytes_after_bits() {
+        let mut writer = BitWriter::new(vec![]);
+        writer.write_bits(0b010, 3).unwrap();
+        writer.align().unwrap();
+        writer.write_u16(0x3412).unwrap();
+        writer.write_u32(0x9a785634).unwrap();
+        writer.write_all(b"xy").unwrap();
+        let bytes = writer.finish().unwrap();
+        assert_eq!(
+            bytes,
+            [0b010, 0x12, 0x34, 0x34, 0x56, 0x78, 0x9a, b'x', b'y']
+        );
+
+        let mut reader = BitReader::new(Cursor::new(bytes));
+        assert_eq!(reader.read_bits_exact(3).unwrap(), 0b010);
+        reader.drop_remaining_bits();
+        assert_eq!(reader.read_u16().unwrap(), 0x3412);
+        assert_eq!(reader.read_u32().unwrap(), 0x9a785634);
+    }
+
+    #[test]
+    fn test_msb_first() {
+        let mut writer = BitWriter::<_, MsbFirst>::with_bit_order(vec![]);
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bits(0b0011, 4).unwrap();
+        writer.write_bits(0b1_1110_0001, 9).unwrap();
+        writer.write_bits(0b01, 2).unwrap();
+        writer.align().unwrap();
+        writer.write_u8(0x5a).unwrap();
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, [0b1010_0111, 0b1110_0001, 0b0100_0000, 0x5a]);
+
+        let mut reader = BitReader::<_, MsbFirst>::with_bit_order(&bytes[..]);
+        assert_eq!(reader.read_bits_exact(3).unwrap(), 0b101);
+        assert_eq!(reader.peek_bits(4).unwrap(), (0b00111, 5));
+        assert_eq!(reader.read_bits_exact(13).unwrap(), 0b0_0111_1110_0001);
+        let mut buf = 0;
+        assert_eq!(reader.read_bits(&mut buf, 8).unwrap(), 8);
+        assert_eq!(buf, 0b0100_0000);
+        // whole bytes are served as they are
+        assert_eq!(reader.peek_bits(8).unwrap(), (0x5a, 8));
+        assert_eq!(reader.read_u8().unwrap(), 0x5a);
+    }
+
+    #[test]
+    fn test_large_output() {
+        let mut writer = BitWriter::new(vec![]);
+        for i in 0..100_000u64 {
+            writer.write_bits(i % 8, 3).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes.len(), 37_500);
+
+        let mut reader = BitReader::new(Cursor::new(bytes));
+        for i in 0..100_000u64 {
+            assert_eq!(reader.read_bits_exact(3).unwrap(), i % 8);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// How the data of a deflate block is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Stored,
+    FixedHuffman,
+    DynamicHuffman,
+}
+
+/// The Huffman codes a dynamic block header declares, see RFC 1951 3.2.7.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicHeader {
+    /// Number of literal/length codes (HLIT + 257).
+    pub nlit: usize,
+    /// Number of distance codes (HDIST + 1).
+    pub ndist: usize,
+    /// Number of code length codes (HCLEN + 4).
+    pub ncode: usize,
+    /// Lengths of the code length code, indexed by symbol (0 to 18).
+    pub code_length_lengths: Vec<u8>,
+    /// Lengths of the literal/length code, indexed by symbol, `nlit` of them.
+    pub litlen_lengths: Vec<u8>,
+    /// Lengths of the distance code, indexed by distance code, `ndist` of them.
+    pub distance_lengths: Vec<u8>,
+}
+
+/// The structure of a deflate block and statistics of its data, as recorded by
+/// [`RZLibReader::set_record_blocks`]. The statistics are 0 until the end of the block is
+/// decoded.
+///
+/// [`RZLibReader::set_record_blocks`]: crate::RZLibReader::set_record_blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// 1-based index of the gzip member or zlib stream holding the block.
+    pub member: u64,
+    /// 1-based index of the block within its member.
+    pub block: u64,
+    /// Offset in the compressed data, in bits, of the block header.
+    pub in_bits: u64,
+    pub block_type: BlockType,
+    pub is_final: bool,
+    /// The codes of a dynamic block, `None` for other blocks.
+    pub dynamic: Option<DynamicHeader>,
+    /// Size of the block in the compressed data, in bits, header included.
+    pub compressed_bits: u64,
+    /// Number of bytes the block decompresses to.
+    pub uncompressed: u64,
+    /// Number of literals, 0 for stored blocks.
+    pub literals: u64,
+    /// Number of matches.
+    pub matches: u64,
+    /// Total length of the matches.
+    pub match_bytes: u64,
+}
+
+impl BlockInfo {
+    /// Average length of the matches, `None` without matches.
+    pub fn average_match_length(&self) -> Option<f64> {
+        average(self.match_bytes, self.matches)
+    }
+}
+
+/// Statistics of the blocks of a gzip member or zlib stream, see [`Stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemberStats {
+    /// 1-based index of the member.
+    pub member: u64,
+    pub blocks: u64,
+    /// Size of the blocks in the compressed data, in bits, leaving out the member header and
+    /// trailer.
+    pub compressed_bits: u64,
+    pub uncompressed: u64,
+    pub literals: u64,
+    pub matches: u64,
+    pub match_bytes: u64,
+}
+
+impl MemberStats {
+    /// Average length of the matches, `None` without matches.
+    pub fn average_match_length(&self) -> Option<f64> {
+        average(self.match_bytes, self.matches)
+    }
+}
+
+/// Per block and per member statistics of the data decoded so far, as returned by
+/// [`RZLibReader::stats`].
+///
+/// [`RZLibReader::stats`]: crate::RZLibReader::stats
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub blocks: Vec<BlockInfo>,
+    pub members: Vec<MemberStats>,
+}
+
+impl Stats {
+    pub(crate) fn from_blocks(blocks: &[BlockInfo]) -> Stats {
+        let mut members: Vec<MemberStats> = vec![];
+        for block in blocks {
+            if members.last().is_none_or(|member| member.member != block.member) {
+                members.push(MemberStats {
+                    member: block.member,
+                    ..MemberStats::default()
+                });
+            }
+            let member = members.last_mut().expect("a member was pushed");
+            member.blocks += 1;
+            member.compressed_bits += block.compressed_bits;
+            member.uncompressed += block.uncompressed;
+            member.literals += block.literals;
+            member.matches += block.matches;
+            member.match_bytes += block.match_bytes;
+        }
+        Stats {
+            blocks: blocks.to_vec(),
+            members,
+        }
+    }
+}
+
+/// How often each symbol was decoded, as recorded by [`RZLibReader::set_record_histograms`]:
+/// the data to judge how well the Huffman codes of a stream fit it.
+///
+/// [`RZLibReader::set_record_histograms`]: crate::RZLibReader::set_record_histograms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histograms {
+    /// Counts of literal/length symbols 0 to 285, 256 being the end of block.
+    pub litlen: Vec<u64>,
+    /// Counts of distance codes 0 to 29, and 30 and 31 of DEFLATE64.
+    pub distance: Vec<u64>,
+}
+
+impl Default for Histograms {
+    fn default() -> Histograms {
+        Histograms {
+            litlen: vec![0; 286],
+            distance: vec![0; 32],
+        }
+    }
+}
+
+fn average(total: u64, count: u64) -> Option<f64> {
+    (count > 0).then(|| total as f64 / count as f64)
+}
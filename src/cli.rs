@@ -0,0 +1,428 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use rzlib::Compression;
+
+pub const USAGE: &str = "\
+Usage: rzlib [OPTION]... [FILE]...
+  or:  rzlib inspect [FILE]...
+  or:  rzlib index [--span SIZE] FILE...
+  or:  rzlib range [--start OFFSET] [--len SIZE] FILE
+Compress or decompress FILEs in the gzip format, in place by default, print the structure of
+their deflate streams, or write a seek index of each FILE to FILE.rzi, with a checkpoint every
+SIZE bytes of decompressed data (1M by default). range writes SIZE bytes (by default all of
+them) of the decompressed data of FILE from OFFSET on to standard output, seeking with the index
+in FILE.rzi, or one built on the fly. Sizes and offsets take K, M and G suffixes.
+
+  -1, --fast        compress faster
+  -9, --best        compress better
+  -c, --stdout      write to standard output, keep original files
+  -d, --decompress  decompress
+  -f, --force       overwrite existing output files, write compressed data to a terminal
+  -h, --help        display this help and exit
+  -k, --keep        keep (don't delete) input files
+  -n, --no-name     name decompressed files after the input file (default)
+  -N, --name        name decompressed files after the name stored in them, if any
+  -l, --list        list the members of compressed files
+      --progress    show decompression progress on standard error
+  -r, --recursive   operate on the files in directories, recursively
+      --rsyncable   compress so that local changes to the input only change the output locally
+  -t, --test        test compressed file integrity
+  -v, --verbose     report the compression ratio of each file
+  -V, --version     display version information and exit
+
+The compression level goes from -1 to -9, and is -6 by default.
+With no FILE, or when FILE is -, read standard input and write standard output.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Compress,
+    Decompress,
+    Test,
+    List,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub mode: Mode,
+    pub level: Compression,
+    pub stdout: bool,
+    pub keep: bool,
+    pub force: bool,
+    pub verbose: bool,
+    // whether decompressed files are named after the FNAME header field
+    pub name: bool,
+    pub recursive: bool,
+    pub progress: bool,
+    pub rsyncable: bool,
+    // `-` stands for standard input
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run(Options),
+    // print the members and blocks of the files, `-` standing for standard input
+    Inspect(Vec<PathBuf>),
+    // write a seek index of each file, with checkpoints `span` decompressed bytes apart
+    Index { span: u64, files: Vec<PathBuf> },
+    // write `len` bytes, or all of them, of the decompressed data of `file` from `start` on
+    Range { file: PathBuf, start: u64, len: Option<u64> },
+    Help,
+    Version,
+}
+
+// the default span of `rzlib index`, as in zlib's zran.c
+pub const DEFAULT_SPAN: u64 = 1 << 20;
+
+// Parses a size in bytes, with an optional binary K, M or G suffix.
+fn parse_size(size: &str) -> Option<u64> {
+    let (digits, shift) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 10),
+        (i, 'm' | 'M') => (&size[..i], 20),
+        (i, 'g' | 'G') => (&size[..i], 30),
+        _ => (size, 0),
+    };
+    let n: u64 = digits.parse().ok()?;
+    n.checked_mul(1 << shift)
+}
+
+// The arguments of a subcommand: the values of its `--name VALUE` (or `--name=VALUE`) options,
+// which are sizes, and its files.
+struct SubcommandArgs {
+    values: Vec<(&'static str, u64)>,
+    files: Vec<PathBuf>,
+}
+
+impl SubcommandArgs {
+    fn value(&self, name: &str) -> Option<u64> {
+        self.values.iter().rev().find(|(option, _)| *option == name).map(|&(_, value)| value)
+    }
+}
+
+// Parses the arguments of `subcommand`, which takes the options `names`. `None` for `--help`.
+fn parse_subcommand_args<I: Iterator<Item = OsString>>(
+    subcommand: &str,
+    names: &[&'static str],
+    mut args: I,
+) -> Result<Option<SubcommandArgs>, String> {
+    let mut parsed = SubcommandArgs { values: vec![], files: vec![] };
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        let option = match arg.to_str() {
+            Some(s) if !only_files && s.starts_with('-') && s != "-" => s,
+            _ => {
+                parsed.files.push(PathBuf::from(arg));
+                continue;
+            }
+        };
+        if option == "--" {
+            only_files = true;
+            continue;
+        }
+        if option == "-h" || option == "--help" {
+            return Ok(None);
+        }
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (option, None),
+        };
+        let name = names
+            .iter()
+            .find(|&&known| name.strip_prefix("--") == Some(known))
+            .ok_or_else(|| format!("unrecognized option '{}'", option))?;
+        let value = match value {
+            Some(value) => value,
+            None => args
+                .next()
+                .ok_or_else(|| format!("option '--{}' requires an argument", name))?
+                .to_string_lossy()
+                .into_owned(),
+        };
+        let size = parse_size(&value).ok_or_else(|| format!("invalid {} '{}'", name, value))?;
+        parsed.values.push((name, size));
+    }
+    if parsed.files.is_empty() {
+        return Err(format!("{}: missing file operand", subcommand));
+    }
+    Ok(Some(parsed))
+}
+
+fn parse_index_args<I: Iterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let Some(parsed) = parse_subcommand_args("index", &["span"], args)? else {
+        return Ok(Command::Help);
+    };
+    let span = parsed.value("span").unwrap_or(DEFAULT_SPAN);
+    if span == 0 {
+        return Err("invalid span '0'".to_string());
+    }
+    Ok(Command::Index { span, files: parsed.files })
+}
+
+fn parse_range_args<I: Iterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let Some(mut parsed) = parse_subcommand_args("range", &["start", "len"], args)? else {
+        return Ok(Command::Help);
+    };
+    if parsed.files.len() > 1 {
+        return Err("range: only one file allowed".to_string());
+    }
+    Ok(Command::Range {
+        start: parsed.value("start").unwrap_or(0),
+        len: parsed.value("len"),
+        file: parsed.files.remove(0),
+    })
+}
+
+/// Parses the command line arguments, without the program name, gzip style: short flags can be
+/// grouped (`-dc`) and `--` ends the options. A first argument of `inspect`, `index` or `range`
+/// is a subcommand, the first taking only files.
+pub fn parse_args<I: IntoIterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().is_some_and(|arg| arg == "inspect") {
+        return Ok(Command::Inspect(args.skip(1).map(PathBuf::from).collect()));
+    }
+    if args.peek().is_some_and(|arg| arg == "index") {
+        return parse_index_args(args.skip(1));
+    }
+    if args.peek().is_some_and(|arg| arg == "range") {
+        return parse_range_args(args.skip(1));
+    }
+    let mut options = Options {
+        mode: Mode::Compress,
+        level: Compression::default(),
+        stdout: false,
+        keep: false,
+        force: false,
+        verbose: false,
+        name: false,
+        recursive: false,
+        progress: false,
+        rsyncable: false,
+        files: vec![],
+    };
+    let mut test = false;
+    let mut list = false;
+    let mut only_files = false;
+
+    for arg in args {
+        let flags = match arg.to_str() {
+            Some(s) if !only_files && s.starts_with('-') && s != "-" => s,
+            _ => {
+                options.files.push(PathBuf::from(arg));
+                continue;
+            }
+        };
+        if flags == "--" {
+            only_files = true;
+            continue;
+        }
+
+        let names: Vec<&str> = match flags.strip_prefix("--") {
+            Some(long) => vec![long],
+            None => flags[1..].matches(|_| true).collect(),
+        };
+        for name in names {
+            match name {
+                "1" | "fast" => options.level = Compression::fast(),
+                "2" | "3" | "4" | "5" | "6" | "7" | "8" => {
+                    options.level = Compression::new(name.parse().unwrap())
+                }
+                "9" | "best" => options.level = Compression::best(),
+                "c" | "stdout" | "to-stdout" => options.stdout = true,
+                "d" | "decompress" | "uncompress" => options.mode = Mode::Decompress,
+                "f" | "force" => options.force = true,
+                "h" | "help" => return Ok(Command::Help),
+                "k" | "keep" => options.keep = true,
+                "l" | "list" => list = true,
+                "n" | "no-name" => options.name = false,
+                "N" | "name" => options.name = true,
+                "progress" => options.progress = true,
+                "r" | "recursive" => options.recursive = true,
+                "rsyncable" => options.rsyncable = true,
+                "t" | "test" => test = true,
+                "v" | "verbose" => options.verbose = true,
+                "V" | "version" => return Ok(Command::Version),
+                _ if flags.starts_with("--") => {
+                    return Err(format!("unrecognized option '{}'", flags))
+                }
+                _ => return Err(format!("invalid option -- '{}'", name)),
+            }
+        }
+    }
+
+    if list {
+        options.mode = Mode::List;
+    } else if test {
+        options.mode = Mode::Test;
+    }
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use rzlib::Compression;
+
+    use super::{parse_args, parse_size, Command, Mode, Options, DEFAULT_SPAN};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(OsString::from))
+    }
+
+    fn parse_options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("unexpected parse result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = parse_options(&[]);
+        assert_eq!(options.mode, Mode::Compress);
+        assert_eq!(options.level, Compression::new(6));
+        assert!(!options.stdout && !options.keep && !options.force && !options.verbose);
+        assert!(!options.name && !options.recursive && !options.progress && !options.rsyncable);
+        assert!(options.files.is_empty());
+    }
+
+    #[test]
+    fn test_flags_and_files() {
+        let options =
+            parse_options(&["-dc", "a.gz", "--keep", "-", "--force", "-v", "--", "-b.gz"]);
+        assert_eq!(options.mode, Mode::Decompress);
+        assert!(options.stdout && options.keep && options.force && options.verbose);
+        assert_eq!(
+            options.files,
+            [
+                PathBuf::from("a.gz"),
+                PathBuf::from("-"),
+                PathBuf::from("-b.gz")
+            ]
+        );
+
+        assert_eq!(parse_options(&["-t", "a.gz"]).mode, Mode::Test);
+        assert_eq!(parse_options(&["--test", "-d"]).mode, Mode::Test);
+        assert_eq!(parse_options(&["-tl"]).mode, Mode::List);
+        assert!(parse_options(&["-nN"]).name);
+        assert!(!parse_options(&["--name", "-n"]).name);
+        assert!(parse_options(&["--rsyncable"]).rsyncable);
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(parse_options(&["-1"]).level, Compression::fast());
+        assert_eq!(parse_options(&["-c4"]).level, Compression::new(4));
+        assert_eq!(parse_options(&["--best", "-k"]).level, Compression::best());
+        // the last one wins
+        assert_eq!(parse_options(&["-9", "--fast"]).level, Compression::fast());
+        assert_eq!(parse_options(&["-37"]).level, Compression::new(7));
+        assert_eq!(parse(&["-0"]), Err("invalid option -- '0'".to_string()));
+        assert_eq!(parse(&["-10"]), Err("invalid option -- '0'".to_string()));
+    }
+
+    #[test]
+    fn test_inspect() {
+        assert_eq!(
+            parse(&["inspect", "a.gz", "-"]),
+            Ok(Command::Inspect(vec![PathBuf::from("a.gz"), PathBuf::from("-")]))
+        );
+        assert_eq!(parse(&["inspect"]), Ok(Command::Inspect(vec![])));
+        // only as the first argument
+        assert_eq!(
+            parse_options(&["-c", "inspect"]).files,
+            [PathBuf::from("inspect")]
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(
+            parse(&["index", "a.gz", "b.gz"]),
+            Ok(Command::Index {
+                span: DEFAULT_SPAN,
+                files: vec![PathBuf::from("a.gz"), PathBuf::from("b.gz")]
+            })
+        );
+        assert_eq!(
+            parse(&["index", "--span", "64K", "a.gz", "--span=2m", "--", "--span"]),
+            Ok(Command::Index {
+                span: 2 << 20,
+                files: vec![PathBuf::from("a.gz"), PathBuf::from("--span")]
+            })
+        );
+        assert_eq!(
+            parse(&["index"]),
+            Err("index: missing file operand".to_string())
+        );
+        assert_eq!(
+            parse(&["index", "--span", "0", "a.gz"]),
+            Err("invalid span '0'".to_string())
+        );
+        assert_eq!(
+            parse(&["index", "a.gz", "--span"]),
+            Err("option '--span' requires an argument".to_string())
+        );
+        assert_eq!(
+            parse(&["index", "-c", "a.gz"]),
+            Err("unrecognized option '-c'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            parse(&["range", "a.gz", "--start", "1M", "--len=100"]),
+            Ok(Command::Range {
+                file: PathBuf::from("a.gz"),
+                start: 1 << 20,
+                len: Some(100)
+            })
+        );
+        assert_eq!(
+            parse(&["range", "-"]),
+            Ok(Command::Range {
+                file: PathBuf::from("-"),
+                start: 0,
+                len: None
+            })
+        );
+        assert_eq!(parse(&["range", "--help"]), Ok(Command::Help));
+        assert_eq!(
+            parse(&["range", "a.gz", "b.gz"]),
+            Err("range: only one file allowed".to_string())
+        );
+        assert_eq!(
+            parse(&["range", "--start", "x", "a.gz"]),
+            Err("invalid start 'x'".to_string())
+        );
+        assert_eq!(
+            parse(&["range", "--span=1", "a.gz"]),
+            Err("unrecognized option '--span=1'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("4k"), Some(4096));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size("0"), Some(0));
+        for size in ["", "K", "-1", "1.5M", "1T", "99999999999999G"] {
+            assert_eq!(parse_size(size), None, "{}", size);
+        }
+    }
+
+    #[test]
+    fn test_help_version_and_errors() {
+        assert_eq!(parse(&["-k", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+        assert_eq!(parse(&["-dx"]), Err("invalid option -- 'x'".to_string()));
+        assert_eq!(
+            parse(&["--bestest"]),
+            Err("unrecognized option '--bestest'".to_string())
+        );
+    }
+}
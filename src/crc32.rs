@@ -0,0 +1,334 @@
+// CRC-32 as used by gzip (ISO 3309 / ITU-T V.42, reflected polynomial 0xedb88320)
+
+const POLYNOMIAL: u32 = 0xedb88320;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { POLYNOMIAL ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+// TABLES[0] is the usual byte-at-a-time table. TABLES[k][n] is the CRC of byte n followed by k
+// zero bytes, which lets `update` fold in 8 bytes at once with independent lookups
+// (slicing-by-8).
+const fn make_tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0; 256]; 8];
+    tables[0] = make_table();
+    let mut k = 1;
+    while k < 8 {
+        let mut n = 0;
+        while n < 256 {
+            let c = tables[k - 1][n];
+            tables[k][n] = (c >> 8) ^ tables[0][(c & 0xff) as usize];
+            n += 1;
+        }
+        k += 1;
+    }
+    tables
+}
+
+static TABLES: [[u32; 256]; 8] = make_tables();
+
+// Updates the checksum `crc` of the data so far with `buf`.
+fn update_slicing(crc: u32, buf: &[u8]) -> u32 {
+    let mut c = !crc;
+    let mut chunks = buf.chunks_exact(8);
+    for chunk in &mut chunks {
+        let lo = c ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let hi = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        c = TABLES[7][(lo & 0xff) as usize]
+            ^ TABLES[6][((lo >> 8) & 0xff) as usize]
+            ^ TABLES[5][((lo >> 16) & 0xff) as usize]
+            ^ TABLES[4][(lo >> 24) as usize]
+            ^ TABLES[3][(hi & 0xff) as usize]
+            ^ TABLES[2][((hi >> 8) & 0xff) as usize]
+            ^ TABLES[1][((hi >> 16) & 0xff) as usize]
+            ^ TABLES[0][(hi >> 24) as usize];
+    }
+    for &b in chunks.remainder() {
+        c = TABLES[0][((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+// Multiplies two polynomials modulo the CRC-32 polynomial, in the reflected bit order of the
+// checksum: the top bit is x^0.
+fn multiply_mod_poly(a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    for bit in (0..32).rev() {
+        if a >> bit & 1 != 0 {
+            product ^= b;
+        }
+        b = if b & 1 != 0 { (b >> 1) ^ 0xedb88320 } else { b >> 1 };
+    }
+    product
+}
+
+// x^(8 * len) modulo the CRC-32 polynomial: appending `len` zero bytes multiplies by it.
+fn shift_mod_poly(mut len: u64) -> u32 {
+    let mut power = 1 << 31;
+    // x^8, then x^16, x^32 and so on
+    let mut square = 1 << 23;
+    while len != 0 {
+        if len & 1 != 0 {
+            power = multiply_mod_poly(square, power);
+        }
+        square = multiply_mod_poly(square, square);
+        len >>= 1;
+    }
+    power
+}
+
+/// Incrementally computed CRC-32 checksum.
+///
+/// With the `hardware-crc` feature, long inputs are checksummed with carry-less multiplication
+/// (PCLMULQDQ) on x86_64 and with the CRC instructions on aarch64, when the CPU supports them.
+#[derive(Debug, Clone, Default)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { crc: 0 }
+    }
+
+    // Continues a checksum from its value over the data so far.
+    pub(crate) fn from_sum(sum: u32) -> Crc32 {
+        Crc32 { crc: sum }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        #[cfg(feature = "hardware-crc")]
+        if let Some(crc) = hardware::update(self.crc, buf) {
+            self.crc = crc;
+            return;
+        }
+        self.crc = update_slicing(self.crc, buf);
+    }
+
+    pub fn sum(&self) -> u32 {
+        self.crc
+    }
+
+    /// Continues the checksum with data of `len` bytes whose checksum is `sum`, without the data
+    /// itself, as zlib's `crc32_combine`. This lets pieces of data be checksummed separately.
+    pub fn combine(&mut self, sum: u32, len: u64) {
+        self.crc = multiply_mod_poly(shift_mod_poly(len), self.crc) ^ sum;
+    }
+
+    pub fn reset(&mut self) {
+        self.crc = 0;
+    }
+}
+
+// Checksumming with CPU instructions, detected at run time.
+#[cfg(feature = "hardware-crc")]
+mod hardware {
+    #[cfg(target_arch = "x86_64")]
+    pub(super) fn update(crc: u32, buf: &[u8]) -> Option<u32> {
+        // shorter inputs are not worth setting up the folding for
+        if buf.len() < 128
+            || !std::is_x86_feature_detected!("pclmulqdq")
+            || !std::is_x86_feature_detected!("sse4.1")
+        {
+            return None;
+        }
+        // SAFETY: the CPU supports the instructions, as just checked
+        Some(unsafe { pclmul::update(crc, buf) })
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub(super) fn update(crc: u32, buf: &[u8]) -> Option<u32> {
+        if !std::arch::is_aarch64_feature_detected!("crc") {
+            return None;
+        }
+        // SAFETY: the CPU supports the instructions, as just checked
+        Some(unsafe { arm::update(crc, buf) })
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) fn update(_crc: u32, _buf: &[u8]) -> Option<u32> {
+        None
+    }
+
+    // Folding of 128 bit lanes with carry-less multiplication, then Barrett reduction, after
+    // Intel's "Fast CRC Computation for Generic Polynomials Using PCLMULQDQ Instruction", with
+    // the constants of the reflected gzip polynomial used by Linux and Chromium.
+    #[cfg(target_arch = "x86_64")]
+    mod pclmul {
+        use core::arch::x86_64::*;
+
+        const K1: i64 = 0x154442bd4;
+        const K2: i64 = 0x1c6e41596;
+        const K3: i64 = 0x1751997d0;
+        const K4: i64 = 0x0ccaa009e;
+        const K5: i64 = 0x163cd6124;
+        const P_X: i64 = 0x1db710641;
+        const U_PRIME: i64 = 0x1f7011641;
+
+        // Requires at least 64 bytes.
+        #[target_feature(enable = "pclmulqdq,sse2,sse4.1")]
+        pub(super) unsafe fn update(crc: u32, mut buf: &[u8]) -> u32 {
+            // fold 4 lanes at a time, 64 bytes apart
+            let mut x3 = load(&mut buf);
+            let mut x2 = load(&mut buf);
+            let mut x1 = load(&mut buf);
+            let mut x0 = load(&mut buf);
+            x3 = _mm_xor_si128(x3, _mm_cvtsi32_si128(!crc as i32));
+            let k1k2 = _mm_set_epi64x(K2, K1);
+            while buf.len() >= 64 {
+                x3 = fold(x3, load(&mut buf), k1k2);
+                x2 = fold(x2, load(&mut buf), k1k2);
+                x1 = fold(x1, load(&mut buf), k1k2);
+                x0 = fold(x0, load(&mut buf), k1k2);
+            }
+
+            // fold the lanes into one, then the rest of the input 16 bytes at a time
+            let k3k4 = _mm_set_epi64x(K4, K3);
+            let mut x = fold(x3, x2, k3k4);
+            x = fold(x, x1, k3k4);
+            x = fold(x, x0, k3k4);
+            while buf.len() >= 16 {
+                x = fold(x, load(&mut buf), k3k4);
+            }
+
+            // reduce 128 bits to 64
+            let low_32 = _mm_set_epi32(0, 0, 0, !0);
+            let x = _mm_xor_si128(_mm_clmulepi64_si128(x, k3k4, 0x10), _mm_srli_si128(x, 8));
+            let x = _mm_xor_si128(
+                _mm_clmulepi64_si128(_mm_and_si128(x, low_32), _mm_set_epi64x(0, K5), 0x00),
+                _mm_srli_si128(x, 4),
+            );
+
+            // Barrett reduction to 32 bits, taking the upper half as the input is reflected
+            let pu = _mm_set_epi64x(U_PRIME, P_X);
+            let t1 = _mm_clmulepi64_si128(_mm_and_si128(x, low_32), pu, 0x10);
+            let t2 = _mm_clmulepi64_si128(_mm_and_si128(t1, low_32), pu, 0x00);
+            let crc = !(_mm_extract_epi32(_mm_xor_si128(x, t2), 1) as u32);
+
+            super::super::update_slicing(crc, buf)
+        }
+
+        #[target_feature(enable = "pclmulqdq,sse2")]
+        unsafe fn fold(a: __m128i, b: __m128i, keys: __m128i) -> __m128i {
+            let t1 = _mm_clmulepi64_si128(a, keys, 0x00);
+            let t2 = _mm_clmulepi64_si128(a, keys, 0x11);
+            _mm_xor_si128(_mm_xor_si128(b, t1), t2)
+        }
+
+        #[target_feature(enable = "sse2")]
+        unsafe fn load(buf: &mut &[u8]) -> __m128i {
+            let (head, tail) = buf.split_at(16);
+            *buf = tail;
+            // SAFETY: `head` holds 16 bytes, and unaligned loads are fine
+            unsafe { _mm_loadu_si128(head.as_ptr() as *const __m128i) }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    mod arm {
+        use core::arch::aarch64::{__crc32b, __crc32d};
+
+        #[target_feature(enable = "crc")]
+        pub(super) unsafe fn update(crc: u32, buf: &[u8]) -> u32 {
+            let mut c = !crc;
+            let mut chunks = buf.chunks_exact(8);
+            for chunk in &mut chunks {
+                c = __crc32d(c, u64::from_le_bytes(chunk.try_into().unwrap()));
+            }
+            for &b in chunks.remainder() {
+                c = __crc32b(c, b);
+            }
+            !c
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Crc32;
+
+    #[test]
+    fn test_check_value() {
+        let mut crc = Crc32::new();
+        assert_eq!(crc.sum(), 0);
+        crc.update(b"123456789");
+        assert_eq!(crc.sum(), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let data = b"The quick brown fox jumps over the lazy dog";
+        let mut crc = Crc32::new();
+        for chunk in data.chunks(5) {
+            crc.update(chunk);
+        }
+        assert_eq!(crc.sum(), 0x414fa339);
+    }
+
+    #[test]
+    fn test_combine() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| ((i * 7) ^ (i >> 5)) as u8).collect();
+        let mut whole = Crc32::new();
+        whole.update(&data);
+        for split in [0, 1, 3, 4096, 65_537, data.len()] {
+            let mut crc = Crc32::new();
+            crc.update(&data[..split]);
+            let mut rest = Crc32::new();
+            rest.update(&data[split..]);
+            crc.combine(rest.sum(), (data.len() - split) as u64);
+            assert_eq!(crc.sum(), whole.sum(), "split at {}", split);
+        }
+    }
+
+    // Bit at a time, straight from the definition.
+    fn reference(data: &[u8]) -> u32 {
+        let mut c = !0u32;
+        for &b in data {
+            c ^= b as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+        }
+        !c
+    }
+
+    #[test]
+    fn test_long_inputs() {
+        // long enough for the hardware paths, with every remainder of their 64 and 16 byte steps
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 + i / 300) as u8).collect();
+        for len in [128, 129, 143, 144, 191, 192, 200, 255, 256, 1000, 4999, 5000] {
+            let mut crc = Crc32::new();
+            crc.update(&data[..len]);
+            assert_eq!(crc.sum(), reference(&data[..len]), "len {}", len);
+            crc.update(&data[len..]);
+            assert_eq!(crc.sum(), reference(&data), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_slicing() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * i % 257) as u8).collect();
+        // every alignment and remainder of the 8 byte steps
+        for len in (0..40).chain([9999, 10_000]) {
+            for split in [0, 1, 3, 7, len / 2] {
+                let (head, tail) = data[..len].split_at(split.min(len));
+                let mut crc = Crc32::new();
+                crc.update(head);
+                crc.update(tail);
+                assert_eq!(crc.sum(), reference(&data[..len]), "len {}, split {}", len, split);
+            }
+        }
+    }
+}
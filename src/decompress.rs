@@ -0,0 +1,205 @@
+use crate::io;
+use crate::io::{ErrorKind, Read};
+
+use crate::gzheader::GzHeader;
+use crate::pushinput::PushInput;
+use crate::rzlibreader::{Format, RZLibReader};
+
+/// How [`Decompress::decompress`] treats the end of its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushDecompress {
+    /// More input may follow.
+    None,
+    /// Same as `None`: all the output the input decodes to is produced anyway, as room allows.
+    Sync,
+    /// The input is complete: a stream that does not end with it fails with
+    /// [`RzlibError::TruncatedStream`](crate::RzlibError::TruncatedStream).
+    Finish,
+}
+
+/// Why a successful [`Decompress::decompress`] call returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All the input was consumed, possibly in the middle of a code or header, and decoding
+    /// resumes from there with the next input.
+    NeedsInput,
+    /// The output is full; the rest of the input is not consumed yet.
+    NeedsOutput,
+    /// The end of the stream was reached; input following it is not consumed.
+    StreamEnd,
+}
+
+/// Decompresses a single gzip member, zlib or raw deflate stream from input slices into output
+/// slices, for callers that do their own buffering (async runtimes, FFI) rather than handing the
+/// decoder a reader.
+///
+/// Each call consumes as much input and fills as much output as it can. How much it did shows in
+/// [`Decompress::total_in`] and [`Decompress::total_out`]. Input consumed without producing output
+/// yet is kept by the decoder, so the caller should only pass again what was not consumed.
+pub struct Decompress {
+    reader: RZLibReader<PushInput>,
+    total_in: u64,
+    done: bool,
+}
+
+impl Decompress {
+    pub fn new(format: Format) -> Decompress {
+        let mut reader = RZLibReader::with_format(PushInput::new(), format);
+        reader.set_multi_member(false);
+        Decompress {
+            reader,
+            total_in: 0,
+            done: false,
+        }
+    }
+
+    /// Total number of input bytes consumed.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Total number of bytes decompressed.
+    pub fn total_out(&self) -> u64 {
+        self.reader.total_out()
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.reader.header()
+    }
+
+    /// Starts over with a new stream of the same format.
+    pub fn reset(&mut self) {
+        self.reader.reset(PushInput::new());
+        self.total_in = 0;
+        self.done = false;
+    }
+
+    /// Decompresses `input` into `output`. Errors are not recoverable: once one is returned, the
+    /// stream can only be [`reset`](Decompress::reset).
+    pub fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushDecompress,
+    ) -> io::Result<Status> {
+        if self.done {
+            return Ok(Status::StreamEnd);
+        }
+        self.reader.get_mut().push(input);
+        if flush == FlushDecompress::Finish {
+            self.reader.get_mut().finish();
+        }
+
+        let mut produced = 0;
+        let status = loop {
+            if produced == output.len() {
+                break Status::NeedsOutput;
+            }
+            match self.reader.read(&mut output[produced..]) {
+                Ok(0) => {
+                    self.done = true;
+                    break Status::StreamEnd;
+                }
+                Ok(n) => produced += n,
+                // the input pushed is used up: the decoder rewinds to the start of the code or
+                // header it could not complete, and picks it up again once more input is pushed
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break Status::NeedsInput,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+
+        // what the decoder did not take is handed back, for the caller to pass again
+        let unconsumed = self.reader.get_ref().remaining().len();
+        self.reader.get_mut().unpush(unconsumed);
+        let consumed = input.len() - unconsumed;
+        self.total_in += consumed as u64;
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decompress, FlushDecompress, Status};
+    use crate::deflateencoder::Compression;
+    use crate::error::RzlibError;
+    use crate::rzlibreader::Format;
+    use crate::{gzip, zlib};
+
+    // Decompresses `compressed` passing at most `in_size` bytes of input and `out_size` bytes of
+    // output at a time.
+    fn decompress_slices(
+        decompress: &mut Decompress,
+        compressed: &[u8],
+        in_size: usize,
+        out_size: usize,
+    ) -> Vec<u8> {
+        let mut out = vec![];
+        let mut buf = vec![0; out_size];
+        loop {
+            let pos = decompress.total_in() as usize;
+            let input = &compressed[pos..(pos + in_size).min(compressed.len())];
+            let out_before = decompress.total_out();
+            let status = decompress
+                .decompress(input, &mut buf, FlushDecompress::None)
+                .unwrap();
+            out.extend_from_slice(&buf[..(decompress.total_out() - out_before) as usize]);
+            match status {
+                Status::StreamEnd => return out,
+                Status::NeedsInput => assert_eq!(decompress.total_in(), (pos + in_size) as u64),
+                Status::NeedsOutput => {
+                    assert_eq!(decompress.total_out() - out_before, out_size as u64)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_slices() {
+        let data: Vec<u8> = (0..60_000u32).map(|i| ((i / 7) ^ (i % 13)) as u8).collect();
+        let compressed = gzip::compress_to_vec(&data, Compression::default());
+        for (in_size, out_size) in [(1, 1), (7, 1000), (1000, 3), (100_000, 100_000)] {
+            let mut decompress = Decompress::new(Format::Gzip);
+            let out = decompress_slices(&mut decompress, &compressed, in_size, out_size);
+            assert!(out == data, "sizes {} {}", in_size, out_size);
+            assert_eq!(decompress.total_in(), compressed.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let compressed = [
+            zlib::compress_to_vec(b"zlib data", Compression::fast()),
+            b"garbage".to_vec(),
+        ]
+        .concat();
+        let mut decompress = Decompress::new(Format::Zlib);
+        let mut out = [0; 100];
+        let status = decompress
+            .decompress(&compressed, &mut out, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(decompress.total_in(), compressed.len() as u64 - 7);
+        assert_eq!(out[..decompress.total_out() as usize], *b"zlib data");
+        let status = decompress
+            .decompress(b"more", &mut out, FlushDecompress::None)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+
+        decompress.reset();
+        assert_eq!(
+            decompress
+                .decompress(&[], &mut out, FlushDecompress::None)
+                .unwrap(),
+            Status::NeedsInput
+        );
+        let err = decompress
+            .decompress(&compressed[..5], &mut out, FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::TruncatedStream)
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::io;
+use crate::io::{BufRead, Read};
+
+use crate::rzlibreader::{Format, RZLibReader};
+
+/// Decompresses a raw deflate (RFC 1951) stream read from `R`, with no gzip or zlib wrapper, as
+/// found in ZIP entries or custom protocols.
+///
+/// Decoding stops after the final block. The underlying reader is then left at the byte
+/// boundary following it, and can be taken back with [`DeflateDecoder::into_inner`].
+pub struct DeflateDecoder<R: Read + BufRead> {
+    inner: RZLibReader<R>,
+}
+
+impl<R: Read + BufRead> DeflateDecoder<R> {
+    pub fn new(reader: R) -> DeflateDecoder<R> {
+        DeflateDecoder {
+            inner: RZLibReader::with_format(reader, Format::Deflate),
+        }
+    }
+
+    /// See [`RZLibReader::set_dictionary`].
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        self.inner.set_dictionary(dictionary);
+    }
+
+    /// See [`RZLibReader::set_max_output_bytes`].
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.inner.set_max_output_bytes(limit);
+    }
+
+    /// See [`RZLibReader::total_in`].
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// See [`RZLibReader::total_out`].
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// See [`RZLibReader::get_mut`].
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Returns the underlying reader. Once the stream has been read to the end, it is positioned
+    /// right after the final block.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: Read + BufRead> Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::DeflateDecoder;
+    use crate::deflateencoder::{Compression, DeflateEncoder};
+
+    #[test]
+    fn test_trailing_bytes() {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"raw deflate, raw deflate").unwrap();
+        let input = [encoder.finish().unwrap(), b"trailing".to_vec()].concat();
+
+        let mut decoder = DeflateDecoder::new(&input[..]);
+        let mut out = vec![];
+        decoder.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"raw deflate, raw deflate");
+        assert_eq!(decoder.total_in() as usize, input.len() - 8);
+        assert_eq!(decoder.into_inner(), b"trailing");
+    }
+}
@@ -0,0 +1,876 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::Write;
+
+use crate::bitwriter::BitWriter;
+use crate::deflatetables::{
+    distance_code, fixed_distance_lengths, fixed_litlen_lengths, length_code, CODE_LENGTH_ORDER,
+    DISTANCE_BASE, DISTANCE_EXTRA_BITS, END_OF_BLOCK, LENGTH_BASE, LENGTH_EXTRA_BITS,
+};
+use crate::huffman::{canonical_codes, Codeword, HuffmanTree};
+use crate::lz77::{MatchFinder, Token, WINDOW_SIZE};
+
+// largest amount of data a single stored block can hold
+const MAX_STORED_BLOCK_LEN: usize = 65535;
+// amount of input collected before it is compressed into a block
+const BLOCK_SIZE: usize = MAX_STORED_BLOCK_LEN;
+// span of the rolling sum finding rsyncable boundaries, and their average distance, as in gzip
+const RSYNC_WINDOW: usize = 4096;
+
+/// Compression level, from 0 (no compression) to 9 (best compression).
+///
+/// Level 0 emits stored blocks; higher levels use LZ77 matching and pick, for each block,
+/// whichever of fixed Huffman, dynamic Huffman or stored encoding is smallest. Higher levels
+/// spend more time looking for matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression(u32);
+
+impl Compression {
+    /// Creates a compression level. Panics if `level` is greater than 9.
+    pub const fn new(level: u32) -> Compression {
+        assert!(level <= 9, "compression level must be between 0 and 9");
+        Compression(level)
+    }
+
+    /// No compression: data is split into stored blocks.
+    pub const fn none() -> Compression {
+        Compression(0)
+    }
+
+    pub const fn fast() -> Compression {
+        Compression(1)
+    }
+
+    pub const fn best() -> Compression {
+        Compression(9)
+    }
+
+    pub const fn level(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression(6)
+    }
+}
+
+/// How [`DeflateEncoder::flush_with`] ends the output so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushMode {
+    /// As zlib's `Z_SYNC_FLUSH`: the data written so far is compressed into a block, followed by
+    /// an empty stored block ending at a byte boundary (`00 00 ff ff`), so that a decoder can
+    /// decompress all of it from the output so far.
+    Sync,
+    /// As zlib's `Z_FULL_FLUSH`: a sync flush, after which the following data does not refer back
+    /// to the data so far either, so that a decoder can start over from the flush point.
+    Full,
+}
+
+fn write_code<W: Write>(bits: &mut BitWriter<W>, code: &Codeword) -> io::Result<()> {
+    bits.write_bits(code.code, code.len)
+}
+
+// Writes `data` as a sequence of stored blocks, the last of which has BFINAL set if `is_final`.
+// An empty `data` still produces one (empty) block.
+fn write_stored_blocks<W: Write>(
+    bits: &mut BitWriter<W>,
+    data: &[u8],
+    is_final: bool,
+) -> io::Result<()> {
+    let mut chunks = data.chunks(MAX_STORED_BLOCK_LEN).peekable();
+    if chunks.peek().is_none() {
+        return write_stored_block(bits, &[], is_final);
+    }
+    while let Some(chunk) = chunks.next() {
+        write_stored_block(bits, chunk, is_final && chunks.peek().is_none())?;
+    }
+    Ok(())
+}
+
+fn write_stored_block<W: Write>(
+    bits: &mut BitWriter<W>,
+    data: &[u8],
+    is_final: bool,
+) -> io::Result<()> {
+    let len = data.len() as u16;
+    // BFINAL, BTYPE = 00 and padding to the byte boundary, followed by LEN and NLEN
+    bits.write_bits(is_final as u64, 1)?;
+    bits.write_bits(0, 2)?;
+    bits.align()?;
+    bits.write_u16(len)?;
+    bits.write_u16(!len)?;
+    bits.write_all(data)
+}
+
+// size in bits of `data_len` bytes stored in blocks, when the bit buffer holds `pending_bits`
+fn stored_cost(data_len: usize, pending_bits: usize) -> usize {
+    let blocks = data_len.div_ceil(MAX_STORED_BLOCK_LEN).max(1);
+    // the first block header is padded from the current position, the others take a full byte
+    let first_header = (pending_bits + 3).div_ceil(8) * 8 - pending_bits;
+    first_header + (blocks - 1) * 8 + blocks * 32 + data_len * 8
+}
+
+// Literal/length and distance codes used to encode a Huffman block.
+struct BlockCodes {
+    litlen: Vec<Codeword>,
+    distance: Vec<Codeword>,
+}
+
+impl BlockCodes {
+    fn fixed() -> BlockCodes {
+        BlockCodes {
+            litlen: canonical_codes(&fixed_litlen_lengths()),
+            distance: canonical_codes(&fixed_distance_lengths()),
+        }
+    }
+
+    // size in bits of `tokens` and the end of block code, without the block header
+    fn cost(&self, tokens: &[Token]) -> usize {
+        let mut cost = self.litlen[END_OF_BLOCK].len;
+        for token in tokens {
+            cost += match *token {
+                Token::Literal(b) => self.litlen[b as usize].len,
+                Token::Match { length, distance } => {
+                    let lcode = length_code(length as usize);
+                    let dcode = distance_code(distance as usize);
+                    self.litlen[257 + lcode].len
+                        + LENGTH_EXTRA_BITS[lcode] as usize
+                        + self.distance[dcode].len
+                        + DISTANCE_EXTRA_BITS[dcode] as usize
+                }
+            };
+        }
+        cost
+    }
+
+    fn write_tokens<W: Write>(&self, bits: &mut BitWriter<W>, tokens: &[Token]) -> io::Result<()> {
+        for token in tokens {
+            match *token {
+                Token::Literal(b) => write_code(bits, &self.litlen[b as usize])?,
+                Token::Match { length, distance } => {
+                    let lcode = length_code(length as usize);
+                    write_code(bits, &self.litlen[257 + lcode])?;
+                    bits.write_bits(
+                        (length - LENGTH_BASE[lcode]) as u64,
+                        LENGTH_EXTRA_BITS[lcode] as usize,
+                    )?;
+                    let dcode = distance_code(distance as usize);
+                    write_code(bits, &self.distance[dcode])?;
+                    bits.write_bits(
+                        (distance - DISTANCE_BASE[dcode]) as u64,
+                        DISTANCE_EXTRA_BITS[dcode] as usize,
+                    )?;
+                }
+            }
+        }
+        write_code(bits, &self.litlen[END_OF_BLOCK])
+    }
+}
+
+// Returns code lengths of a Huffman code for symbols with the given frequencies, none of them
+// longer than `max_len`. Unused symbols get length 0, but at least two symbols are always
+// assigned a code so that the resulting code is complete.
+fn huffman_lengths(freqs: &[u32], max_len: usize) -> Vec<usize> {
+    let used = freqs.iter().filter(|&&f| f > 0).count();
+    if used < 2 {
+        let mut lengths = vec![0; freqs.len()];
+        let first = freqs.iter().position(|&f| f > 0).unwrap_or(0);
+        lengths[first] = 1;
+        lengths[if first == 0 { 1 } else { 0 }] = 1;
+        return lengths;
+    }
+
+    let freqs: Vec<u64> = freqs.iter().map(|&f| f as u64).collect();
+    HuffmanTree::lengths_from_frequencies(&freqs, max_len)
+}
+
+// Run-length encodes code lengths with the code length alphabet of RFC 3.2.7, returning
+// (symbol, extra bits value) pairs.
+fn encode_code_lengths(lengths: &[usize]) -> Vec<(usize, u64)> {
+    let mut symbols = vec![];
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+        let mut remaining = run;
+        if length == 0 {
+            while remaining >= 11 {
+                let count = remaining.min(138);
+                symbols.push((18, (count - 11) as u64));
+                remaining -= count;
+            }
+            if remaining >= 3 {
+                symbols.push((17, (remaining - 3) as u64));
+                remaining = 0;
+            }
+        } else {
+            symbols.push((length, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let count = remaining.min(6);
+                symbols.push((16, (count - 3) as u64));
+                remaining -= count;
+            }
+        }
+        symbols.extend((0..remaining).map(|_| (length, 0)));
+        i += run;
+    }
+    symbols
+}
+
+fn code_length_extra_bits(symbol: usize) -> usize {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+// Codes of a dynamic Huffman block, along with their encoded description for the block header.
+struct DynamicCodes {
+    codes: BlockCodes,
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+    clen_lengths: Vec<usize>,
+    clen_codes: Vec<Codeword>,
+    clen_symbols: Vec<(usize, u64)>,
+}
+
+impl DynamicCodes {
+    fn new(tokens: &[Token]) -> DynamicCodes {
+        let mut litlen_freqs = [0_u32; 286];
+        let mut distance_freqs = [0_u32; 30];
+        for token in tokens {
+            match *token {
+                Token::Literal(b) => litlen_freqs[b as usize] += 1,
+                Token::Match { length, distance } => {
+                    litlen_freqs[257 + length_code(length as usize)] += 1;
+                    distance_freqs[distance_code(distance as usize)] += 1;
+                }
+            }
+        }
+        litlen_freqs[END_OF_BLOCK] = 1;
+
+        let litlen_lengths = huffman_lengths(&litlen_freqs, 15);
+        let distance_lengths = huffman_lengths(&distance_freqs, 15);
+        let hlit = 257.max(litlen_lengths.iter().rposition(|&l| l != 0).unwrap() + 1);
+        let hdist = 1.max(distance_lengths.iter().rposition(|&l| l != 0).unwrap() + 1);
+
+        let all_lengths = [&litlen_lengths[..hlit], &distance_lengths[..hdist]].concat();
+        let clen_symbols = encode_code_lengths(&all_lengths);
+        let mut clen_freqs = [0_u32; 19];
+        for &(symbol, _) in clen_symbols.iter() {
+            clen_freqs[symbol] += 1;
+        }
+        let clen_lengths = huffman_lengths(&clen_freqs, 7);
+        let hclen = 4.max(
+            CODE_LENGTH_ORDER
+                .iter()
+                .rposition(|&i| clen_lengths[i] != 0)
+                .unwrap()
+                + 1,
+        );
+
+        DynamicCodes {
+            codes: BlockCodes {
+                litlen: canonical_codes(&litlen_lengths),
+                distance: canonical_codes(&distance_lengths),
+            },
+            hlit,
+            hdist,
+            hclen,
+            clen_codes: canonical_codes(&clen_lengths),
+            clen_lengths,
+            clen_symbols,
+        }
+    }
+
+    // size in bits of the block header after BFINAL and BTYPE
+    fn header_cost(&self) -> usize {
+        let symbols_cost: usize = self
+            .clen_symbols
+            .iter()
+            .map(|&(symbol, _)| self.clen_lengths[symbol] + code_length_extra_bits(symbol))
+            .sum();
+        5 + 5 + 4 + 3 * self.hclen + symbols_cost
+    }
+
+    fn write_header<W: Write>(&self, bits: &mut BitWriter<W>) -> io::Result<()> {
+        bits.write_bits((self.hlit - 257) as u64, 5)?;
+        bits.write_bits((self.hdist - 1) as u64, 5)?;
+        bits.write_bits((self.hclen - 4) as u64, 4)?;
+        for &i in CODE_LENGTH_ORDER.iter().take(self.hclen) {
+            bits.write_bits(self.clen_lengths[i] as u64, 3)?;
+        }
+        for &(symbol, extra) in self.clen_symbols.iter() {
+            write_code(bits, &self.clen_codes[symbol])?;
+            bits.write_bits(extra, code_length_extra_bits(symbol))?;
+        }
+        Ok(())
+    }
+}
+
+// (max_chain, nice_length, max_lazy) of the match finder for compression levels 1..=9
+const MATCH_PARAMS: [(usize, usize, usize); 9] = [
+    (4, 8, 4),
+    (8, 16, 5),
+    (32, 32, 6),
+    (16, 16, 4),
+    (32, 32, 16),
+    (128, 128, 16),
+    (256, 128, 32),
+    (1024, 258, 128),
+    (4096, 258, 258),
+];
+// lowest level using lazy matching by default
+const LAZY_MATCHING_LEVEL: u32 = 4;
+
+/// Compresses data written to it into a raw deflate stream written to `W`.
+///
+/// The stream is completed by [`DeflateEncoder::finish`], or on drop (ignoring errors) if
+/// `finish` was never called.
+///
+/// `flush` is a sync flush, see [`FlushMode::Sync`], and [`DeflateEncoder::flush_with`] can do a
+/// full flush as well. Each flush costs a few bytes and ends the current block early.
+pub struct DeflateEncoder<W: Write> {
+    writer: Option<BitWriter<W>>,
+    level: Compression,
+    finished: bool,
+    // nothing was written since the last sync point
+    synced: bool,
+    // the last WINDOW_SIZE bytes already compressed, followed by data not yet emitted as a block
+    data: Vec<u8>,
+    history_len: usize,
+    match_finder: MatchFinder,
+    tokens: Vec<Token>,
+    fixed_codes: BlockCodes,
+    rsyncable: bool,
+    // sum of the last `rsync_len` bytes, up to RSYNC_WINDOW, since the last rsyncable boundary
+    rsync_sum: u32,
+    rsync_len: usize,
+    // the pending data ends at an rsyncable boundary
+    at_rsync_boundary: bool,
+}
+
+impl<W: Write> DeflateEncoder<W> {
+    pub fn new(writer: W, level: Compression) -> DeflateEncoder<W> {
+        let (max_chain, nice_length, max_lazy) = match level.level() {
+            0 => (0, 0, 0),
+            l => MATCH_PARAMS[l as usize - 1],
+        };
+        let mut match_finder = MatchFinder::new(max_chain, nice_length, max_lazy);
+        match_finder.set_lazy(level.level() >= LAZY_MATCHING_LEVEL);
+        DeflateEncoder {
+            writer: Some(BitWriter::new(writer)),
+            level,
+            finished: false,
+            synced: false,
+            data: Vec::with_capacity(WINDOW_SIZE + BLOCK_SIZE),
+            history_len: 0,
+            match_finder,
+            tokens: vec![],
+            fixed_codes: BlockCodes::fixed(),
+            rsyncable: false,
+            rsync_sum: 0,
+            rsync_len: 0,
+            at_rsync_boundary: false,
+        }
+    }
+
+    pub fn level(&self) -> Compression {
+        self.level
+    }
+
+    /// Sets whether a match is deferred when the match starting at the next byte is longer.
+    /// This trades speed for a better ratio, and is enabled by default from level 4 on.
+    pub fn set_lazy_matching(&mut self, lazy: bool) {
+        self.match_finder.set_lazy(lazy);
+    }
+
+    /// Sets whether the compressed stream is made friendly to rsync, as with gzip's
+    /// `--rsyncable`: at boundaries depending only on the last 4 KiB of data, every 4 KiB on
+    /// average, the stream is synced to a byte boundary and matches stop reaching back across
+    /// them. A local change to the data then only changes the output up to the next boundary, at
+    /// some cost in ratio. Disabled by default.
+    pub fn set_rsyncable(&mut self, rsyncable: bool) {
+        self.rsyncable = rsyncable;
+    }
+
+    // Lets the matches of the first blocks reach back into `dictionary`, which the decoder must
+    // have as well, as the data preceding the stream. Only before anything is written.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_dictionary(&mut self, dictionary: &[u8]) {
+        debug_assert!(self.data.is_empty());
+        let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+        self.data.extend_from_slice(dictionary);
+        self.history_len = self.data.len();
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().unwrap().get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().unwrap().get_mut()
+    }
+
+    pub(crate) fn take_writer(&mut self) -> io::Result<W> {
+        self.writer.take().unwrap().finish()
+    }
+
+    // Compresses the pending data into a block.
+    fn write_block(&mut self, is_final: bool) -> io::Result<()> {
+        let bits = self.writer.as_mut().unwrap();
+        let block = &self.data[self.history_len..];
+        if self.level.level() == 0 {
+            write_stored_blocks(bits, block, is_final)?;
+        } else {
+            self.tokens.clear();
+            self.match_finder.tokenize(&self.data, self.history_len, &mut self.tokens);
+            let dynamic_codes = DynamicCodes::new(&self.tokens);
+            let dynamic_cost =
+                3 + dynamic_codes.header_cost() + dynamic_codes.codes.cost(&self.tokens);
+            let fixed_cost = 3 + self.fixed_codes.cost(&self.tokens);
+            if stored_cost(block.len(), bits.pending_bits()) < fixed_cost.min(dynamic_cost) {
+                write_stored_blocks(bits, block, is_final)?;
+            } else if fixed_cost <= dynamic_cost {
+                bits.write_bits(is_final as u64, 1)?;
+                bits.write_bits(1, 2)?;
+                self.fixed_codes.write_tokens(bits, &self.tokens)?;
+            } else {
+                bits.write_bits(is_final as u64, 1)?;
+                bits.write_bits(2, 2)?;
+                dynamic_codes.write_header(bits)?;
+                dynamic_codes.codes.write_tokens(bits, &self.tokens)?;
+            }
+        }
+        if is_final {
+            // the container trailer, if any, follows the stream in the underlying writer
+            bits.align()?;
+            bits.flush()?;
+        }
+
+        // keep the last WINDOW_SIZE bytes around for matches in the following blocks
+        if self.data.len() > WINDOW_SIZE {
+            self.data.drain(..self.data.len() - WINDOW_SIZE);
+        }
+        self.history_len = self.data.len();
+        Ok(())
+    }
+
+    // Writes the final block, if it was not written yet.
+    pub(crate) fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_block(true)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    // Ends the output so far at a byte boundary where a decoder has all the data written so far:
+    // the pending data as a block, then an empty stored block (00 00 ff ff once aligned). With
+    // `reset`, the following blocks do not refer back to the data so far either.
+    fn sync(&mut self, reset: bool) -> io::Result<()> {
+        if !self.synced {
+            if self.history_len < self.data.len() {
+                self.write_block(false)?;
+            }
+            write_stored_block(self.writer.as_mut().unwrap(), &[], false)?;
+            self.synced = true;
+        }
+        if reset {
+            self.data.clear();
+            self.history_len = 0;
+            self.rsync_sum = 0;
+            self.rsync_len = 0;
+        }
+        Ok(())
+    }
+
+    // Feeds the bytes of `data` from `start` on to the rolling sum, and returns the end of the
+    // first rsyncable boundary among them, if any. The sum starts over once the stream is reset
+    // there.
+    fn find_rsync_boundary(&mut self, start: usize) -> Option<usize> {
+        for pos in start..self.data.len() {
+            self.rsync_sum = self.rsync_sum.wrapping_add(self.data[pos] as u32);
+            if self.rsync_len < RSYNC_WINDOW {
+                self.rsync_len += 1;
+                continue;
+            }
+            // the bytes since the last boundary are all still in `data`
+            self.rsync_sum = self.rsync_sum.wrapping_sub(self.data[pos - RSYNC_WINDOW] as u32);
+            if self.rsync_sum.is_multiple_of(RSYNC_WINDOW as u32) {
+                return Some(pos + 1);
+            }
+        }
+        None
+    }
+
+    /// Flushes the data written so far as `mode` tells, then flushes the underlying writer. A
+    /// sync flush with nothing written since the last one only does the latter. Has no effect
+    /// on the deflate stream once it is finished.
+    pub fn flush_with(&mut self, mode: FlushMode) -> io::Result<()> {
+        if !self.finished {
+            self.sync(mode == FlushMode::Full || self.at_rsync_boundary)?;
+            self.at_rsync_boundary = false;
+        }
+        self.writer.as_mut().unwrap().flush()
+    }
+
+    /// Writes the final block and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        self.take_writer()
+    }
+}
+
+impl<W: Write> Write for DeflateEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished, "writing to a finished encoder");
+        if self.at_rsync_boundary {
+            self.sync(true)?;
+            self.at_rsync_boundary = false;
+        } else if self.data.len() - self.history_len == BLOCK_SIZE {
+            self.write_block(false)?;
+        }
+        let mut len = buf.len().min(BLOCK_SIZE - (self.data.len() - self.history_len));
+        let start = self.data.len();
+        self.data.extend_from_slice(&buf[..len]);
+        if self.rsyncable {
+            if let Some(end) = self.find_rsync_boundary(start) {
+                self.data.truncate(end);
+                len = end - start;
+                self.at_rsync_boundary = true;
+            }
+        }
+        self.synced &= len == 0;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_with(FlushMode::Sync)
+    }
+}
+
+impl<W: Write> Drop for DeflateEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::{encode_code_lengths, huffman_lengths, Compression, DeflateEncoder, FlushMode};
+    use crate::rzlibreader::{Format, RZLibReader};
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        compress_with(data, Compression::none())
+    }
+
+    fn compress_with(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decompress(compressed: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        RZLibReader::with_format(compressed, Format::Deflate)
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    // deterministic pseudo-random bytes (xorshift)
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn text(len: usize) -> Vec<u8> {
+        let words = ["deflate ", "stream ", "huffman ", "window ", "block ", "rzlib "];
+        let noise = noise(len, 7);
+        let mut out = vec![];
+        let mut i = 0;
+        while out.len() < len {
+            out.extend_from_slice(words[noise[i] as usize % words.len()].as_bytes());
+            i += 1;
+        }
+        out.truncate(len);
+        out
+    }
+
+    // returns (BFINAL, LEN) of each stored block in `compressed`
+    fn stored_blocks(compressed: &[u8]) -> Vec<(bool, usize)> {
+        let mut blocks = vec![];
+        let mut pos = 0;
+        while pos < compressed.len() {
+            let header = &compressed[pos..pos + 5];
+            assert_eq!(header[0] & !1, 0);
+            let len = u16::from_le_bytes([header[1], header[2]]);
+            let nlen = u16::from_le_bytes([header[3], header[4]]);
+            assert_eq!(len, !nlen);
+            blocks.push((header[0] == 1, len as usize));
+            pos += 5 + len as usize;
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_stored_block_framing() {
+        assert_eq!(stored_blocks(&compress(b"")), vec![(true, 0)]);
+        assert_eq!(stored_blocks(&compress(&[7; 65535])), vec![(true, 65535)]);
+        assert_eq!(
+            stored_blocks(&compress(&[7; 65536])),
+            vec![(false, 65535), (true, 1)]
+        );
+        assert_eq!(
+            stored_blocks(&compress(&[7; 3 * 65535])),
+            vec![(false, 65535), (false, 65535), (true, 65535)]
+        );
+    }
+
+    #[test]
+    fn test_stored_round_trip() {
+        for len in [0, 1, 65535, 65536, 200_000] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 31 % 256) as u8).collect();
+            assert_eq!(decompress(&compress(&data)), data);
+        }
+    }
+
+    #[test]
+    fn test_fixed_huffman_block() {
+        let data = b"abcabcabcabcabcabcabcabc";
+        let compressed = compress_with(data, Compression::default());
+        // BFINAL = 1, BTYPE = 01
+        assert_eq!(compressed[0] & 0b111, 0b011);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed), data);
+
+        // an empty stream is a lone end of block code
+        assert_eq!(compress_with(b"", Compression::default()), [0x03, 0x00]);
+    }
+
+    #[test]
+    fn test_dynamic_huffman_block() {
+        let data = text(20_000);
+        let compressed = compress_with(&data, Compression::default());
+        // BFINAL = 1, BTYPE = 10
+        assert_eq!(compressed[0] & 0b111, 0b101);
+        assert_eq!(decompress(&compressed), data);
+    }
+
+    fn kraft_sum(lengths: &[usize]) -> f64 {
+        lengths.iter().filter(|&&l| l > 0).map(|&l| 0.5_f64.powi(l as i32)).sum()
+    }
+
+    #[test]
+    fn test_huffman_lengths() {
+        assert_eq!(huffman_lengths(&[1, 1, 2, 4], 15), vec![3, 3, 2, 1]);
+        assert_eq!(huffman_lengths(&[0, 0, 5, 0], 15), vec![1, 0, 1, 0]);
+        assert_eq!(huffman_lengths(&[0, 0, 0], 15), vec![1, 1, 0]);
+
+        // Fibonacci frequencies give the deepest possible tree
+        let mut freqs = vec![1_u32, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+        assert_eq!(*huffman_lengths(&freqs, 100).iter().max().unwrap(), 29);
+        let limited = huffman_lengths(&freqs, 15);
+        assert!(limited.iter().all(|&l| (1..=15).contains(&l)));
+        assert_eq!(kraft_sum(&limited), 1.0);
+    }
+
+    #[test]
+    fn test_encode_code_lengths() {
+        let mut lengths = vec![8; 10];
+        lengths.extend([0; 150]);
+        lengths.extend([5, 5, 0, 0]);
+        assert_eq!(
+            encode_code_lengths(&lengths),
+            vec![
+                (8, 0),
+                (16, 3),
+                (16, 0),
+                (18, 127),
+                (18, 1),
+                (5, 0),
+                (5, 0),
+                (0, 0),
+                (0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incompressible_data_is_stored() {
+        let data = noise(1000, 1);
+        let compressed = compress_with(&data, Compression::best());
+        assert_eq!(compressed[0] & 0b111, 0b001);
+        assert_eq!(decompress(&compressed), data);
+    }
+
+    #[test]
+    fn test_levels_round_trip() {
+        let inputs = [
+            text(300_000),
+            noise(100_000, 3),
+            vec![0; 150_000],
+            [text(50_000), noise(50_000, 5), text(50_000)].concat(),
+        ];
+        for level in 1..=9 {
+            for data in inputs.iter() {
+                let compressed = compress_with(data, Compression::new(level));
+                assert_eq!(&decompress(&compressed), data, "level {}", level);
+            }
+        }
+        let text = &inputs[0];
+        assert!(compress_with(text, Compression::best()).len() < text.len() / 2);
+    }
+
+    #[test]
+    fn test_lazy_matching_option() {
+        let data = text(100_000);
+        let compress_lazy = |lazy| {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::new(6));
+            encoder.set_lazy_matching(lazy);
+            encoder.write_all(&data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let greedy = compress_lazy(false);
+        let lazy = compress_lazy(true);
+        assert_eq!(decompress(&greedy), data);
+        assert_eq!(decompress(&lazy), data);
+        assert!(lazy.len() < greedy.len());
+    }
+
+    #[test]
+    fn test_sync_flush() {
+        let data = text(100_000);
+        for level in [0, 1, 6] {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::new(level));
+            encoder.write_all(&data[..60_000]).unwrap();
+            encoder.flush().unwrap();
+            assert!(encoder.get_ref().ends_with(&[0, 0, 0xff, 0xff]), "level {}", level);
+            // nothing new to flush
+            let len = encoder.get_ref().len();
+            encoder.flush().unwrap();
+            assert_eq!(encoder.get_ref().len(), len);
+            encoder.write_all(&data[60_000..]).unwrap();
+            let compressed = encoder.finish().unwrap();
+            assert!(decompress(&compressed) == data, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn test_full_flush() {
+        let data = text(100_000);
+        for mode in [FlushMode::Sync, FlushMode::Full] {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(&data[..60_000]).unwrap();
+            encoder.flush_with(mode).unwrap();
+            assert!(encoder.get_ref().ends_with(&[0, 0, 0xff, 0xff]));
+            let flush_point = encoder.get_ref().len();
+            encoder.write_all(&data[60_000..]).unwrap();
+            let compressed = encoder.finish().unwrap();
+            assert!(decompress(&compressed) == data);
+
+            // only after a full flush does the rest decode on its own
+            let mut rest = vec![];
+            let result = RZLibReader::with_format(&compressed[flush_point..], Format::Deflate)
+                .read_to_end(&mut rest);
+            assert_eq!(result.is_ok(), mode == FlushMode::Full);
+            if mode == FlushMode::Full {
+                assert!(rest == data[60_000..]);
+            }
+        }
+
+        // a full flush right after a sync flush only resets the window
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data[..1000]).unwrap();
+        encoder.flush().unwrap();
+        let len = encoder.get_ref().len();
+        encoder.flush_with(FlushMode::Full).unwrap();
+        assert_eq!(encoder.get_ref().len(), len);
+        encoder.write_all(&data[..1000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut rest = vec![];
+        RZLibReader::with_format(&compressed[len..], Format::Deflate)
+            .read_to_end(&mut rest)
+            .unwrap();
+        assert!(rest == data[..1000]);
+    }
+
+    // compressed length of the prefix and suffix `a` and `b` have in common
+    fn common_len(a: &[u8], b: &[u8]) -> usize {
+        let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+        let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+        prefix + suffix
+    }
+
+    // text made of a vocabulary of pseudo-random words, varied enough for rsyncable boundaries
+    fn varied_text(len: usize) -> Vec<u8> {
+        let noise = noise(2 * len, 11);
+        let vocabulary: Vec<&[u8]> = noise.chunks(10).take(200).collect();
+        let mut out = vec![];
+        for pair in noise[2000..].chunks(2) {
+            let word = vocabulary[(pair[0] as usize * 256 + pair[1] as usize) % 200];
+            let word_len = 2 + word[0] as usize % 8;
+            out.extend(word[1..word_len].iter().map(|&b| b'a' + b % 26));
+            out.push(b' ');
+            if out.len() >= len {
+                break;
+            }
+        }
+        out.truncate(len);
+        out
+    }
+
+    #[test]
+    fn test_rsyncable() {
+        let data = varied_text(1_000_000);
+        let mut edited = data.clone();
+        edited[500_000..500_005].copy_from_slice(b"EDIT!");
+        let compress_rsyncable = |data: &[u8], rsyncable: bool| {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.set_rsyncable(rsyncable);
+            // in uneven writes, which must not change where the boundaries are
+            for piece in data.chunks(10_007) {
+                encoder.write_all(piece).unwrap();
+            }
+            encoder.finish().unwrap()
+        };
+
+        let (before, after) = (compress_rsyncable(&data, true), compress_rsyncable(&edited, true));
+        assert_eq!(decompress(&before), data);
+        assert_eq!(decompress(&after), edited);
+        assert!(
+            common_len(&before, &after) > before.len() - 10_000,
+            "{} of {} bytes in common",
+            common_len(&before, &after),
+            before.len()
+        );
+        let plain = compress_rsyncable(&data, false);
+        // blocks are shorter, and start without a window
+        assert!(before.len() < plain.len() * 5 / 4);
+        let plain_after = compress_rsyncable(&edited, false);
+        assert!(common_len(&plain, &plain_after) < plain.len() * 3 / 4);
+    }
+}
@@ -0,0 +1,90 @@
+// Constants of the deflate format (RFC 1951), shared by the decoder and the encoder.
+
+// size base for length codes 257..285
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+// extra bits for length codes 257..285
+pub(crate) const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// DEFLATE64 (ZIP method 9) gives length code 285 16 extra bits on top of a base of 3, rather
+// than standing for 258
+pub(crate) const DEFLATE64_LENGTH_285_BASE: u16 = 3;
+pub(crate) const DEFLATE64_LENGTH_285_EXTRA_BITS: u8 = 16;
+
+// offset base for distance codes 0..31; codes 30 and 31 only occur in DEFLATE64
+pub(crate) const DISTANCE_BASE: [u16; 32] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577, 32769, 49153,
+];
+// extra bits for distance codes 0..31
+pub(crate) const DISTANCE_EXTRA_BITS: [u8; 32] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13, 14, 14,
+];
+// number of distance codes of deflate, the first 30 of the above
+pub(crate) const DISTANCE_CODES: usize = 30;
+
+// order in which code length code lengths are stored, see RFC 3.2.7
+pub(crate) const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub(crate) const END_OF_BLOCK: usize = 256;
+
+// code lengths of the fixed literal/length code, see RFC 3.2.6
+pub(crate) const fn fixed_litlen_lengths() -> [usize; 288] {
+    let mut lengths = [8; 288];
+    let mut symbol = 144;
+    while symbol < 280 {
+        lengths[symbol] = if symbol < 256 { 9 } else { 7 };
+        symbol += 1;
+    }
+    lengths
+}
+
+// code lengths of the fixed distance code; codes 30 and 31 never occur in valid data
+pub(crate) const fn fixed_distance_lengths() -> [usize; 30] {
+    [5; 30]
+}
+
+// code lengths of the fixed distance code of DEFLATE64, where codes 30 and 31 are valid
+pub(crate) const fn fixed_deflate64_distance_lengths() -> [usize; 32] {
+    [5; 32]
+}
+
+// returns the length code (0-based, i.e. litlen symbol - 257) covering match `length`
+pub(crate) fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+// returns the distance code covering match `distance`
+pub(crate) fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_code, length_code};
+
+    #[test]
+    fn test_length_and_distance_codes() {
+        assert_eq!(length_code(3), 0);
+        assert_eq!(length_code(10), 7);
+        assert_eq!(length_code(11), 8);
+        assert_eq!(length_code(12), 8);
+        assert_eq!(length_code(257), 27);
+        assert_eq!(length_code(258), 28);
+
+        assert_eq!(distance_code(1), 0);
+        assert_eq!(distance_code(4), 3);
+        assert_eq!(distance_code(6), 4);
+        assert_eq!(distance_code(24577), 29);
+        assert_eq!(distance_code(32768), 29);
+        assert_eq!(distance_code(32769), 30);
+        assert_eq!(distance_code(65536), 31);
+    }
+}
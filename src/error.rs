@@ -0,0 +1,231 @@
+use core::error::Error;
+use core::fmt;
+
+use crate::io;
+
+/// Ways in which a compressed stream can be malformed.
+///
+/// The decoder reports these through `io::Error`s (of kind `InvalidData`, or `UnexpectedEof`
+/// for [`RzlibError::TruncatedStream`]) wrapping a [`DecodeError`] which holds the
+/// `RzlibError`. It can be recovered with [`RzlibError::from_io_error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RzlibError {
+    /// The gzip member does not start with the 0x1f 0x8b magic bytes.
+    BadMagic { id1: u8, id2: u8 },
+    /// The header declares a compression method other than deflate (8).
+    BadCompressionMethod(u8),
+    /// The zlib header declares a window larger than 32 KiB, or than the decoder was set up with.
+    BadWindowSize(u8),
+    /// The zlib header check bits (FCHECK) are wrong.
+    BadHeaderCheck { cmf: u8, flg: u8 },
+    /// The zlib stream was compressed with a preset dictionary, identified by its Adler-32, but
+    /// none was set.
+    PresetDictionaryRequired(u32),
+    /// The zlib stream was compressed with a different preset dictionary than the one set.
+    DictionaryMismatch { expected: u32, actual: u32 },
+    /// An optional gzip header field is malformed.
+    BadHeaderField(&'static str),
+    /// The block header has the reserved block type 3.
+    BadBlockType(u8),
+    /// The LEN and NLEN fields of a stored block do not match.
+    StoredLengthMismatch { len: u16, nlen: u16 },
+    /// The code lengths of a dynamic block do not describe valid Huffman codes.
+    InvalidCodeLengths,
+    /// The compressed data contains a code that is not part of the block's Huffman codes.
+    InvalidCode,
+    /// A match refers further back than the start of the data decoded so far.
+    InvalidDistance(usize),
+    /// The checksum in the trailer (CRC-32 for gzip, Adler-32 for zlib) does not match the data.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The ISIZE field of the gzip trailer does not match the size of the data (modulo 2^32).
+    SizeMismatch { expected: u32, actual: u32 },
+    /// The input ended in the middle of the stream.
+    TruncatedStream,
+    /// The decompressed data is larger than the limit set with
+    /// [`RZLibReader::set_max_output_bytes`](crate::RZLibReader::set_max_output_bytes).
+    OutputLimitExceeded(u64),
+}
+
+impl RzlibError {
+    /// Returns the `RzlibError` wrapped by `err`, directly or through a [`DecodeError`], if it
+    /// is one.
+    pub fn from_io_error(err: &io::Error) -> Option<&RzlibError> {
+        let inner = err.get_ref()?;
+        match inner.downcast_ref::<DecodeError>() {
+            Some(decode_error) => decode_error.rzlib_error(),
+            None => inner.downcast_ref(),
+        }
+    }
+}
+
+impl fmt::Display for RzlibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RzlibError::BadMagic { id1, id2 } => {
+                write!(f, "wrong id1, id2 (0x{:x}, 0x{:x})", id1, id2)
+            }
+            RzlibError::BadCompressionMethod(cm) => write!(f, "wrong cm (0x{:x})", cm),
+            RzlibError::BadWindowSize(cinfo) => write!(f, "wrong cinfo ({})", cinfo),
+            RzlibError::BadHeaderCheck { cmf, flg } => {
+                write!(f, "wrong fcheck (cmf 0x{:x}, flg 0x{:x})", cmf, flg)
+            }
+            RzlibError::PresetDictionaryRequired(id) => {
+                write!(f, "stream requires preset dictionary 0x{:08x}", id)
+            }
+            RzlibError::DictionaryMismatch { expected, actual } => write!(
+                f,
+                "stream requires preset dictionary 0x{:08x}, got 0x{:08x}",
+                expected, actual
+            ),
+            RzlibError::BadHeaderField(field) => write!(f, "malformed header field: {}", field),
+            RzlibError::BadBlockType(btype) => write!(f, "unknown btype: {}", btype),
+            RzlibError::StoredLengthMismatch { len, nlen } => {
+                write!(f, "len ({}) is not one-complement of nlen ({})", len, nlen)
+            }
+            RzlibError::InvalidCodeLengths => write!(f, "invalid code lengths"),
+            RzlibError::InvalidCode => write!(f, "invalid huffman code"),
+            RzlibError::InvalidDistance(distance) => {
+                write!(f, "match distance {} exceeds the decoded data", distance)
+            }
+            RzlibError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: trailer has 0x{:08x}, data has 0x{:08x}",
+                expected, actual
+            ),
+            RzlibError::SizeMismatch { expected, actual } => write!(
+                f,
+                "isize mismatch: trailer has {}, data has {} (mod 2^32)",
+                expected, actual
+            ),
+            RzlibError::TruncatedStream => write!(f, "unexpected end of compressed stream"),
+            RzlibError::OutputLimitExceeded(limit) => {
+                write!(f, "decompressed data exceeds the limit of {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl Error for RzlibError {}
+
+/// Where in the compressed stream the decoder was when it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamPosition {
+    /// Offset in the compressed input of the byte holding the next unread bit.
+    pub byte: u64,
+    /// Number of bits of that byte already read, from 0 to 7.
+    pub bit: u8,
+    /// Index of the member (gzip) or stream being decoded, starting at 1.
+    pub member: u64,
+    /// Index of the deflate block being decoded within the member, starting at 1, or 0 before
+    /// the first block header.
+    pub block: u64,
+}
+
+impl fmt::Display for StreamPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at byte {}, bit {}, member {}, block {}",
+            self.byte, self.bit, self.member, self.block
+        )
+    }
+}
+
+/// An error returned by [`RZLibReader`](crate::RZLibReader), with the position in the stream
+/// where it happened.
+///
+/// Every error from the decoder's `Read` impl wraps one of these, keeping the kind of the
+/// original error; use [`DecodeError::from_io_error`] to get it back.
+#[derive(Debug)]
+pub struct DecodeError {
+    error: io::Error,
+    position: StreamPosition,
+}
+
+impl DecodeError {
+    pub(crate) fn new(error: io::Error, position: StreamPosition) -> DecodeError {
+        DecodeError { error, position }
+    }
+
+    /// Returns the `DecodeError` wrapped by `err`, if it is one.
+    pub fn from_io_error(err: &io::Error) -> Option<&DecodeError> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    pub fn position(&self) -> StreamPosition {
+        self.position
+    }
+
+    /// The underlying error, as it was before the position was attached.
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// The malformation found in the stream, if that is what failed.
+    pub fn rzlib_error(&self) -> Option<&RzlibError> {
+        self.error.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.error, self.position)
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> io::Error {
+        io::Error::new(err.error.kind(), err)
+    }
+}
+
+impl From<RzlibError> for io::Error {
+    fn from(err: RzlibError) -> io::Error {
+        let kind = match err {
+            RzlibError::TruncatedStream => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{DecodeError, RzlibError, StreamPosition};
+
+    #[test]
+    fn test_io_error_round_trip() {
+        let err: io::Error = RzlibError::BadBlockType(3).into();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unknown btype: 3");
+        assert_eq!(RzlibError::from_io_error(&err), Some(&RzlibError::BadBlockType(3)));
+
+        let err: io::Error = RzlibError::TruncatedStream.into();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = io::Error::other("something else");
+        assert_eq!(RzlibError::from_io_error(&err), None);
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let position = StreamPosition { byte: 12, bit: 3, member: 2, block: 1 };
+        let err: io::Error = DecodeError::new(RzlibError::InvalidCode.into(), position).into();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "invalid huffman code at byte 12, bit 3, member 2, block 1"
+        );
+        assert_eq!(RzlibError::from_io_error(&err), Some(&RzlibError::InvalidCode));
+        assert_eq!(DecodeError::from_io_error(&err).unwrap().position(), position);
+    }
+}
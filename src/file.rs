@@ -0,0 +1,258 @@
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{io, process};
+
+use crate::deflateencoder::Compression;
+use crate::gzencoder::GzEncoder;
+use crate::rzlibreader::RZLibReader;
+
+const BUFFER_SIZE: usize = 128 * 1024;
+
+/// Decompresses the gzip file at `input` into `output`, returning the number of bytes written.
+///
+/// The output is written to a temporary file next to `output` and renamed over it only once
+/// decoding has finished, so `output` is never left half-written. The data and the rename are
+/// synced to disk before returning, so `input` can be removed afterwards without risk.
+/// Permissions and timestamps of `input` are copied over to `output`.
+pub fn decompress_file<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<u64> {
+    decompress_file_with_progress(input, output, |_, _| {})
+}
+
+/// Like [`decompress_file`], calling `progress` with the number of compressed bytes consumed and
+/// decompressed bytes written so far after each chunk of output.
+pub fn decompress_file_with_progress<P, Q, F>(input: P, output: Q, mut progress: F) -> io::Result<u64>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let input = File::open(input)?;
+    let metadata = input.metadata()?;
+    let mut reader = RZLibReader::new(BufReader::with_capacity(BUFFER_SIZE, input));
+
+    write_atomically(output.as_ref(), &metadata, |writer| {
+        copy_with_progress(&mut reader, writer, |reader, written| {
+            progress(reader.total_in(), written)
+        })
+    })
+}
+
+/// Copies `reader` to the end into `writer`, like [`io::copy`], calling `progress` with the
+/// reader and the number of bytes written so far after each chunk. Returns that number.
+pub fn copy_with_progress<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    mut progress: F,
+) -> io::Result<u64>
+where
+    R: Read,
+    W: Write + ?Sized,
+    F: FnMut(&R, u64),
+{
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut written = 0;
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => return Ok(written),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..read])?;
+        written += read as u64;
+        progress(reader, written);
+    }
+}
+
+/// Compresses the file at `input` into the gzip file `output`, returning the number of bytes
+/// written.
+///
+/// Like [`decompress_file`], `output` is replaced atomically and gets the permissions and
+/// timestamps of `input`.
+pub fn compress_file<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<u64> {
+    compress_file_with(input, output, |writer| GzEncoder::new(writer, Compression::default()))
+}
+
+/// Like [`compress_file`], with the encoder `new_encoder` creates over the output, to choose the
+/// compression level and the other settings of the encoder.
+pub fn compress_file_with<P, Q, F>(input: P, output: Q, new_encoder: F) -> io::Result<u64>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnOnce(&mut dyn Write) -> GzEncoder<&mut dyn Write>,
+{
+    let input = File::open(input)?;
+    let metadata = input.metadata()?;
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, input);
+
+    write_atomically(output.as_ref(), &metadata, |writer| {
+        let mut counter = CountingWriter { writer, count: 0 };
+        let mut encoder = new_encoder(&mut counter);
+        io::copy(&mut reader, &mut encoder)?;
+        encoder.finish()?;
+        Ok(counter.count)
+    })
+}
+
+struct CountingWriter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn temporary_path(output: &Path) -> io::Result<PathBuf> {
+    let file_name = output.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file path", output.display()))
+    })?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.rzlib-tmp", process::id()));
+    Ok(output.with_file_name(temp_name))
+}
+
+// Makes a rename into the directory of `path` durable, so that callers can safely remove the
+// input once the output has been written. Directories cannot be opened as files on Windows.
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Runs `write` against a temporary file and atomically moves it to `output` afterwards,
+// propagating permissions and timestamps from `metadata`. The temporary file is removed
+// if anything fails along the way.
+fn write_atomically<F>(output: &Path, metadata: &Metadata, write: F) -> io::Result<u64>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> io::Result<u64>,
+{
+    let temp_path = temporary_path(output)?;
+    let temp_file = File::options().write(true).create_new(true).open(&temp_path)?;
+
+    let result = (|| {
+        let mut writer = BufWriter::with_capacity(BUFFER_SIZE, &temp_file);
+        let written = write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        temp_file.set_permissions(metadata.permissions())?;
+        let mut times = FileTimes::new();
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+        temp_file.set_times(times)?;
+        temp_file.sync_all()?;
+
+        fs::rename(&temp_path, output)?;
+        sync_parent_dir(output)?;
+        Ok(written)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use super::{compress_file, compress_file_with, decompress_file, decompress_file_with_progress};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+    use crate::testdir::TestDir;
+
+    // "hello, rzlib\n" as a single stored block
+    const HELLO_GZ: [u8; 36] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x0d, 0x00, 0xf2, 0xff,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x7a, 0x6c, 0x69, 0x62, 0x0a, 0xd3, 0xb8,
+        0xbe, 0xec, 0x0d, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_decompress_file() {
+        let dir = TestDir::new("decompress-file");
+        let input = dir.join("hello.txt.gz");
+        let output = dir.join("hello.txt");
+        fs::write(&input, HELLO_GZ).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+
+        assert_eq!(decompress_file(&input, &output).unwrap(), 13);
+        assert_eq!(fs::read(&output).unwrap(), b"hello, rzlib\n");
+        assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), mtime);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_decompress_file_with_progress() {
+        let dir = TestDir::new("decompress-file-progress");
+        let input = dir.join("hello.txt.gz");
+        fs::write(&input, HELLO_GZ).unwrap();
+
+        let mut updates = vec![];
+        let written = decompress_file_with_progress(&input, dir.join("hello.txt"), |consumed, written| {
+            updates.push((consumed, written))
+        });
+        assert_eq!(written.unwrap(), 13);
+        assert_eq!(updates.last(), Some(&(28, 13)));
+    }
+
+    #[test]
+    fn test_compress_file_round_trip() {
+        let dir = TestDir::new("compress-file");
+        let input = dir.join("data.bin");
+        let compressed = dir.join("data.bin.gz");
+        let output = dir.join("data.bin.out");
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 97) as u8).collect();
+        fs::write(&input, &data).unwrap();
+
+        let written = compress_file(&input, &compressed).unwrap();
+        assert_eq!(written, fs::metadata(&compressed).unwrap().len());
+        decompress_file(&compressed, &output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), data);
+
+        let stored = compress_file_with(&input, &compressed, |writer| {
+            GzEncoder::new(writer, Compression::none())
+        })
+        .unwrap();
+        assert!(stored > data.len() as u64 && stored > written);
+        decompress_file(&compressed, &output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), data);
+    }
+
+    #[test]
+    fn test_decompress_file_keeps_output_on_error() {
+        let dir = TestDir::new("decompress-file-error");
+        let input = dir.join("broken.gz");
+        let output = dir.join("broken");
+        fs::write(&input, &HELLO_GZ[..20]).unwrap();
+        fs::write(&output, b"previous contents").unwrap();
+
+        assert!(decompress_file(&input, &output).is_err());
+        assert_eq!(fs::read(&output).unwrap(), b"previous contents");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
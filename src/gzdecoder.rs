@@ -0,0 +1,186 @@
+use crate::io;
+use crate::io::{BufRead, Read};
+
+use crate::gzheader::GzHeader;
+use crate::rzlibreader::{Format, RZLibReader, Strictness};
+
+/// Decompresses a single gzip member read from `R`.
+///
+/// Decoding stops after the member's trailer, leaving any following bytes in the underlying
+/// reader, which can be taken back with [`GzDecoder::into_inner`]. Use [`MultiGzDecoder`] to
+/// decode concatenated members as a single stream, like `gzip -d` does.
+pub struct GzDecoder<R: Read + BufRead> {
+    inner: RZLibReader<R>,
+}
+
+impl<R: Read + BufRead> GzDecoder<R> {
+    pub fn new(reader: R) -> GzDecoder<R> {
+        let mut inner = RZLibReader::with_format(reader, Format::Gzip);
+        inner.set_multi_member(false);
+        GzDecoder { inner }
+    }
+
+    /// See [`RZLibReader::set_verify_checksum`].
+    pub fn set_verify_checksum(&mut self, verify: bool) {
+        self.inner.set_verify_checksum(verify);
+    }
+
+    /// See [`RZLibReader::set_strictness`].
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.inner.set_strictness(strictness);
+    }
+
+    /// See [`RZLibReader::set_max_output_bytes`].
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.inner.set_max_output_bytes(limit);
+    }
+
+    /// See [`RZLibReader::total_in`].
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// See [`RZLibReader::total_out`].
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.header()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// See [`RZLibReader::get_mut`].
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Returns the underlying reader. Once the member has been read to the end, it is positioned
+    /// right after the member's trailer.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: Read + BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Decompresses a gzip stream made of any number of concatenated members read from `R`, as if
+/// it were a single member.
+pub struct MultiGzDecoder<R: Read + BufRead> {
+    inner: RZLibReader<R>,
+}
+
+impl<R: Read + BufRead> MultiGzDecoder<R> {
+    pub fn new(reader: R) -> MultiGzDecoder<R> {
+        MultiGzDecoder { inner: RZLibReader::with_format(reader, Format::Gzip) }
+    }
+
+    /// See [`RZLibReader::set_verify_checksum`].
+    pub fn set_verify_checksum(&mut self, verify: bool) {
+        self.inner.set_verify_checksum(verify);
+    }
+
+    /// See [`RZLibReader::set_ignore_trailing_garbage`].
+    pub fn set_ignore_trailing_garbage(&mut self, ignore: bool) {
+        self.inner.set_ignore_trailing_garbage(ignore);
+    }
+
+    /// See [`RZLibReader::set_strictness`].
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.inner.set_strictness(strictness);
+    }
+
+    /// See [`RZLibReader::set_max_output_bytes`].
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.inner.set_max_output_bytes(limit);
+    }
+
+    /// See [`RZLibReader::total_in`].
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// See [`RZLibReader::total_out`].
+    pub fn total_out(&self) -> u64 {
+        self.inner.total_out()
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.header()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// See [`RZLibReader::get_mut`].
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: Read + BufRead> Read for MultiGzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::{GzDecoder, MultiGzDecoder};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_single_member() {
+        let input = [compress(b"first member, first member"), compress(b"second")].concat();
+
+        let mut decoder = GzDecoder::new(&input[..]);
+        let mut out = vec![];
+        decoder.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"first member, first member");
+
+        let mut rest = decoder.into_inner();
+        let mut out = vec![];
+        GzDecoder::new(&mut rest).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"second");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        let input = [compress(b"hello, hello, hello"), b"trailing".to_vec()].concat();
+        let mut decoder = GzDecoder::new(&input[..]);
+        decoder.read_to_end(&mut vec![]).unwrap();
+        assert_eq!(decoder.into_inner(), b"trailing");
+    }
+
+    #[test]
+    fn test_multiple_members() {
+        let input = [compress(b"first"), compress(b""), compress(b"second")].concat();
+        let mut out = vec![];
+        MultiGzDecoder::new(&input[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"firstsecond");
+    }
+}
@@ -0,0 +1,326 @@
+use crate::io;
+use crate::io::Write;
+
+use alloc::vec::Vec;
+
+use crate::crc32::Crc32;
+use crate::deflateencoder::{Compression, DeflateEncoder, FlushMode};
+use crate::gzheader::GzHeader;
+
+// Encodes `header` as the header of a member compressed at `level`, with a CRC-16 of itself if
+// `header_crc` is set.
+pub(crate) fn header_bytes(
+    header: &GzHeader,
+    level: Compression,
+    header_crc: bool,
+) -> io::Result<Vec<u8>> {
+    let xfl = match level.level() {
+        1 => 4,
+        9 => 2,
+        _ => 0,
+    };
+    let flg = header.text as u8
+        | (header_crc as u8) << 1
+        | (header.extra.is_some() as u8) << 2
+        | (header.filename.is_some() as u8) << 3
+        | (header.comment.is_some() as u8) << 4;
+    let mut bytes = Vec::with_capacity(10);
+    bytes.extend_from_slice(&[0x1f, 0x8b, 0x08, flg]); // ID1, ID2, CM = deflate, FLG
+    bytes.extend_from_slice(&header.mtime.to_le_bytes());
+    bytes.extend_from_slice(&[xfl, header.os]);
+    if let Some(extra) = &header.extra {
+        let xlen = u16::try_from(extra.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "extra field too long"))?;
+        bytes.extend_from_slice(&xlen.to_le_bytes());
+        bytes.extend_from_slice(extra);
+    }
+    for string in [&header.filename, &header.comment].into_iter().flatten() {
+        if string.contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "header string contains a NUL byte",
+            ));
+        }
+        bytes.extend_from_slice(string);
+        bytes.push(0);
+    }
+    if header_crc {
+        let mut crc = Crc32::new();
+        crc.update(&bytes);
+        bytes.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Compresses data written to it into a single gzip member written to `W`.
+///
+/// The member is completed by [`GzEncoder::finish`], or on drop (ignoring errors) if `finish`
+/// was never called.
+pub struct GzEncoder<W: Write> {
+    inner: DeflateEncoder<W>,
+    header: GzHeader,
+    header_crc: bool,
+    header_written: bool,
+    finished: bool,
+    crc: Crc32,
+    size: u32,
+}
+
+impl<W: Write> GzEncoder<W> {
+    pub fn new(writer: W, level: Compression) -> GzEncoder<W> {
+        // no metadata, and OS = unknown
+        let header = GzHeader {
+            os: 255,
+            ..GzHeader::default()
+        };
+        GzEncoder::with_header(writer, level, header)
+    }
+
+    /// Creates an encoder writing `header` as the member header, to carry the metadata gzip
+    /// would: the original file name, its modification time, and so on. `header.xfl` is ignored,
+    /// XFL is set from the compression level.
+    ///
+    /// Writing the header fails with `InvalidInput` if the file name or comment contains a NUL
+    /// byte, or if the extra field is longer than 65535 bytes.
+    pub fn with_header(writer: W, level: Compression, header: GzHeader) -> GzEncoder<W> {
+        GzEncoder {
+            inner: DeflateEncoder::new(writer, level),
+            header,
+            header_crc: false,
+            header_written: false,
+            finished: false,
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+
+    /// See [`DeflateEncoder::set_lazy_matching`].
+    pub fn set_lazy_matching(&mut self, lazy: bool) {
+        self.inner.set_lazy_matching(lazy);
+    }
+
+    /// See [`DeflateEncoder::set_rsyncable`].
+    pub fn set_rsyncable(&mut self, rsyncable: bool) {
+        self.inner.set_rsyncable(rsyncable);
+    }
+
+    /// Sets whether the header ends with a CRC-16 of itself (FHCRC). Disabled by default, as in
+    /// gzip. Has no effect once something was written.
+    pub fn set_header_crc(&mut self, header_crc: bool) {
+        self.header_crc = header_crc;
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        let bytes = header_bytes(&self.header, self.inner.level(), self.header_crc)?;
+        self.inner.get_mut().write_all(&bytes)?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_header()?;
+        self.inner.try_finish()?;
+        let mut trailer: [u8; 8] = [0; 8];
+        trailer[..4].copy_from_slice(&self.crc.sum().to_le_bytes());
+        trailer[4..].copy_from_slice(&self.size.to_le_bytes());
+        self.inner.get_mut().write_all(&trailer)?;
+        self.finished = true;
+        self.inner.get_mut().flush()
+    }
+
+    /// See [`DeflateEncoder::flush_with`].
+    pub fn flush_with(&mut self, mode: FlushMode) -> io::Result<()> {
+        // the flush point must come after the header
+        self.write_header()?;
+        self.inner.flush_with(mode)
+    }
+
+    /// Writes the final block and the member trailer, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        self.inner.take_writer()
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        let len = self.inner.write(buf)?;
+        self.crc.update(&buf[..len]);
+        self.size = self.size.wrapping_add(len as u32);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // the sync point must come after the header
+        self.write_header()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for GzEncoder<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::GzEncoder;
+    use crate::deflateencoder::{Compression, DeflateEncoder, FlushMode};
+    use crate::gzheader::GzHeader;
+    use crate::rzlibreader::{Format, RZLibReader};
+
+    fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = compress(data, Compression::default());
+
+        let mut out = vec![];
+        RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(round_trip(b""), b"");
+        assert_eq!(round_trip(b"hello, rzlib\n"), b"hello, rzlib\n");
+
+        let data: Vec<u8> = (0..200_000).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(round_trip(&data), data);
+    }
+
+    #[test]
+    fn test_finish_on_drop() {
+        let mut compressed = vec![];
+        {
+            let mut encoder = GzEncoder::new(&mut compressed, Compression::default());
+            encoder.write_all(b"dropped").unwrap();
+        }
+        let mut out = vec![];
+        RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"dropped");
+    }
+
+    #[test]
+    fn test_flush_with() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        // the header comes before the flush point
+        encoder.flush_with(FlushMode::Full).unwrap();
+        assert_eq!(&encoder.get_ref()[..2], &[0x1f, 0x8b]);
+        encoder.write_all(b"first, ").unwrap();
+        encoder.flush_with(FlushMode::Full).unwrap();
+        encoder.write_all(b"first again").unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut out = vec![];
+        RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"first, first again");
+    }
+
+    #[test]
+    fn test_header() {
+        let mut header = GzHeader {
+            text: true,
+            mtime: 1_000_000_000,
+            os: 3,
+            filename: Some(b"hello.txt".to_vec()),
+            comment: Some(b"a greeting".to_vec()),
+            ..GzHeader::default()
+        };
+        header.add_extra_subfield(u16::from_be_bytes(*b"AB"), b"xyz").unwrap();
+        for header_crc in [false, true] {
+            let mut encoder = GzEncoder::with_header(vec![], Compression::best(), header.clone());
+            encoder.set_header_crc(header_crc);
+            encoder.write_all(b"hello").unwrap();
+            let compressed = encoder.finish().unwrap();
+            assert_eq!(compressed[3] & 0x02 != 0, header_crc);
+            if header_crc {
+                // CRC-16 of the 40 bytes before it
+                assert_eq!(compressed[40..42], [0x31, 0xe0]);
+            }
+
+            let mut reader = RZLibReader::new(&compressed[..]);
+            let mut out = vec![];
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"hello");
+            let decoded = reader.header().unwrap();
+            assert_eq!(decoded, &GzHeader { xfl: 2, ..header.clone() });
+            assert_eq!(decoded.extra.as_deref(), Some(&b"AB\x03\x00xyz"[..]));
+        }
+
+        let header = GzHeader {
+            filename: Some(b"nul\0".to_vec()),
+            ..GzHeader::default()
+        };
+        let mut encoder = GzEncoder::with_header(vec![], Compression::default(), header);
+        assert!(encoder.write_all(b"hello").is_err());
+    }
+
+    // Payloads that make the encoder pick every block type: incompressible bytes end up in
+    // stored blocks, short or repetitive data in fixed or dynamic Huffman blocks.
+    fn payload() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            vec(any::<u8>(), 0..100_000),
+            vec(0..4u8, 0..100_000),
+            vec((any::<u8>(), 1..300usize), 0..500).prop_map(|runs| {
+                runs.into_iter().flat_map(|(byte, n)| std::iter::repeat_n(byte, n)).collect()
+            }),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_round_trip_levels(data in payload(), level in 0..=9u32) {
+            let compressed = compress(&data, Compression::new(level));
+            let mut out = vec![];
+            RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+            prop_assert!(out == data);
+
+            let mut encoder = DeflateEncoder::new(vec![], Compression::new(level));
+            encoder.write_all(&data).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let mut out = vec![];
+            RZLibReader::with_format(&compressed[..], Format::Deflate).read_to_end(&mut out).unwrap();
+            prop_assert!(out == data);
+        }
+
+        #[test]
+        fn test_round_trip_members(members in vec((vec(0..8u8, 0..5000), 0..=9u32), 0..5)) {
+            let compressed: Vec<u8> = members
+                .iter()
+                .flat_map(|(data, level)| compress(data, Compression::new(*level)))
+                .collect();
+            let mut out = vec![];
+            RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+            let data: Vec<u8> = members.iter().flat_map(|(data, _)| data.clone()).collect();
+            prop_assert!(out == data);
+        }
+    }
+}
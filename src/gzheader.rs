@@ -0,0 +1,143 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::io;
+
+/// The optional fields of a gzip member header (RFC 1952, section 2.3).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GzHeader {
+    /// Whether the data is probably ASCII text (FTEXT).
+    pub text: bool,
+    /// Modification time of the original file, in seconds since the Unix epoch, or 0 if not
+    /// available.
+    pub mtime: u32,
+    /// Extra flags, describing the compression level used.
+    pub xfl: u8,
+    /// Operating system the member was compressed on, 255 if unknown.
+    pub os: u8,
+    /// The FEXTRA field, unparsed. See [`GzHeader::extra_subfields`].
+    pub extra: Option<Vec<u8>>,
+    /// Name of the original file (FNAME), without the terminating NUL. RFC 1952 specifies
+    /// ISO-8859-1, but many tools write UTF-8 or the local encoding; see
+    /// [`GzHeader::filename_lossy`].
+    pub filename: Option<Vec<u8>>,
+    /// FCOMMENT, without the terminating NUL, in the same encoding as the file name.
+    pub comment: Option<Vec<u8>>,
+}
+
+impl GzHeader {
+    /// The file name as text: decoded as UTF-8 if it is valid UTF-8, as ISO-8859-1 otherwise.
+    pub fn filename_lossy(&self) -> Option<Cow<'_, str>> {
+        self.filename.as_deref().map(decode_string)
+    }
+
+    /// The comment as text, decoded like [`GzHeader::filename_lossy`].
+    pub fn comment_lossy(&self) -> Option<Cow<'_, str>> {
+        self.comment.as_deref().map(decode_string)
+    }
+
+    /// The subfields of the FEXTRA field, as (ID, data) pairs in order. The ID is the two
+    /// subfield ID bytes, SI1 first. Returns `None` if there is no FEXTRA field, or if it is not
+    /// made of whole subfields.
+    pub fn extra_subfields(&self) -> Option<Vec<(u16, Vec<u8>)>> {
+        let subfields = split_subfields(self.extra.as_ref()?)?;
+        Some(subfields.into_iter().map(|(id, data)| (id, data.to_vec())).collect())
+    }
+
+    /// The data of the first subfield of the FEXTRA field with ID `id`, e.g.
+    /// `u16::from_be_bytes(*b"BC")` for the block size of BGZF.
+    pub fn extra_subfield(&self, id: u16) -> Option<&[u8]> {
+        let subfields = split_subfields(self.extra.as_ref()?)?;
+        subfields.into_iter().find(|&(other, _)| other == id).map(|(_, data)| data)
+    }
+
+    /// Appends a subfield (RFC 1952, section 2.3.1.1) to the FEXTRA field, creating it if needed.
+    /// The ID is the two subfield ID bytes, SI1 first, e.g. `u16::from_be_bytes(*b"BC")`.
+    ///
+    /// Fails with `ErrorKind::InvalidInput`, leaving the field as it was, if `data` is longer
+    /// than the 65535 bytes a subfield can hold.
+    pub fn add_extra_subfield(&mut self, id: u16, data: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "extra subfield too long"))?;
+        let extra = self.extra.get_or_insert_with(Vec::new);
+        extra.extend_from_slice(&id.to_be_bytes());
+        extra.extend_from_slice(&len.to_le_bytes());
+        extra.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+// Decodes a header string as UTF-8, the common case, or else as ISO-8859-1, where each byte is
+// the code point of the same value.
+fn decode_string(bytes: &[u8]) -> Cow<'_, str> {
+    match core::str::from_utf8(bytes) {
+        Ok(string) => Cow::Borrowed(string),
+        Err(_) => Cow::Owned(bytes.iter().map(|&byte| byte as char).collect::<String>()),
+    }
+}
+
+// Splits a FEXTRA field into its subfields (RFC 1952, section 2.3.1.1): SI1, SI2, a 2 byte
+// length LEN and LEN bytes of data each. Returns `None` if the field does not end with a whole
+// subfield.
+pub(crate) fn split_subfields(mut extra: &[u8]) -> Option<Vec<(u16, &[u8])>> {
+    let mut subfields = Vec::new();
+    while !extra.is_empty() {
+        let header = extra.get(..4)?;
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+        subfields.push((u16::from_be_bytes([header[0], header[1]]), data));
+        extra = &extra[4 + len..];
+    }
+    Some(subfields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GzHeader;
+
+    #[test]
+    fn test_extra_subfields() {
+        let mut header = GzHeader::default();
+        assert_eq!(header.extra_subfields(), None);
+        header.add_extra_subfield(u16::from_be_bytes(*b"BC"), &[0x1b, 0x00]).unwrap();
+        header.add_extra_subfield(u16::from_be_bytes(*b"Ap"), &[]).unwrap();
+        header.add_extra_subfield(u16::from_be_bytes(*b"BC"), &[0xff, 0xff]).unwrap();
+        assert_eq!(
+            header.extra_subfields().unwrap(),
+            [(0x4243, vec![0x1b, 0x00]), (0x4170, vec![]), (0x4243, vec![0xff, 0xff])]
+        );
+        assert_eq!(header.extra_subfield(0x4243), Some(&[0x1b, 0x00][..]));
+        assert_eq!(header.extra_subfield(0x4170), Some(&[][..]));
+        assert_eq!(header.extra_subfield(0x4244), None);
+
+        // too long for the 2 byte LEN
+        let err = header.add_extra_subfield(0x4142, &[0; 65536]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(header.extra_subfields().unwrap().len(), 3);
+        header.add_extra_subfield(0x4142, &[0; 65535]).unwrap();
+        assert_eq!(header.extra_subfield(0x4142).map(<[u8]>::len), Some(65535));
+        header.extra.as_mut().unwrap().truncate(16);
+        assert_eq!(header.extra_subfields().unwrap().len(), 3);
+
+        // a truncated subfield
+        header.extra.as_mut().unwrap().pop();
+        assert_eq!(header.extra_subfields(), None);
+        header.extra = Some(b"BC\x02".to_vec());
+        assert_eq!(header.extra_subfields(), None);
+        header.extra = Some(vec![]);
+        assert_eq!(header.extra_subfields(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_lossy_strings() {
+        let mut header = GzHeader::default();
+        assert_eq!(header.filename_lossy(), None);
+        header.filename = Some("caf\u{e9}.txt".as_bytes().to_vec());
+        assert_eq!(header.filename_lossy().unwrap(), "caf\u{e9}.txt");
+        header.filename = Some(b"caf\xe9.txt".to_vec());
+        assert_eq!(header.filename_lossy().unwrap(), "caf\u{e9}.txt");
+        header.comment = Some(b"\xff".to_vec());
+        assert_eq!(header.comment_lossy().unwrap(), "\u{ff}");
+    }
+}
@@ -0,0 +1,454 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::{BufRead, ErrorKind, Read, Write};
+
+use crate::binary::{invalid, ByteReader, ByteWriter};
+use crate::deflateencoder::{Compression, DeflateEncoder};
+use crate::members::GzTrailer;
+use crate::rzlibreader::{Format, RZLibReader};
+use crate::slicedecoder::SliceDecoder;
+
+const MAGIC: &[u8] = b"RZINDEX\x01";
+// the largest window a gzip checkpoint holds
+const MAX_WINDOW: usize = 32 * 1024;
+
+/// A point in a gzip stream where decoding can start, without decoding what comes before it: a
+/// deflate block boundary, and the window the following blocks can refer back to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "de::Checkpoint"))]
+pub struct Checkpoint {
+    /// Offset in the decompressed data.
+    pub out: u64,
+    /// Offset in the compressed data, in bits, of the header of the next block.
+    pub in_bits: u64,
+    /// The last 32 KiB (or less at the start of the stream) of decompressed data.
+    pub window: Vec<u8>,
+    /// CRC-32 of the data of the current member so far, to check the rest of the member against
+    /// its trailer.
+    pub crc: u32,
+    /// Size of the data of the current member so far, modulo 2^32.
+    pub member_size: u32,
+}
+
+/// Checkpoints spread over a gzip stream, like those of zlib's `zran.c`, for random access to
+/// its decompressed data with [`IndexedGzReader`].
+///
+/// Building the index decodes the whole stream once. Each checkpoint holds a 32 KiB window, so
+/// the index takes about 32 KiB per span of decompressed data.
+///
+/// The index can be saved with [`GzIndex::to_bytes`], or through serde with the `serde` feature,
+/// so that it is only built once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "de::GzIndex"))]
+pub struct GzIndex {
+    span: u64,
+    total_in: u64,
+    total_out: u64,
+    trailer: Option<GzTrailer>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl Checkpoint {
+    // Whether the window fits the data before the checkpoint, and a gzip window.
+    fn check(&self) -> Result<(), &'static str> {
+        if self.window.len() > MAX_WINDOW || self.window.len() as u64 > self.out {
+            return Err("checkpoint window too large");
+        }
+        Ok(())
+    }
+}
+
+impl GzIndex {
+    /// Decodes the gzip stream read from `reader`, recording a checkpoint at the first block
+    /// boundary after every `span` bytes of decompressed data.
+    pub fn build<R: Read + BufRead>(reader: R, span: u64) -> io::Result<GzIndex> {
+        let mut decoder = RZLibReader::new(reader);
+        decoder.record_checkpoints(span.max(1));
+        let mut buf = vec![0; 32 * 1024];
+        loop {
+            match decoder.read(&mut buf) {
+                Ok(0) if decoder.at_sync_point() => {}
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let checkpoints = decoder.take_checkpoints();
+        Ok(GzIndex {
+            span,
+            total_in: decoder.total_in(),
+            total_out: decoder.total_out(),
+            trailer: decoder.trailer(),
+            checkpoints,
+        })
+    }
+
+    /// The span the index was built with.
+    pub fn span(&self) -> u64 {
+        self.span
+    }
+
+    /// Size of the compressed data.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Size of the decompressed data.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// The trailer of the last member, which ends the stream. Together with
+    /// [`GzIndex::total_in`], it tells whether the index is still that of a file.
+    pub fn trailer(&self) -> Option<GzTrailer> {
+        self.trailer
+    }
+
+    /// The checkpoints, in stream order. The start of the stream is not one.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Saves the index in a compact binary layout, read back by [`GzIndex::from_bytes`]. The
+    /// windows are compressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::new(MAGIC);
+        writer.u64(self.span);
+        writer.u64(self.total_in);
+        writer.u64(self.total_out);
+        match self.trailer {
+            Some(trailer) => {
+                writer.u8(1);
+                writer.u32(trailer.crc32);
+                writer.u32(trailer.isize);
+            }
+            None => writer.u8(0),
+        }
+        writer.u64(self.checkpoints.len() as u64);
+        for checkpoint in &self.checkpoints {
+            writer.u64(checkpoint.out);
+            writer.u64(checkpoint.in_bits);
+            writer.u32(checkpoint.crc);
+            writer.u32(checkpoint.member_size);
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder
+                .write_all(&checkpoint.window)
+                .expect("writing to a Vec cannot fail");
+            writer.bytes(&encoder.finish().expect("writing to a Vec cannot fail"));
+        }
+        writer.finish()
+    }
+
+    /// Reads an index saved by [`GzIndex::to_bytes`]. Fails with `InvalidData` if `data` is not
+    /// one.
+    pub fn from_bytes(data: &[u8]) -> io::Result<GzIndex> {
+        let mut reader = ByteReader::new(data, MAGIC)?;
+        let span = reader.u64()?;
+        let total_in = reader.u64()?;
+        let total_out = reader.u64()?;
+        let trailer = if reader.bool()? {
+            Some(GzTrailer {
+                crc32: reader.u32()?,
+                isize: reader.u32()?,
+            })
+        } else {
+            None
+        };
+        let count = reader.u64()?;
+        let mut checkpoints: Vec<Checkpoint> = vec![];
+        for _ in 0..count {
+            let out = reader.u64()?;
+            let in_bits = reader.u64()?;
+            let crc = reader.u32()?;
+            let member_size = reader.u32()?;
+            let mut decoder = SliceDecoder::with_format(reader.slice()?, Format::Deflate);
+            decoder.set_max_output_bytes(Some(MAX_WINDOW as u64));
+            let mut window = vec![];
+            decoder
+                .decode_to_vec(&mut window)
+                .map_err(|_| invalid("bad checkpoint window"))?;
+            checkpoints.push(Checkpoint { out, in_bits, window, crc, member_size });
+        }
+        reader.finish()?;
+        let index = GzIndex { span, total_in, total_out, trailer, checkpoints };
+        index.check().map_err(invalid)?;
+        Ok(index)
+    }
+
+    // Whether the checkpoints are valid, in order and within the data, as read back from a saved
+    // index.
+    fn check(&self) -> Result<(), &'static str> {
+        let mut previous_out = 0;
+        for checkpoint in &self.checkpoints {
+            checkpoint.check()?;
+            if checkpoint.out < previous_out
+                || checkpoint.out > self.total_out
+                || checkpoint.in_bits / 8 > self.total_in
+            {
+                return Err("checkpoints out of order");
+            }
+            previous_out = checkpoint.out;
+        }
+        Ok(())
+    }
+
+    // The last checkpoint at or before `out`, if any.
+    #[cfg(feature = "std")]
+    fn checkpoint_before(&self, out: u64) -> Option<&Checkpoint> {
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.out <= out);
+        index.checked_sub(1).map(|index| &self.checkpoints[index])
+    }
+}
+
+// What serde deserializes, checked like `GzIndex::from_bytes` before it becomes a `GzIndex` or
+// a `Checkpoint`.
+#[cfg(feature = "serde")]
+mod de {
+    use alloc::vec::Vec;
+
+    use crate::members::GzTrailer;
+
+    #[derive(serde::Deserialize)]
+    pub struct Checkpoint {
+        out: u64,
+        in_bits: u64,
+        window: Vec<u8>,
+        crc: u32,
+        member_size: u32,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct GzIndex {
+        span: u64,
+        total_in: u64,
+        total_out: u64,
+        trailer: Option<GzTrailer>,
+        checkpoints: Vec<super::Checkpoint>,
+    }
+
+    impl TryFrom<Checkpoint> for super::Checkpoint {
+        type Error = &'static str;
+
+        fn try_from(checkpoint: Checkpoint) -> Result<super::Checkpoint, &'static str> {
+            let Checkpoint { out, in_bits, window, crc, member_size } = checkpoint;
+            let checkpoint = super::Checkpoint { out, in_bits, window, crc, member_size };
+            checkpoint.check()?;
+            Ok(checkpoint)
+        }
+    }
+
+    impl TryFrom<GzIndex> for super::GzIndex {
+        type Error = &'static str;
+
+        fn try_from(index: GzIndex) -> Result<super::GzIndex, &'static str> {
+            let GzIndex { span, total_in, total_out, trailer, checkpoints } = index;
+            let index = super::GzIndex { span, total_in, total_out, trailer, checkpoints };
+            index.check()?;
+            Ok(index)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::indexedgzreader::IndexedGzReader;
+
+#[cfg(feature = "std")]
+mod indexedgzreader {
+    use std::io;
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+
+    use super::GzIndex;
+    use crate::rzlibreader::RZLibReader;
+
+    /// Reads a gzip stream from any offset of its decompressed data, using a [`GzIndex`] of it.
+    ///
+    /// Seeking restores the nearest checkpoint before the target, unless the target is ahead with
+    /// no checkpoint in between, and decodes forward from there: at most about a span of data.
+    pub struct IndexedGzReader<R: BufRead + Seek> {
+        decoder: RZLibReader<R>,
+        index: GzIndex,
+    }
+
+    impl<R: BufRead + Seek> IndexedGzReader<R> {
+        /// Creates a reader of the stream `index` was built from, positioned at its start.
+        pub fn new(mut reader: R, index: GzIndex) -> io::Result<IndexedGzReader<R>> {
+            reader.rewind()?;
+            Ok(IndexedGzReader {
+                decoder: RZLibReader::new(reader),
+                index,
+            })
+        }
+
+        pub fn index(&self) -> &GzIndex {
+            &self.index
+        }
+
+        pub fn into_inner(self) -> R {
+            self.decoder.into_inner()
+        }
+
+        // Moves to `target`, or to the end of the data if it is further.
+        fn seek_to(&mut self, target: u64) -> io::Result<()> {
+            let checkpoint = self.index.checkpoint_before(target);
+            let start = checkpoint.map_or(0, |checkpoint| checkpoint.out);
+            let pos = self.decoder.total_out();
+            if target < pos || start > pos {
+                let offset = checkpoint.map_or(0, |checkpoint| checkpoint.in_bits / 8);
+                self.decoder.get_mut().seek(SeekFrom::Start(offset))?;
+                self.decoder.restart_at(checkpoint)?;
+            }
+            let mut scratch = vec![0; 32 * 1024];
+            while self.decoder.total_out() < target {
+                let len = (target - self.decoder.total_out()).min(scratch.len() as u64) as usize;
+                match self.decoder.read(&mut scratch[..len]) {
+                    Ok(0) if self.decoder.at_sync_point() => {}
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<R: BufRead + Seek> Read for IndexedGzReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.decoder.read(buf)
+        }
+    }
+
+    impl<R: BufRead + Seek> Seek for IndexedGzReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let target = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::End(offset) => self.index.total_out().checked_add_signed(offset),
+                SeekFrom::Current(offset) => self.decoder.total_out().checked_add_signed(offset),
+            };
+            let target = target.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset")
+            })?;
+            self.seek_to(target)?;
+            Ok(self.decoder.total_out())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+
+    #[cfg(feature = "serde")]
+    use super::Checkpoint;
+    use super::{GzIndex, IndexedGzReader};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+
+    fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn data(len: u32) -> Vec<u8> {
+        (0..len)
+            .map(|i| ((i / 5) ^ (i % 253) ^ (i >> 11)) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let data = data(1_000_000);
+        let compressed = compress(&data, Compression::default());
+        let index = GzIndex::build(&compressed[..], 100_000).unwrap();
+        assert_eq!(index.total_in(), compressed.len() as u64);
+        assert_eq!(index.total_out(), data.len() as u64);
+        let trailer = index.trailer().unwrap();
+        assert_eq!(trailer.isize, data.len() as u32);
+        assert_eq!(trailer.crc32.to_le_bytes(), compressed[compressed.len() - 8..][..4]);
+        let checkpoints = index.checkpoints();
+        assert!(checkpoints.len() >= 5, "{} checkpoints", checkpoints.len());
+        for pair in checkpoints.windows(2) {
+            assert!(pair[1].out >= pair[0].out + 100_000);
+        }
+        for checkpoint in checkpoints {
+            let out = checkpoint.out as usize;
+            assert_eq!(checkpoint.window, data[out - checkpoint.window.len()..out]);
+        }
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let data = data(300_000);
+        let compressed = compress(&data, Compression::default());
+        let index = GzIndex::build(&compressed[..], 50_000).unwrap();
+        let saved = index.to_bytes();
+        assert!(saved.len() < index.checkpoints().len() * 32 * 1024);
+        let loaded = GzIndex::from_bytes(&saved).unwrap();
+        assert_eq!(loaded, index);
+
+        for len in [0, 8, 30, saved.len() / 2, saved.len() - 1] {
+            let err = GzIndex::from_bytes(&saved[..len]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let data = data(300_000);
+        let compressed = compress(&data, Compression::default());
+        let index = GzIndex::build(&compressed[..], 50_000).unwrap();
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(serde_json::from_str::<GzIndex>(&json).unwrap(), index);
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["checkpoints"].as_array_mut().unwrap().reverse();
+        assert!(serde_json::from_value::<GzIndex>(value).is_err());
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["total_out"] = 1000.into();
+        assert!(serde_json::from_value::<GzIndex>(value).is_err());
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["checkpoints"][0]["out"] = 10.into();
+        assert!(serde_json::from_value::<GzIndex>(value).is_err());
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["checkpoints"][0]["window"] = vec![0; 40_000].into();
+        assert!(serde_json::from_value::<Checkpoint>(value["checkpoints"][0].take()).is_err());
+    }
+
+    #[test]
+    fn test_seek() {
+        let data = data(500_000);
+        let half = data.len() / 2;
+        let compressed = [
+            compress(&data[..half], Compression::default()),
+            compress(&data[half..], Compression::none()),
+        ]
+        .concat();
+        let index = GzIndex::build(&compressed[..], 50_000).unwrap();
+        let mut reader = IndexedGzReader::new(Cursor::new(&compressed), index).unwrap();
+
+        let mut buf = [0; 1000];
+        for offset in [400_000, 3, 249_500, 120_000, 120_500, 499_000, 0] {
+            assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+            reader.read_exact(&mut buf).unwrap();
+            let offset = offset as usize;
+            assert!(buf[..] == data[offset..offset + 1000], "offset {}", offset);
+        }
+
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 499_990);
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data[499_990..]);
+        assert_eq!(reader.seek(SeekFrom::Current(-100)).unwrap(), 499_900);
+        assert_eq!(reader.seek(SeekFrom::End(10)).unwrap(), 500_000);
+        assert!(reader.seek(SeekFrom::Current(-600_000)).is_err());
+    }
+}
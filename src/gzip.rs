@@ -0,0 +1,91 @@
+//! One-shot compression and decompression of gzip data held in memory.
+
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::Write;
+
+use crate::deflateencoder::Compression;
+use crate::gzencoder::GzEncoder;
+use crate::slicedecoder::SliceDecoder;
+
+// deflate expands data by at most 1032:1, which bounds the ISIZE worth preallocating for
+pub(crate) const MAX_RATIO: usize = 1032;
+
+/// Decompresses a complete gzip stream, possibly made of several members.
+///
+/// The output is preallocated from the size recorded in the trailer of the last member, so a
+/// single-member stream is decompressed without growing the output. Decoding goes through
+/// [`SliceDecoder`], which works straight off `data`.
+pub fn decompress_to_vec(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size_hint(data));
+    SliceDecoder::new(data).decode_to_vec(&mut out)?;
+    Ok(out)
+}
+
+/// Compresses `data` into a single gzip member.
+pub fn compress_to_vec(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), level);
+    encoder
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+// The ISIZE field of the trailer, unless it cannot be right: the stream is too short to have a
+// trailer, or `data` could not decompress to that much.
+fn size_hint(data: &[u8]) -> usize {
+    // header and trailer, around the smallest deflate stream
+    if data.len() < 20 {
+        return 0;
+    }
+    let isize = u32::from_le_bytes(data[data.len() - 4..].try_into().unwrap()) as usize;
+    if isize > data.len().saturating_mul(MAX_RATIO) {
+        return 0;
+    }
+    isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress_to_vec, decompress_to_vec, size_hint};
+    use crate::deflateencoder::Compression;
+    use crate::error::RzlibError;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| ((i % 251) ^ (i / 1000)) as u8)
+            .collect();
+        for level in [0, 1, 6, 9] {
+            let compressed = compress_to_vec(&data, Compression::new(level));
+            assert_eq!(size_hint(&compressed), data.len());
+            let out = decompress_to_vec(&compressed).unwrap();
+            assert!(out == data, "level {}", level);
+            assert_eq!(out.capacity(), data.len());
+        }
+        let members = [
+            compress_to_vec(b"abc", Compression::default()),
+            compress_to_vec(b"def", Compression::fast()),
+        ]
+        .concat();
+        assert_eq!(decompress_to_vec(&members).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn test_bogus_size() {
+        let mut compressed = compress_to_vec(b"not that big", Compression::default());
+        let len = compressed.len();
+        compressed[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(size_hint(&compressed), 0);
+        let err = decompress_to_vec(&compressed).unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::SizeMismatch {
+                expected: u32::MAX,
+                actual: 12
+            })
+        );
+        assert_eq!(size_hint(&[0; 10]), 0);
+    }
+}
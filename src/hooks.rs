@@ -0,0 +1,38 @@
+//! Callbacks from the decoder at member and block boundaries, see [`RZLibReader::set_hooks`].
+//!
+//! [`RZLibReader::set_hooks`]: crate::RZLibReader::set_hooks
+
+use crate::io;
+
+use crate::blockinfo::BlockType;
+use crate::gzheader::GzHeader;
+use crate::members::GzTrailer;
+
+/// Called by [`RZLibReader`](crate::RZLibReader) as it goes through the stream, to log progress,
+/// record offsets or enforce a policy without decoding the stream separately. All methods do
+/// nothing by default.
+///
+/// An error returned by a hook stops decoding: it is returned by the read in progress, with the
+/// position in the stream attached, and the reader is broken afterwards.
+pub trait DecodeHooks {
+    /// Called once the header of a gzip member or zlib stream has been read; `header` is `None`
+    /// for zlib. Not called for raw deflate streams.
+    fn on_member_start(&mut self, header: Option<&GzHeader>) -> io::Result<()> {
+        let _ = header;
+        Ok(())
+    }
+
+    /// Called once the trailer of a gzip member or zlib stream has been read and checked;
+    /// `trailer` is `None` for zlib, and for a gzip member whose missing trailer was accepted by
+    /// [`Strictness::Lenient`](crate::Strictness::Lenient).
+    fn on_member_end(&mut self, trailer: Option<&GzTrailer>) -> io::Result<()> {
+        let _ = trailer;
+        Ok(())
+    }
+
+    /// Called once the header of a deflate block has been read, before its data is decoded.
+    fn on_block(&mut self, block_type: BlockType, is_final: bool) -> io::Result<()> {
+        let _ = (block_type, is_final);
+        Ok(())
+    }
+}
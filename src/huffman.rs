@@ -1,11 +1,23 @@
-use std::fmt::Debug;
-use std::{cmp, fmt, io};
-use crate::bitreader::BitRead;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::{cmp, fmt};
 
+use crate::io;
+use crate::bitreader::{check_peek_bits, BitRead, BitSliceReader};
+use crate::error::RzlibError;
+use crate::deflatetables::{
+    fixed_deflate64_distance_lengths, fixed_distance_lengths, fixed_litlen_lengths,
+};
+
+/// A Huffman code of `len` bits, stored in the order they appear in the stream (first bit in
+/// the least significant position).
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Codeword {
-    len: usize,
-    code: u64,
+    pub(crate) len: usize,
+    pub(crate) code: u64,
 }
 
 fn bitmask(n: u64) -> u64 {
@@ -16,22 +28,30 @@ fn bitmask(n: u64) -> u64 {
     }
 }
 
-fn reverse_bits(a: u64, len: usize) -> u64 {
+const fn reverse_bits(a: u64, len: usize) -> u64 {
     let mut b: u64 = 0;
     let mut a: u64 = a;
-    for _ in 0..len {
+    let mut i = 0;
+    while i < len {
         b = (b << 1) | (a & 1);
         a >>= 1;
+        i += 1;
     }
     b
 }
 impl BitRead for Codeword {
     fn read_bits(&mut self, buf: &mut u64, n: usize) -> io::Result<usize> {
         let n = cmp::min(n, self.len);
-        *buf = *buf | self.code & bitmask(n as u64);
+        *buf |= self.code & bitmask(n as u64);
         self.code >>= n;
+        self.len -= n;
         Ok(n)
     }
+
+    fn peek_bits(&mut self, n: usize) -> io::Result<(u64, usize)> {
+        check_peek_bits(n)?;
+        Ok((self.code & bitmask(self.len as u64), self.len))
+    }
 }
 
 impl Codeword {
@@ -52,10 +72,30 @@ impl fmt::Display for Codeword {
     }
 }
 
+// Longest code a HuffmanTree holds, and so the deepest it gets. Deflate codes are at most 15 bits.
+const MAX_CODE_LEN: usize = 32;
+
+/// A binary tree mapping Huffman codes to values of type `R`, of codes of up to 32 bits.
+///
+/// Decoding walks the tree one bit at a time, which is slow; the decoder uses [`HuffmanTable`]
+/// instead, and the tree is kept around for debugging and inspecting codes.
 pub struct HuffmanTree<R: Debug> {
+    // nodes[0] is the root; children are indices into `nodes`, with 0 meaning no child (the root
+    // is nobody's child)
+    nodes: Vec<HuffmanNode<R>>,
+    // the code of each value, kept up to date by `insert`
+    encodings: BTreeMap<R, Codeword>,
+}
+
+struct HuffmanNode<R> {
     value: Option<R>,
-    zero: Box<Option<HuffmanTree<R>>>,
-    one: Box<Option<HuffmanTree<R>>>
+    children: [u32; 2],
+}
+
+impl<R> HuffmanNode<R> {
+    fn new() -> HuffmanNode<R> {
+        HuffmanNode { value: None, children: [0, 0] }
+    }
 }
 
 impl<R: Debug + Clone> fmt::Debug for HuffmanTree<R> {
@@ -64,128 +104,544 @@ impl<R: Debug + Clone> fmt::Debug for HuffmanTree<R> {
     }
 }
 
+impl<R: Debug + Clone> Default for HuffmanTree<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<R: Debug + Clone> HuffmanTree<R> {
     pub fn new() -> HuffmanTree<R> {
-        HuffmanTree { value: None, zero: Box::new(None), one: Box::new(None)}
+        HuffmanTree { nodes: vec![HuffmanNode::new()], encodings: BTreeMap::new() }
+    }
+
+    fn with_capacity(nodes: usize) -> HuffmanTree<R> {
+        let mut nodes = Vec::with_capacity(nodes);
+        nodes.push(HuffmanNode::new());
+        HuffmanTree { nodes, encodings: BTreeMap::new() }
     }
 
     pub fn size(&self) -> usize {
-        let mut s = 0;
-        if self.value.is_some() {
-            s += 1;
-        }
-        if let Some(t) = self.zero.as_ref() {
-            s += t.size();
+        self.nodes.iter().filter(|node| node.value.is_some()).count()
+    }
+
+}
+
+impl HuffmanTree<usize> {
+    /// Builds the canonical Huffman code described in RFC 1951, section 3.2.2, where symbol `i`
+    /// has a code of `lengths[i]` bits. Symbols with zero length are left out.
+    ///
+    /// Fails with [`RzlibError::InvalidCodeLengths`] if the lengths are oversubscribed, i.e.
+    /// describe more codes than fit in them, or longer than 32 bits. Incomplete codes are fine.
+    pub fn new_from_lengths(lengths: &[usize]) -> Result<HuffmanTree<usize>, RzlibError> {
+        if is_oversubscribed(lengths) {
+            return Err(RzlibError::InvalidCodeLengths);
         }
-        if let Some(t) = self.one.as_ref() {
-            s += t.size();
+        let codes = canonical_codes(lengths);
+        // a tree with n leaves has at most sum of code lengths + 1 nodes
+        let mut tree = HuffmanTree::with_capacity(1 + codes.iter().map(|c| c.len).sum::<usize>());
+        for (b, hcode) in codes.iter().enumerate() {
+            if hcode.len != 0 {
+                tree.insert(&b, hcode)?;
+            }
         }
-        s
+        Ok(tree)
     }
 
-    pub fn new_from_lengths(lengths: &[usize]) -> HuffmanTree<usize> {
-        let n = lengths.len();
-        let mut tree = HuffmanTree::new();
+    /// Builds an optimal canonical code for symbols with the given frequencies, with codes of at
+    /// most `max_len` bits, and returns it with its code lengths. See
+    /// [`HuffmanTree::lengths_from_frequencies`], which also tells when it panics.
+    pub fn new_from_frequencies(freqs: &[u64], max_len: usize) -> (HuffmanTree<usize>, Vec<usize>) {
+        let lengths = Self::lengths_from_frequencies(freqs, max_len);
+        let tree = Self::new_from_lengths(&lengths).expect("optimal lengths form a prefix code");
+        (tree, lengths)
+    }
 
-        let mut bl_count = vec![0; 32];
-        for l in lengths.iter() {
-            bl_count[*l as usize] += 1;
+    /// Returns the code lengths of an optimal prefix code for symbols with the given frequencies,
+    /// none of them longer than `max_len` bits, as deflate requires (15 bits, 7 for the code
+    /// length code). Symbols with frequency 0 get length 0, and a single used symbol gets a one
+    /// bit code.
+    ///
+    /// Uses the package-merge algorithm of Larmore and Hirschberg. Panics if `max_len` bits are
+    /// too few for the number of symbols used.
+    pub fn lengths_from_frequencies(freqs: &[u64], max_len: usize) -> Vec<usize> {
+        let mut lengths = vec![0; freqs.len()];
+        let mut symbols: Vec<usize> = (0..freqs.len()).filter(|&i| freqs[i] > 0).collect();
+        let n = symbols.len();
+        if n <= 1 {
+            if let Some(&symbol) = symbols.first() {
+                lengths[symbol] = 1;
+            }
+            return lengths;
         }
+        let capacity = 1_usize.checked_shl(max_len as u32).unwrap_or(usize::MAX);
+        assert!(n <= capacity, "{} symbols do not fit in codes of {} bits", n, max_len);
+        symbols.sort_by_key(|&i| freqs[i]);
+        let leaves: Vec<u64> = symbols.iter().map(|&i| freqs[i]).collect();
 
-
-        let mut next_code = vec![0_u64; 33];
-        let mut code= 0;
-        for bits in 1..=32 {
-            code = (code + bl_count[bits-1]) << 1;
-            next_code[bits] = code;
+        // lists[d] holds the items, leaves or packages of two items of lists[d - 1], from which
+        // codes of length max_len - d are made, ordered by weight (leaves first on ties)
+        let mut lists: Vec<Vec<(u64, bool)>> = vec![leaves.iter().map(|&w| (w, false)).collect()];
+        for _ in 1..max_len {
+            let previous = &lists[lists.len() - 1];
+            let mut packages = previous
+                .chunks_exact(2)
+                .map(|pair| pair[0].0 + pair[1].0)
+                .peekable();
+            let mut leaves = leaves.iter().copied().peekable();
+            let mut list = Vec::with_capacity(n + previous.len() / 2);
+            loop {
+                match (leaves.peek(), packages.peek()) {
+                    (Some(&leaf), Some(&package)) if leaf <= package => {
+                        list.push((leaf, false));
+                        leaves.next();
+                    }
+                    (_, Some(&package)) => {
+                        list.push((package, true));
+                        packages.next();
+                    }
+                    (Some(&leaf), None) => {
+                        list.push((leaf, false));
+                        leaves.next();
+                    }
+                    (None, None) => break,
+                }
+            }
+            lists.push(list);
         }
 
-        for b in 0..n {
-            let l = lengths[b];
-            if l != 0 {
-                let rev_code = reverse_bits(next_code[l], lengths[b]);
-                let hcode = Codeword::new(lengths[b], rev_code);
-                tree.insert(&b, &hcode);
-                next_code[l] += 1;
+        // the code is made of the lightest 2n - 2 items of the last list, packages standing for
+        // the two items of the previous list they are made of; each time a leaf is among the
+        // items taken, the code of its symbol gets one bit longer
+        let mut taken = 2 * n - 2;
+        for list in lists.iter().rev() {
+            let leaves_taken = list[..taken].iter().filter(|(_, package)| !package).count();
+            for &symbol in &symbols[..leaves_taken] {
+                lengths[symbol] += 1;
             }
+            taken = 2 * (taken - leaves_taken);
         }
-        tree
+        lengths
     }
+}
 
-    fn insert_impl(&mut self, val: &R, code: &mut Codeword, path: u64) {
-        if let Some(val) = &self.value {
-            panic!("found existing value {:?} at path {:b} while trying to insert {:?}", val, path, code)
+// Returns whether the code lengths describe more codes than fit in them (a Kraft sum above 1),
+// or codes longer than a HuffmanTree holds.
+fn is_oversubscribed(lengths: &[usize]) -> bool {
+    let mut bl_count = [0_i64; MAX_CODE_LEN + 1];
+    for &l in lengths {
+        match bl_count.get_mut(l) {
+            Some(count) => *count += 1,
+            None => return true,
         }
+    }
+    let mut left: i64 = 1;
+    for count in bl_count.iter().skip(1) {
+        left = (left << 1) - count;
+        if left < 0 {
+            return true;
+        }
+    }
+    false
+}
 
-        if code.len == 0 {
-            self.value = Some(val.clone());
-            return;
+// Returns whether the code lengths describe a prefix code deflate accepts: one that is neither
+// oversubscribed (Kraft sum above 1), which cannot be decoded, nor incomplete (Kraft sum below
+// 1), which leaves bit sequences that decode to nothing. With `allow_single`, as for literal/
+// length and distance codes, a single code of one bit, or no code at all, is accepted too.
+pub(crate) fn is_valid_code(lengths: &[usize], allow_single: bool) -> bool {
+    let mut left: i64 = 1;
+    let mut bl_count = [0; 16];
+    for &l in lengths {
+        bl_count[l] += 1;
+    }
+    for count in bl_count.iter().skip(1) {
+        left = (left << 1) - count;
+        if left < 0 {
+            return false;
         }
+    }
+    let codes = lengths.iter().filter(|&&l| l != 0).count();
+    left == 0 || (allow_single && codes <= 1 && bl_count[2..].iter().all(|&count| count == 0))
+}
 
-        let bit = code.code & 1;
-        code.code >>= 1;
-        code.len -= 1;
+// Assigns the canonical codes of RFC 1951, section 3.2.2, to symbols with the given code
+// lengths. Symbols with zero length get an empty codeword.
+pub(crate) fn canonical_codes(lengths: &[usize]) -> Vec<Codeword> {
+    let mut bl_count = vec![0; 33];
+    for l in lengths.iter() {
+        bl_count[*l] += 1;
+    }
+    bl_count[0] = 0;
 
-        match bit {
-            0 => {
-                    if self.zero.is_none() {
-                        self.zero = Box::new(Some(HuffmanTree::new()));
-                    }
-                    (*self.zero).as_mut().unwrap().insert_impl(val, code, (path << 1) | 0);
-                },
-            1 => {
-                    if self.one.is_none() {
-                        self.one = Box::new(Some(HuffmanTree::new()));
-                    }
-                    (*self.one).as_mut().unwrap().insert_impl(val, code, (path << 1) | 1);
-                }
-            _ => panic!("found bit neither 0 nor 1")
+    let mut next_code = vec![0_u64; 33];
+    let mut code = 0;
+    for bits in 1..=32 {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&l| {
+            if l == 0 {
+                return Codeword::new(0, 0);
+            }
+            let rev_code = reverse_bits(next_code[l], l);
+            next_code[l] += 1;
+            Codeword::new(l, rev_code)
+        })
+        .collect()
+}
+
+impl<R: Debug + Clone + Ord> HuffmanTree<R> {
+    /// Adds `code`, mapping to `val`. Fails with [`RzlibError::InvalidCodeLengths`], leaving the
+    /// tree as it was, if the code is longer than 32 bits, or if it, one of its prefixes or a
+    /// code it is a prefix of is already in the tree.
+    pub fn insert(&mut self, val: &R, code: &Codeword) -> Result<(), RzlibError> {
+        if code.len > MAX_CODE_LEN {
+            return Err(RzlibError::InvalidCodeLengths);
+        }
+        // check the path before adding any node to it
+        let mut node = Some(0);
+        for i in 0..code.len {
+            let Some(current) = node else { break };
+            if self.nodes[current].value.is_some() {
+                return Err(RzlibError::InvalidCodeLengths);
+            }
+            let child = self.nodes[current].children[((code.code >> i) & 1) as usize];
+            node = (child != 0).then_some(child as usize);
         }
+        if let Some(node) = node {
+            // an existing code, or a prefix of existing codes
+            if self.nodes[node].value.is_some() || self.nodes[node].children != [0, 0] {
+                return Err(RzlibError::InvalidCodeLengths);
+            }
+        }
+
+        let mut node = 0;
+        for i in 0..code.len {
+            let bit = ((code.code >> i) & 1) as usize;
+            if self.nodes[node].children[bit] == 0 {
+                self.nodes[node].children[bit] = self.nodes.len() as u32;
+                self.nodes.push(HuffmanNode::new());
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+        self.nodes[node].value = Some(val.clone());
+        self.encodings.insert(val.clone(), code.clone());
+        Ok(())
     }
-    pub fn insert(&mut self, val: &R, code: &Codeword) {
-        self.insert_impl(val, &mut code.clone(), 0);
+
+    /// Returns the code of `val`, or `None` if it has none. If `val` was inserted with several
+    /// codes, the last one is returned.
+    pub fn encode(&self, val: &R) -> Option<Codeword> {
+        self.encodings.get(val).cloned()
     }
+}
+
+impl<R: Debug + Clone> HuffmanTree<R> {
+    /// Reads a code from `bits` and returns the value it maps to, or `None` if the bits read do
+    /// not form a code of this tree.
     pub fn decode<T: BitRead>(&self, bits: &mut T) -> io::Result<Option<R>> {
-        if let Some(val) = self.value.as_ref() {
-            return Ok(Some(val.clone()));
-        }
-        let subtree = match bits.read_bits_exact(1)? {
-            0 => (*self.zero).as_ref(),
-            1 => (*self.one).as_ref(),
-            _ => panic!("found bit neither 0 nor 1")
-        };
-        match subtree {
-            None => Ok(None),
-            Some(t) => t.decode(bits),
-        }
-    }
-
-    fn dump_impl(&self, path: &Codeword) {
-        match &self.value {
-            Some(val) => eprintln!("{}: {:?}", path, val),
-            None => {
-                let zero_path = Codeword { code: (path.code << 1) | 0, len: path.len+1};
-                match (*self.zero).as_ref() {
-                    None => eprintln!("incomplete tree at {}", zero_path),
-                    Some(subtree) => subtree.dump_impl(&zero_path),
-                }
-                let one_path = Codeword { code: (path.code << 1) | 1, len: path.len+1};
-                match (*self.one).as_ref() {
-                    None => eprintln!("incomplete tree at {}", one_path),
-                    Some(subtree) => subtree.dump_impl(&one_path),
-                }
+        let mut node = &self.nodes[0];
+        // no path is longer than the longest code, but do not trust the tree with the loop bound
+        for _ in 0..=MAX_CODE_LEN {
+            if let Some(val) = node.value.as_ref() {
+                return Ok(Some(val.clone()));
+            }
+            match node.children[bits.read_bits_exact(1)? as usize] {
+                0 => return Ok(None),
+                child => node = &self.nodes[child as usize],
             }
         }
+        Ok(None)
     }
 
+    /// Returns every code of the tree and the value it maps to, in the order of the codes read
+    /// as binary numbers.
+    pub fn codes(&self) -> Vec<(R, Codeword)> {
+        let mut codes = vec![];
+        let _ = self.walk(&mut |value, code| {
+            if let Some(value) = value {
+                codes.push((value.clone(), code.clone()));
+            }
+            Ok::<(), ()>(())
+        });
+        codes
+    }
+
+    // Calls `f` with the value and code of every leaf, and with `None` and the code of every
+    // missing branch, which an incomplete code leaves, in the order of the codes. Iterative, so
+    // deep trees do not need a deep stack.
+    fn walk<E>(&self, f: &mut impl FnMut(Option<&R>, &Codeword) -> Result<(), E>) -> Result<(), E> {
+        // nodes left to visit, last first, `None` standing for a missing branch
+        let mut stack = vec![(Some(0), Codeword::new(0, 0))];
+        while let Some((node, path)) = stack.pop() {
+            let Some(node) = node else {
+                f(None, &path)?;
+                continue;
+            };
+            if let Some(value) = &self.nodes[node].value {
+                f(Some(value), &path)?;
+                continue;
+            }
+            for bit in [1, 0] {
+                let child_path = Codeword::new(path.len + 1, path.code | (bit as u64) << path.len);
+                let child = self.nodes[node].children[bit];
+                stack.push(((child != 0).then_some(child as usize), child_path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the tree to standard error, see the `Display` implementation.
+    #[cfg(feature = "std")]
     pub fn dump(&self) {
-        self.dump_impl(&Codeword{ code: 0, len: 0});
+        eprint!("{}", self);
+    }
+}
+
+/// Lists the codes of the tree, one `code: value` line each with the bits of the code in the
+/// order they are read, and an `incomplete tree at code` line for each code that is missing.
+impl<R: Debug + Clone> fmt::Display for HuffmanTree<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // an empty tree is a single node without a value, and without codes
+        if self.nodes.len() == 1 && self.nodes[0].value.is_none() {
+            return Ok(());
+        }
+        self.walk(&mut |value, code| match value {
+            Some(value) => writeln!(f, "{}: {:?}", code, value),
+            None => writeln!(f, "incomplete tree at {}", code),
+        })
+    }
+}
+// number of bits resolved by the first level of a HuffmanTable
+const ROOT_BITS: usize = 9;
+
+// The longest code deflate allows.
+const MAX_DEFLATE_CODE_LEN: usize = 15;
+
+// HuffmanTable entries pack a value (symbol or subtable offset) in the low 16 bits and a length
+// (code length or subtable index bits) in the next 8. An all-zero entry marks an invalid code.
+const LINK_FLAG: u32 = 1 << 31;
+
+const fn leaf_entry(symbol: usize, len: usize) -> u32 {
+    (len << 16 | symbol) as u32
+}
+
+fn link_entry(offset: usize, bits: usize) -> u32 {
+    LINK_FLAG | (bits << 16 | offset) as u32
+}
+
+fn entry_value(entry: u32) -> usize {
+    (entry & 0xffff) as usize
+}
+
+fn entry_len(entry: u32) -> usize {
+    ((entry >> 16) & 0xff) as usize
+}
+
+// Builds, at compile time, the entries of a table whose codes all fit in the root table, that is
+// of a code of at most ROOT_BITS bits, with `N` being 2 to the longest code length.
+const fn root_only_entries<const N: usize>(lengths: &[usize]) -> [u32; N] {
+    let mut bl_count = [0_u64; ROOT_BITS + 1];
+    let mut i = 0;
+    while i < lengths.len() {
+        bl_count[lengths[i]] += 1;
+        i += 1;
+    }
+    bl_count[0] = 0;
+    let mut next_code = [0_u64; ROOT_BITS + 1];
+    let mut code = 0;
+    let mut bits = 1;
+    while bits <= ROOT_BITS {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+        bits += 1;
+    }
+
+    let mut entries = [0; N];
+    let mut symbol = 0;
+    while symbol < lengths.len() {
+        let len = lengths[symbol];
+        if len != 0 {
+            let code = reverse_bits(next_code[len], len) as usize;
+            next_code[len] += 1;
+            // the code occupies every slot whose low bits match it
+            let mut slot = code;
+            while slot < N {
+                entries[slot] = leaf_entry(symbol, len);
+                slot += 1 << len;
+            }
+        }
+        symbol += 1;
+    }
+    entries
+}
+
+static FIXED_LITLEN_ENTRIES: [u32; 1 << 9] = root_only_entries(&fixed_litlen_lengths());
+static FIXED_DISTANCE_ENTRIES: [u32; 1 << 5] = root_only_entries(&fixed_distance_lengths());
+static FIXED_DEFLATE64_DISTANCE_ENTRIES: [u32; 1 << 5] =
+    root_only_entries(&fixed_deflate64_distance_lengths());
+
+/// Canonical Huffman code decoder using a two-level lookup table.
+///
+/// Codes of up to `ROOT_BITS` (9) bits are resolved by a single lookup in the root table; longer
+/// codes go through a second lookup in a subtable shared by all codes with the same 9-bit prefix.
+#[derive(Debug, Clone)]
+pub struct HuffmanTable {
+    root_bits: usize,
+    // borrowed for the fixed codes, which are built at compile time
+    entries: Cow<'static, [u32]>,
+}
+
+impl HuffmanTable {
+    /// Builds the decoding table of the canonical code where symbol `i` has a code of
+    /// `lengths[i]` bits, as in [`HuffmanTree::new_from_lengths`].
+    ///
+    /// Fails with [`RzlibError::InvalidCodeLengths`] unless the lengths form a code deflate
+    /// accepts: at most 15 bits long, and complete except for a single one-bit code or none.
+    pub fn new_from_lengths(lengths: &[usize]) -> Result<HuffmanTable, RzlibError> {
+        if lengths.iter().any(|&len| len > MAX_DEFLATE_CODE_LEN) || !is_valid_code(lengths, true) {
+            return Err(RzlibError::InvalidCodeLengths);
+        }
+        Ok(Self::build(lengths))
+    }
+
+    // Table that decodes no symbol, for when no block codes are set.
+    pub(crate) fn empty() -> HuffmanTable {
+        Self::build(&[])
+    }
+
+    // Builds the table without checking the lengths, which must be at most 15 bits long and not
+    // oversubscribed.
+    fn build(lengths: &[usize]) -> HuffmanTable {
+        let max_len = lengths.iter().copied().max().unwrap_or(0);
+        let root_bits = max_len.clamp(1, ROOT_BITS);
+        let root_mask = bitmask(root_bits as u64) as usize;
+        let codes = canonical_codes(lengths);
+
+        // size of the subtable needed for each root prefix, in index bits
+        let mut sub_bits = vec![0; 1 << root_bits];
+        for code in codes.iter().filter(|c| c.len > root_bits) {
+            let prefix = code.code as usize & root_mask;
+            sub_bits[prefix] = cmp::max(sub_bits[prefix], code.len - root_bits);
+        }
+
+        let mut entries = vec![0; 1 << root_bits];
+        for (prefix, &bits) in sub_bits.iter().enumerate() {
+            if bits > 0 {
+                entries[prefix] = link_entry(entries.len(), bits);
+                entries.resize(entries.len() + (1 << bits), 0);
+            }
+        }
+
+        for (symbol, code) in codes.iter().enumerate() {
+            if code.len == 0 {
+                continue;
+            }
+            let (offset, index, index_len, table_bits) = if code.len <= root_bits {
+                (0, code.code as usize, code.len, root_bits)
+            } else {
+                let link = entries[code.code as usize & root_mask];
+                let index = (code.code >> root_bits) as usize;
+                (entry_value(link), index, code.len - root_bits, entry_len(link))
+            };
+            // the code occupies every slot whose low bits match it
+            for high in 0..(1 << (table_bits - index_len)) {
+                entries[offset + (high << index_len | index)] = leaf_entry(symbol, code.len);
+            }
+        }
+
+        HuffmanTable { root_bits, entries: Cow::Owned(entries) }
+    }
+
+    /// The table of the fixed literal/length code of deflate, see RFC 1951, section 3.2.6.
+    /// Unlike [`HuffmanTable::new_from_lengths`], it allocates nothing.
+    pub fn fixed_litlen() -> HuffmanTable {
+        HuffmanTable { root_bits: 9, entries: Cow::Borrowed(&FIXED_LITLEN_ENTRIES) }
+    }
+
+    /// The table of the fixed distance code of deflate, see [`HuffmanTable::fixed_litlen`].
+    pub fn fixed_distance() -> HuffmanTable {
+        HuffmanTable { root_bits: 5, entries: Cow::Borrowed(&FIXED_DISTANCE_ENTRIES) }
+    }
+
+    /// The table of the fixed distance code of DEFLATE64, which has two more codes than that of
+    /// deflate.
+    pub fn fixed_deflate64_distance() -> HuffmanTable {
+        HuffmanTable { root_bits: 5, entries: Cow::Borrowed(&FIXED_DEFLATE64_DISTANCE_ENTRIES) }
+    }
+
+    /// Reads a code from `bits` and returns its symbol, or `None` if the bits read do not form
+    /// a code of this table.
+    ///
+    /// Input is only requested as far as needed to resolve the code, so a `BitReader` does not
+    /// take any byte past the end of the code from its underlying reader.
+    pub fn decode<T: BitRead>(&self, bits: &mut T) -> io::Result<Option<usize>> {
+        let mut needed = 1;
+        loop {
+            let (peeked, available) = bits.peek_bits(needed)?;
+            let (entry, lookup_bits) = self.lookup(peeked);
+            // with fewer bits than the lookup depends on, missing bits were taken as zeros
+            let len = entry_len(entry);
+            if len != 0 && len <= available {
+                bits.consume_bits(len)?;
+                return Ok(Some(entry_value(entry)));
+            }
+            if len == 0 && lookup_bits <= available {
+                return Ok(None);
+            }
+            if available < needed {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "got eof when reading huffman code"));
+            }
+            needed = available + 1;
+        }
+    }
+
+    // Like `decode`, but straight from the bits buffered by `bits`, refilling them only when
+    // fewer than the longest code are left.
+    #[inline]
+    pub(crate) fn decode_buffered(&self, bits: &mut BitSliceReader) -> io::Result<Option<usize>> {
+        let (peeked, available) = bits.fill(MAX_DEFLATE_CODE_LEN);
+        let (entry, lookup_bits) = self.lookup(peeked);
+        let len = entry_len(entry);
+        if len != 0 && len <= available {
+            bits.drop_bits(len);
+            return Ok(Some(entry_value(entry)));
+        }
+        if len == 0 && lookup_bits <= available {
+            return Ok(None);
+        }
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "got eof when reading huffman code"))
+    }
+
+    // Looks up the code starting at the low bits of `peeked`, and returns its entry and the
+    // number of bits the lookup depends on.
+    #[inline]
+    fn lookup(&self, peeked: u64) -> (u32, usize) {
+        let mut entry = self.entries[peeked as usize & bitmask(self.root_bits as u64) as usize];
+        let mut lookup_bits = self.root_bits;
+        if entry & LINK_FLAG != 0 {
+            let sub_bits = entry_len(entry);
+            let index = (peeked >> self.root_bits) as usize & bitmask(sub_bits as u64) as usize;
+            entry = self.entries[entry_value(entry) + index];
+            lookup_bits += sub_bits;
+        }
+        (entry, lookup_bits)
     }
 }
+
 #[cfg(test)]
 mod tests {
-    use super::HuffmanTree;
+    use super::{canonical_codes, is_valid_code, HuffmanTable, HuffmanTree};
     use super::Codeword;
+    use crate::error::RzlibError;
+    use crate::bitreader::{BitReader, BitSliceReader};
+    use crate::deflatetables::{
+        fixed_deflate64_distance_lengths, fixed_distance_lengths, fixed_litlen_lengths,
+    };
     impl From<(usize, u64)> for Codeword {
         fn from(value: (usize, u64)) -> Self {
             Codeword::new(value.0, value.1)
@@ -194,7 +650,7 @@ mod tests {
     #[test]
     fn test_from_rfc_1() {
         let ls = vec![2, 1, 3, 3];
-        let tree: HuffmanTree<usize> = HuffmanTree::<usize>::new_from_lengths(&ls);
+        let tree: HuffmanTree<usize> = HuffmanTree::<usize>::new_from_lengths(&ls).unwrap();
         let expected: Vec<Codeword> = vec![
             (2, 0b01),
             (1, 0b0),
@@ -206,23 +662,295 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_display_and_codes() {
+        let tree = HuffmanTree::new_from_lengths(&[2, 1, 3, 3]).unwrap();
+        assert_eq!(tree.to_string(), "0: 1\n10: 0\n110: 2\n111: 3\n");
+        let codes = tree.codes();
+        assert_eq!(codes.iter().map(|(symbol, _)| *symbol).collect::<Vec<_>>(), [1, 0, 2, 3]);
+        // the codes decode to their symbols
+        for (symbol, code) in codes {
+            assert_eq!(tree.decode(&mut code.clone()).unwrap(), Some(symbol));
+        }
+
+        let mut tree = HuffmanTree::new();
+        tree.insert(&'a', &Codeword::new(2, 0b10)).unwrap();
+        assert_eq!(tree.to_string(), "incomplete tree at 00\n01: 'a'\nincomplete tree at 1\n");
+        assert_eq!(HuffmanTree::<usize>::new().to_string(), "");
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // one code of each length up to 32 bits, the deepest a tree gets
+        let mut lengths: Vec<usize> = (1..=32).collect();
+        lengths.push(32);
+        let tree = HuffmanTree::new_from_lengths(&lengths).unwrap();
+        let codes = tree.codes();
+        assert_eq!(codes.len(), 33);
+        for (symbol, code) in codes {
+            assert_eq!(code.len, lengths[symbol]);
+            assert_eq!(tree.decode(&mut code.clone()).unwrap(), Some(symbol));
+        }
+        assert_eq!(tree.to_string().lines().count(), 33);
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        // three codes of one bit, and codes too long for the tree
+        for lengths in [&[1, 1, 1][..], &[1, 2, 3, 3, 3], &[33, 1], &[usize::MAX]] {
+            let err = HuffmanTree::new_from_lengths(lengths).unwrap_err();
+            assert_eq!(err, RzlibError::InvalidCodeLengths);
+        }
+        // incomplete codes are fine, the missing ones decode to nothing
+        let tree = HuffmanTree::new_from_lengths(&[2, 2, 0, 2]).unwrap();
+        assert_eq!(tree.decode(&mut Codeword::new(2, 0b11)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_insert_errors() {
+        let mut tree = HuffmanTree::new();
+        let err = tree.insert(&0, &Codeword::new(33, 0)).unwrap_err();
+        assert_eq!(err, RzlibError::InvalidCodeLengths);
+        tree.insert(&1, &Codeword::new(2, 0b01)).unwrap();
+        // the same code, a prefix of it, and a code it is a prefix of
+        for code in [Codeword::new(2, 0b01), Codeword::new(1, 0b1), Codeword::new(3, 0b101)] {
+            assert_eq!(tree.insert(&2, &code), Err(RzlibError::InvalidCodeLengths));
+        }
+        assert_eq!(tree.codes(), [(1, Codeword::new(2, 0b01))]);
+        assert_eq!(tree.encode(&2), None);
+        tree.insert(&2, &Codeword::new(2, 0b11)).unwrap();
+        assert_eq!(tree.to_string(), "incomplete tree at 0\n10: 1\n11: 2\n");
+    }
+
+    #[test]
+    fn test_encode() {
+        let lengths = [3, 3, 3, 3, 3, 2, 4, 4, 0];
+        let tree = HuffmanTree::new_from_lengths(&lengths).unwrap();
+        for (symbol, &len) in lengths.iter().enumerate().take(8) {
+            let code = tree.encode(&symbol).unwrap();
+            assert_eq!(code.len, len);
+            assert_eq!(tree.decode(&mut code.clone()).unwrap(), Some(symbol));
+        }
+        assert_eq!(tree.encode(&5), Some(Codeword::new(2, 0b00)));
+        assert_eq!(tree.encode(&8), None);
+        assert_eq!(tree.encode(&9), None);
+
+        // codes added by hand, of any type of value
+        let mut tree = HuffmanTree::new();
+        assert_eq!(tree.encode(&'a'), None);
+        tree.insert(&'a', &Codeword::new(1, 0b0)).unwrap();
+        tree.insert(&'b', &Codeword::new(2, 0b01)).unwrap();
+        assert_eq!(tree.encode(&'a'), Some(Codeword::new(1, 0b0)));
+        assert_eq!(tree.encode(&'b'), Some(Codeword::new(2, 0b01)));
+        assert_eq!(tree.encode(&'c'), None);
+    }
+
+    // Cost of the optimal code of `freqs` with lengths of at most `max_len`, by trying every
+    // assignment of lengths that satisfies the Kraft inequality with equality.
+    fn best_cost(freqs: &[u64], max_len: usize) -> u64 {
+        fn search(freqs: &[u64], max_len: usize, left: u64, unit: u64, cost: u64) -> Option<u64> {
+            let Some((&f, rest)) = freqs.split_first() else {
+                return (left == 0).then_some(cost);
+            };
+            (1..=max_len)
+                .filter(|&l| unit >> l <= left)
+                .filter_map(|l| {
+                    search(rest, max_len, left - (unit >> l), unit, cost + f * l as u64)
+                })
+                .min()
+        }
+        search(freqs, max_len, 1 << max_len, 1 << max_len, 0).unwrap()
+    }
+
+    #[test]
+    fn test_lengths_from_frequencies() {
+        let lengths = HuffmanTree::lengths_from_frequencies;
+        assert_eq!(lengths(&[1, 1, 2, 4], 15), [3, 3, 2, 1]);
+        assert_eq!(lengths(&[0, 7, 0], 15), [0, 1, 0]);
+        assert_eq!(lengths(&[0, 0], 7), [0, 0]);
+        // the limit flattens the tree
+        assert_eq!(lengths(&[1, 1, 2, 4], 2), [2, 2, 2, 2]);
+        assert_eq!(lengths(&[1, 2, 4, 8, 16], 3), [3, 3, 3, 3, 1]);
+
+        let freqs_list: [&[u64]; 4] = [
+            &[5, 1, 1, 3, 9, 2, 2],
+            &[1, 1, 1, 2, 3, 5, 8, 13],
+            &[10, 0, 3, 3, 0, 1, 40, 2],
+            &[7, 7, 7, 7, 7, 7],
+        ];
+        for freqs in freqs_list {
+            for max_len in 3..=5 {
+                let lengths = lengths(freqs, max_len);
+                let used: Vec<u64> = freqs.iter().copied().filter(|&f| f > 0).collect();
+                assert!(is_valid_code(&lengths, false), "{:?} {}", freqs, max_len);
+                assert!(lengths.iter().all(|&l| l <= max_len));
+                let cost: u64 = freqs.iter().zip(&lengths).map(|(&f, &l)| f * l as u64).sum();
+                assert_eq!(cost, best_cost(&used, max_len), "{:?} {}", freqs, max_len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_from_frequencies() {
+        let freqs = [40, 0, 3, 3, 12, 1, 1];
+        let (tree, lengths) = HuffmanTree::new_from_frequencies(&freqs, 15);
+        assert_eq!(lengths, HuffmanTree::lengths_from_frequencies(&freqs, 15));
+        assert_eq!(tree.size(), 6);
+        for (symbol, &len) in lengths.iter().enumerate() {
+            let code = tree.encode(&symbol);
+            assert_eq!(code.as_ref().map_or(0, |code| code.len), len);
+            if let Some(code) = code {
+                assert_eq!(tree.decode(&mut code.clone()).unwrap(), Some(symbol));
+            }
+        }
+        // the most frequent symbol gets the shortest code
+        assert_eq!(lengths[0], 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lengths_from_frequencies_too_short() {
+        HuffmanTree::lengths_from_frequencies(&[1; 9], 3);
+    }
+
     #[test]
     fn test_from_rfc2() {
         let ls = vec![3, 3, 3, 3, 3, 2, 4, 4];
-        let tree: HuffmanTree<usize> = HuffmanTree::<usize>::new_from_lengths(&ls);
+        let tree: HuffmanTree<usize> = HuffmanTree::<usize>::new_from_lengths(&ls).unwrap();
+        assert_eq!(tree.size(), 8);
+        // the codewords as read, least significant bit first
         let expected: Vec<Codeword> = vec![
             (3, 0b010),
-            (3, 0b011),
-            (3, 0b100),
-            (3, 0b101),
             (3, 0b110),
+            (3, 0b001),
+            (3, 0b101),
+            (3, 0b011),
             (2, 0b00),
-            (4, 0b1110),
+            (4, 0b0111),
             (4, 0b1111),
         ].into_iter().map(|p| p.into()).collect();
         for (a, code) in expected.iter().enumerate() {
             assert_eq!(Some(a), tree.decode(&mut code.clone()).unwrap())
         }
+
+        let table = HuffmanTable::new_from_lengths(&ls).unwrap();
+        for (a, code) in expected.iter().enumerate() {
+            assert_eq!(Some(a), table.decode(&mut code.clone()).unwrap())
+        }
+    }
+
+    // packs the codes of `symbols` into bytes, in the order they are read by BitReader
+    fn encode(lengths: &[usize], symbols: &[usize]) -> Vec<u8> {
+        let codes = canonical_codes(lengths);
+        let mut bytes = vec![];
+        let (mut buf, mut count) = (0u64, 0);
+        for &symbol in symbols {
+            buf |= codes[symbol].code << count;
+            count += codes[symbol].len;
+            while count >= 8 {
+                bytes.push(buf as u8);
+                buf >>= 8;
+                count -= 8;
+            }
+        }
+        if count > 0 {
+            bytes.push(buf as u8);
+        }
+        bytes
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_is_valid_code() {
+        assert!(is_valid_code(&[2, 1, 3, 3], false));
+        // oversubscribed
+        assert!(!is_valid_code(&[1, 1, 1], true));
+        assert!(!is_valid_code(&[2, 1, 2, 3], true));
+        // incomplete
+        assert!(!is_valid_code(&[2, 1, 0, 3], true));
+        assert!(!is_valid_code(&[0, 2, 0], true));
+        // a single one-bit code, or none, only where allowed
+        assert!(is_valid_code(&[0, 1, 0], true));
+        assert!(!is_valid_code(&[0, 1, 0], false));
+        assert!(is_valid_code(&[0, 0], true));
+    }
+
+    #[test]
+    fn test_table_long_codes() {
+        // lengths 1..=15 plus a second 15-bit code, so the longest codes need subtables
+        let mut ls: Vec<usize> = (1..=15).collect();
+        ls.push(15);
+        let symbols: Vec<usize> = (0..ls.len()).rev().chain(0..ls.len()).collect();
+        let data = encode(&ls, &symbols);
+
+        let table = HuffmanTable::new_from_lengths(&ls).unwrap();
+        let mut reader = BitSliceReader::new(&data);
+        for &symbol in &symbols {
+            assert_eq!(table.decode(&mut reader).unwrap(), Some(symbol));
+        }
+        let mut reader = BitSliceReader::new(&data);
+        for &symbol in &symbols {
+            assert_eq!(table.decode_buffered(&mut reader).unwrap(), Some(symbol));
+        }
+
+        let tree = HuffmanTree::new_from_lengths(&ls).unwrap();
+        let mut reader = BitSliceReader::new(&data);
+        for &symbol in &symbols {
+            assert_eq!(tree.decode(&mut reader).unwrap(), Some(symbol));
+        }
+    }
+
+    #[test]
+    fn test_fixed_tables() {
+        let litlen = HuffmanTable::fixed_litlen();
+        let expected = HuffmanTable::new_from_lengths(&fixed_litlen_lengths()).unwrap();
+        assert_eq!((litlen.root_bits, &litlen.entries), (expected.root_bits, &expected.entries));
+        let distance = HuffmanTable::fixed_distance();
+        let expected = HuffmanTable::build(&fixed_distance_lengths());
+        assert_eq!((distance.root_bits, &distance.entries), (expected.root_bits, &expected.entries));
+        let distance = HuffmanTable::fixed_deflate64_distance();
+        let expected =
+            HuffmanTable::new_from_lengths(&fixed_deflate64_distance_lengths()).unwrap();
+        assert_eq!((distance.root_bits, &distance.entries), (expected.root_bits, &expected.entries));
+    }
+
+    #[test]
+    fn test_table_invalid_code() {
+        // incomplete code: 0 -> 0, 10 -> 2, and 11 is unused
+        let table = HuffmanTable::build(&[1, 0, 2]);
+        let mut reader = BitSliceReader::new(&[0b1110]);
+        assert_eq!(table.decode(&mut reader).unwrap(), Some(0));
+        assert_eq!(table.decode(&mut reader).unwrap(), None);
+        let mut reader = BitSliceReader::new(&[0b1110]);
+        assert_eq!(table.decode_buffered(&mut reader).unwrap(), Some(0));
+        assert_eq!(table.decode_buffered(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_table_invalid_lengths() {
+        // oversubscribed, incomplete, and longer than deflate allows
+        let mut long: Vec<usize> = (1..=16).collect();
+        long.push(16);
+        for lengths in [&[1, 1, 1][..], &[1, 0, 2], &[0, 2, 0], &long, &[33, 1], &[usize::MAX]] {
+            let err = HuffmanTable::new_from_lengths(lengths).unwrap_err();
+            assert_eq!(err, RzlibError::InvalidCodeLengths);
+        }
+        // a single one-bit code, or none at all, is allowed
+        assert!(HuffmanTable::new_from_lengths(&[0, 1]).is_ok());
+        assert!(HuffmanTable::new_from_lengths(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_table_bytes_after_codes() {
+        let ls = vec![3, 3, 3, 3, 3, 2, 4, 4];
+        let table = HuffmanTable::new_from_lengths(&ls).unwrap();
+        let mut data = encode(&ls, &[5, 6]);
+        data.push(0xab);
+        let mut reader = BitReader::new(&data[..]);
+        assert_eq!(table.decode(&mut reader).unwrap(), Some(5));
+        assert_eq!(table.decode(&mut reader).unwrap(), Some(6));
+        reader.drop_remaining_bits();
+        assert_eq!(reader.read_u8().unwrap(), 0xab);
+        assert!(table.decode(&mut reader).is_err());
+    }
+
+}
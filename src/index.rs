@@ -0,0 +1,211 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use rzlib::{GzIndex, IndexedGzReader};
+
+use crate::cli::DEFAULT_SPAN;
+use crate::{stdin_is_input, EXIT_ERROR};
+
+/// Where the index of `file` is saved: `file.gz` has its index in `file.gz.rzi`.
+pub fn index_path(file: &Path) -> PathBuf {
+    let mut path = OsString::from(file);
+    path.push(".rzi");
+    PathBuf::from(path)
+}
+
+// Builds the index of `file`, saves it next to it and prints what it holds to `out`.
+fn index_file<W: Write>(file: &Path, span: u64, out: &mut W) -> io::Result<()> {
+    if stdin_is_input(file) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot index standard input",
+        ));
+    }
+    let compressed = fs::metadata(file)?.len();
+    let index = GzIndex::build(BufReader::new(File::open(file)?), span)?;
+    let bytes = index.to_bytes();
+    let path = index_path(file);
+    fs::write(&path, &bytes)?;
+    writeln!(
+        out,
+        "{}: {} checkpoints, {} bytes compressed, {} uncompressed, index of {} bytes in {}",
+        file.display(),
+        index.checkpoints().len(),
+        compressed,
+        index.total_out(),
+        bytes.len(),
+        path.display()
+    )
+}
+
+/// Indexes each file in turn, with checkpoints every `span` bytes of decompressed data. Returns
+/// the exit status.
+pub fn index_files(files: &[PathBuf], span: u64) -> i32 {
+    let mut stdout = io::stdout().lock();
+    let mut status = 0;
+    for file in files {
+        if let Err(err) = index_file(file, span, &mut stdout) {
+            let _ = stdout.flush();
+            eprintln!("rzlib: {}: {}", file.display(), err);
+            status = EXIT_ERROR;
+        }
+    }
+    status
+}
+
+// Whether `index` is still that of `file`: the file has the same size, and ends with the same
+// trailer, as when the index was built.
+fn is_index_of(index: &GzIndex, file: &Path) -> io::Result<bool> {
+    let mut file = File::open(file)?;
+    if file.metadata()?.len() != index.total_in() {
+        return Ok(false);
+    }
+    let Some(trailer) = index.trailer() else {
+        return Ok(true);
+    };
+    let mut bytes = [0; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes[..4] == trailer.crc32.to_le_bytes() && bytes[4..] == trailer.isize.to_le_bytes())
+}
+
+// The saved index of `file`, or a new one if there is none or `file` changed since it was saved.
+fn load_index(file: &Path) -> io::Result<GzIndex> {
+    let path = index_path(file);
+    let index = match fs::read(&path) {
+        Ok(bytes) => GzIndex::from_bytes(&bytes)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return GzIndex::build(BufReader::new(File::open(file)?), DEFAULT_SPAN);
+        }
+        Err(err) => return Err(err),
+    };
+    if is_index_of(&index, file)? {
+        return Ok(index);
+    }
+    eprintln!("rzlib: {}: out of date, ignored", path.display());
+    GzIndex::build(BufReader::new(File::open(file)?), index.span())
+}
+
+// Writes `len` bytes, or all of them, of the decompressed data of `file` from `start` on to
+// `out`. Returns the number of bytes written, fewer if the data ends before.
+fn extract_range<W: Write>(
+    file: &Path,
+    start: u64,
+    len: Option<u64>,
+    out: &mut W,
+) -> io::Result<u64> {
+    if stdin_is_input(file) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot seek standard input",
+        ));
+    }
+    let index = load_index(file)?;
+    let mut reader = IndexedGzReader::new(BufReader::new(File::open(file)?), index)?;
+    reader.seek(SeekFrom::Start(start))?;
+    io::copy(&mut reader.take(len.unwrap_or(u64::MAX)), out)
+}
+
+/// Writes a range of the decompressed data of `file` to standard output, see
+/// [`extract_range`]. Returns the exit status.
+pub fn range_file(file: &Path, start: u64, len: Option<u64>) -> i32 {
+    let mut stdout = io::stdout().lock();
+    match extract_range(file, start, len, &mut stdout).and_then(|_| stdout.flush()) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("rzlib: {}: {}", file.display(), err);
+            EXIT_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use rzlib::{Compression, GzEncoder, GzIndex, IndexedGzReader};
+
+    use super::{extract_range, index_file, index_path, is_index_of};
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_index_file() {
+        let dir = TestDir::new("cli-index");
+        let data: Vec<u8> = (0..400_000u32)
+            .flat_map(|i| (i.wrapping_mul(2_654_435_761) >> 7).to_le_bytes())
+            .collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let file = dir.join("data.gz");
+        fs::write(&file, encoder.finish().unwrap()).unwrap();
+
+        let mut out = vec![];
+        index_file(&file, 256 * 1024, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(" uncompressed, index of "), "{}", out);
+        assert_eq!(index_path(&file), dir.join("data.gz.rzi"));
+        let index = GzIndex::from_bytes(&fs::read(index_path(&file)).unwrap()).unwrap();
+        assert_eq!(index.total_out(), data.len() as u64);
+        assert!(out.contains(&format!("{} checkpoints", index.checkpoints().len())));
+        assert!(index.checkpoints().len() >= 3);
+
+        let mut reader = IndexedGzReader::new(
+            std::io::BufReader::new(fs::File::open(&file).unwrap()),
+            index,
+        )
+        .unwrap();
+        reader.seek(SeekFrom::Start(1_000_000)).unwrap();
+        let mut buf = [0; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[1_000_000..1_000_100]);
+
+        // the range, with the saved index and without
+        for _ in 0..2 {
+            let mut out = vec![];
+            assert_eq!(
+                extract_range(&file, 700_000, Some(1000), &mut out).unwrap(),
+                1000
+            );
+            assert_eq!(out, data[700_000..701_000]);
+            let mut out = vec![];
+            extract_range(&file, data.len() as u64 - 10, None, &mut out).unwrap();
+            assert_eq!(out, data[data.len() - 10..]);
+            let _ = fs::remove_file(index_path(&file));
+        }
+        assert_eq!(
+            extract_range(&file, data.len() as u64 + 1, Some(10), &mut vec![]).unwrap(),
+            0
+        );
+
+        // a stale index, of the same data compressed differently, or of other data of the same
+        // size
+        index_file(&file, 256 * 1024, &mut vec![]).unwrap();
+        let index = GzIndex::from_bytes(&fs::read(index_path(&file)).unwrap()).unwrap();
+        assert!(is_index_of(&index, &file).unwrap());
+        let stored = |data: &[u8]| {
+            let mut encoder = GzEncoder::new(vec![], Compression::none());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        fs::write(&file, stored(&data)).unwrap();
+        assert!(!is_index_of(&index, &file).unwrap());
+        let mut out = vec![];
+        extract_range(&file, 700_000, Some(1000), &mut out).unwrap();
+        assert_eq!(out, data[700_000..701_000]);
+        let index = GzIndex::build(&stored(&data)[..], 256 * 1024).unwrap();
+        fs::write(&file, stored(&data.iter().map(|b| !b).collect::<Vec<u8>>())).unwrap();
+        assert_eq!(fs::metadata(&file).unwrap().len(), index.total_in());
+        assert!(!is_index_of(&index, &file).unwrap());
+
+        fs::write(index_path(&file), b"not an index").unwrap();
+        assert!(extract_range(&file, 0, None, &mut vec![]).is_err());
+        assert!(extract_range("-".as_ref(), 0, None, &mut vec![]).is_err());
+
+        assert!(index_file(&dir.join("missing.gz"), 1024, &mut vec![]).is_err());
+        assert!(index_file("-".as_ref(), 1024, &mut vec![]).is_err());
+    }
+}
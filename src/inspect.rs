@@ -0,0 +1,208 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use rzlib::{BlockInfo, BlockType, GzHeader, RZLibReader};
+
+use crate::{stdin_is_input, EXIT_ERROR};
+
+// The nonzero code lengths, as `symbol:length`.
+fn format_lengths(lengths: &[u8]) -> String {
+    let mut out = String::new();
+    for (symbol, &len) in lengths.iter().enumerate().filter(|(_, &len)| len > 0) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let _ = write!(out, "{}:{}", symbol, len);
+    }
+    out
+}
+
+fn print_header<W: Write>(
+    out: &mut W,
+    member: u64,
+    offset: u64,
+    header: &GzHeader,
+) -> io::Result<()> {
+    write!(
+        out,
+        "member {} at byte {}: mtime {}, xfl {}, os {}",
+        member, offset, header.mtime, header.xfl, header.os
+    )?;
+    if header.text {
+        write!(out, ", text")?;
+    }
+    if let Some(extra) = &header.extra {
+        write!(out, ", extra {} bytes", extra.len())?;
+    }
+    if let Some(filename) = header.filename_lossy() {
+        write!(out, ", name {:?}", filename)?;
+    }
+    if let Some(comment) = header.comment_lossy() {
+        write!(out, ", comment {:?}", comment)?;
+    }
+    writeln!(out)
+}
+
+// `offset` is the byte offset of the member the block belongs to.
+fn print_block<W: Write>(out: &mut W, block: &BlockInfo, offset: u64) -> io::Result<()> {
+    let block_type = match block.block_type {
+        BlockType::Stored => "stored",
+        BlockType::FixedHuffman => "fixed Huffman",
+        BlockType::DynamicHuffman => "dynamic Huffman",
+    };
+    let is_final = if block.is_final { ", final" } else { "" };
+    writeln!(
+        out,
+        "  block {} at bit {}: {}{}",
+        block.block,
+        offset * 8 + block.in_bits,
+        block_type,
+        is_final
+    )?;
+    if let Some(dynamic) = &block.dynamic {
+        writeln!(
+            out,
+            "    HLIT {}, HDIST {}, HCLEN {}",
+            dynamic.nlit - 257,
+            dynamic.ndist - 1,
+            dynamic.ncode - 4
+        )?;
+        writeln!(
+            out,
+            "    code length code: {}",
+            format_lengths(&dynamic.code_length_lengths)
+        )?;
+        writeln!(
+            out,
+            "    literal/length code: {}",
+            format_lengths(&dynamic.litlen_lengths)
+        )?;
+        writeln!(
+            out,
+            "    distance code: {}",
+            format_lengths(&dynamic.distance_lengths)
+        )?;
+    }
+    if block.block_type != BlockType::Stored {
+        writeln!(
+            out,
+            "    {} literals, {} matches",
+            block.literals, block.matches
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the structure of every gzip member of `reader` to `out`: its header, then the type and
+/// position of each block, with the codes of dynamic blocks and the counts of literals and
+/// matches. On a decoding error, what was decoded up to it is printed first.
+pub fn inspect_stream<R: BufRead, W: Write>(mut reader: R, out: &mut W) -> io::Result<()> {
+    let mut member = 0;
+    let mut offset = 0;
+    while !reader.fill_buf()?.is_empty() {
+        member += 1;
+        let mut decoder = RZLibReader::new(&mut reader);
+        decoder.set_multi_member(false);
+        decoder.set_record_blocks(true);
+        let result = io::copy(&mut decoder, &mut io::sink());
+        if let Some(header) = decoder.header() {
+            print_header(out, member, offset, header)?;
+        }
+        for block in decoder.blocks() {
+            print_block(out, block, offset)?;
+        }
+        result?;
+        offset += decoder.total_in();
+        writeln!(out, "  trailer ends at byte {}", offset)?;
+    }
+    Ok(())
+}
+
+/// Inspects each file in turn, see [`inspect_stream`]. Returns the exit status.
+pub fn inspect_files(files: &[PathBuf]) -> i32 {
+    let stdin = [PathBuf::from("-")];
+    let files = if files.is_empty() { &stdin[..] } else { files };
+    let mut stdout = io::stdout().lock();
+    let mut status = 0;
+    for file in files {
+        let (name, result) = if stdin_is_input(file) {
+            (
+                Path::new("stdin"),
+                inspect_stream(io::stdin().lock(), &mut stdout),
+            )
+        } else {
+            let _ = writeln!(stdout, "{}:", file.display());
+            let result =
+                File::open(file).and_then(|f| inspect_stream(BufReader::new(f), &mut stdout));
+            (file.as_path(), result)
+        };
+        if let Err(err) = result {
+            let _ = stdout.flush();
+            eprintln!("rzlib: {}: {}", name.display(), err);
+            status = EXIT_ERROR;
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rzlib::{Compression, GzEncoder, GzHeader};
+
+    use super::{format_lengths, inspect_stream};
+
+    fn inspect(input: &[u8]) -> (String, bool) {
+        let mut out = vec![];
+        let result = inspect_stream(input, &mut out);
+        (String::from_utf8(out).unwrap(), result.is_ok())
+    }
+
+    #[test]
+    fn test_inspect_stream() {
+        let header = GzHeader {
+            filename: Some(b"data.txt".to_vec()),
+            ..GzHeader::default()
+        };
+        let mut encoder = GzEncoder::with_header(vec![], Compression::none(), header);
+        encoder.write_all(b"stored").unwrap();
+        let first = encoder.finish().unwrap();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&b"abcabcabc, ".repeat(100)).unwrap();
+        let second = encoder.finish().unwrap();
+        let input = [&first[..], &second[..]].concat();
+
+        let (out, ok) = inspect(&input);
+        assert!(ok);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[..3],
+            [
+                "member 1 at byte 0: mtime 0, xfl 0, os 0, name \"data.txt\"",
+                "  block 1 at bit 152: stored, final",
+                &format!("  trailer ends at byte {}", first.len()),
+            ]
+        );
+        assert!(lines[3].starts_with(&format!("member 2 at byte {}: ", first.len())));
+        assert!(lines[4].starts_with("  block 1 at bit "), "{}", lines[4]);
+        assert!(lines.iter().any(|line| line.ends_with(" matches")));
+        assert_eq!(
+            lines.last().unwrap(),
+            &format!("  trailer ends at byte {}", input.len())
+        );
+
+        // what precedes an error is printed
+        let (out, ok) = inspect(&input[..first.len() + 15]);
+        assert!(!ok);
+        assert!(out.contains("member 2 at byte"));
+    }
+
+    #[test]
+    fn test_format_lengths() {
+        assert_eq!(format_lengths(&[0, 2, 0, 3, 3]), "1:2 3:3 4:3");
+        assert_eq!(format_lengths(&[0, 0]), "");
+    }
+}
@@ -0,0 +1,221 @@
+//! The I/O traits the decoder and encoder are written against.
+//!
+//! With the `std` feature (on by default) these are simply the ones from `std::io`. Without it,
+//! they are the minimal stand-ins below, so that the library builds for `no_std` targets with an
+//! allocator: implement [`BufRead`] for the compressed input, or [`Write`] for the compressed
+//! output, and use [`RZLibReader`](crate::RZLibReader) and the encoders as usual.
+
+#[cfg(feature = "std")]
+pub use std::io::{BufRead, Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::core_io::{BufRead, Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+mod core_io {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::{cmp, fmt, result};
+
+    type BoxError = Box<dyn core::error::Error + Send + Sync>;
+
+    /// The subset of `std::io::ErrorKind` used by the library.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidInput,
+        InvalidData,
+        UnexpectedEof,
+        WriteZero,
+        WouldBlock,
+        Interrupted,
+        Unsupported,
+        Other,
+    }
+
+    impl ErrorKind {
+        fn as_str(&self) -> &'static str {
+            match self {
+                ErrorKind::InvalidInput => "invalid input parameter",
+                ErrorKind::InvalidData => "invalid data",
+                ErrorKind::UnexpectedEof => "unexpected end of file",
+                ErrorKind::WriteZero => "write zero",
+                ErrorKind::WouldBlock => "operation would block",
+                ErrorKind::Interrupted => "operation interrupted",
+                ErrorKind::Unsupported => "unsupported",
+                ErrorKind::Other => "other error",
+            }
+        }
+    }
+
+    /// Mirrors `std::io::Error`: a kind, and optionally the error that caused it.
+    pub struct Error {
+        kind: ErrorKind,
+        error: Option<BoxError>,
+    }
+
+    impl Error {
+        pub fn new<E: Into<BoxError>>(kind: ErrorKind, error: E) -> Error {
+            Error { kind, error: Some(error.into()) }
+        }
+
+        pub fn other<E: Into<BoxError>>(error: E) -> Error {
+            Error::new(ErrorKind::Other, error)
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+
+        pub fn get_ref(&self) -> Option<&(dyn core::error::Error + Send + Sync + 'static)> {
+            self.error.as_deref()
+        }
+
+        pub fn into_inner(self) -> Option<BoxError> {
+            self.error
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Error {
+            Error { kind, error: None }
+        }
+    }
+
+    impl fmt::Debug for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.error {
+                Some(error) => {
+                    f.debug_struct("Error").field("kind", &self.kind).field("error", error).finish()
+                }
+                None => f.debug_tuple("Kind").field(&self.kind).finish(),
+            }
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.error {
+                Some(error) => error.fmt(f),
+                None => f.write_str(self.kind.as_str()),
+            }
+        }
+    }
+
+    impl core::error::Error for Error {
+        fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+            self.error.as_ref()?.source()
+        }
+    }
+
+    pub type Result<T> = result::Result<T, Error>;
+
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf) {
+                    Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+                    Ok(n) => buf = &mut buf[n..],
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        }
+
+        fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+            let start = out.len();
+            let mut buf = [0; 4096];
+            loop {
+                match self.read(&mut buf) {
+                    Ok(0) => return Ok(out.len() - start),
+                    Ok(n) => out.extend_from_slice(&buf[..n]),
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+
+    pub trait BufRead: Read {
+        fn fill_buf(&mut self) -> Result<&[u8]>;
+
+        fn consume(&mut self, amt: usize);
+    }
+
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        fn flush(&mut self) -> Result<()>;
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf) {
+                    Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+                    Ok(n) => buf = &buf[n..],
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = cmp::min(buf.len(), self.len());
+            buf[..n].copy_from_slice(&self[..n]);
+            *self = &self[n..];
+            Ok(n)
+        }
+    }
+
+    impl BufRead for &[u8] {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            Ok(self)
+        }
+
+        fn consume(&mut self, amt: usize) {
+            *self = &self[amt..];
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+
+    impl<R: BufRead + ?Sized> BufRead for &mut R {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            (**self).fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            (**self).consume(amt)
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+}
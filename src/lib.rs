@@ -1,5 +1,114 @@
+//! A pure-Rust gzip decoder and encoder.
+//!
+//! [`RZLibReader`] wraps any [`BufRead`](std::io::BufRead) producing a gzip stream and implements
+//! [`Read`](std::io::Read) over the decompressed data:
+//!
+//! ```
+//! use std::io::Read;
+//! use rzlib::RZLibReader;
+//!
+//! let compressed: &[u8] = &[
+//!     0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x0d, 0x00, 0xf2, 0xff,
+//!     0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x7a, 0x6c, 0x69, 0x62, 0x0a, 0xd3, 0xb8,
+//!     0xbe, 0xec, 0x0d, 0x00, 0x00, 0x00,
+//! ];
+//! let mut decompressed = String::new();
+//! RZLibReader::new(compressed).read_to_string(&mut decompressed).unwrap();
+//! assert_eq!(decompressed, "hello, rzlib\n");
+//! ```
+//!
+//! The building blocks of the decoder ([`BitReader`], [`HuffmanTable`] and [`LookbackBuffer`])
+//! are exported as well, for use by other deflate-based formats.
+//!
+//! Everything but [`file`](mod@file) and the async adapter also builds without the default `std` feature,
+//! as `no_std` with `alloc`, against the I/O traits in [`io`].
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[macro_use]
+mod logging;
+
+pub mod adler32;
+pub mod anydecoder;
+mod binary;
+#[cfg(feature = "std")]
+pub mod bgzfreader;
+#[cfg(feature = "tokio")]
+pub mod asyncrzlibreader;
 pub mod bitreader;
+pub mod bitwriter;
+pub mod blockinfo;
+pub mod crc32;
+pub mod decompress;
+pub mod deflatedecoder;
+pub mod deflateencoder;
+pub mod error;
+mod deflatetables;
+mod lz77;
+mod pushinput;
+pub mod gzdecoder;
+pub mod gzencoder;
+pub mod gzheader;
+pub mod gzip;
+pub mod gzindex;
+pub mod hooks;
 pub mod rzlibreader;
+pub mod slicedecoder;
+pub mod snapshot;
 pub mod huffman;
+pub mod io;
+
+pub mod lookbackbuffer;
+pub mod members;
+#[cfg(feature = "std")]
+pub mod parallelgzdecoder;
+#[cfg(feature = "std")]
+pub mod parallelgzencoder;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod file;
+#[cfg(all(test, feature = "std"))]
+mod testdir;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod write;
+pub mod zipwriter;
+pub mod zlib;
+pub mod zlibencoder;
 
-pub mod lookbackbuffer;
\ No newline at end of file
+pub use crate::anydecoder::{detect_format, AnyDecoder};
+#[cfg(feature = "tokio")]
+pub use crate::asyncrzlibreader::AsyncRZLibReader;
+#[cfg(feature = "std")]
+pub use crate::bgzfreader::BgzfReader;
+pub use crate::bitreader::{BitOrder, BitRead, BitReader, BitSliceReader, LsbFirst, MsbFirst};
+pub use crate::bitwriter::BitWriter;
+pub use crate::blockinfo::{
+    BlockInfo, BlockType, DynamicHeader, Histograms, MemberStats, Stats,
+};
+pub use crate::decompress::{Decompress, FlushDecompress, Status};
+pub use crate::deflatedecoder::DeflateDecoder;
+pub use crate::deflateencoder::{Compression, DeflateEncoder, FlushMode};
+pub use crate::error::{DecodeError, RzlibError, StreamPosition};
+pub use crate::gzdecoder::{GzDecoder, MultiGzDecoder};
+pub use crate::gzencoder::GzEncoder;
+pub use crate::gzheader::GzHeader;
+pub use crate::gzindex::{Checkpoint, GzIndex};
+#[cfg(feature = "std")]
+pub use crate::gzindex::IndexedGzReader;
+pub use crate::hooks::DecodeHooks;
+pub use crate::huffman::{Codeword, HuffmanTable, HuffmanTree};
+pub use crate::lookbackbuffer::LookbackBuffer;
+pub use crate::members::{GzTrailer, Member, Members};
+#[cfg(feature = "std")]
+pub use crate::parallelgzdecoder::ParallelGzDecoder;
+#[cfg(feature = "std")]
+pub use crate::parallelgzencoder::ParallelGzEncoder;
+pub use crate::rzlibreader::{Format, RZLibReader, Strictness};
+pub use crate::slicedecoder::SliceDecoder;
+pub use crate::snapshot::Snapshot;
+pub use crate::zipwriter::ZipWriter;
+pub use crate::zlibencoder::ZlibEncoder;
@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use rzlib::GzDecoder;
+
+use crate::cli::Mode;
+use crate::{output_path, ratio, stdin_is_input, EXIT_ERROR};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MemberInfo {
+    pub compressed: u64,
+    pub uncompressed: u64,
+    pub mtime: u32,
+    pub filename: Option<String>,
+}
+
+/// Decodes every gzip member of `reader` to find its sizes. Members are decoded in full rather
+/// than trusting ISIZE, which only holds the uncompressed size modulo 4 GiB.
+pub fn list_members<R: BufRead>(mut reader: R) -> io::Result<Vec<MemberInfo>> {
+    let mut members = vec![];
+    while !reader.fill_buf()?.is_empty() {
+        let mut decoder = GzDecoder::new(&mut reader);
+        let uncompressed = io::copy(&mut decoder, &mut io::sink())?;
+        let header = decoder.header().cloned().unwrap_or_default();
+        members.push(MemberInfo {
+            compressed: decoder.total_in(),
+            uncompressed,
+            mtime: header.mtime,
+            filename: header.filename_lossy().map(String::from),
+        });
+    }
+    Ok(members)
+}
+
+// Formats a Unix timestamp as a UTC date and time, or "-" for 0, which gzip uses for "unknown".
+fn format_mtime(mtime: u32) -> String {
+    if mtime == 0 {
+        return "-".to_string();
+    }
+    let days = (mtime / 86400) as i64;
+    let seconds = mtime % 86400;
+    // civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn print_row(compressed: u64, uncompressed: u64, date: &str, name: &str) {
+    println!(
+        "{:>12} {:>12} {:>6.1}% {:<19} {}",
+        compressed,
+        uncompressed,
+        ratio(compressed, uncompressed),
+        date,
+        name
+    );
+}
+
+/// Prints a `gzip -l` style table of the members of each file, followed by a totals row when
+/// there are several files. Returns the exit status.
+pub fn list_files(files: &[PathBuf]) -> i32 {
+    println!(
+        "{:>12} {:>12} {:>7} {:<19} name",
+        "compressed", "uncompressed", "ratio", "mtime"
+    );
+    let mut status = 0;
+    let (mut total_compressed, mut total_uncompressed) = (0, 0);
+    for file in files {
+        let (name, members) = if stdin_is_input(file) {
+            (Path::new("stdin"), list_members(io::stdin().lock()))
+        } else {
+            let members = File::open(file).and_then(|f| list_members(BufReader::new(f)));
+            (file.as_path(), members)
+        };
+        let members = match members {
+            Ok(members) => members,
+            Err(err) => {
+                eprintln!("rzlib: {}: {}", name.display(), err);
+                status = EXIT_ERROR;
+                continue;
+            }
+        };
+        // members without a stored name are listed under the name decompression would use
+        let fallback = output_path(name, Mode::Decompress)
+            .unwrap_or_else(|_| name.to_path_buf())
+            .display()
+            .to_string();
+        for member in members {
+            let name = member.filename.as_deref().unwrap_or(&fallback);
+            print_row(
+                member.compressed,
+                member.uncompressed,
+                &format_mtime(member.mtime),
+                name,
+            );
+            total_compressed += member.compressed;
+            total_uncompressed += member.uncompressed;
+        }
+    }
+    if files.len() > 1 {
+        print_row(total_compressed, total_uncompressed, "", "(totals)");
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rzlib::{Compression, GzEncoder};
+
+    use super::{format_mtime, list_members, MemberInfo};
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_list_members() {
+        let first = compress(b"first member");
+        let second = compress(&[b'x'; 1000]);
+        let input = [&first[..], &second[..]].concat();
+
+        let member = |compressed: usize, uncompressed| MemberInfo {
+            compressed: compressed as u64,
+            uncompressed,
+            mtime: 0,
+            filename: None,
+        };
+        assert_eq!(
+            list_members(&input[..]).unwrap(),
+            [member(first.len(), 12), member(second.len(), 1000)]
+        );
+        assert!(list_members(&input[..first.len() + 5]).is_err());
+    }
+
+    #[test]
+    fn test_format_mtime() {
+        assert_eq!(format_mtime(0), "-");
+        assert_eq!(format_mtime(1), "1970-01-01 00:00:01");
+        assert_eq!(format_mtime(1_000_000_000), "2001-09-09 01:46:40");
+        assert_eq!(format_mtime(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_mtime(u32::MAX), "2106-02-07 06:28:15");
+    }
+}
@@ -0,0 +1,36 @@
+// Log events of the decoder, through the `log` crate when the `log` feature is on. Without it,
+// they compile to nothing, though their arguments are still type-checked.
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)+) => { log::debug!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! trace {
+    ($($arg:tt)+) => { log::trace!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)+) => { debug!($($arg)+) };
+}
+
+#[cfg(feature = "log")]
+macro_rules! warn {
+    ($($arg:tt)+) => { log::warn!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)+) => { debug!($($arg)+) };
+}
@@ -1,24 +1,69 @@
-use std::{cmp, io};
-use std::io::ErrorKind::InvalidInput;
-use std::io::Write;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
 
+use crate::io;
+use crate::io::ErrorKind::InvalidInput;
+
+/// A ring buffer holding the most recently decoded bytes, used to resolve back-references.
 pub struct LookbackBuffer {
+    // a power of two long, so positions wrap around with `mask`
     data: Vec<u8>,
+    mask: usize,
     pos: usize,
+    // the lookback size, which `data` may be longer than
+    size: usize,
+    // number of bytes written so far, up to `size`
+    filled: usize,
 }
 
 impl LookbackBuffer {
+    /// Creates a buffer remembering the last `lookback_size` bytes. Panics if `lookback_size` is 0.
     pub fn new(lookback_size: usize) -> LookbackBuffer {
         if lookback_size == 0 {
             panic!("lookback_size must be nonzero")
         }
-        LookbackBuffer { data: vec![0; lookback_size], pos: 0 }
+        let len = lookback_size.next_power_of_two();
+        LookbackBuffer { data: vec![0; len], mask: len - 1, pos: 0, size: lookback_size, filled: 0 }
+    }
+
+    /// Number of bytes available for lookback, i.e. the number of bytes written so far up to
+    /// the size of the buffer.
+    pub fn len(&self) -> usize {
+        self.filled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
+    }
+
+    /// Size of the buffer, i.e. the largest lookback distance it can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the bytes available for lookback, oldest first.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let (first, second) = self
+            .slices(self.filled, self.filled)
+            .expect("the filled bytes are available");
+        [first, second].concat()
     }
 
+    /// Forgets all bytes written so far, keeping the allocated buffer.
+    pub fn clear(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+
+    /// Appends `buf` to the buffer. If `buf` is longer than the buffer, only its tail is kept.
     pub fn write_data(&mut self, buf: &[u8]) -> io::Result<()> {
-        if buf.len() > self.data.len() {
-            return Err(io::Error::new(InvalidInput,  format!("trying to write {} bytes to lookback buffer of size {}", buf.len(), self.data.len())));
-        }
+        let buf = if buf.len() > self.data.len() {
+            &buf[(buf.len() - self.data.len())..]
+        } else {
+            buf
+        };
 
         let space_left_before_wraparound = self.data.len() - self.pos;
         if buf.len() <= space_left_before_wraparound {
@@ -29,39 +74,192 @@ impl LookbackBuffer {
             self.data[self.pos..].copy_from_slice(&buf[..first_chunk_len]);
             self.data[..(buf.len()-first_chunk_len)].copy_from_slice(&buf[first_chunk_len..])
         }
-        self.pos = (self.pos + buf.len()) % self.data.len();
+        self.pos = (self.pos + buf.len()) & self.mask;
+        self.filled = cmp::min(self.filled + buf.len(), self.size);
         Ok(())
     }
+
+    /// Returns the space the next bytes are written to, up to `max` bytes but no further than the
+    /// end of the underlying storage. Call `advance` with the number of bytes filled in.
+    pub fn spare_mut(&mut self, max: usize) -> &mut [u8] {
+        let end = cmp::min(self.pos + max, self.data.len());
+        &mut self.data[self.pos..end]
+    }
+
+    /// Appends the first `n` bytes of the space returned by `spare_mut`.
+    pub fn advance(&mut self, n: usize) {
+        debug_assert!(self.pos + n <= self.data.len());
+        self.pos = (self.pos + n) & self.mask;
+        self.filled = cmp::min(self.filled + n, self.size);
+    }
+
+    /// Appends a single byte; prefer `write_data` for runs of bytes.
+    #[inline]
     pub fn write_byte(&mut self, b: u8) -> io::Result<()> {
         self.data[self.pos] = b;
-        self.pos = (self.pos+1) % self.data.len();
+        self.pos = (self.pos + 1) & self.mask;
+        if self.filled < self.size {
+            self.filled += 1;
+        }
         Ok(())
     }
 
+    /// Fills `buf` with the bytes starting `distance` bytes back from the current position.
+    /// `buf` may not be longer than `distance`.
     pub fn read_lookback_exact(&self, buf: &mut [u8], distance: usize) -> io::Result<()> {
         if buf.len() > distance {
             return Err(io::Error::new(InvalidInput,  format!("lookback length {} greater than lookback distance {}", buf.len(), distance)));
         }
-        if distance > self.data.len() {
-            return Err(io::Error::new(InvalidInput,  format!("lookback distance {} greater than lookback window size {}", distance, self.data.len())));
+        let (first, second) = self.slices(distance, buf.len())?;
+        buf[..first.len()].copy_from_slice(first);
+        buf[first.len()..].copy_from_slice(second);
+        Ok(())
+    }
+
+    /// Returns the `len` bytes starting `distance` bytes back from the current position, as two
+    /// slices since they may wrap around the end of the underlying storage. `len` may not be
+    /// greater than `distance`.
+    pub fn slices(&self, distance: usize, len: usize) -> io::Result<(&[u8], &[u8])> {
+        if len > distance {
+            return Err(io::Error::new(InvalidInput,  format!("lookback length {} greater than lookback distance {}", len, distance)));
+        }
+        if distance > self.filled {
+            return Err(io::Error::new(InvalidInput,  format!("lookback distance {} greater than lookback length {}", distance, self.filled)));
         }
+        let start = self.pos.wrapping_sub(distance) & self.mask;
+        let first_len = cmp::min(len, self.data.len() - start);
+        Ok((&self.data[start..start + first_len], &self.data[..len - first_len]))
+    }
 
-        if self.pos > distance {
-            // we can lookback without wrapping around
-            let read_pos = self.pos-distance;
-            buf.copy_from_slice(&self.data[read_pos..(read_pos+buf.len())]);
-            return Ok(())
-        } else {
-            // we must wrap around
-            // TODO: merge both cases here
-            let wraparound_distance = distance-self.pos;
-            let wraparound_pos = self.data.len() - wraparound_distance;
-            let wraparound_length = cmp::min(buf.len(), self.data.len() - wraparound_pos);
-            let remaining_length = buf.len() - wraparound_length;
-            buf[..wraparound_length].copy_from_slice(&self.data[wraparound_pos..(wraparound_pos+wraparound_length)]);
-            buf[wraparound_length..].copy_from_slice(&self.data[..remaining_length]);
+    /// Appends `length` bytes copied from `distance` bytes back, without going through a
+    /// separate buffer. `length` may be greater than `distance`, as in `copy_match`.
+    pub fn repeat(&mut self, length: usize, distance: usize) -> io::Result<()> {
+        if distance > self.filled || distance == 0 {
+            return Err(io::Error::new(InvalidInput,  format!("lookback distance {} greater than lookback length {}", distance, self.filled)));
+        }
+        let size = self.data.len();
+        let mut left = length;
+        while left > 0 {
+            let from = self.pos.wrapping_sub(distance) & self.mask;
+            // neither range wraps around, and the source is written before it is copied
+            let n = cmp::min(cmp::min(left, distance), cmp::min(size - from, size - self.pos));
+            self.data.copy_within(from..from + n, self.pos);
+            self.advance(n);
+            left -= n;
         }
         Ok(())
+    }
+
+    /// Fills `buf` with the bytes of a match starting `distance` bytes back and appends them to
+    /// the buffer. Unlike `read_lookback_exact`, `buf` may be longer than `distance`: the match
+    /// then overlaps itself and repeats its first `distance` bytes, as in deflate.
+    pub fn copy_match(&mut self, buf: &mut [u8], distance: usize) -> io::Result<()> {
+        let first = cmp::min(buf.len(), distance);
+        self.read_lookback_exact(&mut buf[..first], distance)?;
+        // buf[..filled] is always a whole number of repetitions of the period, or all of buf
+        let mut filled = first;
+        while filled < buf.len() {
+            let n = cmp::min(filled, buf.len() - filled);
+            buf.copy_within(..n, filled);
+            filled += n;
+        }
+        self.write_data(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LookbackBuffer;
+
+    #[test]
+    fn test_write_longer_than_window() {
+        let mut lookback = LookbackBuffer::new(4);
+        lookback.write_data(b"ab").unwrap();
+        lookback.write_data(b"cdefgh").unwrap();
+        let mut buf = [0; 4];
+        lookback.read_lookback_exact(&mut buf, 4).unwrap();
+        assert_eq!(&buf, b"efgh");
+    }
+
+    #[test]
+    fn test_copy_match() {
+        let mut lookback = LookbackBuffer::new(8);
+        lookback.write_data(b"xyab").unwrap();
+        let mut buf = [0; 7];
+        lookback.copy_match(&mut buf, 2).unwrap();
+        assert_eq!(&buf, b"abababa");
+        assert_eq!(lookback.snapshot(), b"babababa");
 
+        // the longest deflate match, run-length encoding a single byte
+        let mut buf = [0; 258];
+        lookback.copy_match(&mut buf, 1).unwrap();
+        assert!(buf.iter().all(|&b| b == b'a'));
+        assert_eq!(lookback.snapshot(), b"aaaaaaaa");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_repeat() {
+        let mut lookback = LookbackBuffer::new(8);
+        lookback.write_data(b"xyzab").unwrap();
+        lookback.repeat(7, 2).unwrap();
+        assert_eq!(lookback.snapshot(), b"babababa");
+        let (first, second) = lookback.slices(8, 8).unwrap();
+        assert_eq!((first, second), (&b"baba"[..], &b"baba"[..]));
+        lookback.repeat(3, 8).unwrap();
+        assert_eq!(lookback.snapshot(), b"abababab");
+        lookback.repeat(258, 1).unwrap();
+        assert_eq!(lookback.snapshot(), b"bbbbbbbb");
+        assert!(lookback.repeat(1, 9).is_err());
+        assert!(lookback.slices(2, 3).is_err());
+    }
+
+    #[test]
+    fn test_spare_mut() {
+        let mut lookback = LookbackBuffer::new(4);
+        lookback.write_data(b"abc").unwrap();
+        assert_eq!(lookback.spare_mut(3).len(), 1);
+        lookback.spare_mut(3)[0] = b'd';
+        lookback.advance(1);
+        lookback.spare_mut(2).copy_from_slice(b"ef");
+        lookback.advance(2);
+        assert_eq!(lookback.snapshot(), b"cdef");
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut lookback = LookbackBuffer::new(4);
+        assert_eq!(lookback.snapshot(), b"");
+        lookback.write_data(b"abc").unwrap();
+        assert_eq!(lookback.snapshot(), b"abc");
+        lookback.write_byte(b'd').unwrap();
+        assert_eq!(lookback.snapshot(), b"abcd");
+        lookback.write_data(b"ef").unwrap();
+        assert_eq!(lookback.snapshot(), b"cdef");
+    }
+
+    #[test]
+    fn test_size_not_power_of_two() {
+        let mut lookback = LookbackBuffer::new(5);
+        assert_eq!(lookback.capacity(), 5);
+        for &b in b"abcdefghij" {
+            lookback.write_byte(b).unwrap();
+        }
+        assert_eq!(lookback.len(), 5);
+        assert_eq!(lookback.snapshot(), b"fghij");
+        assert!(lookback.repeat(1, 6).is_err());
+        lookback.write_data(b"0123456789abc").unwrap();
+        lookback.repeat(4, 5).unwrap();
+        assert_eq!(lookback.snapshot(), b"c89ab");
+    }
+
+    #[test]
+    fn test_distance_beyond_written_data() {
+        let mut lookback = LookbackBuffer::new(8);
+        lookback.write_data(b"abc").unwrap();
+        assert_eq!(lookback.len(), 3);
+        let mut buf = [0; 1];
+        assert!(lookback.read_lookback_exact(&mut buf, 4).is_err());
+        lookback.read_lookback_exact(&mut buf, 3).unwrap();
+        assert_eq!(&buf, b"a");
+    }
+}
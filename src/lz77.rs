@@ -0,0 +1,222 @@
+// LZ77 match finding for the encoder, using hash chains over 3-byte prefixes.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub(crate) const MIN_MATCH: usize = 3;
+pub(crate) const MAX_MATCH: usize = 258;
+pub(crate) const WINDOW_SIZE: usize = 32768;
+
+// 3-byte matches further away than this usually cost more bits than the literals they replace
+const TOO_FAR: usize = 4096;
+
+const HASH_BITS: u32 = 15;
+const NO_POS: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+}
+
+pub(crate) struct MatchFinder {
+    // most recent position with a given hash
+    head: Vec<u32>,
+    // previous position with the same hash as the indexed one
+    prev: Vec<u32>,
+    // how many candidates to examine per position
+    max_chain: usize,
+    // stop searching once a match at least this long is found
+    nice_length: usize,
+    // when set, a match is only emitted if the match starting one byte later is not longer
+    lazy: bool,
+    // matches at least this long are emitted without looking for a better one
+    max_lazy: usize,
+}
+
+impl MatchFinder {
+    pub(crate) fn new(max_chain: usize, nice_length: usize, max_lazy: usize) -> MatchFinder {
+        MatchFinder {
+            head: vec![NO_POS; 1 << HASH_BITS],
+            prev: vec![],
+            max_chain,
+            nice_length,
+            lazy: false,
+            max_lazy,
+        }
+    }
+
+    pub(crate) fn set_lazy(&mut self, lazy: bool) {
+        self.lazy = lazy;
+    }
+
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            self.prev[pos] = self.head[h];
+            self.head[h] = pos as u32;
+        }
+    }
+
+    // Returns the (length, distance) of the longest match for data[pos..] found in the chains.
+    fn longest_match(&self, data: &[u8], pos: usize) -> (usize, usize) {
+        let max_length = MAX_MATCH.min(data.len() - pos);
+        if max_length < MIN_MATCH {
+            return (0, 0);
+        }
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash(&data[pos..])];
+        let mut chain = self.max_chain;
+        while candidate != NO_POS && chain > 0 {
+            let candidate_pos = candidate as usize;
+            let distance = pos - candidate_pos;
+            if distance > WINDOW_SIZE {
+                break;
+            }
+            // check the byte that would extend the best match first
+            let probe = best.0.min(max_length - 1);
+            if data[candidate_pos + probe] == data[pos + probe] {
+                let length = data[candidate_pos..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, distance);
+                    if length >= self.nice_length || length == max_length {
+                        break;
+                    }
+                }
+            }
+            candidate = self.prev[candidate_pos];
+            chain -= 1;
+        }
+        if best.0 < MIN_MATCH || (best.0 == MIN_MATCH && best.1 > TOO_FAR) {
+            return (0, 0);
+        }
+        best
+    }
+
+    // Appends the tokens encoding data[start..] to `tokens`, allowing matches to reach back
+    // into data[..start].
+    pub(crate) fn tokenize(&mut self, data: &[u8], start: usize, tokens: &mut Vec<Token>) {
+        self.head.fill(NO_POS);
+        self.prev.clear();
+        self.prev.resize(data.len(), NO_POS);
+        for pos in start.saturating_sub(WINDOW_SIZE)..start {
+            self.insert(data, pos);
+        }
+
+        let mut pos = start;
+        // match at `pos` already found while looking ahead from the previous position
+        let mut next_match = None;
+        while pos < data.len() {
+            let (length, distance) = match next_match.take() {
+                Some(m) => m,
+                None => self.longest_match(data, pos),
+            };
+            if length < MIN_MATCH {
+                tokens.push(Token::Literal(data[pos]));
+                self.insert(data, pos);
+                pos += 1;
+                continue;
+            }
+
+            self.insert(data, pos);
+            if self.lazy && length < self.max_lazy && pos + 1 < data.len() {
+                let lookahead = self.longest_match(data, pos + 1);
+                if lookahead.0 > length {
+                    tokens.push(Token::Literal(data[pos]));
+                    next_match = Some(lookahead);
+                    pos += 1;
+                    continue;
+                }
+            }
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+            for p in pos + 1..pos + length {
+                self.insert(data, p);
+            }
+            pos += length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MatchFinder, Token};
+
+    // expands `tokens` back into bytes, with `history` preceding them
+    fn expand(history: &[u8], tokens: &[Token]) -> Vec<u8> {
+        let mut out = history.to_vec();
+        for token in tokens {
+            match *token {
+                Token::Literal(b) => out.push(b),
+                Token::Match { length, distance } => {
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance as usize]);
+                    }
+                }
+            }
+        }
+        out[history.len()..].to_vec()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let data = b"abcabcabcabcabcX";
+        let mut tokens = vec![];
+        MatchFinder::new(32, 258, 258).tokenize(data, 0, &mut tokens);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c'),
+                Token::Match { length: 12, distance: 3 },
+                Token::Literal(b'X'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_with_history() {
+        let data = b"hello world, hello world";
+        let mut tokens = vec![];
+        MatchFinder::new(32, 258, 258).tokenize(data, 12, &mut tokens);
+        assert_eq!(tokens[0], Token::Literal(b' '));
+        assert_eq!(tokens[1], Token::Match { length: 11, distance: 13 });
+        assert_eq!(expand(&data[..12], &tokens), &data[12..]);
+    }
+
+    #[test]
+    fn test_lazy_matching() {
+        let data = b"abcd_bcdefg_abcdefg";
+        let mut greedy = vec![];
+        MatchFinder::new(32, 258, 258).tokenize(data, 12, &mut greedy);
+        assert_eq!(
+            greedy,
+            vec![
+                Token::Match { length: 4, distance: 12 },
+                Token::Match { length: 3, distance: 8 },
+            ]
+        );
+
+        let mut lazy = vec![];
+        let mut match_finder = MatchFinder::new(32, 258, 258);
+        match_finder.set_lazy(true);
+        match_finder.tokenize(data, 12, &mut lazy);
+        assert_eq!(
+            lazy,
+            vec![Token::Literal(b'a'), Token::Match { length: 6, distance: 8 }]
+        );
+        assert_eq!(expand(&data[..12], &lazy), &data[12..]);
+    }
+}
@@ -1,27 +1,571 @@
-mod rzlibreader;
-mod bitreader;
-mod huffman;
-mod lookbackbuffer;
+mod cli;
+mod index;
+mod inspect;
+mod list;
+mod progress;
+#[cfg(test)]
+mod testdir;
 
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{cmp, env, process};
 
-use std::io;
-use std::io::{Read, Write};
-use crate::rzlibreader::RZLibReader;
+use rzlib::file::{
+    compress_file_with, copy_with_progress, decompress_file, decompress_file_with_progress,
+};
+use rzlib::{GzDecoder, GzEncoder, MultiGzDecoder};
 
+use crate::progress::Progress;
 
-fn main_r() -> io::Result<()> {
-    let stdin = io::stdin().lock();
-    let mut reader = RZLibReader::new(stdin);
+use crate::cli::{Command, Mode, Options};
 
+// exit statuses, as in gzip
+const EXIT_ERROR: i32 = 1;
+const EXIT_WARNING: i32 = 2;
+
+// suffixes recognized when decompressing, and what replaces them in the output name
+const SUFFIXES: [(&str, &str); 4] = [(".gz", ""), (".z", ""), ("-gz", ""), (".tgz", ".tar")];
+
+fn stdin_is_input(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+// An input left alone for a reason that only warrants a warning, see `exit_status`.
+fn skipped(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn exit_status(err: &io::Error) -> i32 {
+    match err.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::AlreadyExists => EXIT_WARNING,
+        _ => EXIT_ERROR,
+    }
+}
+
+fn output_path(input: &Path, mode: Mode) -> io::Result<PathBuf> {
+    let name = input.as_os_str().to_str();
+    match mode {
+        Mode::Compress => {
+            if name.is_some_and(|name| SUFFIXES.iter().any(|(suffix, _)| name.ends_with(suffix))) {
+                return Err(skipped("already has .gz suffix -- unchanged".to_string()));
+            }
+            let mut output = OsString::from(input);
+            output.push(".gz");
+            Ok(PathBuf::from(output))
+        }
+        Mode::Decompress | Mode::Test | Mode::List => name
+            .and_then(|name| {
+                SUFFIXES.iter().find_map(|(suffix, replacement)| {
+                    let stem = name.strip_suffix(suffix).filter(|stem| !stem.is_empty())?;
+                    Some(PathBuf::from(format!("{}{}", stem, replacement)))
+                })
+            })
+            .ok_or_else(|| skipped("unknown suffix -- ignored".to_string())),
+    }
+}
+
+// Reduces a file name stored in a gzip header to a plain name in the current directory: only
+// its last path component is kept, so it cannot point elsewhere.
+fn sanitize_file_name(stored: &str) -> Option<&str> {
+    let name = stored.rsplit(['/', '\\']).next()?;
+    match name {
+        "" | "." | ".." => None,
+        _ => Some(name),
+    }
+}
+
+// Whether `path` is the existing file `input`, however either is spelled.
+fn is_same_file(path: &Path, input: &Path) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(input)) {
+        (Ok(path), Ok(input)) => path == input,
+        _ => false,
+    }
+}
+
+// Returns the file name stored in the header of the first member of `input`, if any.
+fn stored_file_name(input: &Path) -> io::Result<Option<String>> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(input)?));
+    // the first read parses the header; whether it returns any data does not matter
+    let _ = decoder.read(&mut [0])?;
+    Ok(decoder.header().and_then(|header| header.filename_lossy().map(String::from)))
+}
+
+// percentage saved by compression, as reported by gzip -v
+fn ratio(compressed: u64, uncompressed: u64) -> f64 {
+    if uncompressed == 0 {
+        return 0.0;
+    }
+    100.0 * (1.0 - compressed as f64 / uncompressed as f64)
+}
+
+// Decodes `reader` into `writer`, drawing `progress`, if any, along the way. The progress line
+// is only completed if decoding succeeds.
+fn decode_to<R: BufRead, W: Write>(
+    reader: R,
+    writer: &mut W,
+    mut progress: Option<Progress>,
+) -> io::Result<u64> {
+    let mut decoder = MultiGzDecoder::new(reader);
+    let written = copy_with_progress(&mut decoder, writer, |decoder, written| {
+        if let Some(progress) = progress.as_mut() {
+            progress.update(decoder.total_in(), written);
+        }
+    })?;
+    if let Some(progress) = progress.as_mut() {
+        progress.finish(decoder.total_in(), written);
+    }
+    Ok(written)
+}
+
+// A gzip encoder over `writer`, set up as `options` ask.
+fn new_encoder<W: Write>(writer: W, options: &Options) -> GzEncoder<W> {
+    let mut encoder = GzEncoder::new(writer, options.level);
+    encoder.set_rsyncable(options.rsyncable);
+    encoder
+}
+
+fn process_stdin(options: &Options) -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
     let mut stdout = io::stdout().lock();
+    let progress = options
+        .progress
+        .then(|| Progress::new(Path::new("stdin"), None));
+    match options.mode {
+        Mode::Compress => {
+            let mut encoder = new_encoder(stdout, options);
+            io::copy(&mut stdin, &mut encoder)?;
+            encoder.finish()?.flush()
+        }
+        Mode::Decompress => {
+            decode_to(stdin, &mut stdout, progress)?;
+            stdout.flush()
+        }
+        Mode::Test => decode_to(stdin, &mut io::sink(), progress).map(|_| ()),
+        Mode::List => unreachable!("listing is done by list::list_files"),
+    }
+}
 
-    io::copy(&mut reader, &mut stdout)?;
+fn process_file(input: &Path, options: &Options) -> io::Result<()> {
+    let metadata = fs::metadata(input)?;
+    if metadata.is_dir() {
+        return Err(skipped("is a directory -- ignored".to_string()));
+    }
+    if !metadata.is_file() {
+        return Err(skipped("not a regular file -- ignored".to_string()));
+    }
 
-    return Ok(());
+    let progress = options
+        .progress
+        .then(|| Progress::new(input, Some(metadata.len())));
+    if options.mode == Mode::Test {
+        // decoding to the end checks the CRC-32 and ISIZE of every member
+        let reader = BufReader::new(File::open(input)?);
+        return decode_to(reader, &mut io::sink(), progress).map(|_| ());
+    }
+
+    if options.stdout {
+        let mut stdout = io::stdout().lock();
+        let mut reader = BufReader::new(File::open(input)?);
+        if options.mode == Mode::Compress {
+            let mut encoder = new_encoder(&mut stdout, options);
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        } else {
+            let uncompressed = decode_to(reader, &mut stdout, progress)?;
+            if options.verbose {
+                eprintln!(
+                    "{}:\t{:5.1}%",
+                    input.display(),
+                    ratio(metadata.len(), uncompressed)
+                );
+            }
+        }
+        return stdout.flush();
+    }
+
+    let mut output = output_path(input, options.mode)?;
+    if options.mode == Mode::Decompress && options.name {
+        if let Some(stored) = stored_file_name(input)? {
+            if let Some(name) = sanitize_file_name(&stored) {
+                // the stored name may be that of the input itself, which must not be overwritten
+                let named = output.with_file_name(name);
+                if !is_same_file(&named, input) {
+                    output = named;
+                }
+            }
+        }
+    }
+    if !options.force && fs::symlink_metadata(&output).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists; not overwritten", output.display()),
+        ));
+    }
+    let (compressed, uncompressed) = match options.mode {
+        Mode::Compress => {
+            let compressed =
+                compress_file_with(input, &output, |writer| new_encoder(writer, options))?;
+            (compressed, metadata.len())
+        }
+        _ => match progress {
+            Some(mut progress) => {
+                let written = decompress_file_with_progress(input, &output, |consumed, written| {
+                    progress.update(consumed, written)
+                })?;
+                progress.finish(metadata.len(), written);
+                (metadata.len(), written)
+            }
+            None => (metadata.len(), decompress_file(input, &output)?),
+        },
+    };
+    // like gzip, the input goes away only once the output is complete and synced to disk
+    if !options.keep {
+        fs::remove_file(input)?;
+    }
+    if options.verbose {
+        eprintln!(
+            "{}:\t{:5.1}% -- {} {}",
+            input.display(),
+            ratio(compressed, uncompressed),
+            if options.keep {
+                "created"
+            } else {
+                "replaced with"
+            },
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+// Combines two exit statuses into the worse one, errors taking precedence over warnings.
+fn worse_status(a: i32, b: i32) -> i32 {
+    if a == EXIT_ERROR || b == EXIT_ERROR {
+        EXIT_ERROR
+    } else {
+        cmp::max(a, b)
+    }
+}
+
+// Prints `err` for the input `name` and folds it into the exit status so far.
+fn report(name: &Path, err: &io::Error, status: &mut i32) {
+    eprintln!("rzlib: {}: {}", name.display(), err);
+    *status = worse_status(*status, exit_status(err));
+}
+
+// Adds the files below `dir` that `mode` applies to, compressed ones or not, to `files`, in
+// name order. Symbolic links are skipped. Directories that cannot be read are reported and
+// skipped too, without stopping the walk.
+fn walk_dir(dir: &Path, mode: Mode, files: &mut Vec<PathBuf>, status: &mut i32) {
+    let entries = fs::read_dir(dir).and_then(|entries| entries.collect::<io::Result<Vec<_>>>());
+    let mut entries = match entries {
+        Ok(entries) => entries,
+        Err(err) => return report(dir, &err, status),
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk_dir(&path, mode, files, status),
+            Ok(file_type) if file_type.is_file() => {
+                let compressed = output_path(&path, Mode::Decompress).is_ok();
+                if compressed != (mode == Mode::Compress) {
+                    files.push(path);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => report(&path, &err, status),
+        }
+    }
+}
+
+// Like gzip, compressed data is neither written to nor read from a terminal unless forced with
+// -f, as it is useless there. Returns the reason to refuse, if any.
+fn terminal_refusal(
+    options: &Options,
+    stdin_is_terminal: bool,
+    stdout_is_terminal: bool,
+) -> Option<&'static str> {
+    if options.force {
+        return None;
+    }
+    let uses_stdin = options.files.is_empty() || options.files.iter().any(|f| stdin_is_input(f));
+    match options.mode {
+        Mode::Compress if stdout_is_terminal && (options.stdout || uses_stdin) => {
+            Some("compressed data not written to a terminal. Use -f to force compression.")
+        }
+        Mode::Decompress | Mode::Test | Mode::List if stdin_is_terminal && uses_stdin => {
+            Some("compressed data not read from a terminal. Use -f to force decompression.")
+        }
+        _ => None,
+    }
+}
+
+// Processes every input, carrying on past the ones that fail, and returns the exit status: the
+// worst outcome, with errors taking precedence over warnings.
+fn run(options: &Options) -> i32 {
+    let refusal = terminal_refusal(
+        options,
+        io::stdin().is_terminal(),
+        io::stdout().is_terminal(),
+    );
+    if let Some(message) = refusal {
+        eprintln!("rzlib: {}", message);
+        return EXIT_ERROR;
+    }
+
+    let mut status = 0;
+    let mut files = vec![];
+    for file in &options.files {
+        if options.recursive && fs::metadata(file).is_ok_and(|metadata| metadata.is_dir()) {
+            walk_dir(file, options.mode, &mut files, &mut status);
+        } else {
+            files.push(file.clone());
+        }
+    }
+    if options.files.is_empty() {
+        files.push(PathBuf::from("-"));
+    }
+
+    if options.mode == Mode::List {
+        return worse_status(status, list::list_files(&files));
+    }
+    for file in &files {
+        let (name, result) = if stdin_is_input(file) {
+            (Path::new("stdin"), process_stdin(options))
+        } else {
+            (file.as_path(), process_file(file, options))
+        };
+        if options.mode == Mode::Test {
+            let verdict = if result.is_ok() { "OK" } else { "FAILED" };
+            println!("{}:\t{}", name.display(), verdict);
+        }
+        if let Err(err) = result {
+            report(name, &err, &mut status);
+        }
+    }
+    status
 }
 
 fn main() {
-    main_r().expect("main");
+    let options = match cli::parse_args(env::args_os().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Inspect(files)) => process::exit(inspect::inspect_files(&files)),
+        Ok(Command::Index { span, files }) => process::exit(index::index_files(&files, span)),
+        Ok(Command::Range { file, start, len }) => {
+            process::exit(index::range_file(&file, start, len))
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("rzlib {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            eprintln!(
+                "rzlib: {}\nTry 'rzlib --help' for more information.",
+                message
+            );
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    process::exit(run(&options));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    use rzlib::file::compress_file;
+    use rzlib::{Compression, GzDecoder, GzEncoder, GzHeader};
+
+    use super::{run, sanitize_file_name, terminal_refusal, EXIT_ERROR, EXIT_WARNING};
+    use crate::cli::{Mode, Options};
+    use crate::testdir::TestDir;
+
+    fn decompress_options(files: Vec<PathBuf>) -> Options {
+        Options {
+            mode: Mode::Decompress,
+            level: Compression::default(),
+            stdout: false,
+            keep: false,
+            force: false,
+            verbose: false,
+            name: false,
+            recursive: false,
+            progress: false,
+            rsyncable: false,
+            files,
+        }
+    }
+
+    #[test]
+    fn test_continues_past_failures() {
+        let dir = TestDir::new("cli");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["first", "sub/second"] {
+            fs::write(dir.join(name), name).unwrap();
+            compress_file(dir.join(name), dir.join(format!("{}.gz", name))).unwrap();
+            fs::remove_file(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("plain"), b"no suffix").unwrap();
+        fs::write(dir.join("broken.gz"), b"not gzip").unwrap();
+
+        // only warnings
+        let options = decompress_options(vec![dir.join("plain"), dir.join("first.gz")]);
+        assert_eq!(run(&options), EXIT_WARNING);
+        assert_eq!(fs::read(dir.join("first")).unwrap(), b"first");
+        assert!(!dir.join("first.gz").exists());
+
+        let options = decompress_options(vec![
+            dir.join("broken.gz"),
+            dir.join("missing.gz"),
+            dir.join("plain"),
+            dir.join("sub/second.gz"),
+        ]);
+        assert_eq!(run(&options), EXIT_ERROR);
+        assert_eq!(fs::read(dir.join("sub/second")).unwrap(), b"sub/second");
+        assert!(dir.join("broken.gz").exists());
+        assert!(!dir.join("broken").exists());
+    }
+
+    #[test]
+    fn test_recursive() {
+        let dir = TestDir::new("cli-recursive");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        for name in ["top", "a/one", "a/b/two"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("top"), dir.join("a/link")).unwrap();
+
+        let mut options = decompress_options(vec![dir.to_path_buf()]);
+        options.mode = Mode::Compress;
+        options.recursive = true;
+        assert_eq!(run(&options), 0);
+        for name in ["top", "a/one", "a/b/two"] {
+            assert!(!dir.join(name).exists());
+            assert!(dir.join(format!("{}.gz", name)).exists());
+        }
+        #[cfg(unix)]
+        assert!(!dir.join("a/link.gz").exists());
+
+        // compressed files are left alone when compressing, and the other way around
+        fs::write(dir.join("a/plain"), b"plain").unwrap();
+        options.mode = Mode::Decompress;
+        assert_eq!(run(&options), 0);
+        assert_eq!(fs::read(dir.join("a/b/two")).unwrap(), b"a/b/two");
+        assert_eq!(fs::read(dir.join("a/plain")).unwrap(), b"plain");
+        assert!(!dir.join("a/plain.gz").exists());
+
+        // without -r, directories are skipped with a warning
+        options.recursive = false;
+        assert_eq!(run(&options), EXIT_WARNING);
+    }
+
+    #[test]
+    fn test_keep() {
+        let dir = TestDir::new("cli-keep");
+        fs::write(dir.join("data"), b"data").unwrap();
+        compress_file(dir.join("data"), dir.join("data.gz")).unwrap();
+        fs::remove_file(dir.join("data")).unwrap();
+        fs::write(
+            dir.join("truncated.gz"),
+            &fs::read(dir.join("data.gz")).unwrap()[..15],
+        )
+        .unwrap();
+
+        let mut options = decompress_options(vec![dir.join("data.gz"), dir.join("truncated.gz")]);
+        options.keep = true;
+        assert_eq!(run(&options), EXIT_ERROR);
+        assert_eq!(fs::read(dir.join("data")).unwrap(), b"data");
+        assert!(dir.join("data.gz").exists());
+
+        // without -k, only inputs that were fully decoded are removed
+        options.keep = false;
+        options.force = true;
+        assert_eq!(run(&options), EXIT_ERROR);
+        assert!(!dir.join("data.gz").exists());
+        assert!(dir.join("truncated.gz").exists());
+        assert!(!dir.join("truncated").exists());
+    }
+
+    #[test]
+    fn test_terminal_refusal() {
+        let mut options = decompress_options(vec![]);
+        options.mode = Mode::Compress;
+        assert!(terminal_refusal(&options, false, true).is_some());
+        assert!(terminal_refusal(&options, true, false).is_none());
+        options.files.push(PathBuf::from("file"));
+        assert!(terminal_refusal(&options, false, true).is_none());
+        options.stdout = true;
+        assert!(terminal_refusal(&options, false, true).is_some());
+        options.force = true;
+        assert!(terminal_refusal(&options, false, true).is_none());
+
+        let mut options = decompress_options(vec![PathBuf::from("-")]);
+        assert!(terminal_refusal(&options, true, true).is_some());
+        assert!(terminal_refusal(&options, false, true).is_none());
+        options.force = true;
+        assert!(terminal_refusal(&options, true, true).is_none());
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("notes.txt"), Some("notes.txt"));
+        assert_eq!(sanitize_file_name("../../etc/passwd"), Some("passwd"));
+        assert_eq!(sanitize_file_name("/tmp/x"), Some("x"));
+        assert_eq!(sanitize_file_name("..\\windows\\win.ini"), Some("win.ini"));
+        assert_eq!(sanitize_file_name("dir/"), None);
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name(""), None);
+    }
+
+    #[test]
+    fn test_stored_name_of_input() {
+        let dir = TestDir::new("cli-stored-name");
+        let header = GzHeader {
+            filename: Some(b"data.gz".to_vec()),
+            ..GzHeader::default()
+        };
+        let mut encoder = GzEncoder::with_header(vec![], Compression::default(), header);
+        encoder.write_all(b"stored name").unwrap();
+        let input = dir.join("data.gz");
+        fs::write(&input, encoder.finish().unwrap()).unwrap();
+
+        // -d -N -f: the stored name is the input's own, so the suffix-derived name is used
+        let mut options = decompress_options(vec![input.clone()]);
+        options.name = true;
+        options.force = true;
+        options.keep = true;
+        assert_eq!(run(&options), 0);
+        assert_eq!(fs::read(dir.join("data")).unwrap(), b"stored name");
+        assert!(GzDecoder::new(&fs::read(&input).unwrap()[..]).read_to_end(&mut vec![]).is_ok());
+    }
+
+    #[test]
+    fn test_integrity_test_mode() {
+        let dir = TestDir::new("cli-test");
+        fs::write(dir.join("data"), b"some data, some data").unwrap();
+        compress_file(dir.join("data"), dir.join("good.gz")).unwrap();
+        let mut corrupted = fs::read(dir.join("good.gz")).unwrap();
+        let crc_offset = corrupted.len() - 8;
+        corrupted[crc_offset] ^= 1;
+        fs::write(dir.join("bad.gz"), corrupted).unwrap();
 
-    eprintln!("finished!")
+        let mut options = decompress_options(vec![dir.join("good.gz")]);
+        options.mode = Mode::Test;
+        assert_eq!(run(&options), 0);
+        options.files.push(dir.join("bad.gz"));
+        assert_eq!(run(&options), EXIT_ERROR);
+        // nothing is written or removed
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    }
 }
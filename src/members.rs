@@ -0,0 +1,215 @@
+//! Access to the members of a gzip stream one by one, see [`RZLibReader::members`].
+
+use crate::io;
+use crate::io::{BufRead, Read};
+
+use crate::gzheader::GzHeader;
+use crate::rzlibreader::RZLibReader;
+
+/// The trailer of a gzip member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GzTrailer {
+    /// CRC-32 of the decompressed data of the member.
+    pub crc32: u32,
+    /// Size of the decompressed data of the member, modulo 2^32.
+    pub isize: u32,
+}
+
+/// The members of a stream, returned by [`RZLibReader::members`]: concatenated gzip archives
+/// often hold one file per member.
+///
+/// Each [`Member`] borrows the reader, so this is not an `Iterator`; go through the members with
+/// `while let Some(member) = members.next_member()`. Data of a member left unread is skipped
+/// when moving on to the next one. zlib and raw deflate streams are made of a single member,
+/// without header or trailer.
+pub struct Members<'a, R: Read + BufRead> {
+    reader: &'a mut RZLibReader<R>,
+    // whether the current member has been returned, so it is skipped by the next call
+    started: bool,
+    multi_member: bool,
+}
+
+impl<'a, R: Read + BufRead> Members<'a, R> {
+    pub(crate) fn new(reader: &'a mut RZLibReader<R>) -> Members<'a, R> {
+        let multi_member = reader.multi_member();
+        // every member ends the data read, and the next one is started by `next_member`
+        reader.set_multi_member(false);
+        Members {
+            reader,
+            started: false,
+            multi_member,
+        }
+    }
+
+    /// Returns the next member, having read its header, or `None` at the end of the stream.
+    pub fn next_member(&mut self) -> Option<io::Result<Member<'_, R>>> {
+        if self.started {
+            if let Err(err) = self.skip_member() {
+                return Some(Err(err));
+            }
+        }
+        if let Err(err) = self.reader.start_member() {
+            return Some(Err(err));
+        }
+        if self.reader.member_finished() {
+            return None;
+        }
+        self.started = true;
+        Some(Ok(Member {
+            reader: self.reader,
+        }))
+    }
+
+    fn skip_member(&mut self) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        while !self.reader.member_finished() {
+            match self.reader.read(&mut buf) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + BufRead> Drop for Members<'_, R> {
+    fn drop(&mut self) {
+        self.reader.set_multi_member(self.multi_member);
+    }
+}
+
+/// A member of a stream, see [`Members`]. Reading from it returns the decompressed data of the
+/// member only.
+pub struct Member<'a, R: Read + BufRead> {
+    reader: &'a mut RZLibReader<R>,
+}
+
+impl<R: Read + BufRead> Member<'_, R> {
+    /// The header of the member, `None` for zlib and raw deflate streams.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.reader.header()
+    }
+
+    /// Index of the member in the stream, starting at 1.
+    pub fn index(&self) -> u64 {
+        self.reader.member_index()
+    }
+
+    /// The trailer of the member, once all of its data has been read and checked against it.
+    /// `None` before, for zlib and raw deflate streams, and for a gzip member without trailer
+    /// accepted by [`Strictness::Lenient`](crate::Strictness::Lenient).
+    pub fn trailer(&self) -> Option<GzTrailer> {
+        if self.reader.member_finished() {
+            self.reader.trailer()
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: Read + BufRead> Read for Member<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::GzTrailer;
+    use crate::crc32::Crc32;
+    use crate::deflateencoder::{Compression, DeflateEncoder};
+    use crate::gzencoder::GzEncoder;
+    use crate::gzheader::GzHeader;
+    use crate::rzlibreader::{Format, RZLibReader};
+
+    fn member(name: &str, data: &[u8]) -> Vec<u8> {
+        let header = GzHeader {
+            filename: Some(name.as_bytes().to_vec()),
+            ..GzHeader::default()
+        };
+        let mut encoder = GzEncoder::with_header(vec![], Compression::default(), header);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_members() {
+        let files: [(&str, &[u8]); 3] = [
+            ("a.txt", b"first file"),
+            ("b.txt", b""),
+            ("c.txt", b"third"),
+        ];
+        let input: Vec<u8> = files
+            .iter()
+            .flat_map(|(name, data)| member(name, data))
+            .collect();
+
+        let mut reader = RZLibReader::new(&input[..]);
+        let mut members = reader.members();
+        for (i, (name, data)) in files.iter().enumerate() {
+            let mut member = members.next_member().unwrap().unwrap();
+            assert_eq!(member.index(), i as u64 + 1);
+            let header = member.header().unwrap();
+            assert_eq!(header.filename.as_deref(), Some(name.as_bytes()));
+            assert_eq!(member.trailer(), None);
+            let mut out = vec![];
+            member.read_to_end(&mut out).unwrap();
+            assert_eq!(out, *data);
+            let mut crc = Crc32::new();
+            crc.update(data);
+            let expected = GzTrailer {
+                crc32: crc.sum(),
+                isize: data.len() as u32,
+            };
+            assert_eq!(member.trailer(), Some(expected));
+        }
+        assert!(members.next_member().is_none());
+        assert!(members.next_member().is_none());
+        drop(members);
+        assert_eq!(reader.total_in(), input.len() as u64);
+    }
+
+    #[test]
+    fn test_skip_unread_data() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 247) as u8).collect();
+        let input = [member("big", &data), member("small", b"small")].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        let mut members = reader.members();
+        let mut first = members.next_member().unwrap().unwrap();
+        let mut buf = [0; 10];
+        first.read_exact(&mut buf).unwrap();
+        let mut second = members.next_member().unwrap().unwrap();
+        assert_eq!(second.index(), 2);
+        let mut out = vec![];
+        second.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"small");
+        assert!(members.next_member().is_none());
+
+        // the reader decodes members as a single stream again afterwards
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.members().next_member().unwrap().unwrap();
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out.len(), data.len() + 5);
+    }
+
+    #[test]
+    fn test_single_member_formats() {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"raw").unwrap();
+        let input = encoder.finish().unwrap();
+        let mut reader = RZLibReader::with_format(&input[..], Format::Deflate);
+        let mut members = reader.members();
+        let mut member = members.next_member().unwrap().unwrap();
+        assert!(member.header().is_none());
+        let mut out = vec![];
+        member.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"raw");
+        assert_eq!(member.trailer(), None);
+        assert!(members.next_member().is_none());
+    }
+}
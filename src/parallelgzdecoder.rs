@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufReader, Chain, Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{mem, thread};
+
+use crate::error::RzlibError;
+use crate::gzdecoder::MultiGzDecoder;
+use crate::rzlibreader::{Format, RZLibReader};
+
+// compressed data read per batch, for each thread
+const BATCH_SIZE_PER_THREAD: usize = 4 << 20;
+
+// decoder of the rest of the stream, the input left over followed by the reader
+type SequentialDecoder<R> = MultiGzDecoder<BufReader<Chain<Cursor<Vec<u8>>, R>>>;
+
+/// Decompresses a gzip stream made of many members, like the output of `bgzip` or of
+/// `pigz --independent`, on several threads.
+///
+/// The input is read in batches, in which every occurrence of the gzip magic bytes is tried as
+/// the start of a member, in parallel. Decoding then follows the chain of members from the start
+/// of the batch, so candidates that were just compressed data looking like a header are
+/// ignored. Output is returned in order, as with [`MultiGzDecoder`].
+///
+/// A member larger than a batch cannot be decoded this way; from there on, the rest of the
+/// stream is decoded on the calling thread.
+pub struct ParallelGzDecoder<R: Read> {
+    // None once handed over to `sequential`
+    reader: Option<R>,
+    threads: usize,
+    batch_size: usize,
+    // compressed data read but not decoded yet
+    input: Vec<u8>,
+    input_done: bool,
+    // decompressed data not returned yet
+    output: Vec<u8>,
+    output_pos: usize,
+    // error found in the batch, returned after the data decoded before it
+    error: Option<io::Error>,
+    sequential: Option<SequentialDecoder<R>>,
+}
+
+impl<R: Read> ParallelGzDecoder<R> {
+    /// Creates a decoder using as many threads as there are CPUs.
+    pub fn new(reader: R) -> ParallelGzDecoder<R> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_threads(reader, threads)
+    }
+
+    pub fn with_threads(reader: R, threads: usize) -> ParallelGzDecoder<R> {
+        let threads = threads.max(1);
+        Self::with_batch_size(reader, threads, threads * BATCH_SIZE_PER_THREAD)
+    }
+
+    fn with_batch_size(reader: R, threads: usize, batch_size: usize) -> ParallelGzDecoder<R> {
+        ParallelGzDecoder {
+            reader: Some(reader),
+            threads,
+            batch_size,
+            input: vec![],
+            input_done: false,
+            output: vec![],
+            output_pos: 0,
+            error: None,
+            sequential: None,
+        }
+    }
+
+    fn fill_input(&mut self) -> io::Result<()> {
+        let reader = self.reader.as_mut().unwrap();
+        while !self.input_done && self.input.len() < self.batch_size {
+            let len = self.input.len();
+            self.input.resize(self.batch_size, 0);
+            let result = reader.read(&mut self.input[len..]);
+            self.input.truncate(len + *result.as_ref().unwrap_or(&0));
+            match result {
+                Ok(0) => self.input_done = true,
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_batch(&mut self) -> io::Result<()> {
+        self.fill_input()?;
+        let (consumed, output, error) = decode_members(&self.input, self.threads);
+        self.input.drain(..consumed);
+        self.output = output;
+        self.output_pos = 0;
+
+        let Some(error) = error else {
+            return Ok(());
+        };
+        let truncated = RzlibError::from_io_error(&error) == Some(&RzlibError::TruncatedStream);
+        if !truncated || self.input_done {
+            self.error = Some(error);
+        } else if consumed == 0 {
+            // the member does not fit in a batch
+            let input = Cursor::new(mem::take(&mut self.input));
+            let reader = input.chain(self.reader.take().unwrap());
+            self.sequential = Some(MultiGzDecoder::new(BufReader::new(reader)));
+        }
+        // otherwise the member is completed by the next batch
+        Ok(())
+    }
+}
+
+// Returns the offsets in `input` that may start a gzip member: ID1, ID2 and CM of deflate,
+// followed by flags without reserved bits.
+fn member_starts(input: &[u8]) -> Vec<usize> {
+    input
+        .windows(4)
+        .enumerate()
+        .filter(|(_, bytes)| bytes[..3] == [0x1f, 0x8b, 0x08] && bytes[3] & 0xe0 == 0)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+// Decodes the gzip member at the start of `input`, returning its compressed size and its data.
+fn decode_member(input: &[u8]) -> io::Result<(usize, Vec<u8>)> {
+    let mut reader = RZLibReader::with_format(input, Format::Gzip);
+    reader.set_multi_member(false);
+    let mut output = vec![];
+    reader.read_to_end(&mut output)?;
+    Ok((reader.total_in() as usize, output))
+}
+
+// Decodes the members at the start of `input` on `threads` threads. Returns the size of the
+// members decoded, their data, and the error that stopped decoding before the end of `input`.
+fn decode_members(input: &[u8], threads: usize) -> (usize, Vec<u8>, Option<io::Error>) {
+    let starts = member_starts(input);
+    let next = AtomicUsize::new(0);
+    let mut members: HashMap<usize, io::Result<(usize, Vec<u8>)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut members = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&start) = starts.get(index) else {
+                            return members;
+                        };
+                        members.push((start, decode_member(&input[start..])));
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    });
+
+    let mut pos = 0;
+    let mut output = vec![];
+    while pos < input.len() {
+        // no candidate here means no valid header either, decode it again for the error
+        match members.remove(&pos).unwrap_or_else(|| decode_member(&input[pos..])) {
+            Ok((len, data)) => {
+                pos += len;
+                output.extend_from_slice(&data);
+            }
+            Err(err) => return (pos, output, Some(err)),
+        }
+    }
+    (pos, output, None)
+}
+
+impl<R: Read> Read for ParallelGzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(decoder) = &mut self.sequential {
+                return decoder.read(buf);
+            }
+            if self.output_pos < self.output.len() {
+                let n = (&self.output[self.output_pos..]).read(buf)?;
+                self.output_pos += n;
+                return Ok(n);
+            }
+            if let Some(err) = self.error.take() {
+                return Err(err);
+            }
+            if self.input_done && self.input.is_empty() {
+                return Ok(0);
+            }
+            self.decode_batch()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::ParallelGzDecoder;
+    use crate::deflateencoder::Compression;
+    use crate::error::RzlibError;
+    use crate::gzencoder::GzEncoder;
+
+    fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // Members of varied sizes, some stored so that their data contains fake member headers.
+    fn members() -> (Vec<u8>, Vec<u8>) {
+        let (mut data, mut compressed) = (vec![], vec![]);
+        for i in 0..100 {
+            let mut member: Vec<u8> = (0..i * 97).map(|j| (j * i % 251) as u8).collect();
+            member.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x00, 0x00]);
+            let level = if i % 3 == 0 { Compression::none() } else { Compression::default() };
+            compressed.extend(compress(&member, level));
+            data.extend(member);
+        }
+        (data, compressed)
+    }
+
+    fn decode<R: Read>(mut decoder: ParallelGzDecoder<R>) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        decoder.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_parallel_decode() {
+        let (data, compressed) = members();
+        for batch_size in [1000, 5000, 1 << 20] {
+            let decoder = ParallelGzDecoder::with_batch_size(&compressed[..], 4, batch_size);
+            assert!(decode(decoder).unwrap() == data, "batch size {}", batch_size);
+        }
+        assert!(decode(ParallelGzDecoder::new(&compressed[..])).unwrap() == data);
+        assert!(decode(ParallelGzDecoder::new(&[][..])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_member_larger_than_batch() {
+        let (data, compressed) = members();
+        let big: Vec<u8> = (0..100_000u32).map(|i| ((i / 7) ^ (i % 251)) as u8).collect();
+        let input = [compress(&big, Compression::default()), compressed].concat();
+        let decoder = ParallelGzDecoder::with_batch_size(&input[..], 2, 4096);
+        assert!(decode(decoder).unwrap() == [big, data].concat());
+    }
+
+    #[test]
+    fn test_errors() {
+        let (data, compressed) = members();
+        let truncated = &compressed[..compressed.len() - 10];
+        let mut decoder = ParallelGzDecoder::with_batch_size(truncated, 3, 2000);
+        let mut out = vec![];
+        let err = decoder.read_to_end(&mut out).unwrap_err();
+        assert_eq!(RzlibError::from_io_error(&err), Some(&RzlibError::TruncatedStream));
+        assert!(data.starts_with(&out) && out.len() > data.len() / 2);
+
+        let garbage = [compressed.clone(), b"garbage".to_vec()].concat();
+        let err = decode(ParallelGzDecoder::with_threads(&garbage[..], 2)).unwrap_err();
+        assert!(matches!(RzlibError::from_io_error(&err), Some(RzlibError::BadMagic { .. })));
+    }
+}
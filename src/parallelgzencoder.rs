@@ -0,0 +1,340 @@
+use std::io;
+use std::io::Write;
+use std::thread;
+
+use crate::crc32::Crc32;
+use crate::deflateencoder::{Compression, DeflateEncoder};
+use crate::gzencoder::header_bytes;
+use crate::gzheader::GzHeader;
+use crate::lz77::WINDOW_SIZE;
+
+// input compressed by a thread at a time, as in pigz
+const CHUNK_SIZE: usize = 128 * 1024;
+
+/// Compresses data written to it into a single gzip member on several threads, as `pigz` does.
+///
+/// The input is split into chunks of 128 KiB, compressed in batches of one chunk per thread.
+/// Each chunk is compressed on its own, with the last 32 KiB before it as a dictionary so that
+/// matches still reach into the previous chunk, and ends at a byte boundary with an empty stored
+/// block, so that the compressed chunks just follow each other. The CRC-32 of the member is
+/// combined from those of the chunks.
+///
+/// The output is a little larger than that of [`GzEncoder`](crate::GzEncoder): blocks end at
+/// chunk boundaries, and each chunk costs an empty stored block.
+///
+/// As with [`GzEncoder`](crate::GzEncoder), the member is completed by
+/// [`ParallelGzEncoder::finish`], or on drop (ignoring errors), and `flush` is a sync flush.
+pub struct ParallelGzEncoder<W: Write> {
+    writer: Option<W>,
+    level: Compression,
+    header: GzHeader,
+    header_written: bool,
+    finished: bool,
+    threads: usize,
+    chunk_size: usize,
+    // the last WINDOW_SIZE bytes already compressed, followed by data not compressed yet
+    data: Vec<u8>,
+    history_len: usize,
+    crc: Crc32,
+    size: u32,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    /// Creates an encoder using as many threads as there are CPUs.
+    pub fn new(writer: W, level: Compression) -> ParallelGzEncoder<W> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_threads(writer, level, threads)
+    }
+
+    pub fn with_threads(writer: W, level: Compression, threads: usize) -> ParallelGzEncoder<W> {
+        Self::with_chunk_size(writer, level, threads.max(1), CHUNK_SIZE)
+    }
+
+    fn with_chunk_size(
+        writer: W,
+        level: Compression,
+        threads: usize,
+        chunk_size: usize,
+    ) -> ParallelGzEncoder<W> {
+        ParallelGzEncoder {
+            writer: Some(writer),
+            level,
+            // no metadata, and OS = unknown
+            header: GzHeader {
+                os: 255,
+                ..GzHeader::default()
+            },
+            header_written: false,
+            finished: false,
+            threads,
+            chunk_size,
+            data: vec![],
+            history_len: 0,
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+
+    /// Sets the member header, see [`GzEncoder::with_header`](crate::GzEncoder::with_header).
+    /// Has no effect once something was written.
+    pub fn set_header(&mut self, header: GzHeader) {
+        self.header = header;
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().unwrap()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.threads * self.chunk_size
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        let bytes = header_bytes(&self.header, self.level, false)?;
+        self.get_mut().write_all(&bytes)?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    // Compresses the pending data, a chunk per thread, and writes it out. The last chunk ends the
+    // deflate stream if `is_final` is set.
+    fn write_batch(&mut self, is_final: bool) -> io::Result<()> {
+        self.write_header()?;
+        let pending = &self.data[self.history_len..];
+        let mut starts: Vec<usize> = (0..pending.len())
+            .step_by(self.chunk_size)
+            .map(|start| self.history_len + start)
+            .collect();
+        if starts.is_empty() {
+            // the final block needs a chunk, even an empty one
+            starts.push(self.data.len());
+        }
+        let data = &self.data;
+        let (level, chunk_size) = (self.level, self.chunk_size);
+        let chunks: Vec<(Vec<u8>, u32, usize)> = thread::scope(|scope| {
+            let workers: Vec<_> = starts
+                .iter()
+                .enumerate()
+                .map(|(i, &start)| {
+                    let end = (start + chunk_size).min(data.len());
+                    let dictionary = &data[start.saturating_sub(WINDOW_SIZE)..start];
+                    let is_final = is_final && i == starts.len() - 1;
+                    scope.spawn(move || {
+                        let (compressed, crc) =
+                            compress_chunk(dictionary, &data[start..end], level, is_final);
+                        (compressed, crc, end - start)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let writer = self.writer.as_mut().unwrap();
+        for (compressed, crc, len) in chunks {
+            writer.write_all(&compressed)?;
+            self.crc.combine(crc, len as u64);
+            self.size = self.size.wrapping_add(len as u32);
+        }
+        // keep the last WINDOW_SIZE bytes around as the dictionary of the next chunk
+        if self.data.len() > WINDOW_SIZE {
+            self.data.drain(..self.data.len() - WINDOW_SIZE);
+        }
+        self.history_len = self.data.len();
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_batch(true)?;
+        let mut trailer: [u8; 8] = [0; 8];
+        trailer[..4].copy_from_slice(&self.crc.sum().to_le_bytes());
+        trailer[4..].copy_from_slice(&self.size.to_le_bytes());
+        self.get_mut().write_all(&trailer)?;
+        self.finished = true;
+        self.get_mut().flush()
+    }
+
+    /// Compresses the rest of the data, writes the member trailer, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().unwrap())
+    }
+}
+
+// Compresses `chunk` as deflate blocks which may refer back into `dictionary`, ending at a byte
+// boundary. Returns them and the CRC-32 of `chunk`.
+fn compress_chunk(
+    dictionary: &[u8],
+    chunk: &[u8],
+    level: Compression,
+    is_final: bool,
+) -> (Vec<u8>, u32) {
+    let mut crc = Crc32::new();
+    crc.update(chunk);
+    let mut encoder = DeflateEncoder::new(vec![], level);
+    encoder.set_dictionary(dictionary);
+    let compressed = encoder.write_all(chunk).and_then(|()| {
+        if is_final {
+            encoder.finish()
+        } else {
+            // a sync flush ends the blocks with an empty stored block, aligned
+            encoder.flush()?;
+            encoder.take_writer()
+        }
+    });
+    (compressed.expect("writing to a Vec cannot fail"), crc.sum())
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished, "writing to a finished encoder");
+        if self.data.len() - self.history_len == self.batch_size() {
+            self.write_batch(false)?;
+        }
+        let len = buf
+            .len()
+            .min(self.batch_size() - (self.data.len() - self.history_len));
+        self.data.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.finished && self.history_len < self.data.len() {
+            self.write_batch(false)?;
+        }
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for ParallelGzEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::ParallelGzEncoder;
+    use crate::deflateencoder::Compression;
+    use crate::gzdecoder::GzDecoder;
+    use crate::gzencoder::GzEncoder;
+    use crate::gzheader::GzHeader;
+
+    fn data(len: u32) -> Vec<u8> {
+        (0..len)
+            .map(|i| ((i / 7) ^ (i % 251) ^ (i >> 13)) as u8)
+            .collect()
+    }
+
+    fn decompress(compressed: &[u8]) -> Vec<u8> {
+        // a single member, which GzDecoder stops at
+        let mut decoder = GzDecoder::new(compressed);
+        let mut out = vec![];
+        decoder.read_to_end(&mut out).unwrap();
+        assert_eq!(decoder.total_in(), compressed.len() as u64);
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (len, threads, chunk_size) in [
+            (0, 4, 1000),
+            (1, 1, 1000),
+            (100_000, 3, 7000),
+            (100_000, 4, 40_000),
+            (300_000, 8, 128 * 1024),
+        ] {
+            let data = data(len);
+            for level in [0, 1, 6, 9] {
+                let mut encoder = ParallelGzEncoder::with_chunk_size(
+                    vec![],
+                    Compression::new(level),
+                    threads,
+                    chunk_size,
+                );
+                // in uneven writes
+                for piece in data.chunks(33_333) {
+                    encoder.write_all(piece).unwrap();
+                }
+                let compressed = encoder.finish().unwrap();
+                assert_eq!(
+                    decompress(&compressed),
+                    data,
+                    "{} bytes, level {}",
+                    len,
+                    level
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ratio_close_to_sequential() {
+        let data = data(1_000_000);
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let sequential = encoder.finish().unwrap();
+        let mut encoder = ParallelGzEncoder::with_threads(vec![], Compression::default(), 4);
+        encoder.write_all(&data).unwrap();
+        let parallel = encoder.finish().unwrap();
+        assert_eq!(decompress(&parallel), data);
+        // the dictionaries keep matches going across chunks
+        assert!(
+            parallel.len() < sequential.len() * 11 / 10,
+            "{} parallel, {} sequential",
+            parallel.len(),
+            sequential.len()
+        );
+    }
+
+    #[test]
+    fn test_flush_and_header() {
+        let mut encoder = ParallelGzEncoder::with_chunk_size(vec![], Compression::default(), 2, 5);
+        encoder.set_header(GzHeader {
+            filename: Some(b"name".to_vec()),
+            ..GzHeader::default()
+        });
+        encoder.write_all(b"hello, parallel ").unwrap();
+        encoder.flush().unwrap();
+        // everything written so far decodes from the output so far
+        let mut decoder = GzDecoder::new(&encoder.get_ref()[..]);
+        let mut out = vec![0; 16];
+        decoder.read_exact(&mut out).unwrap();
+        assert_eq!(out, b"hello, parallel ");
+        assert_eq!(
+            decoder.header().unwrap().filename.as_deref(),
+            Some(&b"name"[..])
+        );
+
+        encoder.write_all(b"world").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(&compressed), b"hello, parallel world");
+    }
+
+    #[test]
+    fn test_finish_on_drop() {
+        let mut out = vec![];
+        {
+            let mut encoder = ParallelGzEncoder::with_threads(&mut out, Compression::fast(), 2);
+            encoder.write_all(b"dropped").unwrap();
+        }
+        assert_eq!(decompress(&out), b"dropped");
+    }
+}
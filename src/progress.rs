@@ -0,0 +1,135 @@
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// minimum time between two updates of the progress line
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A progress line on stderr for decompressing one input, redrawn in place. When stderr is not
+/// a terminal, only the final state is printed, without the escape sequences redrawing it.
+pub struct Progress {
+    name: String,
+    terminal: bool,
+    // size of the compressed input, if known
+    input_size: Option<u64>,
+    start: Instant,
+    last_update: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(name: &Path, input_size: Option<u64>) -> Progress {
+        Progress {
+            name: name.display().to_string(),
+            terminal: io::stderr().is_terminal(),
+            input_size,
+            start: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    /// Redraws the line, unless it was drawn very recently or stderr is not a terminal.
+    pub fn update(&mut self, consumed: u64, written: u64) {
+        let now = Instant::now();
+        let recent = self
+            .last_update
+            .is_some_and(|last| now - last < UPDATE_INTERVAL);
+        if !self.terminal || recent {
+            return;
+        }
+        self.last_update = Some(now);
+        eprint!("\r\x1b[K{}", self.line(consumed, written, now - self.start));
+    }
+
+    /// Draws the final state of the line and moves to the next one.
+    pub fn finish(&mut self, consumed: u64, written: u64) {
+        let line = self.line(consumed, written, self.start.elapsed());
+        if self.terminal {
+            eprintln!("\r\x1b[K{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+
+    fn line(&self, consumed: u64, written: u64, elapsed: Duration) -> String {
+        let seconds = elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 {
+            consumed as f64 / seconds
+        } else {
+            0.0
+        };
+        let mut line = format!(
+            "{}: {} -> {}, {}/s",
+            self.name,
+            format_bytes(consumed),
+            format_bytes(written),
+            format_bytes(rate as u64)
+        );
+        if let Some(size) = self.input_size.filter(|&size| size > 0) {
+            let percent = 100.0 * consumed as f64 / size as f64;
+            line.push_str(&format!(", {:.1}%", percent.min(100.0)));
+            if rate > 0.0 {
+                let eta = size.saturating_sub(consumed) as f64 / rate;
+                line.push_str(&format!(", ETA {}", format_duration(eta as u64)));
+            }
+        }
+        line
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn format_duration(seconds: u64) -> String {
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::{format_bytes, format_duration, Progress};
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+        assert_eq!(format_duration(65), "1:05");
+        assert_eq!(format_duration(3725), "1:02:05");
+    }
+
+    #[test]
+    fn test_line() {
+        let progress = Progress::new(Path::new("big.gz"), Some(4 << 20));
+        assert_eq!(
+            progress.line(1 << 20, 3 << 20, Duration::from_secs(2)),
+            "big.gz: 1.0 MiB -> 3.0 MiB, 512.0 KiB/s, 25.0%, ETA 0:06"
+        );
+        let progress = Progress::new(Path::new("stdin"), None);
+        assert_eq!(
+            progress.line(100, 200, Duration::from_secs(1)),
+            "stdin: 100 B -> 200 B, 100 B/s"
+        );
+    }
+}
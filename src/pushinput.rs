@@ -0,0 +1,132 @@
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::{BufRead, ErrorKind, Read, Write};
+use crate::rzlibreader::RZLibReader;
+
+// Compressed input handed to the decoder in chunks, for bindings and writers where the caller
+// pushes data instead of the decoder pulling it. Running out of input is reported as `WouldBlock`
+// until `finish` is called, which the decoder recovers from once more input is pushed.
+pub(crate) struct PushInput {
+    data: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl PushInput {
+    pub(crate) fn new() -> PushInput {
+        PushInput { data: Vec::new(), pos: 0, finished: false }
+    }
+
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        // drop what was consumed already, unless it is cheaper to keep it for now
+        if self.pos == self.data.len() || self.pos >= chunk.len() {
+            self.data.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.data.extend_from_slice(chunk);
+    }
+
+    // Marks the end of the input: from now on the decoder sees end of file once it has consumed
+    // everything pushed.
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    // Drops the last `len` bytes pushed, which must not have been consumed yet.
+    pub(crate) fn unpush(&mut self, len: usize) {
+        debug_assert!(len <= self.data.len() - self.pos);
+        self.data.truncate(self.data.len() - len);
+    }
+
+    // Input pushed but not consumed by the decoder, i.e. what follows the stream once it ended.
+    pub(crate) fn remaining(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+}
+
+impl Read for PushInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for PushInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.data.len() && !self.finished {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+// Decodes everything the input pushed so far decompresses to, writing it to `out`. Returns
+// whether the end of the stream was reached.
+pub(crate) fn decode_available<W: Write>(
+    reader: &mut RZLibReader<PushInput>,
+    out: &mut W,
+) -> io::Result<bool> {
+    let mut buf = [0; 32 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(true),
+            Ok(n) => out.write_all(&buf[..n])?,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{decode_available, PushInput};
+    use crate::deflateencoder::Compression;
+    use crate::error::RzlibError;
+    use crate::gzencoder::GzEncoder;
+    use crate::rzlibreader::RZLibReader;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decode_pushed_chunks() {
+        let data: Vec<u8> = (0..30_000u32).map(|i| (i * i / 3 % 239) as u8).collect();
+        let compressed = [compress(&data), compress(b"more")].concat();
+
+        for chunk_size in [1, 5, 1000] {
+            let mut reader = RZLibReader::new(PushInput::new());
+            let mut out = vec![];
+            for chunk in compressed.chunks(chunk_size) {
+                reader.get_mut().push(chunk);
+                decode_available(&mut reader, &mut out).unwrap();
+            }
+            reader.get_mut().finish();
+            assert!(decode_available(&mut reader, &mut out).unwrap());
+            assert!(out == [&data[..], b"more"].concat(), "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_truncated_after_finish() {
+        let compressed = compress(b"hello, hello");
+        let mut reader = RZLibReader::new(PushInput::new());
+        reader.get_mut().push(&compressed[..compressed.len() - 3]);
+        decode_available(&mut reader, &mut vec![]).unwrap();
+
+        reader.get_mut().finish();
+        let err = decode_available(&mut reader, &mut vec![]).unwrap_err();
+        assert_eq!(RzlibError::from_io_error(&err), Some(&RzlibError::TruncatedStream));
+    }
+}
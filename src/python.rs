@@ -0,0 +1,292 @@
+//! Python bindings of the decoder, built with PyO3 when the `python` feature is on, mirroring the
+//! decompression side of the `gzip` and `zlib` modules.
+//!
+//! Build the extension module with `maturin build --features python,pyo3/extension-module`.
+//! Malformed data raises `rzlib.BadGzipFile`, an `OSError` like `gzip.BadGzipFile`, and
+//! truncated data raises `EOFError`.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyEOFError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::error::RzlibError;
+use crate::pushinput::{decode_available, PushInput};
+use crate::rzlibreader::{Format, RZLibReader};
+
+create_exception!(rzlib, BadGzipFile, PyOSError);
+
+fn to_py_err(err: io::Error) -> PyErr {
+    match RzlibError::from_io_error(&err) {
+        Some(RzlibError::TruncatedStream) => PyEOFError::new_err(err.to_string()),
+        Some(_) => BadGzipFile::new_err(err.to_string()),
+        None => err.into(),
+    }
+}
+
+fn parse_format(format: &str) -> PyResult<Format> {
+    match format {
+        "gzip" => Ok(Format::Gzip),
+        "zlib" => Ok(Format::Zlib),
+        "deflate" => Ok(Format::Deflate),
+        "deflate64" => Ok(Format::Deflate64),
+        _ => Err(PyValueError::new_err(format!("unknown format {:?}", format))),
+    }
+}
+
+/// Decompresses a complete gzip stream, possibly made of several members, like
+/// `gzip.decompress`.
+#[pyfunction]
+fn decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let out = py.detach(|| crate::gzip::decompress_to_vec(data));
+    Ok(PyBytes::new(py, &out.map_err(to_py_err)?))
+}
+
+/// Incremental decompression of a single gzip member (or zlib or raw deflate stream), like
+/// `zlib.decompressobj` or `lzma.LZMADecompressor`.
+#[pyclass(module = "rzlib")]
+struct Decompressor {
+    // pyclasses must be Sync, and RZLibReader is not since its hooks need not be; only ever
+    // reached through `get_mut`
+    reader: Mutex<RZLibReader<PushInput>>,
+    eof: bool,
+    unused_data: Vec<u8>,
+}
+
+#[pymethods]
+impl Decompressor {
+    #[new]
+    #[pyo3(signature = (format = "gzip"))]
+    fn new(format: &str) -> PyResult<Decompressor> {
+        let mut reader = RZLibReader::with_format(PushInput::new(), parse_format(format)?);
+        reader.set_multi_member(false);
+        Ok(Decompressor { reader: Mutex::new(reader), eof: false, unused_data: vec![] })
+    }
+
+    /// Feeds the next chunk of compressed data, returning as much decompressed data as it
+    /// completes. Data following the end of the stream is collected in `unused_data`.
+    fn decompress<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        if self.eof {
+            self.unused_data.extend_from_slice(data);
+            return Ok(PyBytes::new(py, b""));
+        }
+        let reader = self.reader.get_mut().unwrap_or_else(PoisonError::into_inner);
+        reader.get_mut().push(data);
+        let mut out = vec![];
+        if decode_available(reader, &mut out).map_err(to_py_err)? {
+            self.eof = true;
+            self.unused_data = reader.get_ref().remaining().to_vec();
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    /// Whether the end of the stream has been reached.
+    #[getter]
+    fn eof(&self) -> bool {
+        self.eof
+    }
+
+    #[getter]
+    fn unused_data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.unused_data)
+    }
+}
+
+/// Returns a `Decompressor` for the given format: "gzip", "zlib" or "deflate".
+#[pyfunction]
+#[pyo3(signature = (format = "gzip"))]
+fn decompressobj(format: &str) -> PyResult<Decompressor> {
+    Decompressor::new(format)
+}
+
+// A Python binary file object, read through its `read` method.
+struct PyFileReader(Py<PyAny>);
+
+impl Read for PyFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::attach(|py| {
+            let chunk = self.0.bind(py).call_method1("read", (buf.len(),))?;
+            let chunk: &[u8] = chunk.extract()?;
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            Ok::<_, PyErr>(n)
+        })
+        .map_err(io::Error::from)
+    }
+}
+
+type GzipReader = BufReader<RZLibReader<BufReader<Box<dyn Read + Send + Sync>>>>;
+
+/// A gzip file opened for reading, like `gzip.open(file, "rb")`. `file` is a path or a binary
+/// file object.
+#[pyclass(module = "rzlib")]
+struct RzGzipFile {
+    // None once closed; in a Mutex like `Decompressor::reader`
+    reader: Option<Mutex<GzipReader>>,
+}
+
+impl RzGzipFile {
+    fn reader(&mut self) -> PyResult<&mut GzipReader> {
+        self.reader
+            .as_mut()
+            .map(|reader| reader.get_mut().unwrap_or_else(PoisonError::into_inner))
+            .ok_or_else(|| PyValueError::new_err("I/O operation on closed file."))
+    }
+}
+
+#[pymethods]
+impl RzGzipFile {
+    #[new]
+    fn new(file: &Bound<'_, PyAny>) -> PyResult<RzGzipFile> {
+        let inner: Box<dyn Read + Send + Sync> = match file.extract::<PathBuf>() {
+            Ok(path) => Box::new(File::open(path)?),
+            Err(_) => Box::new(PyFileReader(file.clone().unbind())),
+        };
+        let reader = RZLibReader::new(BufReader::new(inner));
+        Ok(RzGzipFile { reader: Some(Mutex::new(BufReader::new(reader))) })
+    }
+
+    /// Reads up to `size` decompressed bytes, or all of them if `size` is negative.
+    #[pyo3(signature = (size = -1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: isize) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut out = vec![];
+        let result = match u64::try_from(size) {
+            Ok(size) => reader.take(size).read_to_end(&mut out),
+            Err(_) => reader.read_to_end(&mut out),
+        };
+        result.map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out))
+    }
+
+    /// Reads a line, of at most `size` bytes if `size` is not negative.
+    #[pyo3(signature = (size = -1))]
+    fn readline<'py>(&mut self, py: Python<'py>, size: isize) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut line = vec![];
+        let result = match u64::try_from(size) {
+            Ok(size) => reader.take(size).read_until(b'\n', &mut line),
+            Err(_) => reader.read_until(b'\n', &mut line),
+        };
+        result.map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &line))
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.reader.is_none()
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, _args: &Bound<'_, PyAny>) {
+        self.close();
+    }
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let line = self.readline(py, -1)?;
+        Ok((!line.as_bytes().is_empty()).then_some(line))
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "rzlib")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompressobj, m)?)?;
+    m.add_class::<Decompressor>()?;
+    m.add_class::<RzGzipFile>()?;
+    m.add("BadGzipFile", m.py().get_type::<BadGzipFile>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pyo3::exceptions::PyEOFError;
+    use pyo3::prelude::*;
+    use pyo3::types::PyBytes;
+
+    use super::{decompress, BadGzipFile, Decompressor, RzGzipFile};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        Python::initialize();
+        Python::attach(|py| {
+            let compressed = compress(b"hello, python");
+            assert_eq!(decompress(py, &compressed).unwrap().as_bytes(), b"hello, python");
+
+            let err = decompress(py, &compressed[..10]).unwrap_err();
+            assert!(err.is_instance_of::<PyEOFError>(py));
+            let err = decompress(py, b"not gzip at all").unwrap_err();
+            assert!(err.is_instance_of::<BadGzipFile>(py));
+        });
+    }
+
+    #[test]
+    fn test_decompressor() {
+        Python::initialize();
+        Python::attach(|py| {
+            let input = [&compress(b"first member")[..], b"unused"].concat();
+            let mut decompressor = Decompressor::new("gzip").unwrap();
+            let mut out = vec![];
+            for chunk in input.chunks(3) {
+                out.extend_from_slice(decompressor.decompress(py, chunk).unwrap().as_bytes());
+            }
+            assert_eq!(out, b"first member");
+            assert!(decompressor.eof());
+            assert_eq!(decompressor.unused_data(py).as_bytes(), b"unused");
+            assert!(Decompressor::new("bzip2").is_err());
+        });
+    }
+
+    #[test]
+    fn test_gzip_file() {
+        Python::initialize();
+        Python::attach(|py| {
+            let compressed = compress(b"line one\nline two\nlast");
+            let bytes_io = py.import("io").unwrap().getattr("BytesIO").unwrap();
+            let fileobj = bytes_io.call1((PyBytes::new(py, &compressed),)).unwrap();
+
+            let mut file = RzGzipFile::new(&fileobj).unwrap();
+            assert_eq!(file.readline(py, -1).unwrap().as_bytes(), b"line one\n");
+            assert_eq!(file.read(py, 4).unwrap().as_bytes(), b"line");
+            assert_eq!(file.__next__(py).unwrap().unwrap().as_bytes(), b" two\n");
+            assert_eq!(file.read(py, -1).unwrap().as_bytes(), b"last");
+            assert!(file.__next__(py).unwrap().is_none());
+            file.close();
+            assert!(file.closed());
+            assert!(file.read(py, -1).is_err());
+        });
+    }
+}
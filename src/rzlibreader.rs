@@ -1,23 +1,35 @@
-use std::error::Error;
-use std::fs::{read, File};
-use std::io::ErrorKind::{InvalidData, InvalidInput};
-use std::io::{BufRead, Read, Write};
-use std::{cmp, fmt, io, mem, result};
-use log::debug;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::{cmp, mem};
 
+use crate::io;
+use crate::io::ErrorKind::{Interrupted, InvalidInput, UnexpectedEof, WouldBlock};
+use crate::io::{BufRead, Read};
+
+use crate::adler32::Adler32;
+use crate::binary::invalid;
 use crate::bitreader;
 use crate::bitreader::BitRead;
-use crate::huffman;
-use crate::huffman::HuffmanTree;
+use crate::blockinfo::{BlockInfo, BlockType, DynamicHeader, Histograms, Stats};
+use crate::crc32::Crc32;
+use crate::error::{DecodeError, RzlibError, StreamPosition};
+use crate::gzheader::{split_subfields, GzHeader};
+use crate::gzindex::Checkpoint;
+use crate::hooks::DecodeHooks;
+use crate::members::{GzTrailer, Members};
+use crate::snapshot::{BlockCodes, Snapshot};
+use crate::deflatetables::{
+    distance_code, CODE_LENGTH_ORDER, DEFLATE64_LENGTH_285_BASE, DEFLATE64_LENGTH_285_EXTRA_BITS,
+    DISTANCE_BASE, DISTANCE_CODES, DISTANCE_EXTRA_BITS, END_OF_BLOCK, LENGTH_BASE,
+    LENGTH_EXTRA_BITS,
+};
+use crate::huffman::{is_valid_code, HuffmanTable};
 use crate::lookbackbuffer::LookbackBuffer;
-use crate::rzlibreader::State::{BlockHeader, BrokenStream, EndOfFile, HuffmanBlock, HuffmanBlockMatch, MemberHeader, MemberTrailer, NoCompressionBlock};
-
-fn invalid_data_error(s: &str) -> io::Error {
-    return io::Error::new(InvalidData, s);
-}
+use crate::rzlibreader::State::{BlockHeader, BrokenStream, EndOfFile, HuffmanBlock, HuffmanBlockLength, HuffmanBlockMatch, MemberHeader, MemberTrailer, NoCompressionBlock};
 
-#[derive(Debug)]
-enum State {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum State {
     BrokenStream,
     MemberHeader,
     BlockHeader,
@@ -25,14 +37,14 @@ enum State {
         len: usize,
         is_final: bool,
     },
-    HuffmanBlock {
-        litlen_tree: HuffmanTree<usize>,
-        distance_tree: HuffmanTree<usize>,
+    // the codes of Huffman blocks are kept in the reader, rather than moved from state to state
+    HuffmanBlock { is_final: bool },
+    // the length symbol of a match has been read, but not the rest of it
+    HuffmanBlockLength {
+        symbol: usize,
         is_final: bool,
     },
     HuffmanBlockMatch {
-        litlen_tree: HuffmanTree<usize>,
-        distance_tree: HuffmanTree<usize>,
         length: usize,
         distance: usize,
         is_final: bool,
@@ -40,159 +52,783 @@ enum State {
     MemberTrailer,
     EndOfFile,
 }
-#[derive(Debug)]
-enum Item {
-    Literal { byte: u8 },
-    Match { length: usize, distance: usize },
+
+pub(crate) const LOOKBACK_WINDOW_SIZE: usize = 2_usize.pow(15);
+const DEFLATE64_WINDOW_SIZE: usize = 2_usize.pow(16);
+// Reads shorter than this are served from up to `DECODE_AHEAD` bytes decoded ahead into the
+// window, rather than stopping and resuming decoding every few bytes.
+const SMALL_READ: usize = 256;
+const DECODE_AHEAD: usize = 32 * 1024;
+// Longest run of literals decoded before writing them to the window.
+const LITERAL_RUN: usize = 256;
+
+// Whether `err` only means the underlying reader has no input for now, rather than the stream
+// being broken. Decoding picks up where it stopped on the next read.
+fn is_retryable(err: &io::Error) -> bool {
+    matches!(err.kind(), Interrupted | WouldBlock)
+}
+
+/// The container format wrapping the deflate stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// gzip (RFC 1952), possibly made of several concatenated members.
+    Gzip,
+    /// zlib (RFC 1950), as used by PNG and HTTP `deflate`.
+    Zlib,
+    /// A bare deflate (RFC 1951) stream, without header, trailer or checksum. Decoding stops
+    /// after the final block.
+    Deflate,
+    /// A bare DEFLATE64 stream, the "enhanced deflate" of ZIP method 9: deflate with a 64 KiB
+    /// window, distance codes 30 and 31 reaching into it, and matches of up to 65538 bytes
+    /// through length code 285.
+    Deflate64,
+}
+
+/// How strictly [`RZLibReader`] holds streams to the spec, see [`RZLibReader::set_strictness`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Deviations from the spec are errors, including gzip header fields the spec does not define.
+    Strict,
+    /// Header fields are accepted as they are, but the stream itself must be complete.
+    #[default]
+    Normal,
+    /// Minor deviations that old or broken tools produce are ignored, as long as the data can
+    /// still be decoded.
+    Lenient,
 }
 
-const LOOKBACK_WINDOW_SIZE: usize = 2_usize.pow(15);
+/// Decompresses a gzip (or zlib or raw deflate, see [`RZLibReader::with_format`]) stream read
+/// from `R`.
+///
+/// Concatenated gzip members are decoded one after another, as if they were a single stream,
+/// unless disabled with [`RZLibReader::set_multi_member`].
+///
+/// `R` may be non-blocking: a `WouldBlock` from it is returned from `read` once nothing more
+/// can be decoded, and the next `read` picks up where decoding stopped, even in the middle of a
+/// code. `Interrupted` is retried.
+///
+/// Data is decoded into the window, and copied out of it by `read`. Small reads are served from
+/// a chunk of output decoded ahead, so [`RZLibReader::total_in`] and
+/// the header and blocks recorded may run ahead of the data returned so far.
 pub struct RZLibReader<R: Read + BufRead> {
+    format: Format,
     state: State,
     reader: bitreader::BitReader<R>,
     lookback: LookbackBuffer,
-    total_bytes_read: usize,
-
+    // codes of the current Huffman block, and what they were built from
+    litlen_table: HuffmanTable,
+    distance_table: HuffmanTable,
+    codes: BlockCodes,
+    // decompressed bytes returned so far
+    total_out: u64,
+    max_output_bytes: Option<u64>,
+    crc: Crc32,
+    adler: Adler32,
+    verify_checksum: bool,
+    strictness: Strictness,
+    ignore_trailing_garbage: bool,
+    // whether decoding continues with the next gzip member after a member trailer
+    multi_member: bool,
+    // size of `lookback`, i.e. the largest match distance accepted
+    window_size: usize,
+    // last `window_size` bytes of the preset dictionary, and the Adler-32 of all of it
+    dictionary: Vec<u8>,
+    dictionary_id: Option<u32>,
+    // uncompressed size of the current member, modulo 2^32 like the ISIZE trailer field
+    member_size: u32,
+    // 1-based indices of the current member and of the current block within it, for errors
+    member_index: u64,
+    block_index: u64,
+    // header of the current gzip member, and its trailer once read
+    header: Option<GzHeader>,
+    trailer: Option<GzTrailer>,
+    // see `record_checkpoints`
+    checkpoint_span: Option<u64>,
+    checkpoints: Vec<Checkpoint>,
+    // see `set_record_blocks`, and the statistics of the current block so far
+    blocks: Option<Vec<BlockInfo>>,
+    block_out: u64,
+    block_matches: u64,
+    block_match_bytes: u64,
+    // see `set_record_histograms`
+    histograms: Option<Histograms>,
+    // see `set_hooks`
+    hooks: Option<Box<dyn DecodeHooks + Send>>,
+    // see `set_stop_at_sync_points`; a sync point was just decoded, and the last read stopped at
+    // one
+    stop_at_sync_points: bool,
+    sync_point_decoded: bool,
+    at_sync_point: bool,
+    // where data skipped by seeking is decoded to, allocated on the first seek
+    #[cfg(feature = "std")]
+    scratch: Vec<u8>,
+    // decoded output not returned yet: `unread` bytes starting `unread_distance` bytes back in
+    // the window, followed by the byte past the output limit, if decoded
+    unread: usize,
+    unread_distance: usize,
 }
 impl<R: Read + BufRead> RZLibReader<R> {
     pub fn new(reader: R) -> RZLibReader<R> {
+        Self::with_format(reader, Format::Gzip)
+    }
+
+    pub fn with_format(reader: R, format: Format) -> RZLibReader<R> {
+        let window_size = match format {
+            Format::Deflate64 => DEFLATE64_WINDOW_SIZE,
+            _ => LOOKBACK_WINDOW_SIZE,
+        };
+        Self::with_window_size(reader, format, window_size)
+    }
+
+    /// Creates a decoder keeping `window_size` bytes of history instead of the 32 KiB deflate
+    /// (64 KiB DEFLATE64) allows for. Streams referring further back than that fail with
+    /// [`RzlibError::InvalidDistance`], and zlib streams declaring a larger window with
+    /// [`RzlibError::BadWindowSize`]. A smaller window saves memory when the streams are known
+    /// to have been compressed with one. Panics if `window_size` is 0.
+    pub fn with_window_size(reader: R, format: Format, window_size: usize) -> RZLibReader<R> {
         RZLibReader {
-            state: MemberHeader,
+            format,
+            state: Self::initial_state(format),
             reader: bitreader::BitReader::new(reader),
-            lookback: LookbackBuffer::new(LOOKBACK_WINDOW_SIZE),
-            total_bytes_read: 0,
+            lookback: LookbackBuffer::new(window_size),
+            litlen_table: HuffmanTable::empty(),
+            distance_table: HuffmanTable::empty(),
+            codes: BlockCodes::None,
+            window_size,
+            total_out: 0,
+            max_output_bytes: None,
+            crc: Crc32::new(),
+            adler: Adler32::new(),
+            verify_checksum: true,
+            strictness: Strictness::Normal,
+            ignore_trailing_garbage: false,
+            multi_member: true,
+            dictionary: vec![],
+            dictionary_id: None,
+            member_size: 0,
+            member_index: Self::initial_member_index(format),
+            block_index: 0,
+            header: None,
+            trailer: None,
+            checkpoint_span: None,
+            checkpoints: vec![],
+            blocks: None,
+            block_out: 0,
+            block_matches: 0,
+            block_match_bytes: 0,
+            histograms: None,
+            hooks: None,
+            stop_at_sync_points: false,
+            sync_point_decoded: false,
+            at_sync_point: false,
+            #[cfg(feature = "std")]
+            scratch: vec![],
+            unread: 0,
+            unread_distance: 0,
+        }
+    }
+
+    fn initial_state(format: Format) -> State {
+        match format {
+            Format::Gzip | Format::Zlib => MemberHeader,
+            Format::Deflate | Format::Deflate64 => BlockHeader,
+        }
+    }
+
+    fn initial_member_index(format: Format) -> u64 {
+        match format {
+            Format::Gzip | Format::Zlib => 0,
+            Format::Deflate | Format::Deflate64 => 1,
+        }
+    }
+
+    /// Starts decoding a new stream, of the same format, from `reader`, and returns the previous
+    /// underlying reader. Options are kept, and the window buffer is reused rather than
+    /// allocated again.
+    pub fn reset(&mut self, reader: R) -> R {
+        self.clear_stream_state();
+        // cannot fail, the dictionary is no longer than the window
+        let _ = self.lookback.write_data(&self.dictionary);
+        mem::replace(&mut self.reader, bitreader::BitReader::new(reader)).into_inner()
+    }
+
+    fn clear_stream_state(&mut self) {
+        self.state = Self::initial_state(self.format);
+        self.lookback.clear();
+        self.total_out = 0;
+        self.crc.reset();
+        self.adler.reset();
+        self.member_size = 0;
+        self.member_index = Self::initial_member_index(self.format);
+        self.block_index = 0;
+        self.header = None;
+        self.trailer = None;
+        self.unread = 0;
+        self.unread_distance = 0;
+        self.sync_point_decoded = false;
+        self.at_sync_point = false;
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
+        }
+        if let Some(histograms) = &mut self.histograms {
+            *histograms = Histograms::default();
+        }
+    }
+
+    /// Primes the window with a preset dictionary, so the stream can refer back into it. Must be
+    /// called before reading. zlib streams that declare a dictionary (FDICT) are only decoded if
+    /// it matches the one set here; raw deflate and gzip streams just use it. Only the last window
+    /// size (32 KiB by default) of `dictionary` matters.
+    ///
+    /// The dictionary is kept across [`RZLibReader::reset`].
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let mut adler = Adler32::new();
+        adler.update(dictionary);
+        self.dictionary_id = Some(adler.sum());
+        let start = dictionary.len().saturating_sub(self.window_size);
+        self.dictionary = dictionary[start..].to_vec();
+        self.lookback.clear();
+        let _ = self.lookback.write_data(&self.dictionary);
+    }
+
+    /// Sets whether the checksum of the decompressed data (CRC-32 for gzip, Adler-32 for zlib) is
+    /// checked against the trailer. Enabled by default.
+    pub fn set_verify_checksum(&mut self, verify: bool) {
+        self.verify_checksum = verify;
+    }
+
+    /// Sets how deviations from the spec are handled. With [`Strictness::Strict`], gzip headers
+    /// with reserved FLG bits set, an OS value RFC 1952 does not list, an extra field that is
+    /// not made of subfields or a file name or comment that is not valid UTF-8 fail with
+    /// [`RzlibError::BadHeaderField`]. With
+    /// [`Strictness::Normal`], the default, and [`Strictness::Lenient`], the header fields are
+    /// accepted as they are. A missing trailer fails with [`RzlibError::TruncatedStream`], unless
+    /// lenient: a stream whose input ends right after its final block is then taken as complete.
+    ///
+    /// Header strings are kept as raw bytes when accepted, see [`GzHeader::filename`].
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    /// Sets whether gzip members following the first one are decoded as well, as if they were a
+    /// single stream. Enabled by default; when disabled, decoding stops after the first member's
+    /// trailer and following bytes are left in the underlying reader.
+    pub fn set_multi_member(&mut self, multi_member: bool) {
+        self.multi_member = multi_member;
+    }
+
+    pub(crate) fn multi_member(&self) -> bool {
+        self.multi_member
+    }
+
+    /// Returns the members of the stream one by one, each with its header, its decompressed data
+    /// and, once that is read, its trailer; see [`Members`]. Starts with the member being
+    /// decoded, if any.
+    pub fn members(&mut self) -> Members<'_, R> {
+        Members::new(self)
+    }
+
+    // Moves on to the next member, once the current one is finished, and reads its header. The
+    // reader is left at the end of the stream if there is none.
+    pub(crate) fn start_member(&mut self) -> io::Result<()> {
+        if matches!(self.state, EndOfFile) && self.format == Format::Gzip && self.member_index > 0 {
+            self.state = MemberHeader;
+        }
+        while matches!(self.state, MemberHeader) {
+            let state = mem::replace(&mut self.state, BrokenStream);
+            match self.read_header(state) {
+                Err(err) if err.kind() == Interrupted => {}
+                result => result.map_err(|err| self.decode_error(err))?,
+            }
+        }
+        Ok(())
+    }
+
+    // Whether all the data of the current member has been read, and its trailer too.
+    pub(crate) fn member_finished(&self) -> bool {
+        matches!(self.state, EndOfFile) && self.unread == 0
+    }
+
+    pub(crate) fn member_index(&self) -> u64 {
+        self.member_index
+    }
+
+    pub(crate) fn trailer(&self) -> Option<GzTrailer> {
+        self.trailer
+    }
+
+    /// Sets whether bytes following a gzip member that do not start another member, such as the
+    /// padding of a tape block, end the stream rather than failing with [`RzlibError::BadMagic`].
+    /// Disabled by default. Ignored bytes are logged as a warning and left unconsumed.
+    pub fn set_ignore_trailing_garbage(&mut self, ignore: bool) {
+        self.ignore_trailing_garbage = ignore;
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Number of compressed bytes consumed so far, counting a partially consumed byte.
+    pub fn total_in(&self) -> u64 {
+        self.reader.bit_position().div_ceil(8)
+    }
+
+    /// Number of decompressed bytes produced so far, across all members.
+    pub fn total_out(&self) -> u64 {
+        self.total_out - self.unread_distance as u64
+    }
+
+    /// Returns the last window size (32 KiB by default, less at the start of the stream) of
+    /// decompressed data, which is what the rest of the stream can refer back to. Decoding can be
+    /// resumed later from the following block by passing it to [`RZLibReader::set_dictionary`] of
+    /// a raw deflate decoder.
+    pub fn window(&self) -> Vec<u8> {
+        let mut window = self.lookback.snapshot();
+        // leave out what was decoded ahead, but not returned yet
+        window.truncate(window.len() - self.unread_distance);
+        window
+    }
+
+    /// Returns the header of the gzip member being decoded, or of the last one once the stream
+    /// has ended. `None` before the first header has been read, and for other formats.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Returns the underlying reader. Reading from it in the middle of the stream corrupts the
+    /// decoding.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+    /// Returns the underlying reader. Once the stream has been read to the end, it is positioned
+    /// right after the stream (the zlib trailer, the end of the final deflate block, or the last
+    /// gzip member decoded).
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Sets the most bytes of compressed input taken from the underlying reader, counted from its
+    /// start, for streams whose length is known from their framing, e.g. the next N bytes of a
+    /// connection: nothing past them is read, and a stream needing more fails with
+    /// [`RzlibError::TruncatedStream`]. Unlimited by default.
+    pub fn set_max_input_bytes(&mut self, limit: Option<u64>) {
+        self.reader.set_limit(limit);
+    }
+
+    /// Limits the decompressed output to `limit` bytes; reading past it fails with
+    /// [`RzlibError::OutputLimitExceeded`]. Use this to guard against decompression bombs when
+    /// decoding untrusted input. Unlimited by default.
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.max_output_bytes = limit;
+    }
+
+    /// Sets whether the structure of every block decoded from now on is recorded, see
+    /// [`RZLibReader::blocks`]. Disabled by default.
+    pub fn set_record_blocks(&mut self, record: bool) {
+        self.blocks = record.then(Vec::new);
+    }
+
+    /// Returns the blocks recorded so far, see [`RZLibReader::set_record_blocks`].
+    pub fn blocks(&self) -> &[BlockInfo] {
+        self.blocks.as_deref().unwrap_or_default()
+    }
+
+    /// Returns statistics of the blocks recorded so far, and of the members holding them. Blocks
+    /// are only recorded once enabled with [`RZLibReader::set_record_blocks`].
+    pub fn stats(&self) -> Stats {
+        Stats::from_blocks(self.blocks())
+    }
+
+    /// Sets whether how often each literal/length symbol and distance code is decoded from now
+    /// on is counted, see [`RZLibReader::histograms`]. Disabled by default, as it slows decoding
+    /// down a little.
+    pub fn set_record_histograms(&mut self, record: bool) {
+        self.histograms = record.then(Histograms::default);
+    }
+
+    /// Returns the symbol counts so far, across all members, if enabled with
+    /// [`RZLibReader::set_record_histograms`].
+    pub fn histograms(&self) -> Option<&Histograms> {
+        self.histograms.as_ref()
+    }
+
+    /// Sets the hooks called at member and block boundaries, see [`DecodeHooks`]. Hooks are
+    /// kept across [`RZLibReader::reset`].
+    pub fn set_hooks(&mut self, hooks: Option<Box<dyn DecodeHooks + Send>>) {
+        self.hooks = hooks;
+    }
+
+    /// Sets whether reading stops at each sync point of the stream, the empty stored block a sync
+    /// or full flush leaves (`00 00 ff ff` once byte-aligned): once all the data before it has
+    /// been returned, `read` returns `Ok(0)` there, and [`RZLibReader::at_sync_point`] tells it
+    /// from the end of the stream. Reading again goes on past it. This lets streams of records
+    /// written with a flush after each treat flush points as record boundaries. Disabled by
+    /// default.
+    pub fn set_stop_at_sync_points(&mut self, stop: bool) {
+        self.stop_at_sync_points = stop;
+    }
+
+    /// Whether the last read returned `Ok(0)` at a sync point rather than at the end of the
+    /// stream, see [`RZLibReader::set_stop_at_sync_points`].
+    pub fn at_sync_point(&self) -> bool {
+        self.at_sync_point
+    }
+
+    // Fills in the statistics of the last recorded block, once its end is decoded.
+    fn end_block(&mut self) {
+        let end_bits = self.reader.bit_position();
+        if let Some(info) = self.blocks.as_mut().and_then(|blocks| blocks.last_mut()) {
+            info.compressed_bits = end_bits - info.in_bits;
+            info.uncompressed = self.block_out;
+            if info.block_type != BlockType::Stored {
+                info.literals = self.block_out - self.block_match_bytes;
+            }
+            info.matches = self.block_matches;
+            info.match_bytes = self.block_match_bytes;
+        }
+    }
+
+    // Records checkpoints of a gzip stream at the first block boundary after every `span` bytes of
+    // output, for `GzIndex`.
+    pub(crate) fn record_checkpoints(&mut self, span: u64) {
+        self.checkpoint_span = Some(span);
+    }
+
+    pub(crate) fn take_checkpoints(&mut self) -> Vec<Checkpoint> {
+        mem::take(&mut self.checkpoints)
+    }
+
+    // Called at a block boundary, once the output decoded so far has been accounted for.
+    fn record_checkpoint(&mut self) {
+        let Some(span) = self.checkpoint_span else {
+            return;
+        };
+        let last_out = self.checkpoints.last().map_or(0, |checkpoint| checkpoint.out);
+        if self.format == Format::Gzip && self.total_out >= last_out + span {
+            self.checkpoints.push(Checkpoint {
+                out: self.total_out,
+                in_bits: self.reader.bit_position(),
+                window: self.lookback.snapshot(),
+                crc: self.crc.sum(),
+                member_size: self.member_size,
+            });
+        }
+    }
+
+    /// Takes a [`Snapshot`] of the decoder, to resume decoding from here later with
+    /// [`RZLibReader::restore`]. Fails if the stream is broken.
+    ///
+    /// Options, the preset dictionary, the blocks and statistics recorded and the hooks are not
+    /// part of the snapshot.
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        if self.state == BrokenStream {
+            return Err(io::Error::new(InvalidInput, "cannot take a snapshot of a broken stream"));
         }
+        let codes = match self.state {
+            HuffmanBlock { .. } | HuffmanBlockLength { .. } | HuffmanBlockMatch { .. } => {
+                self.codes.clone()
+            }
+            _ => BlockCodes::None,
+        };
+        Ok(Snapshot {
+            format: self.format,
+            state: self.state.clone(),
+            in_bits: self.reader.bit_position(),
+            total_out: self.total_out,
+            window: self.lookback.snapshot(),
+            unread: self.unread,
+            unread_distance: self.unread_distance,
+            codes,
+            crc: self.crc.sum(),
+            adler: self.adler.sum(),
+            member_size: self.member_size,
+            member_index: self.member_index,
+            block_index: self.block_index,
+            header: self.header.clone(),
+            trailer: self.trailer,
+        })
     }
 
-    fn read_cstring(&mut self) -> io::Result<String> {
-        let mut buf = vec![];
-        let bytes_read = self.reader.read_until(0, &mut buf)?;
-        match buf.pop() {
-            Some(0) => (),
-            None | Some(_) => return Err(invalid_data_error("expect null-terminated string")),
+    /// Goes back to the state of `snapshot`, taken from a decoder of the same format, once the
+    /// underlying reader has been moved to [`Snapshot::input_offset`]; positions in the
+    /// compressed data are counted from the start of the stream as before. Fails with
+    /// `InvalidInput` if the window of this decoder is too small for the snapshot, and with
+    /// `InvalidData` if the snapshot is inconsistent.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if snapshot.format != self.format {
+            return Err(io::Error::new(InvalidInput, "snapshot of a stream of another format"));
+        }
+        if snapshot.window.len() > self.lookback.capacity() {
+            return Err(io::Error::new(InvalidInput, "snapshot window larger than the decoder's"));
+        }
+        if snapshot.unread > snapshot.unread_distance
+            || snapshot.unread_distance > snapshot.window.len()
+            || snapshot.state == BrokenStream
+        {
+            return Err(invalid("inconsistent snapshot"));
+        }
+        let needs_codes = matches!(
+            snapshot.state,
+            HuffmanBlock { .. } | HuffmanBlockLength { .. } | HuffmanBlockMatch { .. }
+        );
+        let (litlen_table, distance_table) = match &snapshot.codes {
+            BlockCodes::None if !needs_codes => {
+                (HuffmanTable::empty(), HuffmanTable::empty())
+            }
+            BlockCodes::Fixed => (
+                HuffmanTable::fixed_litlen(),
+                match self.format {
+                    Format::Deflate64 => HuffmanTable::fixed_deflate64_distance(),
+                    _ => HuffmanTable::fixed_distance(),
+                },
+            ),
+            BlockCodes::Dynamic { nlit, lengths }
+                if (257..=288).contains(nlit)
+                    && (*nlit..=*nlit + DISTANCE_BASE.len()).contains(&lengths.len())
+                    && lengths.iter().all(|&len| len <= 15) =>
+            {
+                let lengths: Vec<usize> = lengths.iter().map(|&len| len as usize).collect();
+                let (litlen, distance) = lengths.split_at(*nlit);
+                if litlen[END_OF_BLOCK] == 0
+                    || !is_valid_code(litlen, true)
+                    || !is_valid_code(distance, true)
+                {
+                    return Err(invalid("inconsistent snapshot"));
+                }
+                (HuffmanTable::new_from_lengths(litlen)?, HuffmanTable::new_from_lengths(distance)?)
+            }
+            _ => return Err(invalid("inconsistent snapshot")),
+        };
+
+        self.clear_stream_state();
+        self.reader.restart_at(snapshot.input_offset());
+        if let Err(err) = self.reader.consume_bits((snapshot.in_bits % 8) as usize) {
+            self.state = BrokenStream;
+            return Err(err);
         }
+        self.lookback.write_data(&snapshot.window)?;
+        self.state = snapshot.state.clone();
+        self.litlen_table = litlen_table;
+        self.distance_table = distance_table;
+        self.codes = snapshot.codes.clone();
+        self.total_out = snapshot.total_out;
+        self.unread = snapshot.unread;
+        self.unread_distance = snapshot.unread_distance;
+        self.crc = Crc32::from_sum(snapshot.crc);
+        self.adler = Adler32::from_sum(snapshot.adler);
+        self.member_size = snapshot.member_size;
+        self.member_index = snapshot.member_index;
+        self.block_index = snapshot.block_index;
+        self.header = snapshot.header.clone();
+        self.trailer = snapshot.trailer;
+        Ok(())
+    }
 
-        String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+    // Starts decoding again from `checkpoint`, or from the start of the stream, once the
+    // underlying reader has been moved to the byte holding its first bit (or to the start).
+    #[cfg(feature = "std")]
+    pub(crate) fn restart_at(&mut self, checkpoint: Option<&Checkpoint>) -> io::Result<()> {
+        self.clear_stream_state();
+        let Some(checkpoint) = checkpoint else {
+            self.reader.restart_at(0);
+            let _ = self.lookback.write_data(&self.dictionary);
+            return Ok(());
+        };
+        self.reader.restart_at(checkpoint.in_bits / 8);
+        if let Err(err) = self.reader.consume_bits((checkpoint.in_bits % 8) as usize) {
+            self.state = BrokenStream;
+            return Err(err);
+        }
+        self.lookback.write_data(&checkpoint.window)?;
+        self.state = BlockHeader;
+        self.total_out = checkpoint.out;
+        self.crc = Crc32::from_sum(checkpoint.crc);
+        self.member_size = checkpoint.member_size;
+        self.member_index = 1;
+        Ok(())
     }
+
     fn read_member_header(&mut self) -> io::Result<()> {
         if self.reader.fill_buf()?.is_empty() {
             self.state = EndOfFile;
             return Ok(());
         }
+        // anything but the magic number after a member ends the stream, if allowed
+        if self.ignore_trailing_garbage && self.member_index > 0 {
+            let (bits, count) = self.reader.peek_bits(16)?;
+            if count < 16 || bits & 0xffff != 0x8b1f {
+                warn!("ignoring trailing garbage at byte {}", self.total_in());
+                self.state = EndOfFile;
+                return Ok(());
+            }
+        }
         let id1 = self.reader.read_u8()?;
         let id2 = self.reader.read_u8()?;
 
+        self.crc.reset();
+        self.member_size = 0;
+        self.member_index += 1;
+        self.trailer = None;
+        self.block_index = 0;
+        // members are separate streams: matches cannot reach back into the previous one
+        self.lookback.clear();
+        let _ = self.lookback.write_data(&self.dictionary);
+
         if id1 != 0x1f || id2 != 0x8b {
-            return Err(invalid_data_error(&format!(
-                "wrong id1, id2 (0x{:x}, 0x{:x})",
-                id1, id2
-            )));
-        }
-
-        let cm = self.reader.read_u8()?;
-
-        if cm != 0x08 {
-            return Err(invalid_data_error(&format!("wrong cm (0x{:x})", cm)));
-        }
-
-        let mut flg = self.reader.read_u8()?;
-        let ftext = flg & 1 == 1;
-        // eprintln!("FTEXT: {}", ftext);
-        flg >>= 1;
-        let fhcrc = flg & 1 == 1;
-        // eprintln!("FHCRC: {}", fhcrc);
-        flg >>= 1;
-        let fextra = flg & 1 == 1;
-        // eprintln!("FEXTRA: {}", fextra);
-        flg >>= 1;
-        let fname = flg & 1 == 1;
-        // eprintln!("FNAME: {}", fname);
-        flg >>= 1;
-        let fcomment = flg & 1 == 1;
-        // eprintln!("FCOMMENT: {}", fcomment);
-
-        let mtime = self.reader.read_u32()?;
-        // eprintln!("MTIME: {}", mtime);
-
-        let xfl = self.reader.read_u8()?;
-        // eprintln!("XFL: {}", xfl);
-
-        let os = self.reader.read_u8()?;
-        // eprintln!("OS: {}", os);
-
-        if fextra {
-            let xlen = self.reader.read_u16()?;
-            // eprintln!("XLEN: {}", xlen);
-
-            let mut fextra_buf = vec![0; xlen as usize];
-            self.reader.read_exact(&mut fextra_buf)?;
-            let extra = match String::from_utf8(fextra_buf) {
-                Ok(s) => s,
-                Err(e) => return Err(invalid_data_error(&format!("error decoding extra: {}", e))),
-            };
-            // eprintln!("EXTRA: {}", extra);
+            return Err(RzlibError::BadMagic { id1, id2 }.into());
         }
 
-        if fname {
-            let file_name = self.read_cstring()?;
-            // eprintln!("FILE NAME: {}", file_name);
+        let header = read_gzip_header(&mut self.reader, self.strictness)?;
+        debug!("member {} at byte {}: {:?}", self.member_index, self.total_in(), header);
+        self.header = Some(header);
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_member_start(self.header.as_ref())?;
         }
+        self.state = BlockHeader;
+        Ok(())
+    }
 
-        if fcomment {
-            let comment = self.read_cstring()?;
-            // eprintln!("COMMENT: {}", comment);
-        }
+    fn read_zlib_header(&mut self) -> io::Result<()> {
+        let cmf = self.reader.read_u8()?;
+        let flg = self.reader.read_u8()?;
 
-        if fhcrc {
-            let mut crc16_buf: [u8; 2] = [0; 2];
-            self.reader.read_exact(&mut crc16_buf)?;
-            let crc16 = u16::from_le_bytes(crc16_buf);
-            // eprintln!("CRC16: {}", crc16);
+        self.adler.reset();
+        self.member_index += 1;
+
+        check_zlib_header(cmf, flg, self.window_size)?;
+        let fdict = flg & 0x20 != 0;
+        if fdict {
+            let mut dictid_buf: [u8; 4] = [0; 4];
+            self.reader.read_exact(&mut dictid_buf)?;
+            let dictid = u32::from_be_bytes(dictid_buf);
+            match self.dictionary_id {
+                Some(id) if id == dictid => (),
+                Some(id) => {
+                    return Err(RzlibError::DictionaryMismatch { expected: dictid, actual: id }.into())
+                }
+                None => return Err(RzlibError::PresetDictionaryRequired(dictid).into()),
+            }
+        }
+        debug!("zlib header: window {} bytes, dictionary {}", 1 << ((cmf >> 4) + 8), fdict);
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_member_start(None)?;
         }
         self.state = BlockHeader;
         Ok(())
     }
 
+    fn read_zlib_trailer(&mut self) -> io::Result<()> {
+        self.reader.drop_remaining_bits();
+        if self.trailer_missing()? {
+            return self.end_member();
+        }
+        let mut adler32_buf: [u8; 4] = [0; 4];
+        self.reader.read_exact(&mut adler32_buf)?;
+        let adler32 = u32::from_be_bytes(adler32_buf);
+        if self.verify_checksum && adler32 != self.adler.sum() {
+            return Err(RzlibError::ChecksumMismatch {
+                expected: adler32,
+                actual: self.adler.sum(),
+            }
+            .into());
+        }
+        debug!("zlib trailer: Adler-32 {:08x}", adler32);
+        // unlike gzip members, zlib streams are not concatenated
+        self.state = EndOfFile;
+        self.end_member()
+    }
+
     fn read_member_trailer(&mut self) -> io::Result<()>{
         self.reader.drop_remaining_bits();
+        if self.trailer_missing()? {
+            return self.end_member();
+        }
         let crc32 = self.reader.read_u32()?;
-        // eprintln!("CRC32: {}", crc32);
+        if self.verify_checksum && crc32 != self.crc.sum() {
+            return Err(RzlibError::ChecksumMismatch {
+                expected: crc32,
+                actual: self.crc.sum(),
+            }
+            .into());
+        }
         let isize = self.reader.read_u32()?;
-        // eprintln!("isize: {}", isize);
-        self.state = MemberHeader;
+        if isize != self.member_size {
+            return Err(RzlibError::SizeMismatch {
+                expected: isize,
+                actual: self.member_size,
+            }
+            .into());
+        }
+        debug!("member {} trailer: CRC-32 {:08x}, size {}", self.member_index, crc32, isize);
+        self.trailer = Some(GzTrailer { crc32, isize });
+        self.state = if self.multi_member { MemberHeader } else { EndOfFile };
+        self.end_member()
+    }
+
+    fn end_member(&mut self) -> io::Result<()> {
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_member_end(self.trailer.as_ref())?;
+        }
         Ok(())
     }
 
+    // In lenient mode, whether the input ends where the trailer should start, in which case the
+    // stream ends there.
+    fn trailer_missing(&mut self) -> io::Result<bool> {
+        if self.strictness == Strictness::Lenient && self.reader.fill_buf()?.is_empty() {
+            self.state = EndOfFile;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
     fn read_no_compression_block_header(&mut self, is_final: bool) -> io::Result<()> {
         self.reader.drop_remaining_bits();
         let len = self.reader.read_u16()?;
         let nlen = self.reader.read_u16()?;
         if !len != nlen {
-            return Err(invalid_data_error(&format!(
-                "len ({}) is not one-complement of nlen ({})",
-                len, nlen
-            )));
+            return Err(RzlibError::StoredLengthMismatch { len, nlen }.into());
         }
 
         self.state = NoCompressionBlock {
             len: len as usize,
             is_final,
         };
+        self.sync_point_decoded = self.stop_at_sync_points && len == 0 && !is_final;
         Ok(())
     }
     fn read_no_compression_block(
         &mut self,
-        buf: &mut [u8],
+        max_len: usize,
         block_len: usize,
         is_final: bool,
     ) -> io::Result<usize> {
-        let can_read = cmp::min(block_len, buf.len());
+        // the data is read straight into the window, as far as it goes without wrapping around
+        let buf = self.lookback.spare_mut(cmp::min(block_len, max_len));
+        let can_read = buf.len();
 
-        let read_len = self.reader.read(&mut buf[..can_read])?;
-        self.lookback.write_data(&buf[..read_len])?;
+        let read_len = match self.reader.read(buf) {
+            Ok(read_len) => read_len,
+            Err(err) if is_retryable(&err) => {
+                self.state = NoCompressionBlock { len: block_len, is_final };
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
+        if read_len == 0 && can_read > 0 {
+            return Err(RzlibError::TruncatedStream.into());
+        }
+        self.lookback.advance(read_len);
+        self.block_out += read_len as u64;
 
         let remaining_len = block_len - read_len;
         self.state = if remaining_len == 0 {
+            self.end_block();
             if is_final {
                 MemberTrailer
             } else {
@@ -204,137 +840,104 @@ impl<R: Read + BufRead> RZLibReader<R> {
                 is_final,
             }
         };
-        return Ok(read_len);
+        Ok(read_len)
     }
 
-    fn read_fixed_huffman_block_header(&mut self) -> io::Result<()> {
-        todo!()
+    fn read_fixed_huffman_block_header(&mut self, is_final: bool) -> io::Result<()> {
+        self.litlen_table = HuffmanTable::fixed_litlen();
+        self.distance_table = match self.format {
+            Format::Deflate64 => HuffmanTable::fixed_deflate64_distance(),
+            _ => HuffmanTable::fixed_distance(),
+        };
+        self.codes = BlockCodes::Fixed;
+        self.state = HuffmanBlock { is_final };
+        Ok(())
     }
 
-    fn read_dynamic_huffman_block_header(&mut self, is_final: bool) -> io::Result<()> {
-        let nlit = self.reader.read_bits_exact(5)? as usize + 257;
-        let ndist = self.reader.read_bits_exact(5)? as usize + 1;
-        let ncode = self.reader.read_bits_exact(4)? as usize + 4;
-
-        // eprintln!("nlit: {}, ndist: {}, ncode: {}", nlit, ndist, ncode);
-
-        // See RFC 3.2.7
-        let clen_order: [usize; 19] = [
-            16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
-        ];
-
-        let mut clen_lengths: Vec<usize> = vec![0; 19];
-        for i in 0..ncode {
-            clen_lengths[clen_order[i]] = self.reader.read_bits_exact(3)? as usize;
-        }
-
-        // eprintln!("clengths:");
-        for (i, l) in clen_lengths.iter().enumerate() {
-            // eprintln!("{}: {}", i, l);
-        }
-
-        let lengths_tree: HuffmanTree<usize> =
-            huffman::HuffmanTree::<usize>::new_from_lengths(&clen_lengths);
-        let mut all_lengths: Vec<usize> = vec![0; nlit + ndist];
-        let mut next_length_i = 0;
-        let mut previous_length = 0;
-        while next_length_i < nlit + ndist {
-            let clc = lengths_tree
-                .decode(&mut self.reader)?
-                .ok_or(invalid_data_error("failed to decode clc"))?;
-            if clc <= 15 {
-                // see 3.2.7 in RFC
-                all_lengths[next_length_i] = clc;
-                // eprintln!("length {:?}: {:?}", next_length_i, clc);
-                next_length_i += 1;
-                previous_length = clc;
-            } else {
-                // code repeats
-                let repeat_count;
-                let repeat_length;
-                if clc == 16 {
-                    repeat_count = self.reader.read_bits_exact(2)? + 3;
-                    repeat_length = previous_length;
-                } else if clc == 17 {
-                    repeat_count = self.reader.read_bits_exact(3)? + 3;
-                    repeat_length = 0;
-                } else if clc == 18 {
-                    repeat_count = self.reader.read_bits_exact(7)? + 11;
-                    repeat_length = 0;
-                } else {
-                    return Err(invalid_data_error(&format!(
-                        "unexpected length code: {:?}",
-                        clc
-                    )));
-                }
-                for _ in 0..repeat_count {
-                    all_lengths[next_length_i] = repeat_length;
-                    // eprintln!("length {:?}: {:?}", next_length_i, repeat_length);
-                    next_length_i += 1;
-                }
-                previous_length = repeat_length;
-            }
-        }
-        // eprintln!("read {:?} lengths:", next_length_i);
-        for (i, l) in all_lengths.iter().enumerate() {
-            // eprintln!("length {:?}: {:?}", i, l);
+    // Number of valid distance codes.
+    fn distance_codes(&self) -> usize {
+        match self.format {
+            Format::Deflate64 => DISTANCE_BASE.len(),
+            _ => DISTANCE_CODES,
         }
+    }
 
-        let litlen_tree = huffman::HuffmanTree::<usize>::new_from_lengths(&all_lengths[..nlit]);
-        let distance_tree =
-            huffman::HuffmanTree::<usize>::new_from_lengths(&all_lengths[nlit..(nlit + ndist)]);
+    // Returns the codes declared, if blocks are recorded.
+    fn read_dynamic_huffman_block_header(
+        &mut self,
+        is_final: bool,
+    ) -> io::Result<Option<DynamicHeader>> {
+        let distance_codes = self.distance_codes();
+        let CodeLengths { nlit, ndist, ncode, clen_lengths, all_lengths } =
+            read_code_lengths(&mut self.reader, distance_codes)?;
+        self.litlen_table = HuffmanTable::new_from_lengths(&all_lengths[..nlit])?;
+        self.distance_table = HuffmanTable::new_from_lengths(&all_lengths[nlit..(nlit + ndist)])?;
+        let to_u8 =
+            |lengths: &[usize]| -> Vec<u8> { lengths.iter().map(|&len| len as u8).collect() };
+        self.codes = BlockCodes::Dynamic { nlit, lengths: to_u8(&all_lengths) };
 
-        self.state = HuffmanBlock {
-            litlen_tree,
-            distance_tree,
-            is_final,
-        };
-        return Ok(());
+        self.state = HuffmanBlock { is_final };
+        Ok(self.blocks.is_some().then(|| DynamicHeader {
+            nlit,
+            ndist,
+            ncode,
+            code_length_lengths: to_u8(&clen_lengths),
+            litlen_lengths: to_u8(&all_lengths[..nlit]),
+            distance_lengths: to_u8(&all_lengths[nlit..]),
+        }))
     }
 
     fn read_huffman_block(
         &mut self,
-        buf: &mut [u8],
-        litlen_tree: HuffmanTree<usize>,
-        distance_tree: HuffmanTree<usize>,
+        max_len: usize,
         is_final: bool,
     ) -> io::Result<usize> {
-        // size base for length codes 257..285
-        let length_offsets = [
-            3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99,
-            115, 131, 163, 195, 227, 258,
-        ];
-        // extra bits for length codes 257..285
-        let length_extra_bits = [
-            0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
-        ];
-
-        // offset base for distance codes 0..29
-        let distance_offsets = [
-            1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025,
-            1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
-        ];
-        // extra bits for distance codes 0..29
-        let distance_extra_bits = [
-            0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12,
-            12, 13, 13,
-        ];
         let mut pos = 0;
+        let mut literals = [0; LITERAL_RUN];
         // actual decode loop
-        while pos < buf.len() {
-            let litlen = litlen_tree
-                .decode(&mut self.reader)?
-                .ok_or(io::Error::new(InvalidData, "failed to decode litlen"))?;
-            if litlen < 256 {
-                // add to buffer and to lookback
-                let b = litlen as u8;
-                buf[pos] = b;
-                pos += 1;
-                self.lookback.write_byte(b)?;
-                continue;
-            } else if litlen == 256 {
-                // eprintln!("end of block, final = {:?}", is_final);
+        while pos < max_len {
+            // decode a run of literals, written to the window in one go
+            let run_len = cmp::min(literals.len(), max_len - pos);
+            let mut run = 0;
+            let result = loop {
+                // decoding a symbol consumes nothing unless it succeeds, so it can be retried
+                let litlen = match self.litlen_table.decode(&mut self.reader) {
+                    Ok(litlen) => litlen,
+                    Err(err) => break Err(err),
+                };
+                let Some(litlen) = litlen else {
+                    break Err(RzlibError::InvalidCode.into());
+                };
+                if let Some(histograms) = &mut self.histograms {
+                    if let Some(count) = histograms.litlen.get_mut(litlen) {
+                        *count += 1;
+                    }
+                }
+                if litlen >= 256 {
+                    break Ok(Some(litlen));
+                }
+                literals[run] = litlen as u8;
+                run += 1;
+                if run == run_len {
+                    break Ok(None);
+                }
+            };
+            self.lookback.write_data(&literals[..run])?;
+            pos += run;
+            self.block_out += run as u64;
+            let litlen = match result {
+                Ok(Some(litlen)) => litlen,
+                Ok(None) => continue,
+                Err(err) if is_retryable(&err) => {
+                    self.state = HuffmanBlock { is_final };
+                    return if pos > 0 { Ok(pos) } else { Err(err) };
+                }
+                Err(err) => return Err(err),
+            };
+            if litlen == 256 {
                 // end of block
+                trace!("end of block {}, final: {}", self.block_index, is_final);
+                self.end_block();
                 if is_final {
                     self.state = MemberTrailer;
                 } else {
@@ -343,64 +946,88 @@ impl<R: Read + BufRead> RZLibReader<R> {
                 return Ok(pos);
             } else if litlen <= 285 {
                 // found a match
-                let match_length = self
-                    .reader
-                    .read_bits_exact(length_extra_bits[litlen - 257])?
-                    + length_offsets[litlen - 257];
-                let dist_code = distance_tree
-                    .decode(&mut self.reader)?
-                    .ok_or(invalid_data_error("failed to decode distance code"))?;
-                let match_distance = self
-                    .reader
-                    .read_bits_exact(distance_extra_bits[dist_code])?
-                    + distance_offsets[dist_code];
-                // eprintln!("match {:?} {:?}", match_length, match_distance);
-                self.state = HuffmanBlockMatch {
-                    litlen_tree,
-                    distance_tree,
-                    length: match_length as usize,
-                    distance: match_distance as usize,
-                    is_final,
+                return match self.start_match(litlen, is_final) {
+                    Err(err) if is_retryable(&err) && pos > 0 => Ok(pos),
+                    result => result.map(|()| pos),
                 };
-                return Ok(pos);
+            } else {
+                return Err(RzlibError::InvalidCode.into());
+            }
+        }
+        // we decoded as much as asked for
+        self.state = HuffmanBlock { is_final };
+        Ok(pos)
+    }
+
+    // Reads the rest of a match following its length symbol, and moves on to copying it. If the
+    // input runs out for now, the match is started over from the length symbol on the next read.
+    fn start_match(
+        &mut self,
+        symbol: usize,
+        is_final: bool,
+    ) -> io::Result<()> {
+        self.reader.mark();
+        let (length, distance) = match self.read_match(symbol) {
+            Ok(result) => result,
+            Err(err) if is_retryable(&err) => {
+                self.reader.rewind();
+                self.state = HuffmanBlockLength { symbol, is_final };
+                return Err(err);
             }
+            Err(err) => return Err(err),
+        };
+        self.reader.unmark();
+        self.block_matches += 1;
+        self.block_match_bytes += length as u64;
+        self.block_out += length as u64;
+        if let Some(histograms) = &mut self.histograms {
+            histograms.distance[distance_code(distance)] += 1;
         }
-        // we filled the entire buffer
-        self.state = HuffmanBlock {
-            litlen_tree,
-            distance_tree,
+        trace!("match length {}, distance {}", length, distance);
+        self.state = HuffmanBlockMatch {
+            length,
+            distance,
             is_final,
         };
-        return Ok(pos);
+        Ok(())
+    }
+
+    fn read_match(&mut self, symbol: usize) -> io::Result<(usize, usize)> {
+        let (base, extra_bits) = if symbol == 285 && self.format == Format::Deflate64 {
+            (DEFLATE64_LENGTH_285_BASE, DEFLATE64_LENGTH_285_EXTRA_BITS)
+        } else {
+            (LENGTH_BASE[symbol - 257], LENGTH_EXTRA_BITS[symbol - 257])
+        };
+        let length = self.reader.read_bits_exact(extra_bits as usize)? + base as u64;
+        let distance_codes = self.distance_codes();
+        let dist_code = self
+            .distance_table
+            .decode(&mut self.reader)?
+            .filter(|&code| code < distance_codes)
+            .ok_or(RzlibError::InvalidCode)?;
+        let distance = self
+            .reader
+            .read_bits_exact(DISTANCE_EXTRA_BITS[dist_code] as usize)?
+            + DISTANCE_BASE[dist_code] as u64;
+        if distance as usize > self.lookback.len() {
+            return Err(RzlibError::InvalidDistance(distance as usize).into());
+        }
+        Ok((length as usize, distance as usize))
     }
 
     fn read_huffman_block_match(
         &mut self,
-        buf: &mut [u8],
-        litlen_tree: HuffmanTree<usize>,
-        distance_tree: HuffmanTree<usize>,
+        max_len: usize,
         length: usize,
         distance: usize,
         is_final: bool,
     ) -> io::Result<usize> {
-        // we can only read at most LOOKBACK_WINDOW_SIZE at a time
-        let read_length = cmp::min(
-            LOOKBACK_WINDOW_SIZE,
-            cmp::min(buf.len(), cmp::min(length, distance)),
-        );
-        self.lookback
-            .read_lookback_exact(&mut buf[..read_length], distance)?;
-        self.lookback.write_data(&mut buf[..read_length])?;
+        let read_length = cmp::min(max_len, length);
+        self.lookback.repeat(read_length, distance)?;
         self.state = if read_length == length {
-            HuffmanBlock {
-                litlen_tree,
-                distance_tree,
-                is_final,
-            }
+            HuffmanBlock { is_final }
         } else {
             HuffmanBlockMatch {
-                litlen_tree,
-                distance_tree,
                 length: length - read_length,
                 distance,
                 is_final,
@@ -409,29 +1036,105 @@ impl<R: Read + BufRead> RZLibReader<R> {
         Ok(read_length)
     }
     fn read_block_header(&mut self) -> io::Result<()> {
+        let in_bits = self.reader.bit_position();
+        self.block_index += 1;
         let bfinal = self.reader.read_bits_exact(1)?;
         let btype = self.reader.read_bits_exact(2)? as u8;
-
-        // eprintln!("bfinal: {}, btype: {}", bfinal, btype);
+        debug!(
+            "member {} block {} at bit {}: type {}, final: {}",
+            self.member_index,
+            self.block_index,
+            in_bits,
+            btype,
+            bfinal == 1
+        );
         let is_final = bfinal == 1;
 
         const NO_COMPRESSION: u8 = 0;
         const FIXED_HUFFMAN: u8 = 1;
         const DYNAMIC_HUFFMAN: u8 = 2;
 
-        match btype {
-            NO_COMPRESSION => self.read_no_compression_block_header(is_final)?,
-            FIXED_HUFFMAN => self.read_fixed_huffman_block_header()?,
-            DYNAMIC_HUFFMAN => self.read_dynamic_huffman_block_header(is_final)?,
-            _ => return Err(invalid_data_error(&format!("unknown btype: {}", btype))),
+        let (block_type, dynamic) = match btype {
+            NO_COMPRESSION => {
+                self.read_no_compression_block_header(is_final)?;
+                (BlockType::Stored, None)
+            }
+            FIXED_HUFFMAN => {
+                self.read_fixed_huffman_block_header(is_final)?;
+                (BlockType::FixedHuffman, None)
+            }
+            DYNAMIC_HUFFMAN => (
+                BlockType::DynamicHuffman,
+                self.read_dynamic_huffman_block_header(is_final)?,
+            ),
+            _ => return Err(RzlibError::BadBlockType(btype).into()),
+        };
+
+        self.block_out = 0;
+        self.block_matches = 0;
+        self.block_match_bytes = 0;
+        if let Some(blocks) = &mut self.blocks {
+            blocks.push(BlockInfo {
+                member: self.member_index,
+                block: self.block_index,
+                in_bits,
+                block_type,
+                is_final,
+                dynamic,
+                compressed_bits: 0,
+                uncompressed: 0,
+                literals: 0,
+                matches: 0,
+                match_bytes: 0,
+            });
+        }
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_block(block_type, is_final)?;
         }
+        Ok(())
+    }
 
-        return Ok(());
+    // Reads a member header or trailer, or a block header, in one go: if the input runs out for
+    // now partway through, what was read of it is given back to be read again on the next read.
+    fn read_header(&mut self, state: State) -> io::Result<()> {
+        let (member_index, block_index) = (self.member_index, self.block_index);
+        self.reader.mark();
+        let result = match (&state, self.format) {
+            (MemberHeader, Format::Gzip) => self.read_member_header(),
+            (MemberHeader, Format::Zlib) => self.read_zlib_header(),
+            (MemberTrailer, Format::Gzip) => self.read_member_trailer(),
+            (MemberTrailer, Format::Zlib) => self.read_zlib_trailer(),
+            (MemberTrailer, Format::Deflate | Format::Deflate64) => {
+                // leave the reader at the byte following the deflate stream
+                self.reader.drop_remaining_bits();
+                self.state = EndOfFile;
+                Ok(())
+            }
+            (BlockHeader, _) => self.read_block_header(),
+            _ => unreachable!("{:?} is not a header state of {:?}", state, self.format),
+        };
+        match result {
+            Err(err) if is_retryable(&err) => {
+                self.reader.rewind();
+                self.member_index = member_index;
+                self.block_index = block_index;
+                self.state = state;
+                Err(err)
+            }
+            result => {
+                self.reader.unmark();
+                result
+            }
+        }
     }
 
-    fn read_impl(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut buf = buf;
+    // Decodes up to `max_len` bytes into the window, which must have room for them.
+    fn read_impl(&mut self, max_len: usize) -> io::Result<usize> {
+        let mut max_len = max_len;
         let mut total_read = 0;
+        if max_len == 0 {
+            return Ok(0);
+        }
         while total_read == 0 {
             let mut reader_state = BrokenStream;
             mem::swap(&mut self.state, &mut reader_state);
@@ -443,39 +1146,31 @@ impl<R: Read + BufRead> RZLibReader<R> {
                         "trying to read from a broken stream",
                     ))
                 }
-                MemberHeader => self.read_member_header()?,
-                MemberTrailer => self.read_member_trailer()?,
-                BlockHeader => self.read_block_header()?,
+                BlockHeader => {
+                    // nothing was decoded by this read yet, so the counters are up to date
+                    self.record_checkpoint();
+                    self.read_header(reader_state)?
+                }
+                MemberHeader | MemberTrailer => self.read_header(reader_state)?,
                 NoCompressionBlock { len, is_final } => {
-                    let read = self.read_no_compression_block(buf, len, is_final)?;
-                    buf = &mut buf[read..];
+                    let read = self.read_no_compression_block(max_len, len, is_final)?;
+                    max_len -= read;
                     total_read += read;
                 }
-                HuffmanBlock {
-                    litlen_tree,
-                    distance_tree,
-                    is_final,
-                } => {
-                    let read = self.read_huffman_block(buf, litlen_tree, distance_tree, is_final)?;
-                    buf = &mut buf[read..];
+                HuffmanBlock { is_final } => {
+                    let read = self.read_huffman_block(max_len, is_final)?;
+                    max_len -= read;
                     total_read += read;
                 },
+                HuffmanBlockLength { symbol, is_final } => self.start_match(symbol, is_final)?,
                 HuffmanBlockMatch {
-                    litlen_tree,
-                    distance_tree,
                     length,
                     distance,
                     is_final,
                 } => {
-                    let read =  self.read_huffman_block_match(
-                        buf,
-                        litlen_tree,
-                        distance_tree,
-                        length,
-                        distance,
-                        is_final,
-                    )?;
-                    buf = &mut buf[read..];
+                    let read =
+                        self.read_huffman_block_match(max_len, length, distance, is_final)?;
+                    max_len -= read;
                     total_read += read;
                 }
                 EndOfFile => {
@@ -484,16 +1179,1496 @@ impl<R: Read + BufRead> RZLibReader<R> {
 
                 }
             }
+            // only reached with nothing decoded yet: headers are read in loop turns of their own
+            if mem::take(&mut self.sync_point_decoded) {
+                self.at_sync_point = true;
+                return Ok(0);
+            }
         }
         Ok(total_read)
     }
 }
 
-impl<R: Read + BufRead> Read for RZLibReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let bytes_read = self.read_impl(buf)?;
-        self.total_bytes_read += bytes_read;
+impl<R: Read + BufRead> RZLibReader<R> {
+    // Decodes more output for a read of `len` bytes, unless some is left unread.
+    fn decode_for_read(&mut self, len: usize) -> io::Result<()> {
+        if self.unread == 0 {
+            let max_len = match len {
+                0 => 0,
+                len if len < SMALL_READ => DECODE_AHEAD,
+                len => len,
+            };
+            self.decode(cmp::min(max_len, self.lookback.capacity()))?;
+        }
+        Ok(())
+    }
+
+    // Copies as much unread output as fits into `buf`.
+    fn copy_unread(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.unread);
+        self.lookback
+            .read_lookback_exact(&mut buf[..len], self.unread_distance)?;
+        self.unread -= len;
+        self.unread_distance -= len;
+        Ok(len)
+    }
+
+    fn position(&self) -> StreamPosition {
+        let bit_position = self.reader.bit_position();
+        StreamPosition {
+            byte: bit_position / 8,
+            bit: (bit_position % 8) as u8,
+            member: self.member_index,
+            block: self.block_index,
+        }
+    }
+
+    // Attaches the current position to `err`. Errors that do not mean the stream is broken are
+    // passed through as they are.
+    fn decode_error(&self, err: io::Error) -> io::Error {
+        match err.kind() {
+            Interrupted | WouldBlock => err,
+            // end of input reported by the bit reader or the underlying reader
+            UnexpectedEof if RzlibError::from_io_error(&err).is_none() => {
+                DecodeError::new(RzlibError::TruncatedStream.into(), self.position()).into()
+            }
+            _ => DecodeError::new(err, self.position()).into(),
+        }
+    }
 
-        return Ok(bytes_read);
+    // Decodes up to `max_len` bytes into the window, checking the output limit and updating the
+    // checksums, and makes them the unread output. Only called once all output has been read.
+    fn decode(&mut self, max_len: usize) -> io::Result<()> {
+        self.at_sync_point = false;
+        let max_len = match self.max_output_bytes {
+            Some(limit) if self.total_out > limit => {
+                return Err(self.decode_error(RzlibError::OutputLimitExceeded(limit).into()));
+            }
+            // decode at most one byte past the limit, to find out whether there is one
+            Some(limit) => {
+                let allowed = limit.saturating_sub(self.total_out).saturating_add(1);
+                cmp::min(max_len as u64, allowed) as usize
+            }
+            None => max_len,
+        };
+        let decoded = loop {
+            match self.read_impl(max_len) {
+                // the decoder is back where it was before the read, so it can just go again
+                Err(err) if err.kind() == Interrupted => {}
+                result => break result.map_err(|e| self.decode_error(e))?,
+            }
+        };
+        self.total_out += decoded as u64;
+        let mut bytes_read = decoded;
+        if let Some(limit) = self.max_output_bytes {
+            if self.total_out > limit {
+                // return the bytes up to the limit now, and fail on the next read
+                bytes_read -= (self.total_out - limit) as usize;
+                if bytes_read == 0 {
+                    return Err(self.decode_error(RzlibError::OutputLimitExceeded(limit).into()));
+                }
+            }
+        }
+        let (first, second) = self.lookback.slices(decoded, bytes_read)?;
+        match self.format {
+            Format::Gzip => {
+                self.crc.update(first);
+                self.crc.update(second);
+            }
+            Format::Zlib => {
+                self.adler.update(first);
+                self.adler.update(second);
+            }
+            Format::Deflate | Format::Deflate64 => (),
+        }
+        self.member_size = self.member_size.wrapping_add(bytes_read as u32);
+        self.unread = bytes_read;
+        self.unread_distance = decoded;
+        if matches!(self.state, BlockHeader) {
+            self.record_checkpoint();
+        }
+        Ok(())
+    }
+}
+
+// Reads a zero-terminated header string, without the terminator. Strictly, it must be UTF-8.
+fn read_cstring<B: BufRead>(reader: &mut B, strictness: Strictness) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Err(RzlibError::TruncatedStream.into());
+        }
+        match available.iter().position(|&byte| byte == 0) {
+            Some(end) => {
+                buf.extend_from_slice(&available[..end]);
+                reader.consume(end + 1);
+                break;
+            }
+            None => {
+                let len = available.len();
+                buf.extend_from_slice(available);
+                reader.consume(len);
+            }
+        }
+    }
+
+    if strictness == Strictness::Strict && core::str::from_utf8(&buf).is_err() {
+        return Err(RzlibError::BadHeaderField("string is not valid UTF-8").into());
+    }
+    Ok(buf)
+}
+
+fn read_array<const N: usize, B: Read>(reader: &mut B) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// Reads the rest of a gzip member header, following the magic number.
+pub(crate) fn read_gzip_header<B: BufRead>(
+    reader: &mut B,
+    strictness: Strictness,
+) -> io::Result<GzHeader> {
+    let [cm, mut flg] = read_array(reader)?;
+
+    if cm != 0x08 {
+        return Err(RzlibError::BadCompressionMethod(cm).into());
+    }
+
+    if flg & 0xe0 != 0 && strictness == Strictness::Strict {
+        return Err(RzlibError::BadHeaderField("reserved flags are set").into());
+    }
+    let ftext = flg & 1 == 1;
+    flg >>= 1;
+    let fhcrc = flg & 1 == 1;
+    flg >>= 1;
+    let fextra = flg & 1 == 1;
+    flg >>= 1;
+    let fname = flg & 1 == 1;
+    flg >>= 1;
+    let fcomment = flg & 1 == 1;
+
+    let mtime = u32::from_le_bytes(read_array(reader)?);
+
+    let [xfl, os] = read_array(reader)?;
+    // 0 to 13 are assigned by RFC 1952, 255 is "unknown"
+    if !matches!(os, 0..=13 | 255) && strictness == Strictness::Strict {
+        return Err(RzlibError::BadHeaderField("unknown OS").into());
+    }
+    let mut header = GzHeader { text: ftext, mtime, xfl, os, ..GzHeader::default() };
+
+    if fextra {
+        let xlen = u16::from_le_bytes(read_array(reader)?);
+        let mut fextra_buf = vec![0; xlen as usize];
+        reader.read_exact(&mut fextra_buf)?;
+        if split_subfields(&fextra_buf).is_none() && strictness == Strictness::Strict {
+            return Err(RzlibError::BadHeaderField("extra field is not made of subfields").into());
+        }
+        header.extra = Some(fextra_buf);
+    }
+
+    if fname {
+        header.filename = Some(read_cstring(reader, strictness)?);
+    }
+
+    if fcomment {
+        header.comment = Some(read_cstring(reader, strictness)?);
+    }
+
+    if fhcrc {
+        let _crc16 = u16::from_le_bytes(read_array(reader)?);
+    }
+    Ok(header)
+}
+
+// Checks the CMF and FLG bytes of a zlib header, for a decoder keeping `window_size` bytes of
+// history.
+pub(crate) fn check_zlib_header(cmf: u8, flg: u8, window_size: usize) -> io::Result<()> {
+    let cm = cmf & 0x0f;
+    let cinfo = cmf >> 4;
+    if cm != 8 {
+        return Err(RzlibError::BadCompressionMethod(cm).into());
+    }
+    if cinfo > 7 || 1 << (cinfo + 8) > window_size {
+        return Err(RzlibError::BadWindowSize(cinfo).into());
+    }
+    if !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+        return Err(RzlibError::BadHeaderCheck { cmf, flg }.into());
+    }
+    Ok(())
+}
+
+// The code lengths declared by a dynamic block header.
+pub(crate) struct CodeLengths {
+    pub(crate) nlit: usize,
+    pub(crate) ndist: usize,
+    pub(crate) ncode: usize,
+    // lengths of the code length code, in symbol order
+    pub(crate) clen_lengths: Vec<usize>,
+    // literal/length code lengths followed by distance code lengths
+    pub(crate) all_lengths: Vec<usize>,
+}
+
+// Reads the code lengths of a dynamic block, following the block type, and checks that they
+// describe valid codes. `distance_codes` is the number of distance codes the format allows.
+pub(crate) fn read_code_lengths<B: BitRead>(
+    reader: &mut B,
+    distance_codes: usize,
+) -> io::Result<CodeLengths> {
+    let nlit = reader.read_bits_exact(5)? as usize + 257;
+    let ndist = reader.read_bits_exact(5)? as usize + 1;
+    let ncode = reader.read_bits_exact(4)? as usize + 4;
+    // symbols 286 and 287, and distance codes 30 and 31 outside DEFLATE64, do not occur in
+    // valid streams
+    if nlit > 286 || ndist > distance_codes {
+        return Err(RzlibError::InvalidCodeLengths.into());
+    }
+
+    let mut clen_lengths: Vec<usize> = vec![0; 19];
+    for &i in CODE_LENGTH_ORDER.iter().take(ncode) {
+        clen_lengths[i] = reader.read_bits_exact(3)? as usize;
+    }
+    trace!(
+        "HLIT {}, HDIST {}, HCLEN {}, code length code lengths {:?}",
+        nlit - 257,
+        ndist - 1,
+        ncode - 4,
+        clen_lengths
+    );
+
+    if !is_valid_code(&clen_lengths, false) {
+        return Err(RzlibError::InvalidCodeLengths.into());
+    }
+    let lengths_table: HuffmanTable = HuffmanTable::new_from_lengths(&clen_lengths)?;
+    let mut all_lengths: Vec<usize> = vec![0; nlit + ndist];
+    let mut next_length_i = 0;
+    let mut previous_length = 0;
+    while next_length_i < nlit + ndist {
+        let clc = lengths_table
+            .decode(reader)?
+            .ok_or(RzlibError::InvalidCodeLengths)?;
+        if clc <= 15 {
+            // see 3.2.7 in RFC
+            all_lengths[next_length_i] = clc;
+            next_length_i += 1;
+            previous_length = clc;
+        } else {
+            // code repeats
+            let repeat_count;
+            let repeat_length;
+            if clc == 16 {
+                if next_length_i == 0 {
+                    return Err(RzlibError::InvalidCodeLengths.into());
+                }
+                repeat_count = reader.read_bits_exact(2)? + 3;
+                repeat_length = previous_length;
+            } else if clc == 17 {
+                repeat_count = reader.read_bits_exact(3)? + 3;
+                repeat_length = 0;
+            } else if clc == 18 {
+                repeat_count = reader.read_bits_exact(7)? + 11;
+                repeat_length = 0;
+            } else {
+                return Err(RzlibError::InvalidCodeLengths.into());
+            }
+            if next_length_i + repeat_count as usize > nlit + ndist {
+                return Err(RzlibError::InvalidCodeLengths.into());
+            }
+            for _ in 0..repeat_count {
+                all_lengths[next_length_i] = repeat_length;
+                next_length_i += 1;
+            }
+            previous_length = repeat_length;
+        }
+    }
+    trace!("literal/length code lengths {:?}", &all_lengths[..nlit]);
+    trace!("distance code lengths {:?}", &all_lengths[nlit..]);
+
+    if all_lengths[END_OF_BLOCK] == 0
+        || !is_valid_code(&all_lengths[..nlit], true)
+        || !is_valid_code(&all_lengths[nlit..], true)
+    {
+        return Err(RzlibError::InvalidCodeLengths.into());
+    }
+    Ok(CodeLengths { nlit, ndist, ncode, clen_lengths, all_lengths })
+}
+
+impl<R: Read + BufRead> Read for RZLibReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decode_for_read(buf.len())?;
+        self.copy_unread(buf)
+    }
+
+    // Decodes once for all of `bufs`, as `read` does for one buffer, and fills them in turn.
+    #[cfg(feature = "std")]
+    fn read_vectored(&mut self, bufs: &mut [std::io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.decode_for_read(bufs.iter().map(|buf| buf.len()).sum())?;
+        let mut read = 0;
+        for buf in bufs {
+            if self.unread == 0 {
+                break;
+            }
+            read += self.copy_unread(buf)?;
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + BufRead + std::io::Seek> RZLibReader<R> {
+    /// Peeks the ISIZE field of the gzip trailer at the end of the input, and returns how many
+    /// bytes are left to read by that count, to preallocate the buffer of `read_to_end`. The
+    /// underlying reader is put back where it was.
+    ///
+    /// ISIZE is the size of the last member modulo 2^32, so the hint is only right for
+    /// single-member streams under 4 GiB. `None` for other formats than gzip, and if the input
+    /// is too short to hold a trailer or to decompress to that much.
+    pub fn size_hint(&mut self) -> io::Result<Option<u64>> {
+        use std::io::SeekFrom;
+
+        if self.format != Format::Gzip {
+            return Ok(None);
+        }
+        let inner = self.reader.get_mut();
+        let pos = inner.stream_position()?;
+        let mut read_isize = || -> io::Result<Option<u64>> {
+            let len = inner.seek(SeekFrom::End(0))?;
+            // header and trailer, around the smallest deflate stream
+            if len < 20 {
+                return Ok(None);
+            }
+            inner.seek(SeekFrom::End(-4))?;
+            let mut isize_buf = [0; 4];
+            inner.read_exact(&mut isize_buf)?;
+            let isize = u32::from_le_bytes(isize_buf) as u64;
+            Ok((isize <= len.saturating_mul(crate::gzip::MAX_RATIO as u64)).then_some(isize))
+        };
+        let isize = read_isize();
+        inner.seek(SeekFrom::Start(pos))?;
+        Ok(isize?.map(|isize| isize.saturating_sub(self.total_out())))
+    }
+}
+
+/// Seeking only goes forward, by decoding the data in between and discarding it; positions are
+/// offsets in the decompressed data. Seeking backward fails with `InvalidInput`, and from the end,
+/// which is not known before decoding everything, with `Unsupported`. Seeking past the end of the
+/// data stops there, and returns that position.
+#[cfg(feature = "std")]
+impl<R: Read + BufRead> std::io::Seek for RZLibReader<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::Current(offset) => self.total_out().checked_add_signed(offset),
+            std::io::SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "cannot seek from the end of a compressed stream",
+                ))
+            }
+        };
+        let target = match target {
+            Some(target) if target >= self.total_out() => target,
+            _ => {
+                return Err(io::Error::new(
+                    InvalidInput,
+                    "cannot seek backward in a compressed stream",
+                ))
+            }
+        };
+        let mut scratch = mem::take(&mut self.scratch);
+        scratch.resize(32 * 1024, 0);
+        let mut result = Ok(());
+        while self.total_out() < target {
+            let len = cmp::min(target - self.total_out(), scratch.len() as u64) as usize;
+            match self.read(&mut scratch[..len]) {
+                Ok(0) if self.at_sync_point => {}
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == Interrupted => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.scratch = scratch;
+        result.map(|()| self.total_out())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, ErrorKind, IoSliceMut, Read, Seek, SeekFrom, Write};
+
+    use std::sync::{Arc, Mutex};
+
+    use super::{Format, RZLibReader, Strictness};
+    use crate::blockinfo::{BlockInfo, BlockType};
+    use crate::gzheader::GzHeader;
+    use crate::hooks::DecodeHooks;
+    use crate::members::GzTrailer;
+    use crate::deflateencoder::{Compression, FlushMode};
+    use crate::error::{DecodeError, RzlibError, StreamPosition};
+    use crate::gzencoder::GzEncoder;
+    use crate::bitwriter::BitWriter;
+    use crate::deflatetables::{fixed_distance_lengths, fixed_litlen_lengths, CODE_LENGTH_ORDER};
+    use crate::huffman::{canonical_codes, Codeword};
+
+    fn rzlib_error(err: std::io::Error) -> RzlibError {
+        RzlibError::from_io_error(&err).unwrap().clone()
+    }
+
+    // "hello, rzlib\n" as a single stored block
+    const HELLO_GZ: [u8; 36] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x0d, 0x00, 0xf2, 0xff,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x7a, 0x6c, 0x69, 0x62, 0x0a, 0xd3, 0xb8,
+        0xbe, 0xec, 0x0d, 0x00, 0x00, 0x00,
+    ];
+
+    // "hello, zlib\n" as a single stored block
+    const HELLO_ZLIB: [u8; 23] = [
+        0x78, 0x01, 0x01, 0x0c, 0x00, 0xf3, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x7a,
+        0x6c, 0x69, 0x62, 0x0a, 0x1c, 0xce, 0x04, 0x1c,
+    ];
+
+    fn decompress(input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        RZLibReader::new(input).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_stored_block() {
+        assert_eq!(decompress(&HELLO_GZ).unwrap(), b"hello, rzlib\n");
+    }
+
+    #[test]
+    fn test_multiple_members() {
+        let input = [HELLO_GZ, HELLO_GZ].concat();
+        assert_eq!(decompress(&input).unwrap(), b"hello, rzlib\nhello, rzlib\n");
+    }
+
+    #[test]
+    fn test_crc32_mismatch() {
+        let mut input = HELLO_GZ;
+        input[28] ^= 1;
+        let err = decompress(&input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(matches!(rzlib_error(err), RzlibError::ChecksumMismatch { .. }));
+
+        let mut out = vec![];
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_verify_checksum(false);
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, rzlib\n");
+    }
+
+    #[test]
+    fn test_isize_mismatch() {
+        let mut input = HELLO_GZ;
+        input[32] += 1;
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_verify_checksum(false);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(rzlib_error(err), RzlibError::SizeMismatch { expected: 14, actual: 13 });
+    }
+
+    fn decompress_zlib(input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        RZLibReader::with_format(input, Format::Zlib).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_zlib() {
+        assert_eq!(decompress_zlib(&HELLO_ZLIB).unwrap(), b"hello, zlib\n");
+    }
+
+    #[test]
+    fn test_zlib_stops_after_trailer() {
+        let input = [&HELLO_ZLIB[..], b"trailing"].concat();
+        let mut reader = RZLibReader::with_format(&input[..], Format::Zlib);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, zlib\n");
+        assert_eq!(reader.get_ref(), b"trailing");
+    }
+
+    #[test]
+    fn test_zlib_bad_header() {
+        let mut input = HELLO_ZLIB;
+        input[1] ^= 1;
+        assert_eq!(
+            rzlib_error(decompress_zlib(&input).unwrap_err()),
+            RzlibError::BadHeaderCheck { cmf: 0x78, flg: 0x00 }
+        );
+
+        // FDICT set, with a valid FCHECK
+        let input = [0x78, 0xbb, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(
+            rzlib_error(decompress_zlib(&input).unwrap_err()),
+            RzlibError::PresetDictionaryRequired(1)
+        );
+    }
+
+    #[test]
+    fn test_adler32_mismatch() {
+        let mut input = HELLO_ZLIB;
+        input[22] ^= 1;
+        assert_eq!(decompress_zlib(&input).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_malformed_streams() {
+        let mut input = HELLO_GZ;
+        input[1] = 0x8c;
+        assert_eq!(
+            rzlib_error(decompress(&input).unwrap_err()),
+            RzlibError::BadMagic { id1: 0x1f, id2: 0x8c }
+        );
+
+        // BTYPE = 11
+        let mut input = HELLO_GZ;
+        input[10] = 0x07;
+        assert_eq!(rzlib_error(decompress(&input).unwrap_err()), RzlibError::BadBlockType(3));
+
+        let mut input = HELLO_GZ;
+        input[13] ^= 1;
+        assert_eq!(
+            rzlib_error(decompress(&input).unwrap_err()),
+            RzlibError::StoredLengthMismatch { len: 13, nlen: 0xfff3 }
+        );
+
+        for len in [5, 20, 30] {
+            let err = decompress(&HELLO_GZ[..len]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+            assert_eq!(rzlib_error(err), RzlibError::TruncatedStream);
+        }
+    }
+
+    // A raw deflate stream of a single dynamic block, made of a literal 0 then the end of block,
+    // with the given literal/length code lengths, and a single distance code.
+    fn dynamic_block(litlen_lengths: &[usize; 257]) -> Vec<u8> {
+        let mut bits = BitWriter::new(vec![]);
+        // BFINAL, BTYPE = 10, HLIT = 0, HDIST = 0, HCLEN = 15
+        for (value, n) in [(1, 1), (2, 2), (0, 5), (0, 5), (15, 4)] {
+            bits.write_bits(value, n).unwrap();
+        }
+        // 2 bit codes for code lengths 0, 1, 2 and 18
+        let mut clen_lengths = [0; 19];
+        for symbol in [0, 1, 2, 18] {
+            clen_lengths[symbol] = 2;
+        }
+        for &symbol in CODE_LENGTH_ORDER.iter() {
+            bits.write_bits(clen_lengths[symbol] as u64, 3).unwrap();
+        }
+        let clen_codes = canonical_codes(&clen_lengths);
+        let lengths: Vec<usize> = litlen_lengths.iter().copied().chain([1]).collect();
+        let mut i = 0;
+        while i < lengths.len() {
+            let zeros = lengths[i..].iter().take_while(|&&l| l == 0).count().min(138);
+            if zeros >= 11 {
+                write_code(&mut bits, &clen_codes[18]).unwrap();
+                bits.write_bits(zeros as u64 - 11, 7).unwrap();
+                i += zeros;
+            } else {
+                write_code(&mut bits, &clen_codes[lengths[i]]).unwrap();
+                i += 1;
+            }
+        }
+        let litlen_codes = canonical_codes(litlen_lengths);
+        write_code(&mut bits, &litlen_codes[0]).unwrap();
+        write_code(&mut bits, &litlen_codes[256]).unwrap();
+        bits.finish().unwrap()
+    }
+
+    fn write_code(bits: &mut BitWriter<Vec<u8>>, code: &Codeword) -> std::io::Result<()> {
+        bits.write_bits(code.code, code.len)
+    }
+
+    #[test]
+    fn test_incomplete_codes() {
+        let decode = |input: &[u8]| {
+            let mut out = vec![];
+            RZLibReader::with_format(input, Format::Deflate).read_to_end(&mut out).map(|_| out)
+        };
+        let mut lengths = [0; 257];
+        lengths[0] = 1;
+        lengths[256] = 1;
+        assert_eq!(decode(&dynamic_block(&lengths)).unwrap(), [0]);
+
+        // 0 and 10 are codes, but 11 is not
+        lengths[256] = 2;
+        let err = decode(&dynamic_block(&lengths)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(rzlib_error(err), RzlibError::InvalidCodeLengths);
+    }
+
+    #[test]
+    fn test_record_blocks() {
+        let input = [HELLO_GZ, HELLO_GZ].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_record_blocks(true);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let stored = |member, in_bits| BlockInfo {
+            member,
+            block: 1,
+            in_bits,
+            block_type: BlockType::Stored,
+            is_final: true,
+            dynamic: None,
+            compressed_bits: 144,
+            uncompressed: 13,
+            literals: 0,
+            matches: 0,
+            match_bytes: 0,
+        };
+        assert_eq!(reader.blocks(), [stored(1, 80), stored(2, 368)]);
+
+        let mut lengths = [0; 257];
+        lengths[0] = 1;
+        lengths[256] = 1;
+        let input = dynamic_block(&lengths);
+        let mut reader = RZLibReader::with_format(&input[..], Format::Deflate);
+        reader.set_record_blocks(true);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let block = &reader.blocks()[0];
+        assert_eq!(
+            (block.block_type, block.literals, block.matches),
+            (BlockType::DynamicHuffman, 1, 0)
+        );
+        let dynamic = block.dynamic.as_ref().unwrap();
+        assert_eq!((dynamic.nlit, dynamic.ndist, dynamic.ncode), (257, 1, 19));
+        assert_eq!(dynamic.code_length_lengths.iter().filter(|&&len| len == 2).count(), 4);
+        assert_eq!(dynamic.litlen_lengths[..2], [1, 0]);
+        assert_eq!(dynamic.distance_lengths, [1]);
+
+        // literals and matches add up to the data
+        let data = b"abcabcabcabc, abcabcabcabc";
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.set_record_blocks(true);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let block = &reader.blocks()[0];
+        assert!(block.literals < data.len() as u64 && block.matches > 0);
+        assert_eq!(block.literals + block.match_bytes, data.len() as u64);
+        assert_eq!(block.uncompressed, data.len() as u64);
+        // the compressed data ends with the 8 byte trailer, padding the block to a byte boundary
+        assert_eq!(
+            block.compressed_bits.div_ceil(8),
+            compressed.len() as u64 - 8 - block.in_bits / 8
+        );
+
+        // nothing is recorded unless asked for
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert!(reader.blocks().is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&b"abcd".repeat(100)).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let input = [&compressed[..], &HELLO_GZ[..]].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_record_blocks(true);
+        reader.read_to_end(&mut vec![]).unwrap();
+
+        let stats = reader.stats();
+        assert_eq!(stats.blocks, reader.blocks());
+        assert_eq!(stats.members.len(), 2);
+        let first = &stats.members[0];
+        assert_eq!((first.member, first.blocks, first.uncompressed), (1, 1, 400));
+        assert_eq!(first.literals + first.match_bytes, 400);
+        let average = first.average_match_length().unwrap();
+        assert_eq!(average, first.match_bytes as f64 / first.matches as f64);
+        assert_eq!(stats.blocks[0].average_match_length(), Some(average));
+        let second = &stats.members[1];
+        assert_eq!((second.member, second.blocks, second.uncompressed), (2, 1, 13));
+        assert_eq!((second.compressed_bits, second.average_match_length()), (144, None));
+    }
+
+    #[test]
+    fn test_histograms() {
+        let data = b"abracadabra, abracadabra, abracadabra".repeat(20);
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.set_record_blocks(true);
+        reader.set_record_histograms(true);
+        reader.read_to_end(&mut vec![]).unwrap();
+
+        let histograms = reader.histograms().unwrap();
+        let stats = &reader.stats().members[0];
+        assert_eq!(histograms.litlen[256], stats.blocks);
+        assert_eq!(histograms.litlen[..256].iter().sum::<u64>(), stats.literals);
+        assert_eq!(histograms.litlen[257..].iter().sum::<u64>(), stats.matches);
+        assert_eq!(histograms.distance.iter().sum::<u64>(), stats.matches);
+        assert!(histograms.litlen[b'a' as usize] > 0 && histograms.litlen[b'z' as usize] == 0);
+
+        assert_eq!(RZLibReader::new(&compressed[..]).histograms(), None);
+    }
+
+    #[test]
+    fn test_error_position() {
+        let input = [HELLO_GZ, HELLO_GZ].concat();
+        let err = decompress(&input[..60]).unwrap_err();
+        let position = DecodeError::from_io_error(&err).unwrap().position();
+        assert_eq!(position, StreamPosition { byte: 60, bit: 0, member: 2, block: 1 });
+        assert_eq!(
+            err.to_string(),
+            "unexpected end of compressed stream at byte 60, bit 0, member 2, block 1"
+        );
+
+        // fixed block: literal 'a', then a block of the reserved type 11
+        let input = [0x4a, 0x04, 0x1c];
+        let err = RZLibReader::with_format(&input[..], Format::Deflate)
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        let position = DecodeError::from_io_error(&err).unwrap().position();
+        assert_eq!(position, StreamPosition { byte: 2, bit: 5, member: 1, block: 2 });
+    }
+
+    #[test]
+    fn test_totals() {
+        let input = [&HELLO_GZ[..], &HELLO_GZ[..], b"trailing"].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_multi_member(false);
+        assert_eq!((reader.total_in(), reader.total_out()), (0, 0));
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        // the rest of the stored block was decoded ahead
+        assert_eq!((reader.total_in(), reader.total_out()), (28, 5));
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert_eq!((reader.total_in(), reader.total_out()), (36, 13));
+
+        let mut reader = RZLibReader::new(&input[..72]);
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert_eq!((reader.total_in(), reader.total_out()), (72, 26));
+    }
+
+    #[test]
+    fn test_header() {
+        let mut input = HELLO_GZ[..10].to_vec();
+        // FTEXT | FEXTRA | FNAME | FCOMMENT, and an mtime
+        input[3] = 0x1d;
+        input[4..8].copy_from_slice(&1_000_000_000u32.to_le_bytes());
+        // a subfield with binary data
+        input.extend_from_slice(b"\x06\x00AB\x02\x00\xff\x00");
+        input.extend_from_slice(b"hello.txt\0greeting\0");
+        input.extend_from_slice(&HELLO_GZ[10..]);
+
+        let mut reader = RZLibReader::new(&input[..]);
+        assert_eq!(reader.header(), None);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let header = reader.header().unwrap();
+        assert!(header.text);
+        assert_eq!((header.mtime, header.xfl, header.os), (1_000_000_000, 4, 3));
+        assert_eq!(header.extra_subfields(), Some(vec![(0x4142, vec![0xff, 0x00])]));
+        assert_eq!(header.filename.as_deref(), Some(&b"hello.txt"[..]));
+        assert_eq!(header.comment.as_deref(), Some(&b"greeting"[..]));
+
+        reader.reset(&HELLO_GZ[..]);
+        assert_eq!(reader.header(), None);
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert_eq!(reader.header().unwrap().filename, None);
+    }
+
+    #[test]
+    fn test_strictness() {
+        let decode = |input: &[u8], strictness| {
+            let mut reader = RZLibReader::new(input);
+            reader.set_strictness(strictness);
+            let mut out = vec![];
+            reader.read_to_end(&mut out).map(|_| (out, reader.header().cloned()))
+        };
+        // only rejected when strict
+        let expect_bad_header = |input: &[u8]| {
+            let err = decode(input, Strictness::Strict).unwrap_err();
+            assert!(matches!(rzlib_error(err), RzlibError::BadHeaderField(_)));
+            assert_eq!(RZLibReader::new(input).read_to_end(&mut vec![]).unwrap(), 13);
+            let (out, header) = decode(input, Strictness::Lenient).unwrap();
+            assert_eq!(out, b"hello, rzlib\n");
+            header.unwrap()
+        };
+
+        let mut reserved_flags = HELLO_GZ;
+        reserved_flags[3] = 0xe0;
+        expect_bad_header(&reserved_flags);
+
+        let mut unknown_os = HELLO_GZ;
+        unknown_os[9] = 200;
+        assert_eq!(expect_bad_header(&unknown_os).os, 200);
+
+        // ISO-8859-1, as RFC 1952 specifies, unless strict
+        let mut latin1_name = HELLO_GZ[..10].to_vec();
+        latin1_name[3] = 0x08;
+        latin1_name.extend_from_slice(b"caf\xe9.txt\0");
+        latin1_name.extend_from_slice(&HELLO_GZ[10..]);
+        let header = expect_bad_header(&latin1_name);
+        assert_eq!(header.filename.as_deref(), Some(&b"caf\xe9.txt"[..]));
+        assert_eq!(header.filename_lossy().unwrap(), "caf\u{e9}.txt");
+        let header = decode(&latin1_name, Strictness::Normal).unwrap().1.unwrap();
+        assert_eq!(header.filename.as_deref(), Some(&b"caf\xe9.txt"[..]));
+
+        let mut latin1_comment = HELLO_GZ[..10].to_vec();
+        latin1_comment[3] = 0x10;
+        latin1_comment.extend_from_slice(b"\xa9 2024\0");
+        latin1_comment.extend_from_slice(&HELLO_GZ[10..]);
+        assert_eq!(expect_bad_header(&latin1_comment).comment.as_deref(), Some(&b"\xa9 2024"[..]));
+        let mut utf8_name = [&HELLO_GZ[..10], b"caf\xc3\xa9.txt\0", &HELLO_GZ[10..]].concat();
+        utf8_name[3] = 0x08;
+        assert!(decode(&utf8_name, Strictness::Strict).is_ok());
+
+        let mut bare_extra = HELLO_GZ[..10].to_vec();
+        bare_extra[3] = 0x04;
+        bare_extra.extend_from_slice(b"\x02\x00ab");
+        bare_extra.extend_from_slice(&HELLO_GZ[10..]);
+        let header = expect_bad_header(&bare_extra);
+        assert_eq!(header.extra.as_deref(), Some(&b"ab"[..]));
+        assert_eq!(header.extra_subfields(), None);
+
+        // no trailer at all, but not a partial one
+        let no_trailer = &HELLO_GZ[..HELLO_GZ.len() - 8];
+        for strictness in [Strictness::Strict, Strictness::Normal] {
+            let err = decode(no_trailer, strictness).unwrap_err();
+            assert_eq!(rzlib_error(err), RzlibError::TruncatedStream);
+        }
+        assert_eq!(decode(no_trailer, Strictness::Lenient).unwrap().0, b"hello, rzlib\n");
+        let partial_trailer = &HELLO_GZ[..HELLO_GZ.len() - 3];
+        assert!(decode(partial_trailer, Strictness::Lenient).is_err());
+
+        let mut reader = RZLibReader::with_format(&HELLO_ZLIB[..HELLO_ZLIB.len() - 4], Format::Zlib);
+        reader.set_strictness(Strictness::Lenient);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, zlib\n");
+    }
+
+    #[test]
+    fn test_trailing_garbage() {
+        for garbage in [&b"\0"[..], &[0; 512], b"\x1f\x8a junk", b"\x1f"] {
+            let input = [&HELLO_GZ, &HELLO_GZ, garbage].concat();
+            let err = decompress(&input).unwrap_err();
+            assert!(matches!(rzlib_error(err), RzlibError::BadMagic { .. } | RzlibError::TruncatedStream));
+
+            let mut reader = RZLibReader::new(&input[..]);
+            reader.set_ignore_trailing_garbage(true);
+            let mut out = vec![];
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"hello, rzlib\nhello, rzlib\n");
+            assert_eq!(reader.total_in(), 2 * HELLO_GZ.len() as u64);
+        }
+
+        // garbage instead of the first member is still an error
+        let mut reader = RZLibReader::new(&b"junk"[..]);
+        reader.set_ignore_trailing_garbage(true);
+        assert!(reader.read_to_end(&mut vec![]).is_err());
+    }
+
+    // Serves `data`, but fails with `WouldBlock` past `limit` until it is raised.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        pos: usize,
+        limit: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.fill_buf()?.read(buf)?;
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for Trickle<'_> {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            if self.pos == self.limit && self.pos < self.data.len() {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            Ok(&self.data[self.pos..self.limit])
+        }
+        fn consume(&mut self, amt: usize) {
+            self.pos += amt;
+        }
+    }
+
+    #[test]
+    fn test_resume_after_would_block() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i * i / 7 % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let mut input = encoder.finish().unwrap();
+        // stored blocks
+        let mut encoder = GzEncoder::new(vec![], Compression::none());
+        encoder.write_all(&data[..1000]).unwrap();
+        input.extend_from_slice(&encoder.finish().unwrap());
+        // a header with optional fields, and a second member
+        input.extend_from_slice(&HELLO_GZ[..3]);
+        input.extend_from_slice(&[0x18, 0, 0, 0, 0, 0, 3]);
+        input.extend_from_slice(b"hello.txt\0comment\0");
+        input.extend_from_slice(&HELLO_GZ[10..]);
+
+        // input arrives one byte at a time
+        let mut reader = RZLibReader::new(Trickle { data: &input, pos: 0, limit: 0 });
+        let mut out = vec![];
+        let mut buf = [0; 1000];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => out.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    reader.get_mut().limit += 1;
+                }
+                Err(err) => panic!("unexpected error {}", err),
+            }
+        }
+        assert_eq!(out.len(), data.len() + 1000 + 13);
+        assert!(out == [&data[..], &data[..1000], b"hello, rzlib\n"].concat());
+        assert_eq!(reader.header().unwrap().comment.as_deref(), Some(&b"comment"[..]));
+        assert_eq!(reader.total_in(), input.len() as u64);
+    }
+
+    // Fails with `Interrupted` on every other call.
+    struct Flaky<'a> {
+        data: &'a [u8],
+        fail: bool,
+    }
+
+    impl Read for Flaky<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.fill_buf()?.read(buf)?;
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for Flaky<'_> {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            self.fail = !self.fail;
+            if self.fail {
+                return Err(ErrorKind::Interrupted.into());
+            }
+            Ok(&self.data[..self.data.len().min(3)])
+        }
+        fn consume(&mut self, amt: usize) {
+            self.data = &self.data[amt..];
+        }
+    }
+
+    #[test]
+    fn test_interrupted_is_retried() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 31 / 11 % 253) as u8).collect();
+        for level in [Compression::none(), Compression::fast(), Compression::best()] {
+            let mut encoder = GzEncoder::new(vec![], level);
+            encoder.write_all(&data).unwrap();
+            let input = encoder.finish().unwrap();
+
+            let mut reader = RZLibReader::new(Flaky { data: &input, fail: false });
+            let mut out = vec![];
+            let mut buf = [0; 777];
+            loop {
+                match reader.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => out.extend_from_slice(&buf[..n]),
+                }
+            }
+            assert!(out == data);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut reader = RZLibReader::new(&HELLO_GZ[..20]);
+        reader.set_max_output_bytes(Some(100));
+        assert!(reader.read_to_end(&mut vec![]).is_err());
+
+        let previous = reader.reset(&HELLO_GZ[..]);
+        assert!(previous.is_empty());
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, rzlib\n");
+
+        reader.reset(&HELLO_GZ[..]);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, rzlib\n");
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let input = [HELLO_GZ, HELLO_GZ].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_max_output_bytes(Some(26));
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out.len(), 26);
+
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_max_output_bytes(Some(20));
+        let mut out = vec![];
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::OutputLimitExceeded(20));
+        assert_eq!(out, b"hello, rzlib\nhello, ");
+        assert!(reader.read(&mut [0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_distance_too_far_back() {
+        // fixed block: literal 'a', then a match of length 3 at distance 2
+        let input = [0x4b, 0x04, 0x42, 0x00];
+        let mut out = vec![];
+        let err = RZLibReader::with_format(&input[..], Format::Deflate)
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::InvalidDistance(2));
+    }
+
+    #[test]
+    fn test_distance_into_previous_member() {
+        // a member starting with a match of length 3 at distance 1
+        let mut bits = BitWriter::new(HELLO_GZ[..10].to_vec());
+        let litlen_codes = canonical_codes(&fixed_litlen_lengths());
+        let distance_codes = canonical_codes(&fixed_distance_lengths());
+        bits.write_bits(0b011, 3).unwrap();
+        write_code(&mut bits, &litlen_codes[257]).unwrap();
+        write_code(&mut bits, &distance_codes[0]).unwrap();
+        write_code(&mut bits, &litlen_codes[256]).unwrap();
+        let member = bits.finish().unwrap();
+
+        let err = decompress(&member).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::InvalidDistance(1));
+        let err = decompress(&[&HELLO_GZ, &member[..]].concat()).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::InvalidDistance(1));
+    }
+
+    #[test]
+    fn test_reserved_symbols() {
+        let litlen_codes = canonical_codes(&fixed_litlen_lengths());
+        let mut distance_lengths = fixed_distance_lengths().to_vec();
+        distance_lengths.extend_from_slice(&[5, 5]);
+        let distance_codes = canonical_codes(&distance_lengths);
+        let decode = |codes: &[&Codeword]| {
+            let mut bits = BitWriter::new(vec![]);
+            // BFINAL, BTYPE = 01
+            bits.write_bits(0b011, 3).unwrap();
+            for code in codes {
+                write_code(&mut bits, code).unwrap();
+            }
+            let input = bits.finish().unwrap();
+            RZLibReader::with_format(&input[..], Format::Deflate).read_to_end(&mut vec![])
+        };
+
+        let literal = &litlen_codes[b'a' as usize];
+        let end = &litlen_codes[256];
+        decode(&[literal, &litlen_codes[257], &distance_codes[0], end]).unwrap();
+        for symbol in [286, 287] {
+            let err = decode(&[literal, &litlen_codes[symbol], end]).unwrap_err();
+            assert_eq!(rzlib_error(err), RzlibError::InvalidCode);
+        }
+        for code in [30, 31] {
+            let err = decode(&[literal, &litlen_codes[257], &distance_codes[code], end]).unwrap_err();
+            assert_eq!(rzlib_error(err), RzlibError::InvalidCode);
+        }
+
+        // dynamic block headers declaring them: HLIT = 30, HDIST = 0, then HLIT = 0, HDIST = 30
+        for (hlit, hdist) in [(30, 0), (0, 30)] {
+            let mut bits = BitWriter::new(vec![]);
+            for (value, n) in [(1, 1), (2, 2), (hlit, 5), (hdist, 5), (15, 4)] {
+                bits.write_bits(value, n).unwrap();
+            }
+            let input = bits.finish().unwrap();
+            let err = RZLibReader::with_format(&input[..], Format::Deflate)
+                .read_to_end(&mut vec![])
+                .unwrap_err();
+            assert_eq!(rzlib_error(err), RzlibError::InvalidCodeLengths);
+        }
+    }
+
+    #[test]
+    fn test_deflate64() {
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut bits = BitWriter::new(vec![]);
+        // a stored block, then a fixed Huffman block with a match 40000 bytes back, out of reach of
+        // deflate, through distance code 30, and 1000 bytes long, through length code 285
+        bits.write_bits(0b000, 3).unwrap();
+        bits.align().unwrap();
+        bits.write_bits(data.len() as u64, 16).unwrap();
+        bits.write_bits(!data.len() as u64 & 0xffff, 16).unwrap();
+        for &b in &data {
+            bits.write_bits(b as u64, 8).unwrap();
+        }
+        bits.write_bits(0b011, 3).unwrap();
+        let litlen_codes = canonical_codes(&fixed_litlen_lengths());
+        let distance_codes = canonical_codes(&[5; 32]);
+        write_code(&mut bits, &litlen_codes[285]).unwrap();
+        bits.write_bits(1000 - 3, 16).unwrap();
+        write_code(&mut bits, &distance_codes[30]).unwrap();
+        bits.write_bits(40_000 - 32769, 14).unwrap();
+        write_code(&mut bits, &litlen_codes[256]).unwrap();
+        let input = bits.finish().unwrap();
+
+        let mut reader = RZLibReader::with_format(&input[..], Format::Deflate64);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert!(out == [&data[..], &data[..1000]].concat());
+        assert_eq!(reader.total_in(), input.len() as u64);
+
+        // taken as deflate, the extra bits of the match are read as codes
+        assert!(RZLibReader::with_format(&input[..], Format::Deflate)
+            .read_to_end(&mut vec![])
+            .is_err());
+    }
+
+    // "hello, dictionary! hello!" compressed with the preset dictionary "hello, dictionary"
+    const DICTIONARY_ZLIB: [u8; 20] = [
+        0x78, 0xf9, 0x39, 0x4e, 0x06, 0x97, 0xcb, 0x40, 0x17, 0x50, 0x54, 0x00, 0x0b, 0x29, 0x02,
+        0x00, 0x78, 0x4a, 0x09, 0x0d,
+    ];
+
+    #[test]
+    fn test_preset_dictionary() {
+        let mut reader = RZLibReader::with_format(&DICTIONARY_ZLIB[..], Format::Zlib);
+        reader.set_dictionary(b"hello, dictionary");
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, dictionary! hello!");
+
+        let err = decompress_zlib(&DICTIONARY_ZLIB).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::PresetDictionaryRequired(0x394e0697));
+
+        let mut reader = RZLibReader::with_format(&DICTIONARY_ZLIB[..], Format::Zlib);
+        reader.set_dictionary(b"hello, dictionarY");
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert!(matches!(rzlib_error(err), RzlibError::DictionaryMismatch { .. }));
+
+        // the same data as a raw deflate stream
+        let mut reader = RZLibReader::with_format(&DICTIONARY_ZLIB[6..16], Format::Deflate);
+        reader.set_dictionary(b"hello, dictionary");
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, dictionary! hello!");
+    }
+
+    #[test]
+    fn test_window() {
+        let mut reader = RZLibReader::new(&HELLO_GZ[..]);
+        assert_eq!(reader.window(), b"");
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.window(), b"hello");
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert_eq!(reader.window(), b"hello, rzlib\n");
+
+        let data: Vec<u8> = (0..100_000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.read_to_end(&mut vec![]).unwrap();
+        assert_eq!(reader.window(), &data[data.len() - 32768..]);
+    }
+
+    #[test]
+    fn test_window_size() {
+        let data: Vec<u8> = (0..5000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // the data repeats every 251 bytes
+        let mut reader = RZLibReader::with_window_size(&compressed[..], Format::Gzip, 256);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut reader = RZLibReader::with_window_size(&compressed[..], Format::Gzip, 128);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::InvalidDistance(251));
+
+        // HELLO_ZLIB with a header declaring a 256 byte window (CINFO = 0)
+        let input = [&[0x08, 0x1d], &HELLO_ZLIB[2..]].concat();
+        let mut reader = RZLibReader::with_window_size(&input[..], Format::Zlib, 256);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let mut reader = RZLibReader::with_window_size(&input[..], Format::Zlib, 128);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::BadWindowSize(0));
+    }
+
+    #[test]
+    fn test_raw_deflate() {
+        let input = [
+            0x01, 0x0f, 0x00, 0xf0, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x64, 0x65,
+            0x66, 0x6c, 0x61, 0x74, 0x65, 0x0a, 0xaa, 0xbb,
+        ];
+        let mut reader = RZLibReader::with_format(&input[..], Format::Deflate);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello, deflate\n");
+        assert_eq!(reader.into_inner(), [0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn test_seek_forward() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 7 * i % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = RZLibReader::new(&compressed[..]);
+        assert_eq!(reader.seek(SeekFrom::Start(70_000)).unwrap(), 70_000);
+        assert_eq!(reader.seek(SeekFrom::Current(5)).unwrap(), 70_005);
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[70_005..70_015]);
+
+        for pos in [SeekFrom::Start(10), SeekFrom::Current(-1), SeekFrom::End(0)] {
+            assert!(reader.seek(pos).is_err());
+        }
+        assert_eq!(reader.stream_position().unwrap(), 70_015);
+        assert_eq!(reader.seek(SeekFrom::Start(200_000)).unwrap(), 100_000);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 253) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = RZLibReader::new(std::io::Cursor::new(&compressed));
+        assert_eq!(reader.size_hint().unwrap(), Some(100_000));
+        let mut out = vec![0; 10];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(reader.size_hint().unwrap(), Some(99_990));
+        let mut out = Vec::with_capacity(reader.size_hint().unwrap().unwrap() as usize);
+        reader.read_to_end(&mut out).unwrap();
+        assert!(out == data[10..]);
+        assert_eq!(out.capacity(), out.len());
+
+        let mut bogus = compressed.clone();
+        let len = bogus.len();
+        bogus[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = RZLibReader::new(std::io::Cursor::new(&bogus));
+        assert_eq!(reader.size_hint().unwrap(), None);
+        assert_eq!(reader.get_ref().position(), 0);
+        let mut reader = RZLibReader::with_format(std::io::Cursor::new(&compressed), Format::Zlib);
+        assert_eq!(reader.size_hint().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_vectored() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 241) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = RZLibReader::new(&compressed[..]);
+        let mut out = vec![];
+        let (mut a, mut b, mut c) = ([0; 7], [0; 3000], [0; 50]);
+        loop {
+            let mut bufs = [
+                IoSliceMut::new(&mut a),
+                IoSliceMut::new(&mut []),
+                IoSliceMut::new(&mut b),
+            ];
+            let n = reader.read_vectored(&mut bufs).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&a[..n.min(7)]);
+            out.extend_from_slice(&b[..n.saturating_sub(7)]);
+            // small reads are served from what was decoded ahead
+            let n = reader.read_vectored(&mut [IoSliceMut::new(&mut c)]).unwrap();
+            out.extend_from_slice(&c[..n]);
+        }
+        assert!(out == data);
+        assert_eq!(reader.total_out(), data.len() as u64);
+    }
+
+    #[test]
+    fn test_hooks() {
+        #[derive(Default)]
+        struct Recorder {
+            events: Arc<Mutex<Vec<String>>>,
+        }
+        impl DecodeHooks for Recorder {
+            fn on_member_start(&mut self, header: Option<&GzHeader>) -> std::io::Result<()> {
+                let name = header.and_then(|header| header.filename.clone()).unwrap_or_default();
+                if name.ends_with(b".exe") {
+                    return Err(std::io::Error::new(ErrorKind::PermissionDenied, "no executables"));
+                }
+                let event = format!("start {}", String::from_utf8_lossy(&name));
+                self.events.lock().unwrap().push(event);
+                Ok(())
+            }
+
+            fn on_member_end(&mut self, trailer: Option<&GzTrailer>) -> std::io::Result<()> {
+                let event = format!("end {:?}", trailer.map(|trailer| trailer.isize));
+                self.events.lock().unwrap().push(event);
+                Ok(())
+            }
+
+            fn on_block(&mut self, block_type: BlockType, is_final: bool) -> std::io::Result<()> {
+                self.events.lock().unwrap().push(format!("block {:?} {}", block_type, is_final));
+                Ok(())
+            }
+        }
+
+        let member = |name: &str, data: &[u8], level| {
+            let header = GzHeader { filename: Some(name.into()), ..GzHeader::default() };
+            let mut encoder = GzEncoder::with_header(vec![], Compression::new(level), header);
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let input = [member("a", b"aaaa", 6), member("b", b"bb", 0)].concat();
+        let recorder = Recorder::default();
+        let events = recorder.events.clone();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_hooks(Some(Box::new(recorder)));
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "start a",
+                "block FixedHuffman true",
+                "end Some(4)",
+                "start b",
+                "block Stored true",
+                "end Some(2)",
+            ]
+        );
+
+        let input = [member("a", b"aaaa", 6), member("b.exe", b"bb", 6)].concat();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_hooks(Some(Box::new(Recorder::default())));
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(DecodeError::from_io_error(&err).unwrap().position().member, 2);
+    }
+
+    #[test]
+    fn test_max_input_bytes() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"framed, framed, framed").unwrap();
+        let stream = encoder.finish().unwrap();
+        let framed = [&stream[..], b"next frame"].concat();
+
+        let mut reader = RZLibReader::new(&framed[..]);
+        reader.set_max_input_bytes(Some(stream.len() as u64));
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"framed, framed, framed");
+        assert_eq!(reader.into_inner(), b"next frame");
+
+        let mut reader = RZLibReader::new(&framed[..]);
+        reader.set_max_input_bytes(Some(stream.len() as u64 - 1));
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(RzlibError::from_io_error(&err), Some(&RzlibError::TruncatedStream));
+        assert_eq!(reader.into_inner().len(), framed.len() - stream.len() + 1);
+    }
+
+    #[test]
+    fn test_stop_at_sync_points() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"first record").unwrap();
+        encoder.flush_with(FlushMode::Sync).unwrap();
+        encoder.write_all(&b"second record, ".repeat(5000)).unwrap();
+        encoder.flush_with(FlushMode::Full).unwrap();
+        encoder.write_all(b"last record").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.set_stop_at_sync_points(true);
+        let mut records = vec![];
+        loop {
+            // small reads, so that output is decoded ahead of them
+            let mut record = vec![];
+            let mut buf = [0; 7];
+            loop {
+                match reader.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => record.extend_from_slice(&buf[..n]),
+                }
+            }
+            records.push(record);
+            if !reader.at_sync_point() {
+                break;
+            }
+        }
+        assert_eq!(
+            records,
+            [
+                b"first record".to_vec(),
+                b"second record, ".repeat(5000),
+                b"last record".to_vec()
+            ]
+        );
+
+        // disabled, the flushes do not show
+        let mut out = vec![];
+        RZLibReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out.len(), 12 + 15 * 5000 + 11);
+    }
+
+    #[test]
+    fn test_seek_across_sync_points() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        for i in 0..10 {
+            encoder.write_all(format!("record {}\n", i).as_bytes()).unwrap();
+            encoder.flush_with(FlushMode::Sync).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.set_stop_at_sync_points(true);
+        assert_eq!(reader.seek(SeekFrom::Start(47)).unwrap(), 47);
+        let mut record = [0; 7];
+        reader.read_exact(&mut record).unwrap();
+        assert_eq!(&record, b"cord 5\n");
+        assert_eq!(reader.seek(SeekFrom::Current(1000)).unwrap(), 90);
+    }
+
+    #[test]
+    fn test_small_reads() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 13 * i % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // tiny reads interleaved with large ones
+        let mut reader = RZLibReader::new(&compressed[..]);
+        let mut out = vec![];
+        let mut buf = vec![0; 40_000];
+        for len in [1, 3, 300, 1, 40_000, 7, 255, 256].iter().cycle() {
+            let n = reader.read(&mut buf[..*len]).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+            assert_eq!(reader.total_out(), out.len() as u64);
+            let window = reader.window();
+            assert!(out.ends_with(&window), "{}", out.len());
+        }
+        assert!(out == data);
+
+        // the output limit holds for data decoded ahead
+        let mut reader = RZLibReader::new(&compressed[..]);
+        reader.set_max_output_bytes(Some(1000));
+        let mut byte = [0];
+        for expected in &data[..1000] {
+            assert_eq!(reader.read(&mut byte).unwrap(), 1);
+            assert_eq!(byte[0], *expected);
+        }
+        let err = reader.read(&mut byte).unwrap_err();
+        assert_eq!(rzlib_error(err), RzlibError::OutputLimitExceeded(1000));
     }
 }
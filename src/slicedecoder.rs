@@ -0,0 +1,488 @@
+//! Decoding of compressed data held in memory as a whole, such as a slice or a memory-mapped
+//! file.
+
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::ErrorKind::UnexpectedEof;
+
+use crate::adler32::Adler32;
+use crate::bitreader::{BitRead, BitSliceReader};
+use crate::crc32::Crc32;
+use crate::deflatetables::{
+    DEFLATE64_LENGTH_285_BASE, DEFLATE64_LENGTH_285_EXTRA_BITS, DISTANCE_BASE, DISTANCE_CODES,
+    DISTANCE_EXTRA_BITS, END_OF_BLOCK, LENGTH_BASE, LENGTH_EXTRA_BITS,
+};
+use crate::error::{DecodeError, RzlibError, StreamPosition};
+use crate::gzheader::GzHeader;
+use crate::huffman::HuffmanTable;
+use crate::rzlibreader::{
+    check_zlib_header, read_code_lengths, read_gzip_header, CodeLengths, Format, Strictness,
+    LOOKBACK_WINDOW_SIZE,
+};
+
+/// Decompresses a gzip (or zlib or raw deflate) stream held in memory in one go.
+///
+/// Unlike [`RZLibReader`](crate::RZLibReader), it has no `BufRead` to go through and no state to
+/// resume from: bits are loaded straight from the slice, and the output itself serves as the
+/// window. The stream has to be complete, and is decoded to a `Vec` as a whole.
+///
+/// Concatenated gzip members are decoded one after another. Preset dictionaries, statistics and
+/// checkpoints are not supported.
+pub struct SliceDecoder<'a> {
+    format: Format,
+    bits: BitSliceReader<'a>,
+    // codes of the current Huffman block
+    litlen_table: HuffmanTable,
+    distance_table: HuffmanTable,
+    verify_checksum: bool,
+    strictness: Strictness,
+    max_output_bytes: Option<u64>,
+    // header of the last gzip member
+    header: Option<GzHeader>,
+    // 1-based indices of the current member and of the current block within it, for errors
+    member_index: u64,
+    block_index: u64,
+}
+
+impl<'a> SliceDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> SliceDecoder<'a> {
+        Self::with_format(data, Format::Gzip)
+    }
+
+    pub fn with_format(data: &'a [u8], format: Format) -> SliceDecoder<'a> {
+        SliceDecoder {
+            format,
+            bits: BitSliceReader::new(data),
+            litlen_table: HuffmanTable::empty(),
+            distance_table: HuffmanTable::empty(),
+            verify_checksum: true,
+            strictness: Strictness::Normal,
+            max_output_bytes: None,
+            header: None,
+            member_index: 0,
+            block_index: 0,
+        }
+    }
+
+    /// See [`RZLibReader::set_verify_checksum`](crate::RZLibReader::set_verify_checksum).
+    pub fn set_verify_checksum(&mut self, verify: bool) {
+        self.verify_checksum = verify;
+    }
+
+    /// See [`RZLibReader::set_strictness`](crate::RZLibReader::set_strictness).
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    /// Sets the most bytes a stream may decompress to, beyond which decoding fails with
+    /// [`RzlibError::OutputLimitExceeded`]. The limit is checked as matches and stored blocks
+    /// are copied, so the output may go past it by the literals of a block before failing.
+    pub fn set_max_output_bytes(&mut self, limit: Option<u64>) {
+        self.max_output_bytes = limit;
+    }
+
+    /// Number of compressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.bits.bit_position().div_ceil(8)
+    }
+
+    /// The input following the stream decoded, or following where decoding failed.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bits.remaining()
+    }
+
+    /// Header of the last gzip member decoded.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+
+    /// Decodes the stream, appending the decompressed data to `out`. Errors are reported as by
+    /// `RZLibReader`, with the position in the input where decoding failed; `out` then holds
+    /// what was decoded up to there.
+    pub fn decode_to_vec(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        let start = out.len();
+        let limit = match self.max_output_bytes {
+            Some(limit) => start.saturating_add(usize::try_from(limit).unwrap_or(usize::MAX)),
+            None => usize::MAX,
+        };
+        let result = match self.format {
+            Format::Gzip => self.decode_gzip(out, limit),
+            Format::Zlib => self.decode_zlib(out, limit),
+            Format::Deflate | Format::Deflate64 => {
+                self.member_index += 1;
+                let result = self.decode_blocks(out, start, limit);
+                self.bits.drop_remaining_bits();
+                result
+            }
+        };
+        result.map_err(|err| self.decode_error(err))
+    }
+
+    fn decode_gzip(&mut self, out: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        while !self.remaining().is_empty() {
+            let magic = self.bits.read_bits_exact(16)?;
+            self.member_index += 1;
+            self.block_index = 0;
+            if magic != 0x8b1f {
+                let [id1, id2] = (magic as u16).to_le_bytes();
+                return Err(RzlibError::BadMagic { id1, id2 }.into());
+            }
+            let strictness = self.strictness;
+            self.header = Some(self.read_bytes(|input| read_gzip_header(input, strictness))?);
+
+            let member_start = out.len();
+            self.decode_blocks(out, member_start, limit)?;
+            self.bits.drop_remaining_bits();
+            if self.trailer_missing() {
+                return Ok(());
+            }
+            let crc32 = self.bits.read_bits_exact(32)? as u32;
+            let isize = self.bits.read_bits_exact(32)? as u32;
+            let mut crc = Crc32::new();
+            crc.update(&out[member_start..]);
+            if self.verify_checksum && crc32 != crc.sum() {
+                return Err(RzlibError::ChecksumMismatch {
+                    expected: crc32,
+                    actual: crc.sum(),
+                }
+                .into());
+            }
+            let member_size = (out.len() - member_start) as u32;
+            if isize != member_size {
+                return Err(RzlibError::SizeMismatch {
+                    expected: isize,
+                    actual: member_size,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_zlib(&mut self, out: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        let cmf = self.bits.read_bits_exact(8)? as u8;
+        let flg = self.bits.read_bits_exact(8)? as u8;
+        self.member_index += 1;
+        check_zlib_header(cmf, flg, LOOKBACK_WINDOW_SIZE)?;
+        if flg & 0x20 != 0 {
+            let dictid = (self.bits.read_bits_exact(32)? as u32).swap_bytes();
+            return Err(RzlibError::PresetDictionaryRequired(dictid).into());
+        }
+
+        let start = out.len();
+        self.decode_blocks(out, start, limit)?;
+        self.bits.drop_remaining_bits();
+        if self.trailer_missing() {
+            return Ok(());
+        }
+        let adler32 = (self.bits.read_bits_exact(32)? as u32).swap_bytes();
+        let mut adler = Adler32::new();
+        adler.update(&out[start..]);
+        if self.verify_checksum && adler32 != adler.sum() {
+            return Err(RzlibError::ChecksumMismatch {
+                expected: adler32,
+                actual: adler.sum(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    // In lenient mode, whether the input ends where the trailer should start, in which case the
+    // stream ends there.
+    fn trailer_missing(&self) -> bool {
+        self.strictness == Strictness::Lenient && self.remaining().is_empty()
+    }
+
+    // Reads byte-aligned fields with `read`, which is given the input from the current byte on.
+    fn read_bytes<T>(
+        &mut self,
+        read: impl FnOnce(&mut &'a [u8]) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut input = self.remaining();
+        let len = input.len();
+        let result = read(&mut input);
+        self.bits.consume_bits((len - input.len()) * 8)?;
+        result
+    }
+
+    // Decodes blocks up to the final one. Matches may reach back to `window_start` in `out`, and
+    // the output may not grow past `limit`.
+    fn decode_blocks(
+        &mut self,
+        out: &mut Vec<u8>,
+        window_start: usize,
+        limit: usize,
+    ) -> io::Result<()> {
+        self.block_index = 0;
+        loop {
+            self.block_index += 1;
+            let is_final = self.bits.read_bits_exact(1)? == 1;
+            match self.bits.read_bits_exact(2)? as u8 {
+                0 => self.decode_stored_block(out)?,
+                1 => {
+                    self.litlen_table = HuffmanTable::fixed_litlen();
+                    self.distance_table = match self.format {
+                        Format::Deflate64 => HuffmanTable::fixed_deflate64_distance(),
+                        _ => HuffmanTable::fixed_distance(),
+                    };
+                    self.decode_huffman_block(out, window_start, limit)?;
+                }
+                2 => {
+                    let distance_codes = self.distance_codes();
+                    let CodeLengths {
+                        nlit,
+                        ndist,
+                        all_lengths,
+                        ..
+                    } = read_code_lengths(&mut self.bits, distance_codes)?;
+                    self.litlen_table = HuffmanTable::new_from_lengths(&all_lengths[..nlit])?;
+                    self.distance_table =
+                        HuffmanTable::new_from_lengths(&all_lengths[nlit..(nlit + ndist)])?;
+                    self.decode_huffman_block(out, window_start, limit)?;
+                }
+                btype => return Err(RzlibError::BadBlockType(btype).into()),
+            }
+            if out.len() > limit {
+                return Err(self.output_limit_exceeded());
+            }
+            if is_final {
+                return Ok(());
+            }
+        }
+    }
+
+    fn decode_stored_block(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.bits.drop_remaining_bits();
+        let len = self.bits.read_bits_exact(16)? as u16;
+        let nlen = self.bits.read_bits_exact(16)? as u16;
+        if !len != nlen {
+            return Err(RzlibError::StoredLengthMismatch { len, nlen }.into());
+        }
+        let data = self.remaining();
+        if data.len() < len as usize {
+            return Err(RzlibError::TruncatedStream.into());
+        }
+        out.extend_from_slice(&data[..len as usize]);
+        self.bits.consume_bits(len as usize * 8)
+    }
+
+    fn decode_huffman_block(
+        &mut self,
+        out: &mut Vec<u8>,
+        window_start: usize,
+        limit: usize,
+    ) -> io::Result<()> {
+        let distance_codes = self.distance_codes();
+        loop {
+            let symbol = self
+                .litlen_table
+                .decode_buffered(&mut self.bits)?
+                .ok_or(RzlibError::InvalidCode)?;
+            if symbol < 256 {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == END_OF_BLOCK {
+                return Ok(());
+            }
+            if symbol > 285 {
+                return Err(RzlibError::InvalidCode.into());
+            }
+
+            let (base, extra_bits) = if symbol == 285 && self.format == Format::Deflate64 {
+                (DEFLATE64_LENGTH_285_BASE, DEFLATE64_LENGTH_285_EXTRA_BITS)
+            } else {
+                (LENGTH_BASE[symbol - 257], LENGTH_EXTRA_BITS[symbol - 257])
+            };
+            let length = self.bits.read_bits_exact(extra_bits as usize)? as usize + base as usize;
+            let dist_code = self
+                .distance_table
+                .decode_buffered(&mut self.bits)?
+                .filter(|&code| code < distance_codes)
+                .ok_or(RzlibError::InvalidCode)?;
+            let distance = self
+                .bits
+                .read_bits_exact(DISTANCE_EXTRA_BITS[dist_code] as usize)?
+                as usize
+                + DISTANCE_BASE[dist_code] as usize;
+            if distance > out.len() - window_start {
+                return Err(RzlibError::InvalidDistance(distance).into());
+            }
+
+            // copy from the same start, doubling the chunk until the match is complete: what was
+            // copied so far is a whole number of repetitions of the last `distance` bytes
+            let from = out.len() - distance;
+            let mut left = length;
+            while left > 0 {
+                let n = left.min(out.len() - from);
+                out.extend_from_within(from..from + n);
+                left -= n;
+            }
+            if out.len() > limit {
+                return Err(self.output_limit_exceeded());
+            }
+        }
+    }
+
+    // Number of valid distance codes.
+    fn distance_codes(&self) -> usize {
+        match self.format {
+            Format::Deflate64 => DISTANCE_BASE.len(),
+            _ => DISTANCE_CODES,
+        }
+    }
+
+    fn output_limit_exceeded(&self) -> io::Error {
+        RzlibError::OutputLimitExceeded(self.max_output_bytes.unwrap_or(u64::MAX)).into()
+    }
+
+    // Attaches the current position to `err`, as `RZLibReader` does.
+    fn decode_error(&self, err: io::Error) -> io::Error {
+        let bit_position = self.bits.bit_position();
+        let position = StreamPosition {
+            byte: bit_position / 8,
+            bit: (bit_position % 8) as u8,
+            member: self.member_index,
+            block: self.block_index,
+        };
+        // end of input reported by the bit reader or a header field
+        if err.kind() == UnexpectedEof && RzlibError::from_io_error(&err).is_none() {
+            return DecodeError::new(RzlibError::TruncatedStream.into(), position).into();
+        }
+        DecodeError::new(err, position).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::SliceDecoder;
+    use crate::deflateencoder::{Compression, DeflateEncoder};
+    use crate::error::{DecodeError, RzlibError};
+    use crate::gzip;
+    use crate::rzlibreader::{Format, RZLibReader, Strictness};
+    use crate::zlibencoder::ZlibEncoder;
+
+    fn test_data() -> Vec<u8> {
+        (0..50_000u32)
+            .map(|i| ((i % 251) ^ (i / 900)) as u8)
+            .collect()
+    }
+
+    fn decode(input: &[u8], format: Format) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        SliceDecoder::with_format(input, format).decode_to_vec(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_formats() {
+        let data = test_data();
+        for level in [0, 1, 6, 9] {
+            let gzip = gzip::compress_to_vec(&data, Compression::new(level));
+            let mut zlib = ZlibEncoder::new(vec![], Compression::new(level));
+            zlib.write_all(&data).unwrap();
+            let mut deflate = DeflateEncoder::new(vec![], Compression::new(level));
+            deflate.write_all(&data).unwrap();
+
+            for (input, format) in [
+                (gzip, Format::Gzip),
+                (zlib.finish().unwrap(), Format::Zlib),
+                (deflate.finish().unwrap(), Format::Deflate),
+            ] {
+                let mut decoder = SliceDecoder::with_format(&input, format);
+                let mut out = vec![];
+                decoder.decode_to_vec(&mut out).unwrap();
+                assert!(out == data, "{:?} level {}", format, level);
+                assert_eq!(decoder.total_in(), input.len() as u64);
+                assert!(decoder.remaining().is_empty());
+            }
+        }
+
+        let members = [
+            gzip::compress_to_vec(b"abc", Compression::default()),
+            gzip::compress_to_vec(b"", Compression::default()),
+            gzip::compress_to_vec(b"def", Compression::fast()),
+        ]
+        .concat();
+        assert_eq!(decode(&members, Format::Gzip).unwrap(), b"abcdef");
+        assert_eq!(decode(&[], Format::Gzip).unwrap(), b"");
+    }
+
+    // Decodes `input` with both decoders, which should agree on the output or on the error.
+    fn check_against_reader(input: &[u8], what: &str) {
+        let mut expected = vec![];
+        let expected = RZLibReader::new(input)
+            .read_to_end(&mut expected)
+            .map(|_| expected);
+        match (decode(input, Format::Gzip), expected) {
+            (Ok(out), Ok(expected)) => assert!(out == expected, "{}", what),
+            (Err(err), Err(expected)) => assert_eq!(
+                RzlibError::from_io_error(&err),
+                RzlibError::from_io_error(&expected),
+                "{}",
+                what
+            ),
+            (out, expected) => panic!("{}: {:?} and {:?}", what, out.is_ok(), expected.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_errors_match_reader() {
+        let mut input = gzip::compress_to_vec(&test_data()[..2000], Compression::default());
+        input.extend_from_slice(&gzip::compress_to_vec(b"second", Compression::new(0)));
+        for len in 0..input.len() {
+            check_against_reader(&input[..len], &format!("truncated to {}", len));
+        }
+        for i in 0..input.len() {
+            let mut corrupted = input.clone();
+            corrupted[i] ^= 0x40;
+            check_against_reader(&corrupted, &format!("byte {} corrupted", i));
+        }
+    }
+
+    #[test]
+    fn test_error_position() {
+        let input = gzip::compress_to_vec(b"hello hello hello", Compression::default());
+        let err = decode(&input[..input.len() - 6], Format::Gzip).unwrap_err();
+        let position = DecodeError::from_io_error(&err).unwrap().position();
+        // at the start of the trailer
+        assert_eq!(
+            (position.byte, position.member),
+            (input.len() as u64 - 8, 1)
+        );
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let input = gzip::compress_to_vec(&[b'a'; 100_000], Compression::default());
+        let mut decoder = SliceDecoder::new(&input);
+        decoder.set_max_output_bytes(Some(1000));
+        let mut out = vec![];
+        let err = decoder.decode_to_vec(&mut out).unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::OutputLimitExceeded(1000))
+        );
+
+        let mut decoder = SliceDecoder::new(&input);
+        decoder.set_max_output_bytes(Some(100_000));
+        decoder.decode_to_vec(&mut out).unwrap();
+    }
+
+    #[test]
+    fn test_lenient_missing_trailer() {
+        let input = gzip::compress_to_vec(b"no trailer", Compression::default());
+        let input = &input[..input.len() - 8];
+        assert!(decode(input, Format::Gzip).is_err());
+        let mut decoder = SliceDecoder::new(input);
+        decoder.set_strictness(Strictness::Lenient);
+        let mut out = vec![];
+        decoder.decode_to_vec(&mut out).unwrap();
+        assert_eq!(out, b"no trailer");
+        assert_eq!(decoder.header().unwrap().os, 255);
+    }
+}
@@ -0,0 +1,380 @@
+//! The complete state of a decoder, to resume decoding later, see [`RZLibReader::snapshot`].
+//!
+//! [`RZLibReader::snapshot`]: crate::RZLibReader::snapshot
+
+use alloc::vec::Vec;
+
+use crate::io;
+
+use crate::binary::{invalid, ByteReader, ByteWriter};
+use crate::gzheader::GzHeader;
+use crate::members::GzTrailer;
+use crate::rzlibreader::{Format, State};
+
+const MAGIC: &[u8] = b"RZSNAP\x00\x01";
+
+// The codes of the Huffman block being decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BlockCodes {
+    // outside Huffman blocks
+    None,
+    Fixed,
+    // literal/length code lengths followed by distance code lengths
+    Dynamic { nlit: usize, lengths: Vec<u8> },
+}
+
+/// The state of an [`RZLibReader`](crate::RZLibReader) between two reads: where it is in the
+/// compressed and decompressed data, the window, the codes of the current block and the
+/// checksums so far. Decoding can start again from it with
+/// [`RZLibReader::restore`](crate::RZLibReader::restore), in another process if saved with
+/// [`Snapshot::to_bytes`], e.g. to resume an interrupted download.
+///
+/// Unlike a [`Checkpoint`](crate::Checkpoint), it can be taken anywhere, even in the middle of
+/// a block, and holds the data decoded ahead but not read yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub(crate) format: Format,
+    pub(crate) state: State,
+    pub(crate) in_bits: u64,
+    // decompressed bytes decoded, including the unread ones
+    pub(crate) total_out: u64,
+    // the window, including the unread data at its end
+    pub(crate) window: Vec<u8>,
+    pub(crate) unread: usize,
+    pub(crate) unread_distance: usize,
+    pub(crate) codes: BlockCodes,
+    pub(crate) crc: u32,
+    pub(crate) adler: u32,
+    pub(crate) member_size: u32,
+    pub(crate) member_index: u64,
+    pub(crate) block_index: u64,
+    pub(crate) header: Option<GzHeader>,
+    pub(crate) trailer: Option<GzTrailer>,
+}
+
+impl Snapshot {
+    /// Offset in the compressed data of the byte decoding resumes at: the underlying reader has
+    /// to be moved there before restoring.
+    pub fn input_offset(&self) -> u64 {
+        self.in_bits / 8
+    }
+
+    /// Offset in the decompressed data of the next byte read after restoring.
+    pub fn total_out(&self) -> u64 {
+        self.total_out - self.unread_distance as u64
+    }
+
+    /// Saves the snapshot in a compact binary layout, read back by [`Snapshot::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::new(MAGIC);
+        writer.u8(match self.format {
+            Format::Gzip => 0,
+            Format::Zlib => 1,
+            Format::Deflate => 2,
+            Format::Deflate64 => 3,
+        });
+        match self.state {
+            State::BrokenStream => unreachable!("snapshots are not taken of broken streams"),
+            State::MemberHeader => writer.u8(0),
+            State::BlockHeader => writer.u8(1),
+            State::NoCompressionBlock { len, is_final } => {
+                writer.u8(2);
+                writer.u64(len as u64);
+                writer.u8(is_final as u8);
+            }
+            State::HuffmanBlock { is_final } => {
+                writer.u8(3);
+                writer.u8(is_final as u8);
+            }
+            State::HuffmanBlockLength { symbol, is_final } => {
+                writer.u8(4);
+                writer.u64(symbol as u64);
+                writer.u8(is_final as u8);
+            }
+            State::HuffmanBlockMatch {
+                length,
+                distance,
+                is_final,
+            } => {
+                writer.u8(5);
+                writer.u64(length as u64);
+                writer.u64(distance as u64);
+                writer.u8(is_final as u8);
+            }
+            State::MemberTrailer => writer.u8(6),
+            State::EndOfFile => writer.u8(7),
+        }
+        writer.u64(self.in_bits);
+        writer.u64(self.total_out);
+        writer.bytes(&self.window);
+        writer.u64(self.unread as u64);
+        writer.u64(self.unread_distance as u64);
+        match &self.codes {
+            BlockCodes::None => writer.u8(0),
+            BlockCodes::Fixed => writer.u8(1),
+            BlockCodes::Dynamic { nlit, lengths } => {
+                writer.u8(2);
+                writer.u64(*nlit as u64);
+                writer.bytes(lengths);
+            }
+        }
+        writer.u32(self.crc);
+        writer.u32(self.adler);
+        writer.u32(self.member_size);
+        writer.u64(self.member_index);
+        writer.u64(self.block_index);
+        match &self.header {
+            Some(header) => {
+                writer.u8(1);
+                writer.u8(header.text as u8);
+                writer.u32(header.mtime);
+                writer.u8(header.xfl);
+                writer.u8(header.os);
+                writer.optional_bytes(header.extra.as_deref());
+                writer.optional_bytes(header.filename.as_deref());
+                writer.optional_bytes(header.comment.as_deref());
+            }
+            None => writer.u8(0),
+        }
+        match &self.trailer {
+            Some(trailer) => {
+                writer.u8(1);
+                writer.u32(trailer.crc32);
+                writer.u32(trailer.isize);
+            }
+            None => writer.u8(0),
+        }
+        writer.finish()
+    }
+
+    /// Reads a snapshot saved by [`Snapshot::to_bytes`]. Fails with `InvalidData` if `data` is
+    /// not one.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Snapshot> {
+        let mut reader = ByteReader::new(data, MAGIC)?;
+        let format = match reader.u8()? {
+            0 => Format::Gzip,
+            1 => Format::Zlib,
+            2 => Format::Deflate,
+            3 => Format::Deflate64,
+            _ => return Err(invalid("unknown format")),
+        };
+        let state = match reader.u8()? {
+            0 => State::MemberHeader,
+            1 => State::BlockHeader,
+            2 => State::NoCompressionBlock {
+                len: reader.usize()?,
+                is_final: reader.bool()?,
+            },
+            3 => State::HuffmanBlock {
+                is_final: reader.bool()?,
+            },
+            4 => State::HuffmanBlockLength {
+                symbol: reader.usize()?,
+                is_final: reader.bool()?,
+            },
+            5 => State::HuffmanBlockMatch {
+                length: reader.usize()?,
+                distance: reader.usize()?,
+                is_final: reader.bool()?,
+            },
+            6 => State::MemberTrailer,
+            7 => State::EndOfFile,
+            _ => return Err(invalid("unknown decoder state")),
+        };
+        let in_bits = reader.u64()?;
+        let total_out = reader.u64()?;
+        let window = reader.bytes()?;
+        let unread = reader.usize()?;
+        let unread_distance = reader.usize()?;
+        let codes = match reader.u8()? {
+            0 => BlockCodes::None,
+            1 => BlockCodes::Fixed,
+            2 => BlockCodes::Dynamic {
+                nlit: reader.usize()?,
+                lengths: reader.bytes()?,
+            },
+            _ => return Err(invalid("unknown block codes")),
+        };
+        let crc = reader.u32()?;
+        let adler = reader.u32()?;
+        let member_size = reader.u32()?;
+        let member_index = reader.u64()?;
+        let block_index = reader.u64()?;
+        let header = if reader.bool()? {
+            Some(GzHeader {
+                text: reader.bool()?,
+                mtime: reader.u32()?,
+                xfl: reader.u8()?,
+                os: reader.u8()?,
+                extra: reader.optional_bytes()?,
+                filename: reader.optional_bytes()?,
+                comment: reader.optional_bytes()?,
+            })
+        } else {
+            None
+        };
+        let trailer = if reader.bool()? {
+            Some(GzTrailer {
+                crc32: reader.u32()?,
+                isize: reader.u32()?,
+            })
+        } else {
+            None
+        };
+        reader.finish()?;
+        Ok(Snapshot {
+            format,
+            state,
+            in_bits,
+            total_out,
+            window,
+            unread,
+            unread_distance,
+            codes,
+            crc,
+            adler,
+            member_size,
+            member_index,
+            block_index,
+            header,
+            trailer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{BlockCodes, Snapshot};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+    use crate::rzlibreader::{Format, RZLibReader};
+    use crate::zlibencoder::ZlibEncoder;
+
+    fn data() -> Vec<u8> {
+        (0..200_000u32)
+            .map(|i| ((i % 251) ^ (i / 1200) ^ (i.wrapping_mul(2654435761) >> 30)) as u8)
+            .collect()
+    }
+
+    // Decodes `input` in reads of `read_len` bytes, resuming from a saved snapshot every few
+    // reads in a new decoder.
+    fn decode_resuming(input: &[u8], format: Format, read_len: usize) -> Vec<u8> {
+        let mut reader = RZLibReader::with_format(input, format);
+        let mut out = vec![];
+        let mut buf = vec![0; read_len];
+        for i in 0.. {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                return out;
+            }
+            out.extend_from_slice(&buf[..n]);
+            if i % 7 == 0 {
+                let saved = reader.snapshot().unwrap().to_bytes();
+                let snapshot = Snapshot::from_bytes(&saved).unwrap();
+                assert_eq!(snapshot.total_out(), out.len() as u64);
+                let offset = snapshot.input_offset() as usize;
+                reader = RZLibReader::with_format(&input[offset..], format);
+                reader.restore(&snapshot).unwrap();
+                assert_eq!(reader.total_out(), out.len() as u64);
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn test_resume() {
+        let data = data();
+        let mut input = vec![];
+        for level in [6, 0, 1] {
+            let mut encoder = GzEncoder::new(vec![], Compression::new(level));
+            encoder.write_all(&data[..70_000]).unwrap();
+            input.extend_from_slice(&encoder.finish().unwrap());
+        }
+        let expected = [&data[..70_000], &data[..70_000], &data[..70_000]].concat();
+        for read_len in [1, 100, 1000, 40_000] {
+            let out = decode_resuming(&input, Format::Gzip, read_len);
+            assert!(out == expected, "reads of {}", read_len);
+        }
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data).unwrap();
+        let input = encoder.finish().unwrap();
+        assert!(decode_resuming(&input, Format::Zlib, 3000) == data);
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data()).unwrap();
+        let input = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.read_exact(&mut [0; 50_000]).unwrap();
+        let snapshot = reader.snapshot().unwrap();
+
+        let mut zlib = RZLibReader::with_format(&input[..], Format::Zlib);
+        assert_eq!(
+            zlib.restore(&snapshot).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        let mut small = RZLibReader::with_window_size(&input[..], Format::Gzip, 1024);
+        assert_eq!(
+            small.restore(&snapshot).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+
+        let saved = snapshot.to_bytes();
+        for len in 0..saved.len() {
+            let err = Snapshot::from_bytes(&saved[..len]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        let mut corrupted = saved.clone();
+        corrupted[8] = 9;
+        assert!(Snapshot::from_bytes(&corrupted).is_err());
+        assert!(Snapshot::from_bytes(&[&saved[..], &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_restore_invalid_codes() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&data()).unwrap();
+        let input = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.read_exact(&mut [0; 50_000]).unwrap();
+        let snapshot = reader.snapshot().unwrap();
+        let BlockCodes::Dynamic { nlit, lengths } = &snapshot.codes else {
+            panic!("not in a dynamic block: {:?}", snapshot.codes);
+        };
+
+        let mut incomplete = lengths.clone();
+        incomplete[..*nlit].fill(15);
+        let mut no_end_of_block = lengths.clone();
+        no_end_of_block[256] = 0;
+        let mut oversubscribed = lengths.clone();
+        oversubscribed[*nlit..].fill(1);
+        for lengths in [incomplete, no_end_of_block, oversubscribed] {
+            let mut snapshot = snapshot.clone();
+            snapshot.codes = BlockCodes::Dynamic { nlit: *nlit, lengths };
+            let mut reader = RZLibReader::new(&input[snapshot.input_offset() as usize..]);
+            assert_eq!(reader.restore(&snapshot).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_restore_trailer() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello, rzlib\n").unwrap();
+        let input = encoder.finish().unwrap();
+        let mut reader = RZLibReader::new(&input[..]);
+        reader.set_multi_member(false);
+        reader.read_to_end(&mut vec![]).unwrap();
+        let trailer = reader.trailer();
+        assert!(trailer.is_some());
+
+        let snapshot = Snapshot::from_bytes(&reader.snapshot().unwrap().to_bytes()).unwrap();
+        let mut restored = RZLibReader::new(&input[snapshot.input_offset() as usize..]);
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.trailer(), trailer);
+    }
+}
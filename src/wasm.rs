@@ -0,0 +1,88 @@
+//! JavaScript bindings of the decoder, built with `wasm-bindgen` when the `wasm` feature is on.
+//!
+//! Build them for the browser with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib`
+//! followed by `wasm-bindgen --target web` on the resulting `rzlib.wasm`. Errors are thrown as JS
+//! `Error`s carrying the decoder's message.
+
+use wasm_bindgen::prelude::*;
+
+use crate::pushinput::{decode_available, PushInput};
+use crate::rzlibreader::RZLibReader;
+
+/// Decompresses a complete gzip stream, possibly made of several members.
+#[wasm_bindgen]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(crate::gzip::decompress_to_vec(data)?)
+}
+
+/// Decompresses a gzip stream fed in chunks as it arrives, e.g. from the body of a `fetch`
+/// response.
+#[wasm_bindgen]
+pub struct Gunzip {
+    reader: RZLibReader<PushInput>,
+}
+
+#[wasm_bindgen]
+impl Gunzip {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Gunzip {
+        Gunzip { reader: RZLibReader::new(PushInput::new()) }
+    }
+
+    /// Feeds the next chunk of compressed data, returning as much decompressed data as it
+    /// completes (possibly none).
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<u8>, JsError> {
+        self.reader.get_mut().push(chunk);
+        let mut out = vec![];
+        decode_available(&mut self.reader, &mut out)?;
+        Ok(out)
+    }
+
+    /// Signals the end of the compressed data, returning the rest of the decompressed data.
+    /// Throws if the stream is incomplete.
+    pub fn finish(&mut self) -> Result<Vec<u8>, JsError> {
+        self.reader.get_mut().finish();
+        let mut out = vec![];
+        decode_available(&mut self.reader, &mut out)?;
+        Ok(out)
+    }
+
+    /// Number of decompressed bytes returned so far.
+    #[wasm_bindgen(getter, js_name = totalOut)]
+    pub fn total_out(&self) -> f64 {
+        self.reader.total_out() as f64
+    }
+}
+
+impl Default for Gunzip {
+    fn default() -> Gunzip {
+        Gunzip::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{decompress, Gunzip};
+    use crate::deflateencoder::Compression;
+    use crate::gzencoder::GzEncoder;
+
+    #[test]
+    fn test_decompress_and_push() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello from the browser").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), b"hello from the browser");
+
+        let mut gunzip = Gunzip::new();
+        let mut out = vec![];
+        for chunk in compressed.chunks(4) {
+            out.extend(gunzip.push(chunk).unwrap());
+        }
+        out.extend(gunzip.finish().unwrap());
+        assert_eq!(out, b"hello from the browser");
+        assert_eq!(gunzip.total_out(), 22.0);
+    }
+}
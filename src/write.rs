@@ -0,0 +1,224 @@
+//! Encoders and decoders that are written to rather than read from, passing their output on to
+//! an underlying writer as it becomes available. The decoders suit pipelines that receive
+//! compressed data in chunks and push it into a sink.
+//!
+//! The underlying writer should block rather than fail with `WouldBlock`, which the decoders do
+//! not retry.
+
+use crate::io;
+use crate::io::Write;
+
+use crate::gzheader::GzHeader;
+use crate::pushinput::{decode_available, PushInput};
+use crate::rzlibreader::{Format, RZLibReader};
+
+pub use crate::deflateencoder::DeflateEncoder;
+pub use crate::gzencoder::GzEncoder;
+pub use crate::zlibencoder::ZlibEncoder;
+
+// What both decoders share: the decoder of the input pushed so far, and where its output goes.
+struct Decoder<W: Write> {
+    reader: RZLibReader<PushInput>,
+    writer: W,
+    done: bool,
+}
+
+impl<W: Write> Decoder<W> {
+    fn new(writer: W, multi_member: bool) -> Decoder<W> {
+        let mut reader = RZLibReader::with_format(PushInput::new(), Format::Gzip);
+        reader.set_multi_member(multi_member);
+        Decoder {
+            reader,
+            writer,
+            done: false,
+        }
+    }
+
+    // Decodes `buf`, returning how much of it was part of the stream.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        self.reader.get_mut().push(buf);
+        self.done = decode_available(&mut self.reader, &mut self.writer)?;
+        Ok(buf.len() - self.reader.get_ref().remaining().len())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        if !self.done {
+            self.reader.get_mut().finish();
+            decode_available(&mut self.reader, &mut self.writer)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Decompresses a single gzip member written to it, writing the decompressed data to `W`.
+///
+/// Once the member has ended, writes return `Ok(0)`: bytes following the member are not
+/// consumed, so that `write` reports how many bytes the member took up to its end.
+pub struct GzDecoder<W: Write> {
+    inner: Decoder<W>,
+}
+
+impl<W: Write> GzDecoder<W> {
+    pub fn new(writer: W) -> GzDecoder<W> {
+        GzDecoder {
+            inner: Decoder::new(writer, false),
+        }
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.reader.header()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner.writer
+    }
+
+    /// Returns the underlying writer. Writing to it directly interleaves with the decompressed
+    /// data.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner.writer
+    }
+
+    /// Checks that the member is complete, failing with
+    /// [`RzlibError::TruncatedStream`](crate::RzlibError::TruncatedStream) otherwise, and returns
+    /// the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.inner.finish()
+    }
+}
+
+impl<W: Write> Write for GzDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.writer.flush()
+    }
+}
+
+/// Decompresses a gzip stream made of any number of concatenated members written to it, as if
+/// it were a single member, writing the decompressed data to `W`.
+pub struct MultiGzDecoder<W: Write> {
+    inner: Decoder<W>,
+}
+
+impl<W: Write> MultiGzDecoder<W> {
+    pub fn new(writer: W) -> MultiGzDecoder<W> {
+        MultiGzDecoder {
+            inner: Decoder::new(writer, true),
+        }
+    }
+
+    /// See [`RZLibReader::header`].
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.reader.header()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner.writer
+    }
+
+    /// See [`GzDecoder::get_mut`].
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner.writer
+    }
+
+    /// See [`GzDecoder::finish`].
+    pub fn finish(self) -> io::Result<W> {
+        self.inner.finish()
+    }
+}
+
+impl<W: Write> Write for MultiGzDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{GzDecoder, GzEncoder, MultiGzDecoder};
+    use crate::deflateencoder::Compression;
+    use crate::error::RzlibError;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decode_chunks() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 17 * i % 233) as u8).collect();
+        let compressed = compress(&data);
+        for chunk_size in [1, 100, 100_000] {
+            let mut decoder = GzDecoder::new(vec![]);
+            for chunk in compressed.chunks(chunk_size) {
+                decoder.write_all(chunk).unwrap();
+            }
+            assert!(
+                decoder.finish().unwrap() == data,
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_member() {
+        let first = compress(b"first");
+        let input = [first.clone(), compress(b"second")].concat();
+        let mut decoder = GzDecoder::new(vec![]);
+        assert_eq!(decoder.write(&input).unwrap(), first.len());
+        assert_eq!(decoder.write(&input).unwrap(), 0);
+        assert_eq!(decoder.finish().unwrap(), b"first");
+
+        let mut decoder = MultiGzDecoder::new(vec![]);
+        decoder.write_all(&input).unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"firstsecond");
+    }
+
+    #[test]
+    fn test_truncated() {
+        let compressed = compress(b"truncated");
+        let mut decoder = GzDecoder::new(vec![]);
+        decoder
+            .write_all(&compressed[..compressed.len() - 1])
+            .unwrap();
+        let err = decoder.finish().unwrap_err();
+        assert_eq!(
+            RzlibError::from_io_error(&err),
+            Some(&RzlibError::TruncatedStream)
+        );
+    }
+
+    #[test]
+    fn test_flush() {
+        // everything written before a flush decompresses from the output so far
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        let mut decoder = GzDecoder::new(vec![]);
+        let mut sent = 0;
+        for message in [&b"first message"[..], b"", b"second", b"third"] {
+            encoder.write_all(message).unwrap();
+            encoder.flush().unwrap();
+            decoder.write_all(&encoder.get_ref()[sent..]).unwrap();
+            sent = encoder.get_ref().len();
+            assert!(decoder.get_ref().ends_with(message));
+        }
+        let compressed = encoder.finish().unwrap();
+        decoder.write_all(&compressed[sent..]).unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"first messagesecondthird");
+    }
+}
@@ -0,0 +1,333 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::Write;
+
+use crate::crc32::Crc32;
+use crate::deflateencoder::{Compression, DeflateEncoder};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+// general purpose flags: sizes and CRC in a data descriptor (bit 3), UTF-8 names (bit 11)
+const FLAGS: u16 = 1 << 3 | 1 << 11;
+// version 2.0, the first with deflate, and MS-DOS attributes
+const VERSION: u16 = 20;
+// DOS date of 1980-01-01, the earliest one, as no modification time is recorded
+const DOS_DATE: u16 = 1 << 5 | 1;
+
+// A writer that counts the bytes written through it, to know the offsets of the records.
+struct Counter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+enum EntryWriter<W: Write> {
+    Stored(Counter<W>),
+    Deflated(Box<DeflateEncoder<Counter<W>>>),
+}
+
+// What the central directory records about an entry.
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Writes a ZIP archive to `W`, one entry at a time: [`ZipWriter::start_file`] begins an entry,
+/// whose data is then written through the `Write` impl.
+///
+/// As `W` need not be seekable, the CRC-32 and sizes of each entry follow its data in a data
+/// descriptor, and readers find them in the central directory written by [`ZipWriter::finish`].
+/// ZIP64 is not supported: entries and archives are limited to 4 GiB, and to 65535 entries.
+pub struct ZipWriter<W: Write> {
+    // None once finished, or once writing a record failed
+    writer: Option<EntryWriter<W>>,
+    // the entry being written, if any, and the ones before it
+    current: Option<Entry>,
+    entries: Vec<Entry>,
+    crc: Crc32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> ZipWriter<W> {
+        ZipWriter {
+            writer: Some(EntryWriter::Stored(Counter { writer, count: 0 })),
+            current: None,
+            entries: Vec::new(),
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Starts an entry named `name` (a `/`-separated path), compressed at `level`, or stored if
+    /// the level is [`Compression::none`]. The previous entry, if any, is completed.
+    pub fn start_file(&mut self, name: &str, level: Compression) -> io::Result<()> {
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "entry name too long"))?;
+        if self.entries.len() + self.current.is_some() as usize == usize::from(u16::MAX) {
+            return Err(zip64_error());
+        }
+        let mut counter = self.finish_entry()?;
+        let method = if level.level() == 0 {
+            METHOD_STORED
+        } else {
+            METHOD_DEFLATED
+        };
+        let offset = counter.count;
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // time
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        // CRC-32, compressed and uncompressed sizes, in the data descriptor
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        header.extend_from_slice(name.as_bytes());
+        counter.write_all(&header)?;
+
+        self.writer = Some(match method {
+            METHOD_STORED => EntryWriter::Stored(counter),
+            _ => EntryWriter::Deflated(Box::new(DeflateEncoder::new(counter, level))),
+        });
+        self.current = Some(Entry {
+            name: name.into(),
+            method,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            offset,
+        });
+        self.crc.reset();
+        Ok(())
+    }
+
+    // Completes the current entry, if any, with its data descriptor, and returns the underlying
+    // writer.
+    fn finish_entry(&mut self) -> io::Result<Counter<W>> {
+        let mut counter = match self.writer.take().ok_or_else(broken_error)? {
+            EntryWriter::Stored(counter) => counter,
+            EntryWriter::Deflated(encoder) => encoder.finish()?,
+        };
+        let Some(mut entry) = self.current.take() else {
+            return Ok(counter);
+        };
+        entry.crc = self.crc.sum();
+        // the local header is followed by the name only
+        entry.compressed_size = counter.count - entry.offset - 30 - entry.name.len() as u64;
+        if entry.compressed_size > u32::MAX as u64 || entry.size > u32::MAX as u64 {
+            return Err(zip64_error());
+        }
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        descriptor.extend_from_slice(&entry.crc.to_le_bytes());
+        descriptor.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+        descriptor.extend_from_slice(&(entry.size as u32).to_le_bytes());
+        counter.write_all(&descriptor)?;
+        self.entries.push(entry);
+        Ok(counter)
+    }
+
+    fn try_finish(&mut self) -> io::Result<W> {
+        let mut counter = self.finish_entry()?;
+        let directory_offset = counter.count;
+        if directory_offset > u32::MAX as u64 {
+            return Err(zip64_error());
+        }
+
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&VERSION.to_le_bytes()); // version made by
+            directory.extend_from_slice(&VERSION.to_le_bytes()); // version needed
+            directory.extend_from_slice(&FLAGS.to_le_bytes());
+            directory.extend_from_slice(&entry.method.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes()); // time
+            directory.extend_from_slice(&DOS_DATE.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+            directory.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // extra field and comment lengths, disk number, internal and external attributes
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&(entry.offset as u32).to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = directory.len() as u32;
+        let entries = self.entries.len() as u16;
+        directory.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        directory.extend_from_slice(&[0; 4]); // disk numbers
+        directory.extend_from_slice(&entries.to_le_bytes()); // entries on this disk
+        directory.extend_from_slice(&entries.to_le_bytes());
+        directory.extend_from_slice(&directory_size.to_le_bytes());
+        directory.extend_from_slice(&(directory_offset as u32).to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        counter.write_all(&directory)?;
+        counter.flush()?;
+        Ok(counter.writer)
+    }
+
+    /// Completes the last entry and writes the central directory, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()
+    }
+}
+
+// The writer is lost once writing a header or a data descriptor has failed.
+fn broken_error() -> io::Error {
+    io::Error::other("a previous write to the archive failed")
+}
+
+fn zip64_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "archive needs ZIP64, which is not supported",
+    )
+}
+
+impl<W: Write> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let entry = self.current.as_mut().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no entry started with start_file",
+            )
+        })?;
+        let len = match self.writer.as_mut().ok_or_else(broken_error)? {
+            EntryWriter::Stored(counter) => counter.write(buf)?,
+            EntryWriter::Deflated(encoder) => encoder.write(buf)?,
+        };
+        self.crc.update(&buf[..len]);
+        entry.size += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(EntryWriter::Stored(counter)) => counter.flush(),
+            Some(EntryWriter::Deflated(encoder)) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for ZipWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::ZipWriter;
+    use crate::crc32::Crc32;
+    use crate::deflateencoder::Compression;
+    use crate::rzlibreader::{Format, RZLibReader};
+
+    fn u16_at(data: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([data[offset], data[offset + 1]]) as usize
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    // Reads the entries of `archive` through its central directory: name, method and data.
+    fn entries(archive: &[u8]) -> Vec<(String, usize, Vec<u8>)> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), 0x06054b50);
+        let mut pos = u32_at(archive, end + 16);
+        let mut entries = vec![];
+        for _ in 0..u16_at(archive, end + 10) {
+            assert_eq!(u32_at(archive, pos), 0x02014b50);
+            let method = u16_at(archive, pos + 10);
+            let (crc, compressed_size) = (u32_at(archive, pos + 16), u32_at(archive, pos + 20));
+            let name_len = u16_at(archive, pos + 28);
+            let name = String::from_utf8(archive[pos + 46..pos + 46 + name_len].to_vec()).unwrap();
+
+            let local = u32_at(archive, pos + 42);
+            assert_eq!(u32_at(archive, local), 0x04034b50);
+            let start = local + 30 + u16_at(archive, local + 26) + u16_at(archive, local + 28);
+            let compressed = &archive[start..start + compressed_size];
+            let data = match method {
+                0 => compressed.to_vec(),
+                _ => {
+                    let mut data = vec![];
+                    let mut reader = RZLibReader::with_format(compressed, Format::Deflate);
+                    reader.read_to_end(&mut data).unwrap();
+                    data
+                }
+            };
+            let mut actual_crc = Crc32::new();
+            actual_crc.update(&data);
+            assert_eq!(actual_crc.sum() as usize, crc);
+            // the data descriptor follows the data
+            assert_eq!(u32_at(archive, start + compressed_size), 0x08074b50);
+            assert_eq!(u32_at(archive, start + compressed_size + 4), crc);
+
+            entries.push((name, method, data));
+            pos += 46 + name_len;
+        }
+        entries
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let text: Vec<u8> = b"hello, zip\n".repeat(1000);
+        let mut zip = ZipWriter::new(vec![]);
+        zip.start_file("docs/hello.txt", Compression::default())
+            .unwrap();
+        zip.write_all(&text).unwrap();
+        zip.start_file("empty", Compression::default()).unwrap();
+        zip.start_file("stored.bin", Compression::none()).unwrap();
+        zip.write_all(b"\x00\x01\x02").unwrap();
+        let archive = zip.finish().unwrap();
+
+        let entries = entries(&archive);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], ("docs/hello.txt".into(), 8, text));
+        assert_eq!(entries[1], ("empty".into(), 8, vec![]));
+        assert_eq!(entries[2], ("stored.bin".into(), 0, vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut zip = ZipWriter::new(vec![]);
+        assert!(zip.write_all(b"no entry").is_err());
+        assert!(zip
+            .start_file(&"x".repeat(70_000), Compression::default())
+            .is_err());
+        let archive = zip.finish().unwrap();
+        assert!(entries(&archive).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+//! One-shot compression and decompression of zlib data held in memory.
+
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::io::Write;
+
+use crate::deflateencoder::Compression;
+use crate::rzlibreader::Format;
+use crate::slicedecoder::SliceDecoder;
+use crate::zlibencoder::ZlibEncoder;
+
+/// Decompresses a complete zlib stream. Unlike gzip, zlib does not record the decompressed size,
+/// so the output grows as it is decompressed.
+pub fn decompress_to_vec(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    SliceDecoder::with_format(data, Format::Zlib).decode_to_vec(&mut out)?;
+    Ok(out)
+}
+
+/// Compresses `data` into a zlib stream.
+pub fn compress_to_vec(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress_to_vec, decompress_to_vec};
+    use crate::deflateencoder::Compression;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| ((i % 239) ^ (i / 700)) as u8)
+            .collect();
+        for level in [0, 1, 6, 9] {
+            let compressed = compress_to_vec(&data, Compression::new(level));
+            assert!(
+                decompress_to_vec(&compressed).unwrap() == data,
+                "level {}",
+                level
+            );
+        }
+        // what zlib.compress(b"hello") produces
+        let hello = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15,
+        ];
+        assert_eq!(decompress_to_vec(&hello).unwrap(), b"hello");
+    }
+}
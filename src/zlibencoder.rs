@@ -0,0 +1,156 @@
+use crate::io;
+use crate::io::Write;
+
+use crate::adler32::Adler32;
+use crate::deflateencoder::{Compression, DeflateEncoder, FlushMode};
+
+/// Compresses data written to it into a zlib stream (RFC 1950) written to `W`, as used by PNG
+/// and HTTP `deflate`.
+///
+/// The stream is completed by [`ZlibEncoder::finish`], or on drop (ignoring errors) if `finish`
+/// was never called.
+pub struct ZlibEncoder<W: Write> {
+    inner: DeflateEncoder<W>,
+    header_written: bool,
+    finished: bool,
+    adler: Adler32,
+}
+
+impl<W: Write> ZlibEncoder<W> {
+    pub fn new(writer: W, level: Compression) -> ZlibEncoder<W> {
+        ZlibEncoder {
+            inner: DeflateEncoder::new(writer, level),
+            header_written: false,
+            finished: false,
+            adler: Adler32::new(),
+        }
+    }
+
+    /// See [`DeflateEncoder::set_lazy_matching`].
+    pub fn set_lazy_matching(&mut self, lazy: bool) {
+        self.inner.set_lazy_matching(lazy);
+    }
+
+    /// See [`DeflateEncoder::set_rsyncable`].
+    pub fn set_rsyncable(&mut self, rsyncable: bool) {
+        self.inner.set_rsyncable(rsyncable);
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        // CM = deflate, CINFO = 7 for a 32 KiB window
+        let cmf: u8 = 0x78;
+        // FLEVEL, with the same level ranges as zlib
+        let flevel: u8 = match self.inner.level().level() {
+            0 | 1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        };
+        let mut flg = flevel << 6;
+        // FCHECK makes CMF * 256 + FLG a multiple of 31
+        flg += 31 - (((cmf as u16) << 8 | flg as u16) % 31) as u8;
+        self.inner.get_mut().write_all(&[cmf, flg])?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_header()?;
+        self.inner.try_finish()?;
+        self.inner
+            .get_mut()
+            .write_all(&self.adler.sum().to_be_bytes())?;
+        self.finished = true;
+        self.inner.get_mut().flush()
+    }
+
+    /// See [`DeflateEncoder::flush_with`].
+    pub fn flush_with(&mut self, mode: FlushMode) -> io::Result<()> {
+        // the flush point must come after the header
+        self.write_header()?;
+        self.inner.flush_with(mode)
+    }
+
+    /// Writes the final block and the Adler-32 trailer, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        self.inner.take_writer()
+    }
+}
+
+impl<W: Write> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        let len = self.inner.write(buf)?;
+        self.adler.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // the sync point must come after the header
+        self.write_header()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ZlibEncoder<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::ZlibEncoder;
+    use crate::deflateencoder::Compression;
+    use crate::rzlibreader::{Format, RZLibReader};
+
+    fn compress(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(vec![], level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..100_000).map(|i| (i * 13 % 241) as u8).collect();
+        for level in 0..=9 {
+            let compressed = compress(&data, Compression::new(level));
+            let mut out = vec![];
+            RZLibReader::with_format(&compressed[..], Format::Zlib)
+                .read_to_end(&mut out)
+                .unwrap();
+            assert!(out == data, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        // the headers zlib writes for each FLEVEL
+        assert_eq!(compress(b"", Compression::fast())[..2], [0x78, 0x01]);
+        assert_eq!(compress(b"", Compression::new(3))[..2], [0x78, 0x5e]);
+        assert_eq!(compress(b"", Compression::new(6))[..2], [0x78, 0x9c]);
+        assert_eq!(compress(b"", Compression::best())[..2], [0x78, 0xda]);
+        // the Adler-32 of no data is 1
+        let compressed = compress(b"", Compression::default());
+        assert_eq!(compressed[compressed.len() - 4..], [0, 0, 0, 1]);
+    }
+}